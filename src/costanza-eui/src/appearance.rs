@@ -0,0 +1,45 @@
+//! How the application looks, and whether it takes over the whole display (kiosk mode) for panels
+//! mounted next to the machine.
+
+use iced::theme::{Palette, Theme};
+use iced::Color;
+use serde::{Deserialize, Serialize};
+
+/// The themes to pick from.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeChoice {
+  #[default]
+  Dark,
+  Light,
+
+  /// Pure black and white, with saturated accents; for panels in bright shops or viewed from afar.
+  HighContrast,
+}
+
+impl ThemeChoice {
+  pub fn theme(&self) -> Theme {
+    match self {
+      Self::Dark => Theme::Dark,
+      Self::Light => Theme::Light,
+      Self::HighContrast => Theme::custom(Palette {
+        background: Color::BLACK,
+        text: Color::WHITE,
+        primary: Color::from_rgb(1.0, 0.85, 0.0),
+        success: Color::from_rgb(0.0, 1.0, 0.0),
+        danger: Color::from_rgb(1.0, 0.2, 0.2),
+      }),
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct AppearanceConfiguration {
+  #[serde(default)]
+  pub theme: ThemeChoice,
+
+  /// Runs fullscreen, without window decorations and above every other window. The cursor is not
+  /// hidden by us; that is left to the display server (e.g `unclutter`, or `-nocursor` for X).
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub kiosk: bool,
+}
@@ -0,0 +1,51 @@
+//! Entering the api token we authenticate with. Tokens are created from a browser signed in to the
+//! middleware (a `POST` to `/auth/tokens`), and saved to our configuration once entered.
+
+use iced::widget::{button, column, row, text, text_input};
+use iced::{Element, Length};
+
+/// The messages of the token entry.
+#[derive(Debug, Clone)]
+pub enum Message {
+  Input(String),
+  Save,
+}
+
+#[derive(Default)]
+pub struct Auth {
+  input: String,
+}
+
+impl Auth {
+  /// Handles a message of the token entry, returning the token to save once one was submitted.
+  pub fn update(&mut self, message: Message) -> Option<String> {
+    match message {
+      Message::Input(value) => {
+        self.input = value;
+        None
+      }
+      Message::Save if self.input.trim().is_empty() => None,
+      Message::Save => Some(std::mem::take(&mut self.input).trim().to_string()),
+    }
+  }
+
+  /// Renders the token entry, explaining why we need one.
+  pub fn view(&self, rejected: bool) -> Element<Message> {
+    let reason = match rejected {
+      true => "the middleware did not accept our api token",
+      false => "an api token is needed to connect to the middleware",
+    };
+
+    let entry = row![
+      text_input("api token", &self.input, Message::Input)
+        .on_submit(Message::Save)
+        .password()
+        .padding(6)
+        .width(Length::Fill),
+      button("save").on_press(Message::Save),
+    ]
+    .spacing(6);
+
+    column![text(reason), entry].spacing(6).into()
+  }
+}
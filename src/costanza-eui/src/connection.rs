@@ -0,0 +1,169 @@
+//! The websocket connection to the middleware, run as a subscription. Every frame received is parsed
+//! and handed to the application as an event, and the connection is opened again whenever it is lost.
+//! The middleware only upgrades requests it can authenticate, so our api token (if we have one) is
+//! sent along with every attempt.
+
+use crate::protocol;
+use async_tungstenite::tungstenite;
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use iced_native::subscription::{self, Subscription};
+
+/// How long we wait before opening the connection again after losing it; each failed attempt after
+/// that doubles the wait, up to `MAX_RECONNECT_DELAY`.
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The longest we wait between attempts to open the connection.
+const MAX_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The amount of frames we hold on to while they wait to be sent.
+const OUTBOUND_CAPACITY: usize = 100;
+
+/// The events produced by our connection.
+#[derive(Debug, Clone)]
+pub enum Event {
+  /// The connection has been opened; frames can be sent through the provided handle.
+  Connected(Connection),
+
+  /// A frame has been received.
+  Frame(protocol::Frame),
+
+  /// The middleware refused to open the connection; our token is missing, expired or revoked. It is
+  /// attempted again after the provided amount of time, in case the token is accepted by then.
+  Unauthorized(std::time::Duration),
+
+  /// The connection could not be opened, or was lost, with why and how long until it is attempted
+  /// again.
+  Disconnected(String, std::time::Duration),
+}
+
+/// A handle for sending frames through an open connection.
+#[derive(Debug, Clone)]
+pub struct Connection(mpsc::Sender<String>);
+
+impl Connection {
+  /// Queues a request to be sent. Requests queued after the connection is lost are dropped.
+  pub fn send(&mut self, message: &protocol::ClientMessage) {
+    match serde_json::to_string(message) {
+      Ok(payload) => {
+        if let Err(error) = self.0.try_send(payload) {
+          eprintln!("unable to queue request - {error}");
+        }
+      }
+      Err(error) => eprintln!("unable to serialize request - {error}"),
+    }
+  }
+}
+
+enum State {
+  /// Not connected, with the amount of attempts in a row that have failed.
+  Disconnected(u32),
+  Connected(
+    async_tungstenite::WebSocketStream<async_tungstenite::async_std::ConnectStream>,
+    mpsc::Receiver<String>,
+  ),
+}
+
+/// Returns the subscription connected to the websocket at the provided address, authenticated with
+/// the provided api token. Changing either opens a new connection.
+pub fn connect(addr: String, token: Option<String>) -> Subscription<Event> {
+  struct Connect;
+
+  subscription::unfold(
+    (std::any::TypeId::of::<Connect>(), addr.clone(), token.clone()),
+    State::Disconnected(0),
+    move |state| next(addr.clone(), token.clone(), state),
+  )
+}
+
+/// Returns the request upgrading our connection, with our token attached.
+fn upgrade(addr: &str, token: Option<&str>) -> tungstenite::Result<tungstenite::handshake::client::Request> {
+  let mut request = addr.into_client_request()?;
+
+  if let Some(token) = token {
+    let value = tungstenite::http::HeaderValue::from_str(&format!("Bearer {token}"))
+      .map_err(|error| tungstenite::Error::HttpFormat(error.into()))?;
+    request.headers_mut().insert("Authorization", value);
+  }
+
+  Ok(request)
+}
+
+/// Returns how long we wait before the next attempt, after the provided amount of failed ones.
+fn backoff(failures: u32) -> std::time::Duration {
+  match failures {
+    0 => std::time::Duration::ZERO,
+    failures => RECONNECT_DELAY
+      .saturating_mul(2u32.saturating_pow(failures - 1))
+      .min(MAX_RECONNECT_DELAY),
+  }
+}
+
+/// Returns the event for losing the connection (or failing to open it), along with our next state.
+fn disconnected(reason: String, failures: u32) -> (Option<Event>, State) {
+  let failures = failures.saturating_add(1);
+  (
+    Some(Event::Disconnected(reason, backoff(failures))),
+    State::Disconnected(failures),
+  )
+}
+
+/// Waits on whatever happens next to our connection.
+async fn next(addr: String, token: Option<String>, state: State) -> (Option<Event>, State) {
+  match state {
+    State::Disconnected(failures) => {
+      async_std::task::sleep(backoff(failures)).await;
+
+      let attempt = match upgrade(&addr, token.as_deref()) {
+        Ok(request) => async_tungstenite::async_std::connect_async(request).await,
+        Err(error) => Err(error),
+      };
+
+      match attempt {
+        Ok((websocket, _)) => {
+          let (sender, receiver) = mpsc::channel(OUTBOUND_CAPACITY);
+          (
+            Some(Event::Connected(Connection(sender))),
+            State::Connected(websocket, receiver),
+          )
+        }
+        // The middleware responds to unauthenticated upgrades as if there were nothing there.
+        Err(tungstenite::Error::Http(response)) if matches!(response.status().as_u16(), 401 | 403 | 404) => {
+          let failures = failures.saturating_add(1);
+          (
+            Some(Event::Unauthorized(backoff(failures))),
+            State::Disconnected(failures),
+          )
+        }
+        Err(error) => disconnected(error.to_string(), failures),
+      }
+    }
+    State::Connected(mut websocket, mut outbound) => {
+      let mut inbound = websocket.by_ref().fuse();
+
+      futures::select! {
+        received = inbound.select_next_some() => match received {
+          Ok(tungstenite::Message::Text(text)) => match serde_json::from_str::<protocol::Frame>(&text) {
+            Ok(frame) => (Some(Event::Frame(frame)), State::Connected(websocket, outbound)),
+            Err(error) => {
+              eprintln!("unable to parse frame - {error}");
+              (None, State::Connected(websocket, outbound))
+            }
+          },
+          Ok(tungstenite::Message::Close(frame)) => {
+            let reason = frame.map(|frame| frame.reason.to_string()).unwrap_or_else(|| "closed".into());
+            disconnected(reason, 0)
+          }
+          Ok(_) => (None, State::Connected(websocket, outbound)),
+          Err(error) => disconnected(error.to_string(), 0),
+        },
+        payload = outbound.select_next_some() => {
+          match websocket.send(tungstenite::Message::Text(payload)).await {
+            Ok(()) => (None, State::Connected(websocket, outbound)),
+            Err(error) => disconnected(error.to_string(), 0),
+          }
+        }
+      }
+    }
+  }
+}
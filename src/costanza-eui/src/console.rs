@@ -0,0 +1,135 @@
+//! The console screen: our history with the controller, and a line for sending it raw commands. Each
+//! command we send is shown along with the status of its request, and the response the controller
+//! answered it with.
+
+use crate::protocol;
+use iced::widget::{column, row, scrollable, text, text_input};
+use iced::{Command, Element, Length};
+
+/// The amount of request statuses we hold on to; the history they are shown in is capped too.
+const REQUEST_CAPACITY: usize = 200;
+
+/// Where our request is at, by its tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestStatus {
+  Pending,
+  Ok,
+  Failed(String),
+}
+
+/// The messages of the console screen.
+#[derive(Debug, Clone)]
+pub enum Message {
+  Input(String),
+  Submit,
+}
+
+#[derive(Default)]
+pub struct Console {
+  input: String,
+  requests: std::collections::BTreeMap<u32, RequestStatus>,
+}
+
+/// The id of the scrollable holding our history, used to keep it scrolled to the newest entry.
+fn history_id() -> scrollable::Id {
+  scrollable::Id::new("console-history")
+}
+
+/// Returns the command scrolling our history to the newest entry.
+pub fn scroll_to_end<M>() -> Command<M>
+where
+  M: 'static,
+{
+  scrollable::snap_to(history_id(), 1.0)
+}
+
+impl Console {
+  /// Handles a message of the console, returning the raw line to send when one was submitted.
+  pub fn update(&mut self, message: Message) -> Option<String> {
+    match message {
+      Message::Input(value) => {
+        self.input = value;
+        None
+      }
+      Message::Submit if self.input.trim().is_empty() => None,
+      Message::Submit => Some(std::mem::take(&mut self.input)),
+    }
+  }
+
+  /// Records a request we have sent, which is pending until its response arrives.
+  pub fn sent(&mut self, tick: u32) {
+    self.requests.insert(tick, RequestStatus::Pending);
+
+    while self.requests.len() > REQUEST_CAPACITY {
+      match self.requests.keys().next().copied() {
+        Some(oldest) => self.requests.remove(&oldest),
+        None => break,
+      };
+    }
+  }
+
+  /// Records the response to one of our requests.
+  pub fn answered(&mut self, response: &protocol::Response) {
+    let status = match (response.status.as_str(), response.detail.as_ref()) {
+      ("ok", _) => RequestStatus::Ok,
+      (_, Some(detail)) => RequestStatus::Failed(detail.clone()),
+      (_, None) => RequestStatus::Failed(response.code.clone().unwrap_or_else(|| "failed".into())),
+    };
+
+    if let Some(current) = self.requests.get_mut(&response.tick) {
+      *current = status;
+    }
+  }
+
+  /// Returns the line rendering a history entry, if it is one we show.
+  fn line(&self, entry: &protocol::HistoryEntry) -> Option<String> {
+    match entry {
+      protocol::HistoryEntry::SentCommand { tick, request } => {
+        let value = request
+          .get("value")
+          .and_then(|value| value.as_str())
+          .or_else(|| request.get("kind").and_then(|kind| kind.as_str()))
+          .unwrap_or_default();
+
+        // Lines sent on behalf of the middleware (e.g the lines of a job) have no request of ours.
+        let badge = match self.requests.get(tick) {
+          Some(RequestStatus::Pending) => " [...]".to_string(),
+          Some(RequestStatus::Ok) => " [ok]".to_string(),
+          Some(RequestStatus::Failed(detail)) => format!(" [{detail}]"),
+          None => String::new(),
+        };
+
+        Some(format!("> {value}{badge}"))
+      }
+      protocol::HistoryEntry::ReceivedData {
+        content,
+        reply_to: Some(_),
+      } => Some(format!("  < {content}")),
+      protocol::HistoryEntry::ReceivedData {
+        content,
+        reply_to: None,
+      } => Some(content.clone()),
+      protocol::HistoryEntry::Other => None,
+    }
+  }
+
+  /// Renders the history, along with the line raw commands are entered on. Commands can only be
+  /// entered while we are allowed to send them.
+  pub fn view<'a>(&'a self, history: &'a [protocol::HistoryEntry], enabled: bool) -> Element<'a, Message> {
+    let lines = history
+      .iter()
+      .filter_map(|entry| self.line(entry))
+      .fold(column![].spacing(2), |lines, line| lines.push(text(line).size(14)));
+
+    let history = scrollable(lines).id(history_id()).height(Length::Fill);
+
+    let input = match enabled {
+      true => text_input("G0 X0 Y0", &self.input, Message::Input)
+        .on_submit(Message::Submit)
+        .padding(6),
+      false => text_input("only the operator may send commands", &self.input, Message::Input).padding(6),
+    };
+
+    column![history, row![input]].spacing(6).into()
+  }
+}
@@ -0,0 +1,124 @@
+//! Finding middleware instances to connect to. Instances advertise themselves over mdns; each one
+//! found (or lost) is handed to the application as an event. Whether an address can be reached is
+//! checked separately, by opening (and immediately closing) a tcp connection to it.
+
+use iced_native::subscription::{self, Subscription};
+
+/// The service type instances advertise themselves as.
+const SERVICE_TYPE: &str = "_costanza._tcp.local.";
+
+/// How long we wait on an instance to accept a connection before considering it unreachable.
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The events produced while browsing.
+#[derive(Debug, Clone)]
+pub enum Event {
+  /// An instance was found, by name, along with the address of its websocket.
+  Found(String, String),
+
+  /// An instance (by name) is no longer advertised.
+  Lost(String),
+}
+
+/// Whether an address could be reached when last checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reachability {
+  /// The address accepted a connection, after the provided amount of time.
+  Reachable(std::time::Duration),
+  Unreachable,
+}
+
+enum State {
+  Starting,
+  Browsing(mdns_sd::ServiceDaemon, mdns_sd::Receiver<mdns_sd::ServiceEvent>),
+
+  /// Browsing is not possible (e.g no network interfaces); manually entered addresses still work.
+  Failed,
+}
+
+/// Returns the subscription browsing for instances.
+pub fn browse() -> Subscription<Event> {
+  struct Browse;
+
+  subscription::unfold(std::any::TypeId::of::<Browse>(), State::Starting, next)
+}
+
+/// Returns the name of an instance from the full name it is advertised under.
+fn instance(fullname: &str) -> String {
+  fullname
+    .strip_suffix(SERVICE_TYPE)
+    .map(|name| name.trim_end_matches('.'))
+    .unwrap_or(fullname)
+    .to_string()
+}
+
+/// Waits on whatever happens next while browsing.
+async fn next(state: State) -> (Option<Event>, State) {
+  match state {
+    State::Starting => {
+      let browsing =
+        mdns_sd::ServiceDaemon::new().and_then(|daemon| daemon.browse(SERVICE_TYPE).map(|receiver| (daemon, receiver)));
+
+      match browsing {
+        Ok((daemon, receiver)) => (None, State::Browsing(daemon, receiver)),
+        Err(error) => {
+          eprintln!("unable to browse for instances - {error}");
+          (None, State::Failed)
+        }
+      }
+    }
+    State::Browsing(daemon, receiver) => match receiver.recv_async().await {
+      Ok(mdns_sd::ServiceEvent::ServiceResolved(info)) => {
+        let path = info.get_property_val_str("path").unwrap_or("/ws").to_string();
+
+        // Prefer the lowest address, so an instance does not jump between addresses as it is resolved.
+        let event = info.get_addresses().iter().min().map(|ip| {
+          let addr = format!("ws://{ip}:{}{path}", info.get_port());
+          Event::Found(instance(info.get_fullname()), addr)
+        });
+
+        (event, State::Browsing(daemon, receiver))
+      }
+      Ok(mdns_sd::ServiceEvent::ServiceRemoved(_, fullname)) => (
+        Some(Event::Lost(instance(&fullname))),
+        State::Browsing(daemon, receiver),
+      ),
+      Ok(_) => (None, State::Browsing(daemon, receiver)),
+      Err(error) => {
+        eprintln!("stopped browsing for instances - {error}");
+        (None, State::Failed)
+      }
+    },
+    State::Failed => futures::future::pending().await,
+  }
+}
+
+/// Checks whether the host of a websocket address accepts connections.
+pub async fn probe(addr: String) -> (String, Reachability) {
+  let host = addr
+    .split("://")
+    .nth(1)
+    .and_then(|rest| rest.split('/').next())
+    .unwrap_or_default()
+    .to_string();
+
+  // Addresses without a port use the default of their scheme.
+  let port = host
+    .rsplit_once(':')
+    .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+  let host = match (port, addr.starts_with("wss://")) {
+    (true, _) => host,
+    (false, true) => format!("{host}:443"),
+    (false, false) => format!("{host}:80"),
+  };
+
+  let started = std::time::Instant::now();
+  let attempt = async_std::future::timeout(PROBE_TIMEOUT, async_std::net::TcpStream::connect(host.as_str())).await;
+
+  let reachability = match attempt {
+    Ok(Ok(_)) => Reachability::Reachable(started.elapsed()),
+    _ => Reachability::Unreachable,
+  };
+
+  (addr, reachability)
+}
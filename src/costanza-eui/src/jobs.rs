@@ -0,0 +1,120 @@
+//! The jobs screen: the programs stored by the middleware (its macros), and the controls for running
+//! one of them. The progress of the job being sent is shown as it is broadcast to us.
+
+use crate::protocol;
+use iced::widget::{button, column, progress_bar, row, scrollable, text};
+use iced::{Element, Length};
+use std::collections::BTreeMap;
+
+/// The messages of the jobs screen.
+#[derive(Debug, Clone)]
+pub enum Message {
+  Select(String),
+  Start,
+  Pause,
+  Resume,
+  Cancel,
+}
+
+#[derive(Default)]
+pub struct Jobs {
+  /// Every program available to run, by name, along with its lines.
+  programs: BTreeMap<String, String>,
+  selected: Option<String>,
+}
+
+impl Jobs {
+  /// Replaces the programs available to run, e.g once the middleware lets us know one was saved.
+  pub fn set_programs(&mut self, programs: BTreeMap<String, String>) {
+    if let Some(selected) = self.selected.as_ref() {
+      if !programs.contains_key(selected) {
+        self.selected = None;
+      }
+    }
+
+    self.programs = programs;
+  }
+
+  /// Handles a message of the jobs screen, returning the request to send for it, if any.
+  pub fn update(&mut self, message: Message, state: &protocol::State) -> Option<protocol::Request> {
+    match message {
+      Message::Select(name) => {
+        self.selected = Some(name);
+        None
+      }
+      Message::Start => self
+        .selected
+        .clone()
+        .map(|name| protocol::Request::RunMacro(protocol::RunMacroRequest { name })),
+      Message::Pause => Some(protocol::Request::FeedHold),
+      // A job stopped at a line (e.g a tool change) is waiting on us, not on the controller.
+      Message::Resume if state.paused.is_some() => Some(protocol::Request::ResumeJob),
+      Message::Resume => Some(protocol::Request::CycleStart),
+      Message::Cancel => Some(protocol::Request::CancelJob),
+    }
+  }
+
+  /// Renders the programs, the progress of the job being sent and its controls. The controls only
+  /// do anything while we are allowed to send requests.
+  pub fn view<'a>(&'a self, state: &'a protocol::State, enabled: bool) -> Element<'a, Message> {
+    let programs = self
+      .programs
+      .iter()
+      .fold(column![].spacing(2), |programs, (name, lines)| {
+        let label = match self.selected.as_ref() == Some(name) {
+          true => format!("> {name} ({} lines)", lines.lines().count()),
+          false => format!("{name} ({} lines)", lines.lines().count()),
+        };
+
+        programs.push(
+          button(text(label).size(14))
+            .width(Length::Fill)
+            .on_press(Message::Select(name.clone())),
+        )
+      });
+
+    let programs: Element<'a, Message> = match self.programs.is_empty() {
+      true => text("no stored programs").into(),
+      false => scrollable(programs).height(Length::Fill).into(),
+    };
+
+    let (summary, value) = match state.job.as_ref() {
+      Some(job) => (
+        format!(
+          "{}: line {} of {}",
+          job.name.as_deref().unwrap_or("unnamed job"),
+          job.line,
+          job.total
+        ),
+        job.line as f32 / job.total.max(1) as f32,
+      ),
+      None => (String::from("no job running"), 0.0),
+    };
+
+    let running = state.job.is_some();
+    let control = |label: &'a str, message: Message, available: bool| {
+      let control = button(label);
+      match enabled && available {
+        true => control.on_press(message),
+        false => control,
+      }
+    };
+
+    let controls = row![
+      control("start", Message::Start, !running && self.selected.is_some()),
+      control("pause", Message::Pause, running),
+      control("resume", Message::Resume, running),
+      control("cancel", Message::Cancel, running),
+    ]
+    .spacing(6);
+
+    column![
+      programs,
+      text(summary),
+      progress_bar(0.0..=1.0, value).height(Length::Units(8)),
+      controls
+    ]
+    .spacing(6)
+    .into()
+  }
+}
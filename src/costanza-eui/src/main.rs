@@ -1,28 +1,75 @@
 #![allow(dead_code, unused)]
 
-//! TODO: this whole application is pending active construction. What exists here only serves to
-//! provide a distributable artifact for hardware/os verification purposes.
+//! A small desktop client for the middleware, meant for the touchscreen mounted next to the machine.
+//! Once an instance of the middleware is picked (from the ones found on the network, or entered by
+//! hand), it connects to its websocket and renders the state of the connection and the machine as it
+//! changes, along with a console for sending the controller raw commands and a screen for running the
+//! programs stored by the middleware.
 
 use clap::Parser;
-use iced::widget::{button, column};
-use iced::{executor, Alignment, Application, Command, Element, Settings, Theme};
-use serde::Deserialize;
+use iced::widget::{button, column, row, text};
+use iced::{executor, window, Alignment, Application, Command, Element, Length, Settings, Subscription, Theme};
+use serde::{Deserialize, Serialize};
 use std::io;
 
-#[derive(Deserialize)]
+mod appearance;
+mod auth;
+mod connection;
+mod console;
+mod discovery;
+mod jobs;
+mod protocol;
+mod servers;
+
+/// How often the instances shown on the servers screen are checked on.
+const PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The amount of requests held on to while we are not connected; the oldest are dropped first.
+const PENDING_CAPACITY: usize = 50;
+
+#[derive(Deserialize, Serialize)]
 struct WebsocketConfiguration {
-  addr: String,
+  /// The address of the websocket most recently connected to, e.g `ws://costanza.local:8080/ws`.
+  /// It is saved whenever an instance is picked, and listed first the next time we start.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  addr: Option<String>,
+
+  /// Addresses entered by hand, for instances that are not advertised on the network.
+  #[serde(default)]
+  addresses: Vec<String>,
+
+  /// The api token we authenticate with. When the middleware does not accept it (or there is none),
+  /// a new one can be entered, and is saved here.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  token: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct Configuration {
   websocket: WebsocketConfiguration,
+
+  #[serde(default)]
+  appearance: appearance::AppearanceConfiguration,
+}
+
+/// What the application is started with.
+struct Flags {
+  /// Where our configuration was read from, and is saved to.
+  path: String,
+  config: Configuration,
+
+  /// Whether we run in kiosk mode, from our configuration or our command line.
+  kiosk: bool,
 }
 
 #[derive(Parser)]
 struct CommandLineArguments {
   #[clap(short = 'c')]
   config: String,
+
+  /// Runs fullscreen, without window decorations, regardless of our configuration.
+  #[clap(long)]
+  kiosk: bool,
 }
 
 pub fn main() -> io::Result<()> {
@@ -32,41 +79,495 @@ pub fn main() -> io::Result<()> {
   let args = CommandLineArguments::parse();
   let contents = std::fs::read_to_string(&args.config)?;
   let config = toml::from_str::<Configuration>(&contents)?;
-  let mut settings = Settings::with_flags(config);
+  let kiosk = args.kiosk || config.appearance.kiosk;
+  let mut settings = Settings::with_flags(Flags {
+    path: args.config,
+    config,
+    kiosk,
+  });
   settings.window.size = (480, 272);
   settings.window.resizable = false;
-  Counter::run(settings).map_err(|error| io::Error::new(io::ErrorKind::Other, format!("runtime error - {error}")))
+
+  if kiosk {
+    settings.window.decorations = false;
+    settings.window.always_on_top = true;
+  }
+
+  Costanza::run(settings).map_err(|error| io::Error::new(io::ErrorKind::Other, format!("runtime error - {error}")))
 }
 
-struct Counter {}
+/// Where we are with the connection to the middleware.
+enum ConnectionStatus {
+  Connecting,
+
+  /// The connection is open, but the middleware has not said hello yet.
+  Handshaking(connection::Connection),
+  Connected(connection::Connection),
+
+  /// The middleware speaks a version of the protocol we do not.
+  Incompatible(u32),
+
+  /// The connection was lost (or could not be opened), with why; it is retried on its own after the
+  /// provided amount of time.
+  Disconnected(String, std::time::Duration),
+
+  /// The middleware refused our connection; we need a (new) api token.
+  Unauthorized,
+}
+
+/// The screens of the application, picked from the bar along the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Screen {
+  Servers,
+  Status,
+  Console,
+  Jobs,
+}
+
+struct Costanza {
+  path: String,
+  config: Configuration,
+  kiosk: bool,
+  status: ConnectionStatus,
+  screen: Screen,
+
+  /// The address of the instance we are connected (or connecting) to, once one has been picked.
+  target: Option<String>,
+  servers: servers::Servers,
 
-#[derive(Debug, Clone, Copy)]
+  /// The tick of the most recent request we have sent.
+  tick: u32,
+
+  /// The requests made while we were not connected, sent once we are again. Only requests that are
+  /// safe to replay are held on to.
+  pending: std::collections::VecDeque<protocol::ClientMessage>,
+
+  /// The token of our session with the middleware, resumed when we reconnect.
+  resume_token: Option<String>,
+
+  /// While we are resuming our session, the tick of the request doing so and the token we were
+  /// given by the connection it replaces.
+  resuming: Option<(u32, String)>,
+  console: console::Console,
+  jobs: jobs::Jobs,
+  auth: auth::Auth,
+
+  /// The version of the middleware we are connected to.
+  server_version: Option<String>,
+
+  /// Our state, as most recently sent by the middleware.
+  state: Option<protocol::State>,
+}
+
+#[derive(Debug, Clone)]
 enum Message {
-  Home,
+  Connection(connection::Event),
+  Show(Screen),
+  Console(console::Message),
+  Jobs(jobs::Message),
+  Auth(auth::Message),
+  Servers(servers::Message),
+
+  /// Time to check on the instances shown on the servers screen.
+  Probe,
 }
 
-impl Application for Counter {
+impl Application for Costanza {
   type Message = Message;
-  type Flags = Configuration;
+  type Flags = Flags;
   type Executor = executor::Default;
   type Theme = Theme;
 
   fn new(flags: Self::Flags) -> (Self, Command<Message>) {
-    (Self {}, Command::none())
+    let app = Self {
+      path: flags.path,
+      config: flags.config,
+      kiosk: flags.kiosk,
+      status: ConnectionStatus::Connecting,
+      screen: Screen::Servers,
+      target: None,
+      servers: servers::Servers::default(),
+      tick: 0,
+      pending: std::collections::VecDeque::default(),
+      resume_token: None,
+      resuming: None,
+      console: console::Console::default(),
+      jobs: jobs::Jobs::default(),
+      auth: auth::Auth::default(),
+      server_version: None,
+      state: None,
+    };
+
+    let probes = app.probe();
+    (app, probes)
   }
 
   fn title(&self) -> String {
-    String::from("Counter - Iced")
+    String::from("costanza")
+  }
+
+  fn theme(&self) -> Theme {
+    self.config.appearance.theme.theme()
+  }
+
+  fn mode(&self) -> window::Mode {
+    match self.kiosk {
+      true => window::Mode::Fullscreen,
+      false => window::Mode::Windowed,
+    }
+  }
+
+  fn subscription(&self) -> Subscription<Message> {
+    let connection = match self.target.as_ref() {
+      Some(addr) => connection::connect(addr.clone(), self.config.websocket.token.clone()).map(Message::Connection),
+      None => Subscription::none(),
+    };
+
+    // Instances are only looked for while they are being picked from.
+    let discovery = match self.screen {
+      Screen::Servers => Subscription::batch([
+        discovery::browse().map(|event| Message::Servers(servers::Message::Discovery(event))),
+        iced::time::every(PROBE_INTERVAL).map(|_| Message::Probe),
+      ]),
+      _ => Subscription::none(),
+    };
+
+    Subscription::batch([connection, discovery])
   }
 
   fn update(&mut self, message: Message) -> Command<Message> {
+    match message {
+      Message::Connection(connection::Event::Connected(connection)) => {
+        self.status = ConnectionStatus::Handshaking(connection);
+      }
+      Message::Connection(connection::Event::Disconnected(reason, retry_in)) => {
+        eprintln!("websocket disconnected - {reason}");
+        self.status = ConnectionStatus::Disconnected(reason, retry_in);
+      }
+      Message::Connection(connection::Event::Unauthorized(_)) => {
+        eprintln!("websocket refused, api token missing or not accepted");
+        self.status = ConnectionStatus::Unauthorized;
+      }
+      Message::Connection(connection::Event::Frame(protocol::Frame::Hello(hello))) => {
+        let status = std::mem::replace(&mut self.status, ConnectionStatus::Connecting);
+
+        self.status = match status {
+          _ if hello.protocol_version != protocol::PROTOCOL_VERSION => {
+            eprintln!("middleware speaks protocol {}", hello.protocol_version);
+            ConnectionStatus::Incompatible(hello.protocol_version)
+          }
+          ConnectionStatus::Handshaking(connection) | ConnectionStatus::Connected(connection) => {
+            ConnectionStatus::Connected(connection)
+          }
+          other => other,
+        };
+
+        self.server_version = Some(hello.server_version);
+        self.tick = self.tick.max(hello.state.tick);
+        self.jobs.set_programs(hello.macros);
+
+        let previous = std::mem::replace(&mut self.resume_token, hello.resume_token);
+        let since = self.state.as_ref().map(protocol::State::history_end);
+
+        match (previous, since) {
+          // Keep what we have, and have the middleware send whatever we missed while disconnected.
+          (Some(token), Some(since)) if matches!(self.status, ConnectionStatus::Connected(_)) => {
+            let tick = self.send(protocol::Request::ResumeSession(protocol::ResumeSessionRequest {
+              token: token.clone(),
+              since,
+            }));
+            self.resuming = Some((tick, token));
+          }
+          _ => {
+            let mut state = hello.state;
+            state.trim();
+            self.state = Some(state);
+          }
+        }
+
+        self.flush();
+      }
+      Message::Connection(connection::Event::Frame(protocol::Frame::State(state))) => {
+        let added = !state.history.is_empty();
+
+        match self.state.as_mut() {
+          Some(current) => current.merge(state),
+          None => self.state = Some(state),
+        }
+
+        if added && self.screen == Screen::Console {
+          return console::scroll_to_end();
+        }
+      }
+      Message::Connection(connection::Event::Frame(protocol::Frame::Response(response))) => {
+        match self.resuming.take() {
+          Some((tick, token)) if tick == response.tick => match response.status.as_str() {
+            "ok" => self.resume_token = Some(token),
+            // Our session is gone (e.g we were away for too long); start over from a full snapshot.
+            _ => {
+              eprintln!("unable to resume session - {:?}", response.code);
+              self.send(protocol::Request::ResyncState(protocol::ResyncRequest {
+                history: None,
+              }));
+            }
+          },
+          resuming => self.resuming = resuming,
+        }
+
+        self.console.answered(&response);
+      }
+      Message::Connection(connection::Event::Frame(protocol::Frame::Macros(macros))) => {
+        self.jobs.set_programs(macros.macros);
+      }
+      Message::Connection(connection::Event::Frame(_)) => (),
+      Message::Show(screen) => {
+        self.screen = screen;
+
+        match screen {
+          Screen::Console => return console::scroll_to_end(),
+          Screen::Servers => return self.probe(),
+          _ => (),
+        }
+      }
+      Message::Probe => return self.probe(),
+      Message::Servers(message) => match self.servers.update(message) {
+        Some(servers::Action::Add(addr)) => {
+          if !self.config.websocket.addresses.contains(&addr) {
+            self.config.websocket.addresses.push(addr.clone());
+
+            if let Err(error) = self.save() {
+              eprintln!("unable to save address - {error}");
+            }
+          }
+
+          return Command::perform(discovery::probe(addr), |probed| {
+            Message::Servers(servers::Message::Probed(probed))
+          });
+        }
+        Some(servers::Action::Probe(addr)) => {
+          return Command::perform(discovery::probe(addr), |probed| {
+            Message::Servers(servers::Message::Probed(probed))
+          });
+        }
+        Some(servers::Action::Connect(addr)) => {
+          // Our subscription is keyed by the address; replacing it drops any previous connection.
+          if self.target.as_ref() != Some(&addr) {
+            self.status = ConnectionStatus::Connecting;
+            self.server_version = None;
+            self.state = None;
+            self.pending.clear();
+            self.resume_token = None;
+            self.resuming = None;
+            self.target = Some(addr.clone());
+          }
+
+          self.screen = Screen::Status;
+          self.config.websocket.addr = Some(addr);
+
+          if let Err(error) = self.save() {
+            eprintln!("unable to save address - {error}");
+          }
+        }
+        None => (),
+      },
+      Message::Console(message) => {
+        if let Some(value) = self.console.update(message) {
+          let tick = self.send(protocol::Request::RawSerial(protocol::RawSerialRequest {
+            value,
+            force: false,
+          }));
+          self.console.sent(tick);
+        }
+      }
+      Message::Auth(message) => {
+        if let Some(token) = self.auth.update(message) {
+          // Our subscription is keyed by the token; replacing it opens a new connection.
+          self.config.websocket.token = Some(token);
+          self.status = ConnectionStatus::Connecting;
+
+          if let Err(error) = self.save() {
+            eprintln!("unable to save api token - {error}");
+          }
+        }
+      }
+      Message::Jobs(message) => {
+        let request = self.state.as_ref().and_then(|state| self.jobs.update(message, state));
+
+        if let Some(request) = request {
+          self.send(request);
+        }
+      }
+    }
+
     Command::none()
   }
 
   fn view(&self) -> Element<Message> {
-    column![button("Home").on_press(Message::Home),]
-      .padding(20)
-      .align_items(Alignment::Center)
+    let screens = row![
+      button("servers").on_press(Message::Show(Screen::Servers)),
+      button("status").on_press(Message::Show(Screen::Status)),
+      button("console").on_press(Message::Show(Screen::Console)),
+      button("jobs").on_press(Message::Show(Screen::Jobs)),
+    ]
+    .spacing(6);
+
+    let enabled = self
+      .state
+      .as_ref()
+      .is_some_and(|state| state.role == protocol::Role::Operator)
+      && matches!(self.status, ConnectionStatus::Connected(_));
+
+    // What is shown is stale while we are not connected; the status screen already says as much.
+    let banner = match (&self.status, self.screen) {
+      (ConnectionStatus::Disconnected(reason, retry_in), Screen::Console | Screen::Jobs) => Some(text(format!(
+        "connection lost ({reason}), reconnecting in {}s",
+        retry_in.as_secs()
+      ))),
+      _ => None,
+    };
+
+    let content = match (self.screen, self.state.as_ref()) {
+      (Screen::Console, Some(state)) => self.console.view(&state.history, enabled).map(Message::Console),
+      (Screen::Jobs, Some(state)) => self.jobs.view(state, enabled).map(Message::Jobs),
+      (Screen::Servers, _) => self
+        .servers
+        .view(&self.config.websocket.addresses, self.config.websocket.addr.as_ref())
+        .map(Message::Servers),
+      _ => self.status_view(),
+    };
+
+    let mut page = column![screens].spacing(6);
+
+    if let Some(banner) = banner {
+      page = page.push(banner);
+    }
+
+    page
+      .push(content)
+      .padding(8)
+      .width(Length::Fill)
+      .height(Length::Fill)
       .into()
   }
 }
+
+impl Costanza {
+  /// Writes our configuration back to where it was read from.
+  fn save(&self) -> io::Result<()> {
+    let contents = toml::to_string(&self.config).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+    std::fs::write(&self.path, contents)
+  }
+
+  /// Returns the command checking on whether every instance we know of can be reached.
+  fn probe(&self) -> Command<Message> {
+    let probes = self
+      .servers
+      .addresses(&self.config.websocket.addresses)
+      .map(|addr| {
+        Command::perform(discovery::probe(addr.clone()), |probed| {
+          Message::Servers(servers::Message::Probed(probed))
+        })
+      })
+      .collect::<Vec<_>>();
+
+    Command::batch(probes)
+  }
+
+  /// Sends a request through our connection, returning its tick. Requests made while we are not
+  /// connected are held on to when they are safe to replay, and dropped otherwise.
+  fn send(&mut self, request: protocol::Request) -> u32 {
+    self.tick += 1;
+
+    let message = protocol::ClientMessage {
+      tick: self.tick,
+      request,
+    };
+
+    match &mut self.status {
+      ConnectionStatus::Connected(connection) => connection.send(&message),
+      _ if protocol::replayable(&message.request) => {
+        if self.pending.len() >= PENDING_CAPACITY {
+          self.pending.pop_front();
+        }
+
+        self.pending.push_back(message);
+      }
+      _ => eprintln!("not connected, dropping request {:?}", message.request),
+    }
+
+    self.tick
+  }
+
+  /// Sends every request held on to while we were not connected.
+  fn flush(&mut self) {
+    if let ConnectionStatus::Connected(connection) = &mut self.status {
+      for message in self.pending.drain(..) {
+        connection.send(&message);
+      }
+    }
+  }
+
+  /// Renders the state of our connection, and of the machine.
+  fn status_view(&self) -> Element<Message> {
+    let target = match self.target.as_deref() {
+      Some(target) => target,
+      None => return text("no instance picked yet").into(),
+    };
+
+    let status = match &self.status {
+      ConnectionStatus::Connecting => format!("connecting to {target}"),
+      ConnectionStatus::Handshaking(_) => format!("waiting on {target}"),
+      ConnectionStatus::Connected(_) => format!(
+        "connected (middleware {})",
+        self.server_version.as_deref().unwrap_or("unknown")
+      ),
+      ConnectionStatus::Incompatible(version) => format!("unsupported protocol version {version}"),
+      ConnectionStatus::Disconnected(reason, retry_in) => {
+        format!("disconnected ({reason}), retrying in {}s", retry_in.as_secs())
+      }
+      ConnectionStatus::Unauthorized => format!("not allowed to connect to {target}"),
+    };
+
+    let mut content = column![text(status).size(20)].spacing(6);
+
+    if let ConnectionStatus::Unauthorized = self.status {
+      let rejected = self.config.websocket.token.is_some();
+      content = content.push(self.auth.view(rejected).map(Message::Auth));
+    }
+
+    if let Some(state) = self.state.as_ref() {
+      let serial = if state.serial_available {
+        "machine connected"
+      } else {
+        "machine not connected"
+      };
+
+      let role = match (state.role, state.controller.as_ref()) {
+        (protocol::Role::Operator, _) => String::from("operating"),
+        (protocol::Role::Observer, Some(controller)) => format!("observing ({controller} is operating)"),
+        (protocol::Role::Observer, None) => String::from("observing"),
+      };
+
+      let position = match state.position {
+        Some(position) => format!("X {:.3}  Y {:.3}  Z {:.3}", position.x, position.y, position.z),
+        None => String::from("position unknown"),
+      };
+
+      content = content.push(text(serial)).push(text(role)).push(text(position));
+
+      if let Some(pause) = state.paused.as_ref() {
+        content = content.push(text(format!("paused at '{}'", pause.line)));
+      }
+
+      if let Some(job) = state.last_job.as_ref() {
+        let name = job.name.as_deref().unwrap_or("unnamed job");
+        content = content.push(text(format!(
+          "last job: {name}, {} ({} lines)",
+          job.outcome, job.lines_sent
+        )));
+      }
+    }
+
+    content.align_items(Alignment::Start).into()
+  }
+}
@@ -0,0 +1,192 @@
+//! The frames exchanged with the middleware over its websocket. Our requests (and the responses to
+//! them) are the ones defined by the middleware; of the frames carrying its state, only the parts
+//! this application renders are deserialized, and everything else is ignored.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub use costanza::protocol::{
+  ClientMessage, ClientMessageRequest as Request, ClientResponse as Response, RawSerialRequest, ResumeSessionRequest,
+  ResyncRequest, RunMacroRequest, PROTOCOL_VERSION,
+};
+
+/// The amount of history entries we hold on to; older entries are dropped as new ones arrive.
+const HISTORY_CAPACITY: usize = 500;
+
+/// Every frame sent to us by the middleware.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Frame {
+  Hello(Hello),
+  State(State),
+  Response(Response),
+  Macros(Macros),
+
+  /// Frames we have no use for (yet), e.g chat messages.
+  #[serde(other)]
+  Other,
+}
+
+/// The first frame sent once connected, with a complete snapshot of our state.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Hello {
+  pub protocol_version: u32,
+  pub server_version: String,
+
+  #[serde(default)]
+  pub capabilities: Vec<String>,
+  pub state: State,
+
+  /// Every program stored by the middleware, by name.
+  #[serde(default)]
+  pub macros: BTreeMap<String, String>,
+
+  /// Sent back to the middleware to pick our state back up should the connection be lost.
+  #[serde(default)]
+  pub resume_token: Option<String>,
+}
+
+/// Whether or not we are the client allowed to control the machine.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+  #[default]
+  Observer,
+  Operator,
+}
+
+/// A machine position, along the axes as they are shown to operators.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+  pub x: f32,
+  pub y: f32,
+  pub z: f32,
+}
+
+/// The line a job is stopped at, waiting on the operator (e.g a tool change).
+#[derive(Deserialize, Debug, Clone)]
+pub struct Pause {
+  pub line: String,
+}
+
+/// The programs stored by the middleware, sent whenever they change.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Macros {
+  pub macros: BTreeMap<String, String>,
+}
+
+/// How far the job being sent has gotten.
+#[derive(Deserialize, Debug, Clone)]
+pub struct JobProgress {
+  #[serde(default)]
+  pub name: Option<String>,
+
+  /// The line most recently sent, where the first line is `1`.
+  pub line: usize,
+  pub total: usize,
+}
+
+/// A job that has finished.
+#[derive(Deserialize, Debug, Clone)]
+pub struct JobRecord {
+  #[serde(default)]
+  pub name: Option<String>,
+  pub lines_sent: usize,
+
+  /// How the job ended, e.g `completed`.
+  pub outcome: String,
+}
+
+/// An entry in our history; what was sent to (and received from) the controller.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "history_kind", rename_all = "snake_case")]
+pub enum HistoryEntry {
+  SentCommand {
+    tick: u32,
+    request: serde_json::Value,
+  },
+  ReceivedData {
+    content: String,
+
+    /// The tick of the request of ours this answers, if any.
+    #[serde(default)]
+    reply_to: Option<u32>,
+  },
+
+  /// Entries we do not render, e.g markers.
+  #[serde(other)]
+  Other,
+}
+
+/// Our state, as tracked by the middleware. When `delta` is true, `history` only contains the
+/// entries we have not been sent before.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct State {
+  pub tick: u32,
+  pub serial_available: bool,
+
+  #[serde(default)]
+  pub delta: bool,
+
+  #[serde(default)]
+  pub role: Role,
+
+  #[serde(default)]
+  pub can_operate: bool,
+
+  /// The id of the client holding the control lock, if any.
+  #[serde(default)]
+  pub controller: Option<String>,
+
+  #[serde(default)]
+  pub position: Option<Position>,
+
+  #[serde(default)]
+  pub paused: Option<Pause>,
+
+  #[serde(default)]
+  pub job: Option<JobProgress>,
+
+  #[serde(default)]
+  pub last_job: Option<JobRecord>,
+
+  #[serde(default)]
+  pub history_start: u64,
+
+  #[serde(default)]
+  pub history: Vec<HistoryEntry>,
+}
+
+impl State {
+  /// The sequence number one past our most recent history entry.
+  pub fn history_end(&self) -> u64 {
+    self.history_start + self.history.len() as u64
+  }
+
+  /// Applies the next state sent to us, appending its history to ours when it is a delta.
+  pub fn merge(&mut self, mut next: State) {
+    if next.delta {
+      let mut history = std::mem::take(&mut self.history);
+      history.append(&mut next.history);
+      next.history = history;
+      next.history_start = self.history_start;
+    }
+
+    *self = next;
+    self.trim();
+  }
+
+  /// Drops the oldest history entries beyond our capacity.
+  pub fn trim(&mut self) {
+    let excess = self.history.len().saturating_sub(HISTORY_CAPACITY);
+    self.history.drain(0..excess);
+    self.history_start += excess as u64;
+  }
+}
+
+/// Whether or not a request is safe to send once we reconnect, when it was made while we were not
+/// connected. Anything that moves (or could move) the machine is not; the situation may well have
+/// changed by the time the connection is back.
+pub fn replayable(request: &Request) -> bool {
+  matches!(request, Request::ResyncState(_))
+}
@@ -0,0 +1,132 @@
+//! The screen shown on startup: every middleware instance we know of, whether discovered over mdns
+//! or entered by hand, along with whether each of them can be reached. Nothing is connected to until
+//! one is picked.
+
+use crate::discovery::{self, Reachability};
+use iced::widget::{button, column, row, scrollable, text, text_input};
+use iced::{Element, Length};
+use std::collections::{BTreeMap, HashMap};
+
+/// The messages of the servers screen.
+#[derive(Debug, Clone)]
+pub enum Message {
+  Discovery(discovery::Event),
+  Probed((String, Reachability)),
+  Input(String),
+  Add,
+  Connect(String),
+}
+
+/// What the application is asked to do by the servers screen.
+pub enum Action {
+  /// An address was entered by hand, and should be remembered.
+  Add(String),
+  Connect(String),
+
+  /// An address was found, and should be checked on right away.
+  Probe(String),
+}
+
+#[derive(Default)]
+pub struct Servers {
+  input: String,
+
+  /// The addresses of the instances currently advertised, by name.
+  discovered: BTreeMap<String, String>,
+
+  /// Whether each address could be reached when last checked.
+  reachability: HashMap<String, Reachability>,
+}
+
+impl Servers {
+  /// Handles a message of the servers screen, returning what the application should do about it.
+  pub fn update(&mut self, message: Message) -> Option<Action> {
+    match message {
+      Message::Discovery(discovery::Event::Found(name, addr)) => {
+        self.discovered.insert(name, addr.clone());
+        Some(Action::Probe(addr))
+      }
+      Message::Discovery(discovery::Event::Lost(name)) => {
+        self.discovered.remove(&name);
+        None
+      }
+      Message::Probed((addr, reachability)) => {
+        self.reachability.insert(addr, reachability);
+        None
+      }
+      Message::Input(value) => {
+        self.input = value;
+        None
+      }
+      Message::Add if self.input.trim().is_empty() => None,
+      Message::Add => Some(Action::Add(std::mem::take(&mut self.input).trim().to_string())),
+      Message::Connect(addr) => Some(Action::Connect(addr)),
+    }
+  }
+
+  /// Returns every address worth checking on; the ones we have discovered, and the ones provided.
+  pub fn addresses<'a>(&'a self, known: &'a [String]) -> impl Iterator<Item = &'a String> {
+    self.discovered.values().chain(known.iter())
+  }
+
+  /// Renders a single instance, by name and address.
+  fn entry<'a>(&self, name: String, addr: &'a str, last: bool) -> Element<'a, Message> {
+    let reachability = match self.reachability.get(addr) {
+      Some(Reachability::Reachable(latency)) => format!("reachable ({}ms)", latency.as_millis()),
+      Some(Reachability::Unreachable) => String::from("unreachable"),
+      None => String::from("checking"),
+    };
+
+    let name = match last {
+      true => format!("{name} (last used)"),
+      false => name,
+    };
+
+    let details = column![text(name), text(format!("{addr} - {reachability}")).size(12)].width(Length::Fill);
+
+    row![details, button("connect").on_press(Message::Connect(addr.to_string()))]
+      .spacing(6)
+      .into()
+  }
+
+  /// Renders the instances we know of, the most recently used first, along with the line new
+  /// addresses are entered on.
+  pub fn view<'a>(&'a self, known: &'a [String], last: Option<&'a String>) -> Element<'a, Message> {
+    let mut entries = vec![];
+
+    for (name, addr) in &self.discovered {
+      entries.push((name.clone(), addr, last == Some(addr)));
+    }
+
+    for addr in known
+      .iter()
+      .filter(|addr| !self.discovered.values().any(|found| found == *addr))
+    {
+      entries.push((String::from("entered by hand"), addr, last == Some(addr)));
+    }
+
+    entries.sort_by_key(|(_, _, last)| !last);
+
+    let list = entries
+      .into_iter()
+      .fold(column![].spacing(6), |list, (name, addr, last)| {
+        list.push(self.entry(name, addr, last))
+      });
+
+    let list: Element<'a, Message> = match self.discovered.is_empty() && known.is_empty() {
+      true => text("looking for instances...").into(),
+      false => scrollable(list).height(Length::Fill).into(),
+    };
+
+    let entry = row![
+      text_input("ws://costanza.local:8081/ws", &self.input, Message::Input)
+        .on_submit(Message::Add)
+        .padding(6)
+        .width(Length::Fill),
+      button("add").on_press(Message::Add),
+    ]
+    .spacing(6);
+
+    column![list, entry].spacing(6).into()
+  }
+}
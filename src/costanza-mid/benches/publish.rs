@@ -0,0 +1,22 @@
+//! Measures the cost of publishing client state with a large amount of connected clients that each
+//! have a large amount of history.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const CLIENTS: usize = 100;
+const HISTORY: usize = 1_000;
+
+fn publish(criterion: &mut Criterion) {
+  let mut fixture = costanza::BenchFixture::new(CLIENTS, HISTORY);
+
+  criterion.bench_function("broadcast 100 clients x 1k history", |bencher| {
+    bencher.iter(|| fixture.broadcast())
+  });
+
+  criterion.bench_function("client request 100 clients x 1k history", |bencher| {
+    bencher.iter(|| fixture.client_request())
+  });
+}
+
+criterion_group!(benches, publish);
+criterion_main!(benches);
@@ -0,0 +1,111 @@
+//! Controllers agree on most of the lines of a file, but not on how they are asked for their status,
+//! how they report it, how they are jogged or how they are reset. Those differences are kept behind
+//! the `Firmware` trait, whose implementation is picked by the `firmware` of the serial
+//! configuration.
+
+use super::grbl;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// What is specific to the firmware running on the controller.
+pub trait Firmware: std::fmt::Debug + Sync {
+  /// The bytes asking the controller for a status report. These are not followed by a newline.
+  fn status_query(&self) -> Vec<u8>;
+
+  /// Parses a line sent by the controller.
+  fn parse(&self, line: &str) -> Result<grbl::Response, Error>;
+
+  /// Returns the line moving the machine by the provided distance along each machine axis (in
+  /// millimeters), at the provided feed rate (in millimeters per minute).
+  fn jog(&self, distances: [f32; 3], feed: f32) -> String;
+
+  /// The bytes stopping the controller immediately, discarding whatever it has buffered.
+  fn reset(&self) -> Vec<u8>;
+}
+
+/// grbl 0.9 and 1.1.
+#[derive(Debug)]
+pub struct Grbl;
+
+impl Firmware for Grbl {
+  fn status_query(&self) -> Vec<u8> {
+    grbl::Command::Status.bytes()
+  }
+
+  fn parse(&self, line: &str) -> Result<grbl::Response, Error> {
+    line.parse()
+  }
+
+  fn jog(&self, distances: [f32; 3], feed: f32) -> String {
+    let words = ["X", "Y", "Z"]
+      .iter()
+      .zip(distances)
+      .filter(|(_, distance)| *distance != 0.0)
+      .map(|(axis, distance)| format!(" {axis}{distance:.3}"))
+      .collect::<String>();
+    format!("$J=G91 G21{words} F{feed:.0}")
+  }
+
+  fn reset(&self) -> Vec<u8> {
+    grbl::Command::SoftReset.bytes()
+  }
+}
+
+/// The firmware a controller may be configured as running.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FirmwareFlavor {
+  #[default]
+  Grbl,
+}
+
+impl FirmwareFlavor {
+  /// Returns the implementation of this firmware.
+  pub fn firmware(self) -> &'static dyn Firmware {
+    match self {
+      Self::Grbl => &Grbl,
+    }
+  }
+}
+
+/// The firmware a controller announced itself as running once it reset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FirmwareInfo {
+  pub name: &'static str,
+  pub version: String,
+
+  /// The implementation used for this firmware, unless another is configured.
+  pub flavor: FirmwareFlavor,
+}
+
+/// Recognizes the banner a controller sends once it has reset (e.g `Grbl 1.1h ['$' for help]`).
+/// grblHAL announces itself as `GrblHAL 1.1f ['$' or '$HELP' for help]`, while FluidNC follows a grbl
+/// version with its own, e.g `Grbl 3.7 [FluidNC v3.7.8 (wifi) '$' for help]`.
+pub fn detect(line: &str) -> Option<FirmwareInfo> {
+  let (name, rest) = line.trim().split_once(' ')?;
+  let (version, details) = rest.split_once(' ')?;
+
+  if !details.starts_with('[') || !details.ends_with(']') {
+    return None;
+  }
+
+  if let Some(fluidnc) = details.strip_prefix("[FluidNC v") {
+    return Some(FirmwareInfo {
+      name: "FluidNC",
+      version: fluidnc.split_whitespace().next()?.to_string(),
+      flavor: FirmwareFlavor::Grbl,
+    });
+  }
+
+  let name = match name {
+    "Grbl" => "grbl",
+    "GrblHAL" => "grblHAL",
+    _ => return None,
+  };
+
+  Some(FirmwareInfo {
+    name,
+    version: version.to_string(),
+    flavor: FirmwareFlavor::Grbl,
+  })
+}
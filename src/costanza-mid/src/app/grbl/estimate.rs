@@ -0,0 +1,102 @@
+//! Estimates how long each line of a file takes a machine to run, from the distance of its moves and
+//! the feed rate they are made at. Acceleration is not accounted for, so estimates are optimistic.
+
+use super::modal::{number, words};
+
+/// The rate (in millimeters per minute) rapid moves are assumed to be made at; grbl does not tell us
+/// the maximum rates of the machine.
+pub const RAPID_RATE: f64 = 3000.0;
+
+/// Millimeters in an inch, for files in inches (`G20`).
+const MILLIMETERS_PER_INCH: f64 = 25.4;
+
+/// How the moves of a line are made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+  Rapid,
+  Feed,
+
+  /// Arcs (`G2`/`G3`); these are estimated by the straight distance between their ends.
+  Arc,
+}
+
+/// What we need to know about the lines that came before to estimate the next one.
+#[derive(Debug, Clone, Default)]
+pub struct Estimator {
+  position: [f64; 3],
+  motion: Option<Motion>,
+
+  /// The feed rate, in millimeters per minute.
+  feed: Option<f64>,
+  relative: bool,
+  inches: bool,
+}
+
+impl Estimator {
+  /// Returns how long the line takes to run, updating what we know with its words.
+  pub fn line(&mut self, line: &str) -> std::time::Duration {
+    let mut target = self.position;
+    let mut moved = false;
+    let mut dwell = 0.0;
+    let mut non_modal = false;
+    let words = words(line);
+    let dwelling = words
+      .iter()
+      .any(|(letter, value)| *letter == 'G' && value.parse::<f64>().ok().map(number).as_deref() == Some("4"));
+
+    for (letter, value) in &words {
+      let parsed = value.parse::<f64>().ok();
+      let code = parsed.map(number).unwrap_or_default();
+      let scale = if self.inches { MILLIMETERS_PER_INCH } else { 1.0 };
+
+      match (letter, code.as_str()) {
+        ('G', "0") => self.motion = Some(Motion::Rapid),
+        ('G', "1") => self.motion = Some(Motion::Feed),
+        ('G', "2" | "3") => self.motion = Some(Motion::Arc),
+        ('G', "20") => self.inches = true,
+        ('G', "21") => self.inches = false,
+        ('G', "90") => self.relative = false,
+        ('G', "91") => self.relative = true,
+        // Moves in machine coordinates (or to predefined positions) are not somewhere we can follow.
+        ('G', "53" | "28" | "30" | "92" | "10") => non_modal = true,
+        ('P', _) if dwelling => dwell = parsed.unwrap_or_default(),
+        ('F', _) => self.feed = parsed.map(|feed| feed * scale).or(self.feed),
+        ('X' | 'Y' | 'Z', _) => {
+          let axis = match letter {
+            'X' => 0,
+            'Y' => 1,
+            _ => 2,
+          };
+
+          if let Some(value) = parsed.map(|value| value * scale) {
+            target[axis] = if self.relative { target[axis] + value } else { value };
+            moved = true;
+          }
+        }
+        _ => (),
+      }
+    }
+
+    let distance = match (moved, non_modal) {
+      (true, false) => {
+        let distance = self
+          .position
+          .iter()
+          .zip(target)
+          .map(|(from, to)| (to - from).powi(2))
+          .sum::<f64>()
+          .sqrt();
+        self.position = target;
+        distance
+      }
+      _ => 0.0,
+    };
+
+    let rate = match self.motion {
+      Some(Motion::Rapid) | None => RAPID_RATE,
+      Some(Motion::Feed | Motion::Arc) => self.feed.filter(|feed| *feed > 0.0).unwrap_or(RAPID_RATE),
+    };
+
+    std::time::Duration::from_secs_f64(distance / rate * 60.0 + dwell)
+  }
+}
@@ -1,14 +1,58 @@
-use std::io;
+use crate::error::Error;
+use serde::Serialize;
+
+/// Reconstructing the modal state of the controller at any line of a file.
+pub mod modal;
+
+/// Estimating how long the lines of a file take to run.
+pub mod estimate;
+
+/// Scaling, rotating and offsetting the lines of a file.
+pub mod transform;
+
+/// Reading and changing the `$` settings of the controller.
+pub mod settings;
 
 #[derive(Debug)]
 pub enum Command {
   Status,
+
+  /// A realtime command that pauses motion; this is acted on immediately by the controller.
+  FeedHold,
+
+  /// A realtime command that resumes motion after a `FeedHold`.
+  CycleStart,
+
+  /// A grbl 1.1 realtime override (e.g `0x91` to increase the feed rate by 10%). These bytes are
+  /// outside of ascii, and can only be written through `bytes`.
+  Override(u8),
+
+  /// A realtime command (`ctrl-x`) that stops the controller immediately, discarding whatever it
+  /// has buffered.
+  SoftReset,
+}
+
+impl Command {
+  /// Returns the bytes written to the controller for this command.
+  pub fn bytes(&self) -> Vec<u8> {
+    match self {
+      Self::Override(byte) => vec![*byte],
+      Self::SoftReset => vec![0x18],
+      other => other.to_string().into_bytes(),
+    }
+  }
 }
 
 impl std::fmt::Display for Command {
   fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
     match self {
-      Self::Status => writeln!(formatter, "?"),
+      // Realtime commands are not followed by a newline; the controller would otherwise respond
+      // to the empty line with an "ok" that we would mistake for a line acknowledgement.
+      Self::Status => write!(formatter, "?"),
+      Self::FeedHold => write!(formatter, "!"),
+      Self::CycleStart => write!(formatter, "~"),
+      Self::Override(byte) => write!(formatter, "{byte:#04X}"),
+      Self::SoftReset => write!(formatter, "0x18"),
     }
   }
 }
@@ -19,47 +63,186 @@ pub enum MachineState {
   Idle,
   Home,
   Alarm,
+  Sleep,
+  Hold,
+  Jog,
+  Door,
+  Check,
 }
 
 impl std::str::FromStr for MachineState {
-  type Err = io::Error;
+  type Err = Error;
 
   fn from_str(input: &str) -> Result<Self, Self::Err> {
-    match input {
+    // grbl 1.1 follows some states with a substate (e.g `Hold:0`), which we have no use for.
+    match input.split(':').next().unwrap_or_default() {
       "Idle" => Ok(Self::Idle),
       "Run" => Ok(Self::Run),
       "Home" => Ok(Self::Home),
       "Alarm" => Ok(Self::Alarm),
-      unknown => Err(io::Error::new(
-        io::ErrorKind::Other,
-        format!("bad machine state - {unknown}"),
-      )),
+      "Sleep" => Ok(Self::Sleep),
+      "Hold" => Ok(Self::Hold),
+      "Jog" => Ok(Self::Jog),
+      "Door" => Ok(Self::Door),
+      "Check" => Ok(Self::Check),
+      unknown => Err(Error::Parse(format!("bad machine state - {unknown}"))),
     }
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct MachinePosition {
-  #[allow(dead_code)]
   x: f32,
-  #[allow(dead_code)]
   y: f32,
-  #[allow(dead_code)]
   z: f32,
 }
 
+impl MachinePosition {
+  /// Returns the position of each axis, in `X`, `Y`, `Z` order.
+  pub fn axes(&self) -> [f32; 3] {
+    [self.x, self.y, self.z]
+  }
+}
+
+/// The override percentages reported by grbl 1.1 controllers (the `Ov:` field of their status).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Overrides {
+  pub feed: u16,
+  pub rapid: u16,
+  pub spindle: u16,
+}
+
+impl std::str::FromStr for Overrides {
+  type Err = Error;
+
+  fn from_str(input: &str) -> Result<Self, Self::Err> {
+    let percentages = input
+      .split(',')
+      .map(|raw| raw.parse::<u16>())
+      .collect::<Result<Vec<u16>, _>>()
+      .map_err(|error| Error::Parse(format!("bad overrides - {error}")))?;
+
+    match percentages[..] {
+      [feed, rapid, spindle] => Ok(Self { feed, rapid, spindle }),
+      _ => Err(Error::Parse(format!("bad overrides - '{input}'"))),
+    }
+  }
+}
+
+/// Parses the `x,y,z` of a reported machine position.
+fn parse_position(input: &str) -> Result<MachinePosition, Error> {
+  let axes = input
+    .split(',')
+    .map(|raw| raw.parse::<f32>())
+    .collect::<Result<Vec<f32>, _>>()
+    .map_err(|error| Error::Parse(format!("bad machine pos - {error}")))?;
+
+  match axes[..] {
+    [x, y, z, ..] => Ok(MachinePosition { x, y, z }),
+    _ => Err(Error::Parse(format!("bad machine pos - '{input}'"))),
+  }
+}
+
+/// A status report of the controller, sent in response to `?`.
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+  pub state: MachineState,
+  pub position: MachinePosition,
+
+  /// Whether `position` is in work coordinates (`WPos:`) rather than machine coordinates (`MPos:`).
+  /// grbl 1.1 reports one or the other, depending on its status report options (`$10`).
+  pub work_position: bool,
+
+  /// The override percentages, when reported (only grbl 1.1 does, and not in every status).
+  pub overrides: Option<Overrides>,
+
+  /// How far the work coordinates are from machine coordinates, when reported. grbl 0.9 reports the
+  /// work position in every status, while grbl 1.1 only reports the offset (`WCO:`) now and then.
+  pub work_offset: Option<MachinePosition>,
+}
+
 #[derive(Debug)]
 pub enum Response {
   Ok,
-  Status(MachineState, MachinePosition),
+  Status(Status),
+
+  /// The controller refused a line, with whatever it told us about why (a code on grbl 1.1, e.g
+  /// `20`, or a message on grbl 0.9, e.g `Bad number format`).
+  Error(String),
+
+  /// The controller has entered an alarm state (e.g a hard limit was hit), with the alarm's code.
+  Alarm(u8),
+
+  /// The result of a probing cycle (e.g `G38.2`): the machine position where it stopped, and
+  /// whether or not the probe touched anything before getting there.
+  Probe(MachinePosition, bool),
+
+  /// A single setting, as listed in response to `$$` (e.g `$110=5000.000`).
+  Setting(u16, f32),
 }
 
 impl std::str::FromStr for Response {
-  type Err = io::Error;
+  type Err = Error;
 
   fn from_str(input: &str) -> Result<Self, Self::Err> {
     match input.trim() {
       "ok" | "Ok" | "OK" => Ok(Self::Ok),
+      error if error.starts_with("error:") => Ok(Self::Error(error.trim_start_matches("error:").trim().to_string())),
+      alarm if alarm.starts_with("ALARM:") => alarm
+        .trim_start_matches("ALARM:")
+        .parse::<u8>()
+        .map(Self::Alarm)
+        .map_err(|error| Error::Parse(format!("bad alarm code - {error}"))),
+      // e.g `[PRB:0.000,0.000,-5.120:1]`
+      probe if probe.starts_with("[PRB:") => {
+        let inner = probe.trim_start_matches("[PRB:").trim_end_matches(']');
+
+        match inner.rsplit_once(':') {
+          Some((position, "1")) => Ok(Self::Probe(parse_position(position)?, true)),
+          Some((position, "0")) => Ok(Self::Probe(parse_position(position)?, false)),
+          _ => Err(Error::Parse(format!("bad probe result - '{probe}'"))),
+        }
+      }
+      setting if setting.starts_with('$') && setting.contains('=') => {
+        settings::parse(setting).map(|(id, value)| Self::Setting(id, value))
+      }
+      // grbl 1.1 separates fields with pipes, e.g `<Run|MPos:1.000,2.000,0.000|FS:500,0|Ov:100,100,100>`.
+      status if status.starts_with('<') && status.contains('|') => {
+        let mut fields = status.trim_start_matches('<').trim_end_matches('>').split('|');
+        let state = fields.next().unwrap_or_default().parse::<MachineState>()?;
+        let mut position = None;
+        let mut work_position = false;
+        let mut overrides = None;
+        let mut work_offset = None;
+
+        for field in fields {
+          if let Some(raw) = field.strip_prefix("MPos:") {
+            position = Some(parse_position(raw)?);
+          }
+
+          if let Some(raw) = field.strip_prefix("WPos:") {
+            position = Some(parse_position(raw)?);
+            work_position = true;
+          }
+
+          if let Some(raw) = field.strip_prefix("Ov:") {
+            overrides = Some(raw.parse::<Overrides>()?);
+          }
+
+          if let Some(raw) = field.strip_prefix("WCO:") {
+            work_offset = Some(parse_position(raw)?);
+          }
+        }
+
+        let position = position.ok_or_else(|| Error::Parse(format!("missing machine pos - '{status}'")))?;
+        Ok(Self::Status(Status {
+          state,
+          position,
+          work_position,
+          overrides,
+          work_offset,
+        }))
+      }
       status if status.starts_with('<') => {
         let chars = status.chars().skip(1);
         let state = chars
@@ -70,30 +253,43 @@ impl std::str::FromStr for Response {
         tracing::info!("parsed machine state - {state:?} (from {status})");
 
         match &status.split(',').skip(1).collect::<Vec<&str>>()[..] {
-          [header, raw_y, raw_z, _, _, _] if header.starts_with("MPos:") => {
+          [header, raw_y, raw_z, work_x, work_y, work_z] if header.starts_with("MPos:") => {
             let x = header
               .trim_start_matches("MPos:")
               .parse::<f32>()
-              .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("bad machine pos - {error}")))?;
+              .map_err(|error| Error::Parse(format!("bad machine pos - {error}")))?;
             let y = raw_y
               .parse::<f32>()
-              .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("bad machine pos - {error}")))?;
+              .map_err(|error| Error::Parse(format!("bad machine pos - {error}")))?;
             let z = raw_z
               .parse::<f32>()
-              .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("bad machine pos - {error}")))?;
+              .map_err(|error| Error::Parse(format!("bad machine pos - {error}")))?;
             tracing::info!("found machine pos ({x}, {y}, {z})");
-            Ok(Self::Status(state, MachinePosition { x, y, z }))
+
+            // The offset is only known when the work position parses; it is not worth failing over.
+            let work = format!(
+              "{},{work_y},{}",
+              work_x.trim_start_matches("WPos:"),
+              work_z.trim_end_matches('>')
+            );
+            let work_offset = parse_position(&work).ok().map(|work| MachinePosition {
+              x: x - work.x,
+              y: y - work.y,
+              z: z - work.z,
+            });
+
+            Ok(Self::Status(Status {
+              state,
+              position: MachinePosition { x, y, z },
+              work_position: false,
+              overrides: None,
+              work_offset,
+            }))
           }
-          unknown => Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("bad status bits - '{unknown:?}'"),
-          )),
+          unknown => Err(Error::Parse(format!("bad status bits - '{unknown:?}'"))),
         }
       }
-      other => Err(io::Error::new(
-        io::ErrorKind::Other,
-        format!("unknown grbl response - '{other}'"),
-      )),
+      other => Err(Error::Parse(format!("unknown grbl response - '{other}'"))),
     }
   }
 }
@@ -0,0 +1,330 @@
+//! Reconstructs the modal state of the controller (units, distance mode, work coordinates, spindle,
+//! etc...) at an arbitrary line of a gcode file by replaying every line that came before it without
+//! any of the motion. This is what allows a job to be restarted part way through, e.g after a
+//! broken bit has been replaced.
+
+/// A position in the work coordinate system.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Position {
+  x: f64,
+  y: f64,
+  z: f64,
+}
+
+/// Everything we know about the state of the controller at some line of a file.
+#[derive(Debug, Clone, Default)]
+pub struct ModalState {
+  /// The motion mode (e.g `G1`).
+  motion: Option<String>,
+
+  /// The plane arcs are made in (e.g `G17`).
+  plane: Option<String>,
+
+  /// The units of measure (e.g `G21`).
+  units: Option<String>,
+
+  /// The distance mode (e.g `G90`).
+  distance: Option<String>,
+
+  /// The feed rate mode (e.g `G94`).
+  feed_mode: Option<String>,
+
+  /// The work coordinate system (e.g `G54`).
+  coordinates: Option<String>,
+
+  /// The spindle direction (e.g `M3`).
+  spindle: Option<String>,
+
+  /// Whether or not mist (`M7`) and flood (`M8`) coolant are on.
+  coolant: (bool, bool),
+
+  feed: Option<f64>,
+  speed: Option<f64>,
+  tool: Option<u32>,
+
+  /// The last known position of the tool, when every move so far has been absolute.
+  position: Position,
+
+  /// The highest `Z` the file has moved to; this is assumed to be clear of the work.
+  clearance: Option<f64>,
+
+  /// Whether or not we saw anything that makes `position` unreliable (relative moves, offsets).
+  position_unknown: bool,
+}
+
+/// The words of a single line of gcode, with comments removed.
+pub(super) fn words(line: &str) -> Vec<(char, String)> {
+  let mut stripped = String::with_capacity(line.len());
+  let mut in_comment = false;
+
+  for character in line.chars() {
+    match character {
+      ';' if !in_comment => break,
+      '(' => in_comment = true,
+      ')' => in_comment = false,
+      other if !in_comment && !other.is_whitespace() => stripped.push(other.to_ascii_uppercase()),
+      _ => (),
+    }
+  }
+
+  let mut words: Vec<(char, String)> = vec![];
+
+  for character in stripped.chars() {
+    match (character.is_ascii_alphabetic(), words.last_mut()) {
+      (true, _) => words.push((character, String::new())),
+      (false, Some((_, value))) => value.push(character),
+      (false, None) => (),
+    }
+  }
+
+  words
+}
+
+/// Formats a gcode number without any trailing zeros. Values that only round to zero (e.g after being
+/// rotated) are written without a sign.
+pub(super) fn number(value: f64) -> String {
+  let formatted = format!("{value:.4}");
+
+  match formatted.trim_end_matches('0').trim_end_matches('.') {
+    "-0" => "0".to_string(),
+    trimmed => trimmed.to_string(),
+  }
+}
+
+impl ModalState {
+  /// Replays the provided lines, returning the state of the controller once they have all been
+  /// sent.
+  pub fn replay<'a, I>(lines: I) -> Self
+  where
+    I: IntoIterator<Item = &'a String>,
+  {
+    let mut state = Self::default();
+
+    for line in lines {
+      state.apply(line);
+    }
+
+    state
+  }
+
+  /// Updates our state with the words of a single line.
+  fn apply(&mut self, line: &str) {
+    let words = words(line);
+    let mut target = self.position;
+    let mut moved = false;
+    let mut non_modal = false;
+
+    for (letter, value) in &words {
+      let parsed = value.parse::<f64>().ok();
+      // Codes are compared without any leading zeros, e.g `G01` is `G1`.
+      let code = parsed.map(number).unwrap_or_default();
+
+      match (letter, code.as_str()) {
+        ('G', "0" | "1" | "2" | "3" | "80") => self.motion = Some(format!("G{code}")),
+        ('G', "17" | "18" | "19") => self.plane = Some(format!("G{code}")),
+        ('G', "20" | "21") => self.units = Some(format!("G{code}")),
+        ('G', "90") => self.distance = Some("G90".into()),
+        ('G', "91") => {
+          self.distance = Some("G91".into());
+          self.position_unknown = true;
+        }
+        ('G', "93" | "94") => self.feed_mode = Some(format!("G{code}")),
+        ('G', "54" | "55" | "56" | "57" | "58" | "59") => self.coordinates = Some(format!("G{code}")),
+        // Moves in machine coordinates (or to predefined positions) are not in our work coordinates,
+        // and coordinate offsets make everything before them meaningless.
+        ('G', "53" | "28" | "30") => non_modal = true,
+        ('G', "92" | "10") => {
+          non_modal = true;
+          self.position_unknown = true;
+        }
+        ('M', "3" | "4" | "5") => self.spindle = Some(format!("M{code}")),
+        ('M', "7") => self.coolant.0 = true,
+        ('M', "8") => self.coolant.1 = true,
+        ('M', "9") => self.coolant = (false, false),
+        ('F', _) => self.feed = parsed.or(self.feed),
+        ('S', _) => self.speed = parsed.or(self.speed),
+        ('T', _) => self.tool = value.parse().ok().or(self.tool),
+        ('X', _) => {
+          target.x = parsed.unwrap_or(target.x);
+          moved = true;
+        }
+        ('Y', _) => {
+          target.y = parsed.unwrap_or(target.y);
+          moved = true;
+        }
+        ('Z', _) => {
+          target.z = parsed.unwrap_or(target.z);
+          moved = true;
+        }
+        _ => (),
+      }
+    }
+
+    if moved && !non_modal && self.distance.as_deref() != Some("G91") {
+      self.position = target;
+      self.clearance = Some(self.clearance.map_or(target.z, |clearance| clearance.max(target.z)));
+    }
+  }
+
+  /// Returns the lines that will put the controller back into this state, including the moves that
+  /// bring the tool back to where it was: up to the clearance height, over, and back down at the
+  /// last feed rate. Positioning is left out (with a warning) when it cannot be trusted.
+  pub fn preamble(&self) -> (Vec<String>, Vec<String>) {
+    let mut lines = vec![];
+    let mut warnings = vec![];
+
+    let modal = [
+      &self.units,
+      &self.distance,
+      &self.plane,
+      &self.feed_mode,
+      &self.coordinates,
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .collect::<Vec<String>>();
+
+    if !modal.is_empty() {
+      lines.push(modal.join(" "));
+    }
+
+    if let Some(tool) = self.tool {
+      lines.push(format!("T{tool}"));
+    }
+
+    match (self.spindle.as_deref(), self.speed) {
+      (Some(spindle), Some(speed)) => lines.push(format!("S{} {spindle}", number(speed))),
+      (Some(spindle), None) => lines.push(spindle.to_string()),
+      (None, _) => (),
+    }
+
+    match self.coolant {
+      (true, true) => lines.push("M7 M8".into()),
+      (true, false) => lines.push("M7".into()),
+      (false, true) => lines.push("M8".into()),
+      (false, false) => (),
+    }
+
+    match (self.position_unknown, self.clearance) {
+      (false, Some(clearance)) => {
+        let Position { x, y, z } = self.position;
+        lines.push(format!("G0 Z{}", number(clearance)));
+        lines.push(format!("G0 X{} Y{}", number(x), number(y)));
+
+        match self.feed {
+          Some(feed) => lines.push(format!("G1 Z{} F{}", number(z), number(feed))),
+          None => {
+            warnings.push("no feed rate was set before this line; the plunge is made at rapid".into());
+            lines.push(format!("G0 Z{}", number(z)));
+          }
+        }
+      }
+      (true, _) => warnings.push("relative moves or offsets were used; the tool is not positioned".into()),
+      (false, None) => (),
+    }
+
+    // The motion mode goes last so that the first line of the file continues the way it expects.
+    if let Some(motion) = self.motion.as_ref().filter(|motion| motion.as_str() != "G80") {
+      lines.push(motion.clone());
+    }
+
+    (lines, warnings)
+  }
+}
+
+/// Why a line of a file stops the job until the operator resumes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Pause {
+  /// `M6`; the tool needs to be changed, to the one of the `T` word on the same line when provided.
+  ToolChange { tool: Option<u32> },
+
+  /// `M0` (or the optional stop, `M1`); the file asks the operator to step in.
+  ProgramPause,
+}
+
+/// When the line stops the job for the operator (see `Pause`), returns why along with what is left
+/// of the line without the words that stop it (e.g the `T2` of `T2 M6`). Firmware either does not
+/// know what to do with these words (grbl rejects `M6`), or pauses on its own without telling us.
+pub fn pause(line: &str) -> Option<(Pause, String)> {
+  let words = words(line);
+  let mut pause = None;
+  let mut tool = None;
+  let mut remainder = vec![];
+
+  for (letter, value) in words {
+    let code = value.parse::<f64>().ok().map(number).unwrap_or_default();
+
+    match (letter, code.as_str()) {
+      ('M', "6") => pause = Some(Pause::ToolChange { tool: None }),
+      ('M', "0" | "1") => pause = pause.or(Some(Pause::ProgramPause)),
+      ('T', _) => {
+        tool = value.parse().ok();
+        remainder.push(format!("{letter}{value}"));
+      }
+      _ => remainder.push(format!("{letter}{value}")),
+    }
+  }
+
+  let pause = match pause? {
+    Pause::ToolChange { .. } => Pause::ToolChange { tool },
+    other => other,
+  };
+
+  Some((pause, remainder.join(" ")))
+}
+
+/// A straight move (`G0`, `G1` or a `$J=` jog) made by a single line. Moves are assumed to be in
+/// millimeters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+  /// Whether the axes are in machine coordinates (`G53`) rather than work coordinates.
+  pub machine: bool,
+
+  /// Whether the axes are relative to where the tool is (`G91`), when the line sets it itself.
+  pub relative: Option<bool>,
+
+  /// Where each axis (in `X`, `Y`, `Z` order) is moved to, or by, for the axes the line moves.
+  pub axes: [Option<f32>; 3],
+}
+
+/// Returns the distance mode set by the line: `true` for relative (`G91`), `false` for absolute.
+pub fn distance_mode(line: &str) -> Option<bool> {
+  words(line)
+    .iter()
+    .filter(|(letter, _)| *letter == 'G')
+    .filter_map(|(_, value)| value.parse::<f64>().ok().map(number))
+    .fold(None, |mode, code| match code.as_str() {
+      "90" => Some(false),
+      "91" => Some(true),
+      _ => mode,
+    })
+}
+
+/// Returns the straight move made by the line, if it explicitly makes one.
+pub fn straight_move(line: &str) -> Option<Move> {
+  let jog = line.trim_start().strip_prefix("$J=");
+  let mut motion = jog.is_some();
+  let mut machine = false;
+  let mut axes = [None; 3];
+
+  for (letter, value) in words(jog.unwrap_or(line)) {
+    let code = value.parse::<f64>().ok().map(number).unwrap_or_default();
+
+    match (letter, code.as_str()) {
+      ('G', "0" | "1") => motion = true,
+      ('G', "53") => machine = true,
+      ('X', _) => axes[0] = value.parse().ok(),
+      ('Y', _) => axes[1] = value.parse().ok(),
+      ('Z', _) => axes[2] = value.parse().ok(),
+      _ => (),
+    }
+  }
+
+  motion.then_some(Move {
+    machine,
+    relative: distance_mode(jog.unwrap_or(line)),
+    axes,
+  })
+}
@@ -0,0 +1,95 @@
+use crate::error::Error;
+use serde::Serialize;
+
+/// The settings of the controller, by number, as most recently listed (or written).
+pub type Settings = std::collections::BTreeMap<u16, f32>;
+
+/// The name and unit of every setting documented by grbl 1.1.
+const KNOWN: [(u16, &str, &str); 34] = [
+  (0, "step pulse time", "microseconds"),
+  (1, "step idle delay", "milliseconds"),
+  (2, "step pulse invert", "mask"),
+  (3, "step direction invert", "mask"),
+  (4, "invert step enable pin", "boolean"),
+  (5, "invert limit pins", "boolean"),
+  (6, "invert probe pin", "boolean"),
+  (10, "status report options", "mask"),
+  (11, "junction deviation", "mm"),
+  (12, "arc tolerance", "mm"),
+  (13, "report in inches", "boolean"),
+  (20, "soft limits enable", "boolean"),
+  (21, "hard limits enable", "boolean"),
+  (22, "homing cycle enable", "boolean"),
+  (23, "homing direction invert", "mask"),
+  (24, "homing locate feed rate", "mm/min"),
+  (25, "homing search seek rate", "mm/min"),
+  (26, "homing switch debounce delay", "milliseconds"),
+  (27, "homing switch pull-off distance", "mm"),
+  (30, "maximum spindle speed", "rpm"),
+  (31, "minimum spindle speed", "rpm"),
+  (32, "laser mode enable", "boolean"),
+  (100, "x travel resolution", "step/mm"),
+  (101, "y travel resolution", "step/mm"),
+  (102, "z travel resolution", "step/mm"),
+  (110, "x maximum rate", "mm/min"),
+  (111, "y maximum rate", "mm/min"),
+  (112, "z maximum rate", "mm/min"),
+  (120, "x acceleration", "mm/sec^2"),
+  (121, "y acceleration", "mm/sec^2"),
+  (122, "z acceleration", "mm/sec^2"),
+  (130, "x maximum travel", "mm"),
+  (131, "y maximum travel", "mm"),
+  (132, "z maximum travel", "mm"),
+];
+
+/// A setting as presented to clients, named when it is one grbl documents.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Described {
+  pub id: u16,
+  pub value: f32,
+  pub name: Option<&'static str>,
+  pub unit: Option<&'static str>,
+}
+
+/// Parses a `$x=value` line, as listed for every setting in response to `$$` (and written to change
+/// one). grbl 0.9 follows the value with a description, e.g `$0=10 (step pulse, usec)`.
+pub fn parse(line: &str) -> Result<(u16, f32), Error> {
+  let (id, value) = line
+    .trim()
+    .strip_prefix('$')
+    .and_then(|setting| setting.split_once('='))
+    .ok_or_else(|| Error::Parse(format!("bad setting - '{line}'")))?;
+
+  let id = id
+    .parse::<u16>()
+    .map_err(|error| Error::Parse(format!("bad setting number - {error}")))?;
+  let value = value
+    .split_whitespace()
+    .next()
+    .unwrap_or_default()
+    .parse::<f32>()
+    .map_err(|error| Error::Parse(format!("bad setting value - {error}")))?;
+
+  Ok((id, value))
+}
+
+/// Returns the line changing a setting.
+pub fn line(id: u16, value: f32) -> String {
+  format!("${id}={value}")
+}
+
+/// Returns every setting, in order, along with its name and unit when known.
+pub fn describe(settings: &Settings) -> Vec<Described> {
+  settings
+    .iter()
+    .map(|(id, value)| {
+      let known = KNOWN.iter().find(|(known, _, _)| known == id);
+      Described {
+        id: *id,
+        value: *value,
+        name: known.map(|(_, name, _)| *name),
+        unit: known.map(|(_, _, unit)| *unit),
+      }
+    })
+    .collect()
+}
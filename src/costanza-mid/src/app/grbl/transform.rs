@@ -0,0 +1,227 @@
+//! Transforms the lines of a file before they are sent, e.g to cut the same program a little further
+//! along the stock, turned to fit it, or at a different size. Points are scaled, then rotated about
+//! the origin, then offset; only the `X`/`Y` plane is scaled and rotated, while `Z` is only offset.
+//!
+//! Lines without coordinates are sent as they are. Lines that are transformed are rebuilt from their
+//! words, which drops their comments.
+
+use super::modal::{number, words};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Millimeters in an inch, for files in inches (`G20`); offsets are always in millimeters.
+const MILLIMETERS_PER_INCH: f64 = 25.4;
+
+/// How the points of a file are moved.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+  /// How much larger (or smaller) the `X`/`Y` plane is made.
+  #[serde(default = "default_scale")]
+  pub scale: f64,
+
+  /// How far (in degrees, counterclockwise) the `X`/`Y` plane is turned about the origin.
+  #[serde(default)]
+  pub rotation: f64,
+
+  /// How far (in millimeters) each point is moved along `X`, `Y` and `Z`, after scaling and rotating.
+  #[serde(default)]
+  pub offset: [f64; 3],
+}
+
+/// Used by serde when no scale is provided.
+fn default_scale() -> f64 {
+  1.0
+}
+
+/// What we need to know about the lines that came before to transform the next one.
+#[derive(Debug, Default)]
+struct Tracked {
+  relative: bool,
+  inches: bool,
+
+  /// Whether arcs are in a plane other than `X`/`Y` (`G18`/`G19`).
+  other_plane: bool,
+
+  /// Where the file has moved to along `X` and `Y`, before being transformed.
+  position: [Option<f64>; 2],
+}
+
+impl Transform {
+  /// Whether or not points are turned; turned points need both of `X` and `Y` on every move.
+  fn rotated(&self) -> bool {
+    self.rotation.rem_euclid(360.0) != 0.0
+  }
+
+  /// Scales and rotates a point (or distance) in the `X`/`Y` plane.
+  fn turn(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+    let (sin, cos) = self.rotation.to_radians().sin_cos();
+    let (x, y) = (x * self.scale, y * self.scale);
+    [x * cos - y * sin, x * sin + y * cos]
+  }
+
+  /// Returns the lines of the file, transformed.
+  pub fn apply(&self, lines: &[String]) -> Result<Vec<String>> {
+    if !(self.scale.is_finite() && self.scale > 0.0) {
+      return Err(Error::Parse(format!(
+        "scale must be greater than zero, not {}",
+        self.scale
+      )));
+    }
+
+    if !(self.rotation.is_finite() && self.offset.iter().all(|offset| offset.is_finite())) {
+      return Err(Error::Parse("rotation and offsets must be finite".into()));
+    }
+
+    let mut tracked = Tracked::default();
+
+    lines
+      .iter()
+      .enumerate()
+      .map(|(index, line)| {
+        self
+          .line(line, &mut tracked)
+          .map_err(|error| Error::Parse(format!("line {} ('{line}') - {error}", index + 1)))
+      })
+      .collect()
+  }
+
+  /// Returns the line transformed, updating what we know with its words.
+  fn line(&self, line: &str, tracked: &mut Tracked) -> Result<String> {
+    let words = words(line);
+    let mut machine = false;
+
+    for (letter, value) in &words {
+      let code = value.parse::<f64>().ok().map(number).unwrap_or_default();
+
+      match (letter, code.as_str()) {
+        ('G', "90") => tracked.relative = false,
+        ('G', "91") => tracked.relative = true,
+        ('G', "20") => tracked.inches = true,
+        ('G', "21") => tracked.inches = false,
+        ('G', "17") => tracked.other_plane = false,
+        ('G', "18" | "19") => tracked.other_plane = true,
+        // Moves in machine coordinates (or to predefined positions) are not part of the program.
+        ('G', "53" | "28" | "30") => machine = true,
+        ('G', "92" | "10") => return Err(Error::Parse("coordinate offsets cannot be transformed".into())),
+        _ => (),
+      }
+    }
+
+    let value = |wanted: char| {
+      words
+        .iter()
+        .find(|(letter, _)| *letter == wanted)
+        .map(|(_, value)| value.parse::<f64>())
+        .transpose()
+        .map_err(|error| Error::Parse(format!("bad {wanted} - {error}")))
+    };
+
+    let (x, y, z, i, j, r) = (
+      value('X')?,
+      value('Y')?,
+      value('Z')?,
+      value('I')?,
+      value('J')?,
+      value('R')?,
+    );
+
+    if machine || [x, y, z, i, j, r].iter().all(Option::is_none) {
+      return Ok(line.to_string());
+    }
+
+    // Arcs in the other planes involve `Z`, which is not scaled or turned along with the rest.
+    let arc = i.is_some() || j.is_some() || r.is_some() || value('K')?.is_some();
+    if tracked.other_plane && arc && (self.rotated() || self.scale != 1.0) {
+      return Err(Error::Parse(
+        "arcs outside of the X/Y plane cannot be scaled or rotated".into(),
+      ));
+    }
+
+    let units = if tracked.inches { MILLIMETERS_PER_INCH } else { 1.0 };
+    let mut coordinates: Vec<(char, f64)> = vec![];
+
+    if x.is_some() || y.is_some() {
+      let point = match tracked.relative {
+        true => [x.unwrap_or_default(), y.unwrap_or_default()],
+        false => {
+          let known = [x.or(tracked.position[0]), y.or(tracked.position[1])];
+          tracked.position = known;
+
+          match (known, self.rotated()) {
+            ([Some(x), Some(y)], _) => [x, y],
+            (_, true) => {
+              return Err(Error::Parse(
+                "the other axis is not known yet, it cannot be rotated".into(),
+              ))
+            }
+            ([x, y], false) => [x.unwrap_or_default(), y.unwrap_or_default()],
+          }
+        }
+      };
+
+      if tracked.relative {
+        tracked.position = [
+          tracked.position[0]
+            .zip(x)
+            .map(|(from, by)| from + by)
+            .or(tracked.position[0]),
+          tracked.position[1]
+            .zip(y)
+            .map(|(from, by)| from + by)
+            .or(tracked.position[1]),
+        ];
+      }
+
+      let [mut moved_x, mut moved_y] = self.turn(point);
+
+      if !tracked.relative {
+        moved_x += self.offset[0] / units;
+        moved_y += self.offset[1] / units;
+      }
+
+      // Unturned moves keep only the axes they had; turned moves need both.
+      if x.is_some() || self.rotated() {
+        coordinates.push(('X', moved_x));
+      }
+
+      if y.is_some() || self.rotated() {
+        coordinates.push(('Y', moved_y));
+      }
+    }
+
+    if let Some(z) = z {
+      let offset = if tracked.relative { 0.0 } else { self.offset[2] / units };
+      coordinates.push(('Z', z + offset));
+    }
+
+    // Arc centers are relative to where the arc starts, so they are only scaled and turned.
+    if i.is_some() || j.is_some() {
+      let [moved_i, moved_j] = self.turn([i.unwrap_or_default(), j.unwrap_or_default()]);
+
+      if i.is_some() || self.rotated() {
+        coordinates.push(('I', moved_i));
+      }
+
+      if j.is_some() || self.rotated() {
+        coordinates.push(('J', moved_j));
+      }
+    }
+
+    if let Some(r) = r {
+      coordinates.push(('R', r * self.scale));
+    }
+
+    let rebuilt = words
+      .iter()
+      .filter(|(letter, _)| !matches!(letter, 'X' | 'Y' | 'Z' | 'I' | 'J' | 'R'))
+      .map(|(letter, value)| format!("{letter}{value}"))
+      .chain(
+        coordinates
+          .into_iter()
+          .map(|(letter, value)| format!("{letter}{}", number(value))),
+      )
+      .collect::<Vec<String>>();
+
+    Ok(rebuilt.join(" "))
+  }
+}
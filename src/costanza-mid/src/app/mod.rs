@@ -1,22 +1,145 @@
 #![forbid(unsafe_code)]
 
-mod grbl;
+pub(crate) mod grbl;
 
-use crate::effects;
+/// What differs between the firmware our controllers may be running.
+pub(crate) mod firmware;
+
+/// Exercising the whole application against the simulator.
+mod selftest;
+
+pub use selftest::{self_test, SelfTestReport};
+
+use crate::protocol::{
+  ClientMessage, ClientMessageRequest, ClientPreferences, ClientResponse, DisplayAxis, JogRequest, OverrideDirection,
+  OverrideKind, OverrideRequest, ProbeRequest, RawSerialRequest, ResumeSessionRequest, UploadChunkRequest,
+  WriteSettingRequest, PROTOCOL_VERSION,
+};
+use crate::{catalog, effects};
+use firmware::{Firmware, FirmwareFlavor, FirmwareInfo};
 use futures_lite::future::FutureExt;
 use serde::{Deserialize, Serialize};
-use std::io;
 
 /// The timing configuration is used to hold all of the application-specific timing requirements
 /// that we may have, including the websocket broadcast interval and our tick time itself.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 struct TimingConfiguration {
   broadcast_interval: u64,
+
+  /// The amount of milliseconds between the ticks that send queued commands to the controller.
+  #[serde(default = "default_tick_interval")]
+  tick_interval_ms: u64,
+
+  /// The amount of milliseconds between those same ticks while a file is being sent, where waiting
+  /// on the next tick leaves the controller's buffer empty for longer.
+  #[serde(default = "default_job_tick_interval")]
+  job_tick_interval_ms: u64,
+}
+
+fn default_tick_interval() -> u64 {
+  50
+}
+
+fn default_job_tick_interval() -> u64 {
+  10
+}
+
+/// The keep-alive configuration controls how (and if) we periodically ask the controller for its
+/// status. How often depends on what the controller last told us it was doing: often while it is
+/// moving, so positions stay current, and less often while it is sitting still. Quiet or battery
+/// powered deployments may want to avoid waking a sleeping controller when nobody is watching.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct KeepAliveConfiguration {
+  /// The amount of seconds between status queries while idle.
+  interval: u64,
+
+  /// The amount of milliseconds between status queries while the controller is moving (running a
+  /// job, jogging, homing or holding).
+  #[serde(default = "default_active_interval")]
+  active_interval_ms: u64,
+
+  /// When true, we will stop querying once the controller has reported that it is sleeping.
+  #[serde(default)]
+  pause_when_sleeping: bool,
+
+  /// When true, we will stop querying while there are no connected clients.
+  #[serde(default)]
+  pause_without_clients: bool,
+}
+
+/// The control configuration determines how long the control lock is held by an operator that is
+/// no longer sending anything.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+struct ControlConfiguration {
+  /// The amount of seconds without a control request from the operator before the lock is
+  /// released automatically. When not provided, the lock is held until released.
+  idle_timeout: Option<u64>,
+
+  /// What happens to raw lines sent by the operator while a job is being sent.
+  #[serde(default)]
+  raw_during_job: RawDuringJob,
+}
+
+/// Raw lines written in between the lines of a job interfere with the flow control of the job, so
+/// they are either held until the job is done or refused outright.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RawDuringJob {
+  /// The line is sent once the job is done.
+  #[default]
+  Queue,
+
+  /// The request fails with a `job_active` error.
+  Reject,
+}
+
+/// Used by serde when no active interval is provided.
+fn default_active_interval() -> u64 {
+  200
+}
+
+impl Default for KeepAliveConfiguration {
+  fn default() -> Self {
+    Self {
+      interval: 3,
+      active_interval_ms: default_active_interval(),
+      pause_when_sleeping: false,
+      pause_without_clients: false,
+    }
+  }
+}
+
+impl KeepAliveConfiguration {
+  /// Whether or not the controller is moving in the provided state.
+  fn active(state: Option<grbl::MachineState>) -> bool {
+    matches!(
+      state,
+      Some(
+        grbl::MachineState::Run
+          | grbl::MachineState::Jog
+          | grbl::MachineState::Home
+          | grbl::MachineState::Hold
+          | grbl::MachineState::Check
+      )
+    )
+  }
+
+  /// Returns how long we wait between status queries while the controller is in the provided state
+  /// (if we know it), or `None` when we should not be asking at all. Controllers in an alarm have
+  /// nothing new to tell us until they are sent something.
+  fn interval(&self, state: Option<grbl::MachineState>) -> Option<std::time::Duration> {
+    match state {
+      _ if Self::active(state) => Some(std::time::Duration::from_millis(self.active_interval_ms)),
+      Some(grbl::MachineState::Alarm) => None,
+      Some(grbl::MachineState::Sleep) if self.pause_when_sleeping => None,
+      _ => Some(std::time::Duration::from_secs(self.interval)),
+    }
+  }
 }
 
 /// The configuration we will load from the filesystem is an amalgamation of internal
 /// configurations for the various effect systems.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Configuration {
   /// The configuration used by our http server.
   http: effects::http::Configuration,
@@ -25,9 +148,44 @@ pub struct Configuration {
   serial: Option<effects::serial::SerialConfiguration>,
 
   timing: Option<TimingConfiguration>,
+
+  keep_alive: Option<KeepAliveConfiguration>,
+
+  /// When provided, every message received by the application will be recorded into a journal
+  /// file at this path. The journal can later be fed back through the application via `replay`.
+  journal: Option<String>,
+
+  /// When provided, the history of jobs is persisted to (and loaded from) a file at this path.
+  jobs: Option<String>,
+
+  /// Determines how much of the data we accumulate is held on to.
+  retention: Option<crate::retention::RetentionConfiguration>,
+
+  /// Macros defined ahead of time, and where macros saved by clients are persisted.
+  macros: Option<crate::macros::MacroConfiguration>,
+
+  /// Determines when the control lock is released.
+  control: Option<ControlConfiguration>,
+
+  /// When provided, local terminal sessions may attach to the serial connection through a unix
+  /// socket (see the `attach` subcommand).
+  terminal: Option<effects::terminal::Configuration>,
+
+  /// When provided, the keys of a jog pendant (or keypad) attached to our host control the machine.
+  input: Option<effects::input::Configuration>,
+
+  /// Determines where (and how) our logs are written.
+  logging: Option<crate::logging::LoggingConfiguration>,
+
+  /// When provided, operators are notified of finished jobs, alarms and disconnects.
+  notify: Option<effects::notify::Configuration>,
+
+  /// When provided, the progress of the current job (among other things) is periodically written
+  /// to disk, and restored when we start.
+  snapshot: Option<crate::snapshot::SnapshotConfiguration>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 enum Message {
   /// The `Tick` message is used to process serial events.
   Tick,
@@ -35,9 +193,34 @@ enum Message {
   /// The `Broadcast` message is used to publish websocket events to clients.
   Broadcast,
 
+  /// The `Prune` message is used to remove data that falls outside our retention policies.
+  Prune,
+
+  /// Sent when our process has been asked to stop (`SIGINT`, `SIGTERM`).
+  Shutdown,
+
+  /// Sent when our process has been asked to reload its configuration (`SIGHUP`).
+  Reload,
+
+  /// Sent when our process has been asked to log its current state (`SIGUSR1`).
+  DumpState,
+
+  /// The `Snapshot` message is used to write the state we restore on startup to disk.
+  Snapshot,
+
+  /// Sent when our configuration file has changed (or been reloaded), after it has been parsed and
+  /// validated.
+  ConfigurationChanged(Box<Configuration>),
+
   Serial(String),
   Http(effects::http::Message),
 
+  /// Events from the terminal sessions attached through our unix socket.
+  Terminal(effects::terminal::TerminalEvent),
+
+  /// Keys pressed on the jog pendant (or keypad) attached to our host.
+  Input(effects::input::InputEvent),
+
   DisconnectedSerial,
   ConnectedSerial,
 }
@@ -47,50 +230,251 @@ enum SerialCommand {
   #[allow(dead_code)]
   Raw(String),
 
-  Status,
+  /// Asks the controller, running the provided firmware, for its status.
+  Status(FirmwareFlavor),
+
+  /// Stops the controller, running the provided firmware, discarding whatever it has buffered.
+  Reset(FirmwareFlavor),
+
+  /// Realtime commands that the controller acts on immediately, regardless of what it has queued.
+  Realtime(grbl::Command),
 
   Configure(effects::serial::SerialConfiguration),
 
   Control(bool),
 }
 
-/// TODO: This implementation is used when mapping our concrete application command into a string
-/// that can actually be sent to the serial connection.
-impl std::fmt::Display for SerialCommand {
-  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-    match &self {
-      SerialCommand::Raw(inner) => writeln!(formatter, "{inner}"),
-      SerialCommand::Status => write!(formatter, "{}", grbl::Command::Status),
-      _ => Ok(()),
+/// Maps our concrete application command into what is actually written to the serial connection.
+/// Lines are terminated by the serial effect; realtime commands (including status queries) must not
+/// be, since the controller would answer the empty line that follows them with an "ok".
+impl effects::serial::Encode for SerialCommand {
+  fn encode(&self) -> effects::serial::Encoded {
+    match self {
+      SerialCommand::Raw(inner) => effects::serial::Encoded::Line(inner.clone()),
+      SerialCommand::Status(flavor) => effects::serial::Encoded::RawBytes(flavor.firmware().status_query()),
+      SerialCommand::Reset(flavor) => effects::serial::Encoded::RawBytes(flavor.firmware().reset()),
+      SerialCommand::Realtime(inner) => effects::serial::Encoded::RawBytes(inner.bytes()),
+      SerialCommand::Configure(_) | SerialCommand::Control(_) => effects::serial::Encoded::None,
     }
   }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-struct RawSerialRequest {
-  value: String,
+impl ClientPreferences {
+  /// Whether or not a line received from the controller (parsed, if it could be) belongs in the
+  /// history of a client with these preferences. `reply_to` is the tick of the request of this
+  /// client the line answers, if any.
+  fn wants(&self, response: Option<&grbl::Response>, reply_to: Option<u32>) -> bool {
+    match response {
+      Some(grbl::Response::Status(_)) => self.statuses,
+      Some(grbl::Response::Ok) => self.verbose || reply_to.is_some(),
+      _ => true,
+    }
+  }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(tag = "kind", rename_all = "snake_case")]
-enum ClientMessageRequest {
-  RawSerial(RawSerialRequest),
-  Configuration(effects::serial::SerialConfiguration),
-  CloseSerial,
-  RetrySerial,
+/// The largest page of history entries we will send in response to a single request.
+const MAX_HISTORY_PAGE: usize = 500;
+
+impl JogRequest {
+  /// Returns the jog line moving the machine by this request, mapped onto machine axes.
+  fn line(&self, mapping: effects::serial::AxisMapping, firmware: &dyn Firmware) -> String {
+    firmware.jog(mapping.machine([self.x, self.y, self.z]), self.feed)
+  }
 }
 
-/// This type represents the schema of data that can be sent from individual websocket
-/// connections. The `Application` receives that data as raw `String` data and will attempt to
-/// parse it here as json.
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(rename_all = "snake_case")]
-struct ClientMessage {
-  // Every request from the client should have a unqiue identifier so the response that comes
-  // through the websocket can be re-associated on the client with the request.
-  tick: u32,
+impl ProbeRequest {
+  /// Returns the grbl lines probing for this request, mapped onto machine axes, along with the line
+  /// setting the work offset once the probe has touched (when we were asked to). Probing is done
+  /// relative to where the machine is, and absolute distances are restored immediately after.
+  fn lines(&self, mapping: effects::serial::AxisMapping) -> Option<(Vec<String>, Option<String>)> {
+    let mut distances = [0.0; 3];
+    distances[self.axis as usize] = self.distance;
+
+    let (axis, distance) = ["X", "Y", "Z"]
+      .into_iter()
+      .zip(mapping.machine(distances))
+      .find(|(_, distance)| *distance != 0.0)?;
+
+    let probe = format!("G91 G21 G38.2 {axis}{distance:.3} F{:.0}", self.feed);
+
+    // The touched point is in the opposite direction of the probe from the surface it is on, e.g
+    // probing down onto a plate leaves the tool one plate thickness above zero.
+    let offset = self
+      .offset
+      .map(|offset| format!("G10 L20 P0 {axis}{:.3}", -distance.signum() * offset));
+
+    Some((vec![probe, "G90".to_string()], offset))
+  }
+}
+
+/// The outcome of the most recent probing cycle.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+struct ProbeResult {
+  /// Where the machine stopped, as shown to operators.
+  position: DisplayPosition,
+
+  /// Whether or not the probe touched anything before reaching the end of its distance.
+  touched: bool,
+  probed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A probing cycle sent on behalf of a client, waiting on the controller to report its result.
+#[derive(Debug)]
+struct PendingProbe {
+  /// The line setting the work offset of the probed axis, sent once the probe has touched.
+  offset: Option<String>,
+}
+
+/// A machine position as it is shown to operators, after the `AxisMapping` of the profile has been
+/// applied.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+struct DisplayPosition {
+  x: f32,
+  y: f32,
+  z: f32,
+}
+
+impl OverrideRequest {
+  /// Returns the realtime command making this adjustment. Rapids can only be set to 100%, 50% or
+  /// 25%, so they are stepped between those from the most recently reported percentage.
+  fn command(&self, current: Option<grbl::Overrides>) -> grbl::Command {
+    let rapid = current.map_or(100, |overrides| overrides.rapid);
+
+    grbl::Command::Override(match (self.kind, self.direction) {
+      (OverrideKind::Feed, OverrideDirection::Reset) => 0x90,
+      (OverrideKind::Feed, OverrideDirection::Increase) => 0x91,
+      (OverrideKind::Feed, OverrideDirection::Decrease) => 0x92,
+      (OverrideKind::Feed, OverrideDirection::IncreaseFine) => 0x93,
+      (OverrideKind::Feed, OverrideDirection::DecreaseFine) => 0x94,
+      (OverrideKind::Rapid, OverrideDirection::Reset) => 0x95,
+      (OverrideKind::Rapid, OverrideDirection::Increase | OverrideDirection::IncreaseFine) if rapid < 50 => 0x96,
+      (OverrideKind::Rapid, OverrideDirection::Increase | OverrideDirection::IncreaseFine) => 0x95,
+      (OverrideKind::Rapid, OverrideDirection::Decrease | OverrideDirection::DecreaseFine) if rapid > 50 => 0x96,
+      (OverrideKind::Rapid, OverrideDirection::Decrease | OverrideDirection::DecreaseFine) => 0x97,
+      (OverrideKind::Spindle, OverrideDirection::Reset) => 0x99,
+      (OverrideKind::Spindle, OverrideDirection::Increase) => 0x9A,
+      (OverrideKind::Spindle, OverrideDirection::Decrease) => 0x9B,
+      (OverrideKind::Spindle, OverrideDirection::IncreaseFine) => 0x9C,
+      (OverrideKind::Spindle, OverrideDirection::DecreaseFine) => 0x9D,
+    })
+  }
+}
+
+/// A file being uploaded by a client over the websocket.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct PendingUpload {
+  #[serde(flatten)]
+  metadata: effects::http::UploadMetadata,
+
+  /// The size of the file, in bytes, as declared when the upload began.
+  size: usize,
+
+  /// The amount of bytes received so far.
+  received: usize,
+
+  /// The sequence number of the chunk expected next.
+  next: u32,
+
+  #[serde(skip)]
+  content: String,
+}
+
+/// The amount of upcoming job lines included in client state while step mode is on.
+const UPCOMING_LINES: usize = 5;
+
+/// The preview sent in response to an unconfirmed `RunFromLine` request.
+#[derive(Serialize)]
+struct RunFromLinePreview<'a> {
+  line: usize,
+  total_lines: usize,
+
+  /// The lines sent before the line being run from, restoring the modal state and position.
+  preamble: Vec<String>,
+
+  /// Anything the operator should know before confirming.
+  warnings: Vec<String>,
 
-  request: ClientMessageRequest,
+  /// The lines of the file that will follow the preamble.
+  upcoming: &'a [String],
+}
+
+impl ClientMessageRequest {
+  /// The authority the user that opened a client needs for this request to be accepted from it.
+  /// Changing how the machine is set up is left to admins.
+  fn required_authority(&self) -> effects::http::Authority {
+    match self {
+      // Lines forced in between the lines of a job are left to the people setting the machine up.
+      Self::RawSerial(inner) if inner.force => effects::http::Authority::Admin,
+      Self::Configuration(_) | Self::CloseSerial | Self::RetrySerial | Self::SaveMacro(_) | Self::WriteSetting(_) => {
+        effects::http::Authority::Admin
+      }
+      Self::TakeOver
+      | Self::AcquireControl
+      | Self::RawSerial(_)
+      | Self::FeedHold
+      | Self::CycleStart
+      | Self::RunMacro(_)
+      | Self::StepMode(_)
+      | Self::Step
+      | Self::RunFromLine(_)
+      | Self::Jog(_)
+      | Self::StartJob(_)
+      | Self::BeginUpload(_)
+      | Self::UploadChunk(_)
+      | Self::FinishUpload
+      | Self::Override(_)
+      | Self::Probe(_)
+      | Self::ResumeJob
+      | Self::ResumeFromLine(_)
+      | Self::ReadSettings
+      | Self::CancelJob => effects::http::Authority::Operator,
+      Self::ReleaseControl
+      | Self::ResyncState(_)
+      | Self::ChatMessage(_)
+      | Self::MarkEvent(_)
+      | Self::HistoryPage(_)
+      | Self::SetPreferences(_)
+      | Self::ResumeSession(_) => effects::http::Authority::Observer,
+    }
+  }
+
+  /// Whether or not this request controls the machine; these are only accepted from the operator.
+  fn is_control(&self) -> bool {
+    match self {
+      Self::RawSerial(_)
+      | Self::Configuration(_)
+      | Self::CloseSerial
+      | Self::RetrySerial
+      | Self::FeedHold
+      | Self::CycleStart
+      | Self::SaveMacro(_)
+      | Self::RunMacro(_)
+      | Self::StepMode(_)
+      | Self::Step
+      | Self::RunFromLine(_)
+      | Self::Jog(_)
+      | Self::StartJob(_)
+      | Self::BeginUpload(_)
+      | Self::UploadChunk(_)
+      | Self::FinishUpload
+      | Self::Override(_)
+      | Self::Probe(_)
+      | Self::ResumeJob
+      | Self::ResumeFromLine(_)
+      | Self::ReadSettings
+      | Self::WriteSetting(_)
+      | Self::CancelJob => true,
+      Self::TakeOver
+      | Self::AcquireControl
+      | Self::ReleaseControl
+      | Self::ResyncState(_)
+      | Self::ChatMessage(_)
+      | Self::MarkEvent(_)
+      | Self::HistoryPage(_)
+      | Self::SetPreferences(_)
+      | Self::ResumeSession(_) => false,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -99,13 +483,27 @@ enum Command {
   Serial(SerialCommand),
 
   Http(effects::http::Command),
+
+  Terminal(effects::terminal::TerminalCommand),
+
+  Watcher(effects::watcher::WatcherCommand),
+
+  /// Tells the operator about something that happened while they may be away from the machine.
+  Notify(effects::notify::Event),
+
+  /// Stops the application; every effect is dropped once this has been published.
+  Exit,
 }
 
-impl std::fmt::Display for Command {
-  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl Command {
+  /// Realtime serial commands need to jump ahead of anything else we are sending the controller,
+  /// including the lines of a file.
+  fn priority(&self) -> crate::eff::Priority {
     match self {
-      Command::Serial(inner) => write!(formatter, "{inner}"),
-      Command::Http(_) => Ok(()),
+      Command::Serial(SerialCommand::Realtime(_) | SerialCommand::Status(_) | SerialCommand::Reset(_)) => {
+        crate::eff::Priority::High
+      }
+      _ => crate::eff::Priority::Normal,
     }
   }
 }
@@ -114,6 +512,25 @@ impl std::fmt::Display for Command {
 #[serde(rename_all = "snake_case")]
 struct ReceivedDataEntry {
   content: String,
+
+  /// When this is the `ok` (or `error`) answering a raw line this client sent, the tick of that
+  /// request; clients use this to show each line along with its result.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  reply_to: Option<u32>,
+}
+
+/// A timestamped bookmark recorded by an operator, along with where the machine was at the time.
+#[derive(Debug, Serialize, Clone)]
+struct JobMarker {
+  label: String,
+
+  /// The line of the file being sent when the marker was recorded, if we were sending one.
+  line: Option<usize>,
+
+  /// The last position reported by the controller.
+  position: Option<grbl::MachinePosition>,
+
+  marked_at: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Debug, Serialize)]
@@ -121,160 +538,1671 @@ struct ReceivedDataEntry {
 enum ClientHistoryEntry {
   SentCommand(ClientMessage),
   ReceivedData(ReceivedDataEntry),
+  Marker(JobMarker),
+}
+
+/// What a connected client is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ClientRole {
+  /// Receives every update, but any request that would control the machine is refused.
+  #[default]
+  Observer,
+
+  /// The single client currently allowed to control the machine.
+  Operator,
 }
 
-#[derive(Serialize, Debug, Default)]
+#[derive(Debug, Default)]
 struct DerivedClientState {
   tick: u32,
   history: Vec<ClientHistoryEntry>,
 
+  role: ClientRole,
+
+  /// Whether or not the user that opened this client is allowed to become the operator. Clients
+  /// opened by observers never are.
+  can_operate: bool,
+
+  /// The authority of the user that opened this client.
+  authority: Option<effects::http::Authority>,
+
+  /// The id of the client holding the control lock (the operator), if any.
+  controller: Option<String>,
+
+  /// The sequence number of the first entry in `history`. Every entry is given the next sequence
+  /// number as it is added, which stays with it even after older entries have been dropped.
+  history_start: u64,
+
+  /// The sequence number of the next entry this client has not yet been sent.
+  delivered: u64,
+
+  /// When provided, only this many of the most recent history entries are held on to.
+  history_limit: Option<usize>,
+
   /// Whether or not the serial connection is available.
   serial_available: bool,
   last_config: Option<crate::effects::serial::SerialConfiguration>,
+
+  /// While the job being sent is in step mode, the lines that will be sent next.
+  upcoming: Option<Vec<String>>,
+
+  /// The last reported machine position, as shown to operators.
+  position: Option<DisplayPosition>,
+
+  /// The most recently finished job, so operators reconnecting can tell how it ended.
+  last_job: Option<crate::jobs::JobRecord>,
+
+  /// The override percentages most recently reported by the controller.
+  overrides: Option<grbl::Overrides>,
+
+  /// The firmware the controller announced itself as running.
+  firmware: Option<FirmwareInfo>,
+
+  /// The outcome of the most recent probing cycle.
+  probe: Option<ProbeResult>,
+
+  /// The line the job being sent is stopped at, waiting on the operator.
+  paused: Option<JobPause>,
+
+  /// How far the job being sent has gotten.
+  job: Option<crate::snapshot::JobProgress>,
+
+  /// What this client wants added to its history.
+  preferences: ClientPreferences,
+
+  /// The file this client is uploading over the websocket, if any.
+  upload: Option<PendingUpload>,
+
+  /// The secret a reconnecting client provides to resume this state.
+  token: String,
+
+  /// When the connection of this client was lost. The state is held on to (and kept up to date) for
+  /// a while in case the client reconnects, but nothing is sent to it.
+  detached_at: Option<std::time::Instant>,
+
+  /// Whether or not anything has changed since the last `State` payload was delivered to this
+  /// client. Unchanged clients are not sent anything when publishing.
+  changed: bool,
+
+  /// A hash of the last `State` payload delivered to this client, used to skip sending payloads
+  /// identical to it even when the state was marked as changed.
+  delivered_hash: Option<u64>,
 }
 
-#[derive(Serialize, Debug, Default)]
-struct ClientResponse {
+/// The schema of the state sent to clients. When `delta` is true, the history only contains the
+/// entries the client has not yet been sent, and should be appended to what it already has.
+#[derive(Serialize)]
+struct StatePayload<'a> {
   tick: u32,
-  status: String,
+  serial_available: bool,
+  last_config: Option<&'a crate::effects::serial::SerialConfiguration>,
+  delta: bool,
+  role: ClientRole,
+  can_operate: bool,
+  controller: Option<&'a str>,
+
+  /// While the job being sent is in step mode, the lines that will be sent next.
+  upcoming: Option<&'a [String]>,
+
+  /// The last reported machine position, with the axis mapping of the profile applied.
+  position: Option<DisplayPosition>,
+
+  /// The most recently finished job, if any.
+  last_job: Option<&'a crate::jobs::JobRecord>,
+
+  /// The feed, rapid and spindle override percentages, when the controller reports them.
+  overrides: Option<grbl::Overrides>,
+
+  /// The firmware the controller announced itself as running, once it has.
+  firmware: Option<&'a FirmwareInfo>,
+
+  /// The outcome of the most recent probing cycle, if any.
+  probe: Option<ProbeResult>,
+
+  /// When the job being sent is stopped at a line (e.g a tool change), why; it is only resumed by a
+  /// `ResumeJob` request.
+  paused: Option<&'a JobPause>,
+
+  /// How far the job being sent has gotten, if one is.
+  job: Option<&'a crate::snapshot::JobProgress>,
+
+  /// What this client has asked to be added to its history.
+  preferences: ClientPreferences,
+
+  /// How much of the file this client is uploading over the websocket has been received.
+  upload: Option<&'a PendingUpload>,
+
+  /// The sequence number of the first entry in `history`.
+  history_start: u64,
+  history: &'a [ClientHistoryEntry],
 }
 
+/// The schema of a page of older history entries requested by a client.
 #[derive(Serialize)]
-#[serde(rename_all = "snake_case", tag = "kind")]
-enum ResponseKinds<'a> {
-  State(&'a DerivedClientState),
-  Response(ClientResponse),
+struct HistoryPagePayload<'a> {
+  /// The sequence number of the first entry in `history`.
+  history_start: u64,
+  history: &'a [ClientHistoryEntry],
 }
 
-#[derive(Debug)]
-struct FileQueue {
-  pending: Vec<String>,
-  waiting: bool,
-  sent: Vec<String>,
-}
+impl DerivedClientState {
+  /// Whether or not the user that opened this client holds (at least) the provided authority.
+  fn allows(&self, required: effects::http::Authority) -> bool {
+    self.authority.is_some_and(|authority| authority.allows(required))
+  }
 
-enum FileQueueNext {
-  Ready(String),
-  Waiting,
-  Done,
-}
+  /// Adds a `SentCommand` entry into this client's history for some line of data we are sending to
+  /// the serial connection on behalf of the application (file lines, startup sequences, etc...).
+  fn record_sent(&mut self, line: &str) {
+    self.push_history(ClientHistoryEntry::SentCommand(ClientMessage {
+      tick: 0,
+      request: ClientMessageRequest::RawSerial(RawSerialRequest {
+        value: line.to_string(),
+        force: false,
+      }),
+    }));
+  }
 
-impl FileQueue {
-  fn from_str<S>(target: S) -> Self
-  where
-    S: AsRef<str>,
-  {
-    let lines = target.as_ref().lines().map(String::from).collect();
-    Self {
-      pending: lines,
-      waiting: false,
-      sent: vec![],
+  /// Appends an entry to this client's history, dropping the oldest entries beyond our limit.
+  fn push_history(&mut self, entry: ClientHistoryEntry) {
+    self.history.push(entry);
+
+    if let Some(excess) = self.history_limit.map(|limit| self.history.len().saturating_sub(limit)) {
+      self.history.drain(0..excess);
+      self.history_start += excess as u64;
     }
+
+    self.invalidate();
   }
 
-  fn update(&mut self, response: &grbl::Response) {
-    match response {
-      grbl::Response::Ok => {
-        tracing::info!("no longer waiting, will send next line {:?}", self.pending.get(0));
-        self.waiting = false;
-      }
-      other => tracing::warn!("file queue not ready to handle '{other:?}'"),
-    }
+  /// Returns the sequence number the next history entry will be given.
+  fn history_end(&self) -> u64 {
+    self.history_start + self.history.len() as u64
   }
 
-  fn next(&mut self) -> FileQueueNext {
-    if self.waiting {
-      return FileQueueNext::Waiting;
-    }
+  /// Returns the index into our history of the entry with the provided sequence number.
+  fn history_index(&self, sequence: u64) -> usize {
+    (sequence.saturating_sub(self.history_start) as usize).min(self.history.len())
+  }
 
-    let next = match self.pending.len() {
-      0 => self.pending.pop(),
-      _ => self.pending.drain(0..1).next(),
+  /// Updates the holder of the control lock (and with it, the role of this client), marking this
+  /// state as changed if it differs.
+  fn set_controller(&mut self, id: &str, controller: Option<&String>) {
+    let role = match controller {
+      Some(holder) if holder == id => ClientRole::Operator,
+      _ => ClientRole::Observer,
     };
 
-    if let Some(borrowed) = next {
-      self.waiting = true;
-      self.sent.push(borrowed.clone());
-      return FileQueueNext::Ready(borrowed);
+    if self.role != role || self.controller.as_ref() != controller {
+      self.role = role;
+      self.controller = controller.cloned();
+      self.invalidate();
     }
+  }
 
-    FileQueueNext::Done
+  /// Updates the upcoming lines of a job in step mode.
+  fn set_upcoming(&mut self, upcoming: Option<&[String]>) {
+    if self.upcoming.as_deref() != upcoming {
+      self.upcoming = upcoming.map(<[String]>::to_vec);
+      self.invalidate();
+    }
   }
-}
 
-#[derive(Debug, Default)]
-enum SerialConnectionState {
-  #[default]
-  Disconnected,
-  PendingAttempt,
-  Idle(
-    Option<std::time::Instant>,
-    Option<(grbl::MachineState, grbl::MachinePosition)>,
-  ),
-  SendingFile(FileQueue, Option<(grbl::MachineState, grbl::MachinePosition)>),
-}
+  /// Updates the machine position shown to this client.
+  fn set_position(&mut self, position: Option<DisplayPosition>) {
+    if self.position != position {
+      self.position = position;
+      self.invalidate();
+    }
+  }
 
-impl SerialConnectionState {
-  fn available(&self) -> bool {
-    matches!(&self, SerialConnectionState::Idle(_, _))
+  /// Updates the override percentages shown to this client.
+  fn set_overrides(&mut self, overrides: Option<grbl::Overrides>) {
+    if self.overrides != overrides {
+      self.overrides = overrides;
+      self.invalidate();
+    }
   }
 
-  fn update_status(&mut self, status: (grbl::MachineState, grbl::MachinePosition)) {
-    match self {
-      Self::SendingFile(_, other) => std::mem::swap(other, &mut Some(status)),
-      Self::Idle(_, other) => std::mem::swap(other, &mut Some(status)),
-      _ => (),
+  /// Updates the firmware of the controller shown to this client.
+  fn set_firmware(&mut self, firmware: Option<&FirmwareInfo>) {
+    if self.firmware.as_ref() != firmware {
+      self.firmware = firmware.cloned();
+      self.invalidate();
     }
   }
-}
 
-#[derive(Debug, Default)]
-struct DerivedSerialState {
-  connection: SerialConnectionState,
-  last_config: Option<crate::effects::serial::SerialConfiguration>,
-}
+  /// Updates the line the job is stopped at shown to this client.
+  fn set_paused(&mut self, paused: Option<&JobPause>) {
+    if self.paused.as_ref() != paused {
+      self.paused = paused.cloned();
+      self.invalidate();
+    }
+  }
 
-impl DerivedSerialState {
-  fn available(&self) -> bool {
-    self.connection.available()
+  /// Updates how far the job being sent has gotten, as shown to this client.
+  fn set_job(&mut self, job: Option<&crate::snapshot::JobProgress>) {
+    if self.job.as_ref() != job {
+      self.job = job.cloned();
+      self.invalidate();
+    }
   }
-}
 
-#[derive(Default)]
-struct Application {
-  /// The `last_broadcast` field is used to determine during which tick we should broadcast all
-  /// updated state messages to connected clients.
-  last_broadcast: Option<std::time::Instant>,
+  /// Starts (or restarts) the upload of a file over the websocket.
+  fn begin_upload(&mut self, upload: Option<PendingUpload>) {
+    self.upload = upload;
+    self.invalidate();
+  }
 
-  /// The map of connected clients available to us through websockets.
-  connected_clients: std::collections::HashMap<String, DerivedClientState>,
+  /// Adds the next chunk to the file being uploaded, refusing chunks that arrive out of order or
+  /// would make the file larger than it was declared to be.
+  fn receive_chunk(&mut self, chunk: &UploadChunkRequest) -> Result<(), catalog::Failure> {
+    let upload = self.upload.as_mut().ok_or(catalog::Code::UploadNotStarted)?;
 
-  /// Whether or not our serial connection is available.
-  serial: DerivedSerialState,
-}
+    if chunk.seq != upload.next {
+      return Err(catalog::Failure::new(catalog::Code::UploadOutOfOrder).with("expected", upload.next));
+    }
 
-impl Application {
-  /// There are a few times where we will want to append to a list of commands a "state refresh"
-  /// command for every client that is connected:
-  ///
-  /// 1. disconnect
-  /// 2. connect
-  /// 3. pending connect
-  /// 4. etc...
-  #[inline]
-  fn add_statuses(&mut self, command_list: &mut Vec<Command>) {
+    if upload.received + chunk.data.len() > upload.size {
+      return Err(catalog::Code::UploadTooLarge.into());
+    }
+
+    upload.content.push_str(&chunk.data);
+    upload.received += chunk.data.len();
+    upload.next += 1;
+    self.invalidate();
+    Ok(())
+  }
+
+  /// Updates what this client wants added to its history.
+  fn set_preferences(&mut self, preferences: ClientPreferences) {
+    if self.preferences != preferences {
+      self.preferences = preferences;
+      self.invalidate();
+    }
+  }
+
+  /// Updates the outcome of the most recent probing cycle shown to this client.
+  fn set_probe(&mut self, probe: Option<ProbeResult>) {
+    if self.probe != probe {
+      self.probe = probe;
+      self.invalidate();
+    }
+  }
+
+  /// Updates the most recently finished job shown to this client.
+  fn set_last_job(&mut self, job: Option<crate::jobs::JobRecord>) {
+    self.last_job = job;
+    self.invalidate();
+  }
+
+  /// Updates whether or not the serial connection is available, marking this state as changed if
+  /// it differs from what the client last knew.
+  fn set_serial_available(&mut self, serial_available: bool) {
+    if self.serial_available != serial_available {
+      self.serial_available = serial_available;
+      self.invalidate();
+    }
+  }
+
+  /// Marks this state as having changed since the last payload delivered to this client. Anything
+  /// that mutates the client state directly is responsible for calling this.
+  fn invalidate(&mut self) {
+    self.changed = true;
+  }
+
+  /// Returns the state payload for this client, with the history starting at the provided index.
+  fn view(&self, from: usize, delta: bool) -> StatePayload<'_> {
+    StatePayload {
+      tick: self.tick,
+      serial_available: self.serial_available,
+      last_config: self.last_config.as_ref(),
+      delta,
+      role: self.role,
+      can_operate: self.can_operate,
+      controller: self.controller.as_deref(),
+      upcoming: self.upcoming.as_deref(),
+      position: self.position,
+      last_job: self.last_job.as_ref(),
+      overrides: self.overrides,
+      firmware: self.firmware.as_ref(),
+      probe: self.probe,
+      paused: self.paused.as_ref(),
+      job: self.job.as_ref(),
+      preferences: self.preferences,
+      upload: self.upload.as_ref(),
+      history_start: self.history_start + from as u64,
+      history: &self.history[from..],
+    }
+  }
+
+  /// Returns the serialized `State` payload for this client containing only the history entries it
+  /// has not been sent yet; the client is considered to have received them once this returns. When
+  /// nothing has changed since the last payload delivered to this client, `None` is returned.
+  fn payload(&mut self) -> serde_json::Result<Option<String>> {
+    if !self.changed {
+      return Ok(None);
+    }
+
+    let from = self.history_index(self.delivered);
+    let payload = serde_json::to_string(&ResponseKinds::State(self.view(from, true)))?;
+    self.changed = false;
+    self.delivered = self.history_end();
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::hash::Hash::hash(&payload, &mut hasher);
+    let hash = std::hash::Hasher::finish(&hasher);
+
+    if self.delivered_hash.replace(hash) == Some(hash) {
+      return Ok(None);
+    }
+
+    Ok(Some(payload))
+  }
+
+  /// Serializes the complete state of this client, optionally limiting the history to only the
+  /// `window` most recent entries.
+  fn snapshot(&mut self, window: Option<usize>) -> serde_json::Result<String> {
+    let from = window.map_or(0, |window| self.history.len().saturating_sub(window));
+    let payload = serde_json::to_string(&ResponseKinds::State(self.view(from, false)))?;
+    self.changed = false;
+    self.delivered = self.history_end();
+    Ok(payload)
+  }
+
+  /// Serializes up to `limit` of the history entries that came before the entry with the provided
+  /// sequence number (or the end of our history).
+  fn history_page(&self, before: Option<u64>, limit: usize) -> serde_json::Result<String> {
+    let end = before.map_or(self.history.len(), |before| self.history_index(before));
+    let start = end.saturating_sub(limit);
+
+    serde_json::to_string(&ResponseKinds::HistoryPage(HistoryPagePayload {
+      history_start: self.history_start + start as u64,
+      history: &self.history[start..end],
+    }))
+  }
+}
+
+/// The amount of chat messages we will hold on to for clients that connect later.
+const CHAT_CAPACITY: usize = 50;
+
+/// The longest chat message (in characters) we will relay; chat is meant for short notes.
+const CHAT_MAX_LENGTH: usize = 500;
+
+/// How long the state of a client that lost its connection is held on to, waiting for it to resume.
+const RESUME_WINDOW: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// A chat message that has been relayed to clients.
+#[derive(Serialize, Debug)]
+struct ChatEntry {
+  author: String,
+  content: String,
+  sent_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ClientResponse {
+  /// The response sent when a request was accepted.
+  fn ok(tick: u32) -> Self {
+    Self {
+      tick,
+      status: "ok".into(),
+      ..Self::default()
+    }
+  }
+
+  /// The response sent when a request was not accepted.
+  fn failed<F>(tick: u32, failure: F) -> Self
+  where
+    F: Into<catalog::Failure>,
+  {
+    let failure = failure.into();
+    Self {
+      tick,
+      status: "failed".into(),
+      code: Some(failure.code.as_str().to_string()),
+      detail: Some(failure.render()),
+      params: Some(
+        failure
+          .params
+          .into_iter()
+          .map(|(name, value)| (name.to_string(), value))
+          .collect(),
+      ),
+    }
+  }
+}
+
+/// The kinds of client requests this server understands, sent to clients in the `Hello` frame.
+const CAPABILITIES: &[&str] = &[
+  "raw_serial",
+  "configuration",
+  "close_serial",
+  "retry_serial",
+  "resync_state",
+  "history_page",
+  "set_preferences",
+  "resume_session",
+  "chat_message",
+  "mark_event",
+  "feed_hold",
+  "cycle_start",
+  "save_macro",
+  "run_macro",
+  "step_mode",
+  "step",
+  "run_from_line",
+  "jog",
+  "start_job",
+  "begin_upload",
+  "upload_chunk",
+  "finish_upload",
+  "override",
+  "probe",
+  "resume_job",
+  "resume_from_line",
+  "read_settings",
+  "write_setting",
+  "cancel_job",
+  "take_over",
+  "acquire_control",
+  "release_control",
+  "file_upload",
+];
+
+/// The first frame sent to every client as soon as it connects, so it does not need to wait for the
+/// next broadcast before it has something to render.
+#[derive(Serialize)]
+struct Hello<'a> {
+  protocol_version: u32,
+  server_version: &'static str,
+  capabilities: &'static [&'static str],
+
+  /// The frame encodings clients may ask for when connecting.
+  encodings: &'static [&'static str],
+
+  /// The serial configuration currently in use, if any.
+  profile: Option<&'a crate::effects::serial::SerialConfiguration>,
+
+  /// The firmware the controller announced itself as running, if it has since we connected.
+  firmware: Option<&'a FirmwareInfo>,
+
+  /// The full state snapshot of the newly connected client.
+  state: StatePayload<'a>,
+
+  /// The most recent chat messages, oldest first.
+  chat: &'a std::collections::VecDeque<ChatEntry>,
+
+  /// Every macro available to run, by name.
+  macros: std::collections::BTreeMap<&'a str, &'a str>,
+
+  /// The settings of the controller, as most recently listed.
+  settings: Vec<grbl::settings::Described>,
+
+  /// The job that was being sent when the server last stopped without finishing it, if any.
+  interrupted: Option<&'a crate::snapshot::JobProgress>,
+
+  /// Sent in a `ResumeSession` request to pick this client back up should its connection be lost.
+  resume_token: &'a str,
+}
+
+/// The schema of the macros sent to clients any time they change.
+#[derive(Serialize)]
+struct MacrosPayload<'a> {
+  /// Every macro available to run, by name.
+  macros: std::collections::BTreeMap<&'a str, &'a str>,
+}
+
+/// The schema of the settings of the controller, sent to clients once it has listed (or accepted a
+/// change to) them.
+#[derive(Serialize)]
+struct SettingsPayload {
+  settings: Vec<grbl::settings::Described>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+enum ResponseKinds<'a> {
+  Hello(Hello<'a>),
+  State(StatePayload<'a>),
+  HistoryPage(HistoryPagePayload<'a>),
+  Chat(&'a ChatEntry),
+  Macros(MacrosPayload<'a>),
+  Settings(SettingsPayload),
+  RunFromLine(RunFromLinePreview<'a>),
+  Response(ClientResponse),
+}
+
+#[derive(Debug)]
+struct FileQueue {
+  pending: Vec<String>,
+  waiting: bool,
+  sent: Vec<String>,
+
+  /// The markers recorded by operators while this file was being sent.
+  markers: Vec<JobMarker>,
+
+  /// The details provided alongside the upload, used when recording this job into our history.
+  metadata: effects::http::UploadMetadata,
+
+  started_at: chrono::DateTime<chrono::Utc>,
+
+  /// While in step mode, the amount of lines the operator has asked to be sent that have not been.
+  /// Lines are only sent automatically while this is `None`.
+  steps: Option<usize>,
+
+  /// Every line of the original file, kept so the job can be run again from any of them.
+  source: Vec<String>,
+
+  /// The line (e.g a tool change) this job is stopped at until the operator resumes it.
+  paused: Option<JobPause>,
+
+  /// When this job is a dry run, what stands in for the controller.
+  dry_run: Option<DryRun>,
+
+  /// When run from a line of its file, the lines of the file before it, which are not sent.
+  skipped: usize,
+
+  /// When run from a line of its file, the lines sent first to restore the modal state of the
+  /// controller, which are not lines of the file.
+  preamble: usize,
+}
+
+/// Stands in for the controller during a dry run, answering each line once the time it is estimated
+/// to take has passed. Lines are taken no faster than one per tick.
+#[derive(Debug)]
+struct DryRun {
+  estimator: grbl::estimate::Estimator,
+
+  /// When the line most recently taken is done.
+  due: std::time::Instant,
+
+  /// Whether or not the serial connection was available when the dry run started; it is left the
+  /// way it was found once the dry run is done.
+  connected: bool,
+}
+
+impl DryRun {
+  fn new(connected: bool) -> Self {
+    Self {
+      estimator: grbl::estimate::Estimator::default(),
+      due: std::time::Instant::now(),
+      connected,
+    }
+  }
+
+  /// Starts running the line, from the time the previous one was done (or now, if we have been
+  /// waiting on something else since).
+  fn take(&mut self, line: &str, now: std::time::Instant) {
+    self.due = self.due.max(now) + self.estimator.line(line);
+  }
+
+  /// Whether or not the line most recently taken is done.
+  fn done(&self, now: std::time::Instant) -> bool {
+    self.due <= now
+  }
+}
+
+/// A job stopped at one of its lines until the operator resumes it, e.g to change tools.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct JobPause {
+  /// The line of the file being waited on, as it was written.
+  line: String,
+
+  #[serde(flatten)]
+  reason: grbl::modal::Pause,
+}
+
+/// The file of the most recent job, kept after it has finished so it can be run again from any line.
+#[derive(Debug)]
+struct LastJob {
+  source: Vec<String>,
+  metadata: effects::http::UploadMetadata,
+  finished_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A raw line sent by a client. These are sent one at a time, each waiting on the response to the one
+/// before it, so that the response can be paired with the request it answers.
+#[derive(Debug)]
+struct MdiLine {
+  /// The id of the client that sent the line.
+  client: String,
+
+  /// The tick of the request the line was sent in.
+  tick: u32,
+  line: String,
+}
+
+/// Who a line sent to the controller was sent for, and so who its answer is for.
+#[derive(Debug)]
+enum LineOrigin {
+  /// A line of the job being sent.
+  Job,
+
+  /// A line sent in response to a request of a client (e.g a raw line, jog or probe); the answer is
+  /// added to the history of that client as the reply to the request.
+  Client(String, u32),
+
+  /// A line nobody is waiting on the answer to, e.g the startup lines, or the lines of the pendant
+  /// and of a terminal session.
+  Internal,
+}
+
+/// A line sent to the controller that it has yet to answer.
+#[derive(Debug)]
+struct SentLine {
+  line: String,
+  origin: LineOrigin,
+}
+
+enum FileQueueNext {
+  Ready(String),
+
+  /// The job has just stopped at a line, waiting on the operator.
+  Paused(JobPause),
+  Waiting,
+  Done,
+}
+
+impl FileQueue {
+  fn from_str<S>(target: S) -> Self
+  where
+    S: AsRef<str>,
+  {
+    Self::from_lines(target.as_ref().lines().map(String::from).collect())
+  }
+
+  fn from_lines(lines: Vec<String>) -> Self {
+    Self {
+      pending: lines.clone(),
+      waiting: false,
+      sent: vec![],
+      markers: vec![],
+      metadata: effects::http::UploadMetadata::default(),
+      started_at: chrono::Utc::now(),
+      steps: None,
+      source: lines,
+      paused: None,
+      dry_run: None,
+      skipped: 0,
+      preamble: 0,
+    }
+  }
+
+  /// Returns the lines that will be sent next while in step mode.
+  fn upcoming(&self) -> Option<&[String]> {
+    self
+      .steps
+      .map(|_| &self.pending[..self.pending.len().min(UPCOMING_LINES)])
+  }
+
+  /// Returns the line of the file most recently sent, where the first line is `1`; nothing has been
+  /// sent while this is `0`.
+  fn line(&self) -> usize {
+    match self.sent.len().saturating_sub(self.preamble) {
+      0 => 0,
+      sent => (self.skipped + sent).min(self.source.len()),
+    }
+  }
+
+  /// Surrounds the lines of this job with the sequences of our machine (e.g warming the spindle up
+  /// before, parking after). This needs to happen before anything has been sent.
+  fn wrap(&mut self, config: Option<&effects::serial::SerialConfiguration>) {
+    let (before, after) = match config.map(|config| config.job_sequences()) {
+      Some(sequences) => sequences,
+      None => return,
+    };
+
+    self.pending.splice(0..0, before.iter().cloned());
+    self.pending.extend(after.iter().cloned());
+    self.preamble += before.len();
+  }
+
+  /// Returns how far this job has gotten, as of now.
+  fn progress(&self, position: Option<[f32; 3]>) -> crate::snapshot::JobProgress {
+    crate::snapshot::JobProgress {
+      name: self.metadata.name.clone(),
+      line: self.line(),
+      total: self.source.len(),
+      started_at: self.started_at,
+      position,
+    }
+  }
+
+  /// Creates the record of this job for our history, as of now.
+  fn record(&self, outcome: crate::jobs::JobOutcome) -> crate::jobs::JobRecord {
+    crate::jobs::JobRecord {
+      name: self.metadata.name.clone(),
+      material: self.metadata.material.clone(),
+      started_at: self.started_at,
+      finished_at: chrono::Utc::now(),
+      lines_sent: self.sent.len(),
+      outcome,
+    }
+  }
+
+  fn update(&mut self, response: &grbl::Response) {
+    match response {
+      grbl::Response::Ok => {
+        tracing::info!("no longer waiting, will send next line {:?}", self.pending.get(0));
+        self.waiting = false;
+      }
+      other => tracing::warn!("file queue not ready to handle '{other:?}'"),
+    }
+  }
+
+  fn next(&mut self) -> FileQueueNext {
+    if self.waiting || self.paused.is_some() || self.steps == Some(0) {
+      return FileQueueNext::Waiting;
+    }
+
+    // Lines that stop the job are not sent until the operator resumes it, and then without the words
+    // that stopped it. Whatever is left (even nothing) is still sent, so the lines we have sent keep
+    // lining up with the lines of the file.
+    if let Some((reason, remainder)) = self.pending.first().and_then(|line| grbl::modal::pause(line)) {
+      let line = std::mem::replace(&mut self.pending[0], remainder);
+      let pause = JobPause { line, reason };
+      self.paused = Some(pause.clone());
+      return FileQueueNext::Paused(pause);
+    }
+
+    if let Some(steps) = self.steps.as_mut() {
+      *steps -= 1;
+    }
+
+    let next = match self.pending.len() {
+      0 => self.pending.pop(),
+      _ => self.pending.drain(0..1).next(),
+    };
+
+    if let Some(borrowed) = next {
+      self.waiting = true;
+      self.sent.push(borrowed.clone());
+      return FileQueueNext::Ready(borrowed);
+    }
+
+    FileQueueNext::Done
+  }
+}
+
+#[derive(Debug, Default)]
+enum SerialConnectionState {
+  #[default]
+  Disconnected,
+  PendingAttempt,
+  Idle(Option<(grbl::MachineState, grbl::MachinePosition)>),
+  SendingFile(FileQueue, Option<(grbl::MachineState, grbl::MachinePosition)>),
+}
+
+impl SerialConnectionState {
+  fn available(&self) -> bool {
+    matches!(&self, SerialConnectionState::Idle(_))
+  }
+
+  fn status(&self) -> Option<(grbl::MachineState, grbl::MachinePosition)> {
+    match self {
+      Self::SendingFile(_, status) | Self::Idle(status) => *status,
+      _ => None,
+    }
+  }
+
+  fn update_status(&mut self, status: (grbl::MachineState, grbl::MachinePosition)) {
+    match self {
+      Self::SendingFile(_, other) => std::mem::swap(other, &mut Some(status)),
+      Self::Idle(other) => std::mem::swap(other, &mut Some(status)),
+      _ => (),
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+struct DerivedSerialState {
+  connection: SerialConnectionState,
+  last_config: Option<crate::effects::serial::SerialConfiguration>,
+
+  /// How far the work coordinates are from machine coordinates, as most recently reported.
+  work_offset: Option<grbl::MachinePosition>,
+
+  /// Whether or not the controller reports its position in work coordinates, as of its most recent
+  /// status.
+  work_position: bool,
+
+  /// Whether or not the controller is in relative distance mode (`G91`), as of the lines we have
+  /// sent it since connecting.
+  relative: bool,
+
+  /// When we last asked the controller for its status.
+  last_poll: Option<std::time::Instant>,
+
+  /// The settings of the controller, as most recently listed (or written) since connecting.
+  settings: grbl::settings::Settings,
+
+  /// Whether or not the settings have changed since clients were last sent them.
+  settings_changed: bool,
+
+  /// The firmware the controller announced itself as running, once it has since we connected.
+  firmware: Option<FirmwareInfo>,
+}
+
+impl DerivedSerialState {
+  fn available(&self) -> bool {
+    self.connection.available()
+  }
+
+  /// The firmware the controller is configured as running or, when it is not configured, the one it
+  /// announced itself as running.
+  fn flavor(&self) -> FirmwareFlavor {
+    self
+      .last_config
+      .as_ref()
+      .and_then(|config| config.firmware())
+      .or(self.firmware.as_ref().map(|firmware| firmware.flavor))
+      .unwrap_or_default()
+  }
+
+  /// The implementation of the firmware the controller is running.
+  fn firmware(&self) -> &'static dyn Firmware {
+    self.flavor().firmware()
+  }
+
+  /// Keeps track of the distance mode set by a line we are sending.
+  fn track(&mut self, line: &str) {
+    if let Some(relative) = grbl::modal::distance_mode(line) {
+      self.relative = relative;
+    }
+  }
+
+  /// Ensures the line would not move the machine beyond the travel limits of our configuration.
+  /// Lines that are not straight moves (and machines without limits) are not checked.
+  fn check_limits(&self, line: &str) -> Result<(), catalog::Failure> {
+    let limits = match self.last_config.as_ref().and_then(|config| config.limits()) {
+      Some(limits) => limits,
+      None => return Ok(()),
+    };
+
+    let motion = match grbl::modal::straight_move(line) {
+      Some(motion) => motion,
+      None => return Ok(()),
+    };
+
+    let reported = self
+      .connection
+      .status()
+      .map(|(_, position)| position.axes())
+      .ok_or(catalog::Code::PositionUnknown)?;
+    let offset = self.work_offset.map(|offset| offset.axes());
+
+    // Limits are in machine coordinates; a position reported in work coordinates is moved back into
+    // them by the work offset.
+    let position = match (self.work_position, offset) {
+      (false, _) => reported,
+      (true, Some(offset)) => [0, 1, 2].map(|axis| reported[axis] + offset[axis]),
+      (true, None) => return Err(catalog::Code::PositionUnknown.into()),
+    };
+
+    // Absolute moves in work coordinates need the work offset to know where they end up.
+    let origin = match (motion.machine, motion.relative.unwrap_or(self.relative)) {
+      (true, _) => [0.0; 3],
+      (false, true) => position,
+      (false, false) => offset.ok_or(catalog::Code::PositionUnknown)?,
+    };
+
+    let mut target = position;
+    for (axis, value) in motion.axes.into_iter().enumerate() {
+      if let Some(value) = value {
+        target[axis] = origin[axis] + value;
+      }
+    }
+
+    match limits.violation(target) {
+      None => Ok(()),
+      Some((axis, value)) => {
+        tracing::warn!("refusing '{line}', {axis} would move to {value:.3} (beyond {limits:?})");
+        Err(
+          catalog::Failure::new(catalog::Code::BeyondLimits)
+            .with("axis", axis)
+            .with("target", format!("{value:.3}")),
+        )
+      }
+    }
+  }
+}
+
+#[derive(Default)]
+struct Application {
+  /// The `last_broadcast` field is used to determine during which tick we should broadcast all
+  /// updated state messages to connected clients.
+  last_broadcast: Option<std::time::Instant>,
+
+  /// The map of connected clients available to us through websockets.
+  connected_clients: std::collections::HashMap<String, DerivedClientState>,
+
+  /// Whether or not our serial connection is available.
+  serial: DerivedSerialState,
+
+  /// How frequently we want to receive `Broadcast` messages.
+  broadcast_interval: std::time::Duration,
+
+  /// How frequently we want to receive `Tick` messages; the second is used while sending a file.
+  tick_intervals: (std::time::Duration, std::time::Duration),
+
+  /// Determines when we will be pinging an idle controller for its status.
+  keep_alive: KeepAliveConfiguration,
+
+  /// The most recent chat messages sent between clients, capped at `CHAT_CAPACITY`.
+  chat: std::collections::VecDeque<ChatEntry>,
+
+  /// The history of files that have been sent through the serial connection.
+  jobs: crate::jobs::JobHistory,
+
+  /// Determines how much of the data we accumulate is held on to.
+  retention: Option<crate::retention::RetentionConfiguration>,
+
+  /// Prunes the data held outside of our state (job records, our journal, etc...) along with the
+  /// file of our most recent job.
+  pruner: crate::retention::Pruner,
+
+  /// The macros operators can run.
+  macros: crate::macros::MacroLibrary,
+
+  /// The client currently allowed to control the machine, if any.
+  control: Option<ControlLock>,
+
+  /// How long the control lock is held without any control requests from its holder.
+  control_timeout: Option<std::time::Duration>,
+
+  /// What happens to raw lines sent while a job is being sent.
+  raw_during_job: RawDuringJob,
+
+  /// Raw lines held until the current job is done.
+  queued_raw: Vec<MdiLine>,
+
+  /// Raw lines waiting on the response to the line sent before them.
+  mdi: std::collections::VecDeque<MdiLine>,
+
+  /// Every line sent to the controller that it has yet to answer, oldest first. The controller
+  /// answers lines in the order they arrive, so every `ok` (or `error`) is for the first of these.
+  awaiting: std::collections::VecDeque<SentLine>,
+
+  /// The file of the most recent job, which can be run again from any line.
+  last_job: Option<LastJob>,
+
+  /// The id of the terminal session attached to the serial connection, if any. While a session is
+  /// attached it holds the control lock and the lines of any job are not sent.
+  terminal: Option<String>,
+
+  /// Whether or not we are waiting for the http server to drain before exiting.
+  stopping: bool,
+
+  /// Where we report the state of the serial connection and the current job for health checks.
+  health: crate::health::Health,
+
+  /// Where (and how often) our snapshot is written.
+  snapshot: Option<crate::snapshot::SnapshotConfiguration>,
+
+  /// The job that was being sent when we last stopped without finishing it, restored from our
+  /// snapshot. This is forgotten as soon as another job starts.
+  interrupted: Option<crate::snapshot::JobProgress>,
+
+  /// The override percentages most recently reported by the controller. Controllers only include
+  /// these in some of their statuses, so they are held on to until the connection is lost.
+  overrides: Option<grbl::Overrides>,
+
+  /// The probing cycle the controller is running on behalf of a client, if any.
+  probing: Option<PendingProbe>,
+
+  /// The maximum amount of bytes accepted for files uploaded over the websocket.
+  max_upload_size: usize,
+
+  /// The outcome of the most recent probing cycle.
+  last_probe: Option<ProbeResult>,
+}
+
+/// Written to a terminal session that is attached while another session already is.
+const TERMINAL_BUSY: &str = "% another terminal session is already attached";
+
+/// Lines written by a terminal session that detach it, rather than being sent to the controller.
+const TERMINAL_QUIT: &str = "%quit";
+
+/// The holder of the control lock while a terminal session is attached.
+fn terminal_holder(id: &str) -> String {
+  format!("terminal:{id}")
+}
+
+/// The control lock ensures only a single client (the operator) is sending anything to the machine
+/// at any given time.
+#[derive(Debug)]
+struct ControlLock {
+  /// The id of the client holding the lock.
+  holder: String,
+
+  /// When the holder last sent a control request (or acquired the lock).
+  last_active: std::time::Instant,
+}
+
+impl Application {
+  /// Moves the control lock to the provided client, or releases it, and lets every client know.
+  fn set_controller(&mut self, holder: Option<String>) {
+    self.control = holder.map(|holder| ControlLock {
+      holder,
+      last_active: std::time::Instant::now(),
+    });
+
+    let holder = self.control.as_ref().map(|lock| &lock.holder);
     for (id, client) in &mut self.connected_clients {
-      client.serial_available = self.serial.available();
+      client.set_controller(id, holder);
+    }
+  }
+
+  /// Lets every client know which lines of the job will be sent next while it is in step mode.
+  fn sync_upcoming(&mut self) {
+    let upcoming = match &self.serial.connection {
+      SerialConnectionState::SendingFile(queue, _) => queue.upcoming(),
+      _ => None,
+    };
+
+    for client in self.connected_clients.values_mut() {
+      client.set_upcoming(upcoming);
+    }
+  }
+
+  /// Lets every client know whether the job is stopped at a line, waiting on the operator.
+  fn sync_paused(&mut self) {
+    let paused = match &self.serial.connection {
+      SerialConnectionState::SendingFile(queue, _) => queue.paused.as_ref(),
+      _ => None,
+    };
+
+    for client in self.connected_clients.values_mut() {
+      client.set_paused(paused);
+    }
+  }
+
+  /// Lets every client know how far the job being sent has gotten.
+  fn sync_job(&mut self) {
+    let job = match &self.serial.connection {
+      SerialConnectionState::SendingFile(queue, _) => Some(queue.progress(None)),
+      _ => None,
+    };
+
+    for client in self.connected_clients.values_mut() {
+      client.set_job(job.as_ref());
+    }
+  }
+
+  /// Returns the last reported machine position, mapped onto the axes shown to operators.
+  fn display_position(&self) -> Option<DisplayPosition> {
+    let mapping = self
+      .serial
+      .last_config
+      .as_ref()
+      .map(|config| config.display())
+      .unwrap_or_default();
+    self.serial.connection.status().map(|(_, position)| {
+      let [x, y, z] = mapping.display(position.axes());
+      DisplayPosition { x, y, z }
+    })
+  }
+
+  /// Lets every client know where the machine is.
+  fn sync_position(&mut self) {
+    let position = self.display_position();
+    for client in self.connected_clients.values_mut() {
+      client.set_position(position);
+    }
+  }
+
+  /// Updates the firmware of the controller, and of every client.
+  fn sync_firmware(&mut self, firmware: Option<FirmwareInfo>) {
+    for client in self.connected_clients.values_mut() {
+      client.set_firmware(firmware.as_ref());
+    }
+    self.serial.firmware = firmware;
+  }
+
+  /// Updates the override percentages of the controller, and of every client.
+  fn sync_overrides(&mut self, overrides: Option<grbl::Overrides>) {
+    self.overrides = overrides;
+    for client in self.connected_clients.values_mut() {
+      client.set_overrides(overrides);
+    }
+  }
+
+  /// Records the result of a probing cycle for every client, returning the line that sets the work
+  /// offset of the probed axis when the probe touched and we were asked for one.
+  fn finish_probe(&mut self, position: grbl::MachinePosition, touched: bool) -> Option<Command> {
+    let mapping = self
+      .serial
+      .last_config
+      .as_ref()
+      .map(|config| config.display())
+      .unwrap_or_default();
+    let [x, y, z] = mapping.display(position.axes());
+    let result = ProbeResult {
+      position: DisplayPosition { x, y, z },
+      touched,
+      probed_at: chrono::Utc::now(),
+    };
+
+    tracing::info!("probing cycle finished - {result:?}");
+    self.last_probe = Some(result);
+
+    let offset = self
+      .probing
+      .take()
+      .and_then(|pending| pending.offset)
+      .filter(|_| touched);
+
+    for client in self.connected_clients.values_mut() {
+      client.set_probe(Some(result));
+
+      if let Some(line) = offset.as_ref() {
+        client.record_sent(line);
+      }
+    }
+
+    offset.map(|line| self.send_line(line, LineOrigin::Internal))
+  }
+
+  /// Sends the settings of the controller to every client, if they have changed since they were
+  /// last sent.
+  fn sync_settings(&mut self) -> Option<Command> {
+    if !std::mem::take(&mut self.serial.settings_changed) {
+      return None;
+    }
+
+    let settings = grbl::settings::describe(&self.serial.settings);
+    match serde_json::to_string(&ResponseKinds::Settings(SettingsPayload { settings })) {
+      Ok(payload) => Some(Command::Http(effects::http::Command::Broadcast(payload))),
+      Err(error) => {
+        tracing::warn!("unable to serialize settings - {error}");
+        None
+      }
+    }
+  }
+
+  /// Queues a line listing (or changing) the settings of the controller, which are not accepted
+  /// while it is running a job.
+  fn queue_settings(&mut self, client: &str, tick: u32, line: String) -> Result<(), catalog::Failure> {
+    match &self.serial.connection {
+      SerialConnectionState::SendingFile(_, _) => Err(catalog::Code::JobActive.into()),
+      _ if !self.serial.available() => Err(catalog::Code::SerialUnavailable.into()),
+      _ => {
+        tracing::info!("queueing settings line '{line}'");
+        self.mdi.push_back(MdiLine {
+          client: client.to_string(),
+          tick,
+          line,
+        });
+        Ok(())
+      }
+    }
+  }
+
+  /// Sends the next raw line sent by a client, unless the controller has yet to answer the one before
+  /// it (or is busy with a job or terminal session). The line is added to the history of the client
+  /// that sent it as it goes out, so the response that follows can be shown alongside it.
+  fn send_mdi(&mut self) -> Option<Command> {
+    let idle = matches!(self.serial.connection, SerialConnectionState::Idle(_));
+
+    if !self.awaiting.is_empty() || !idle || self.terminal.is_some() {
+      return None;
+    }
+
+    let next = self.mdi.pop_front()?;
+    Some(self.send_raw(next))
+  }
+
+  /// Sends a raw line, adding it to the history of the client that sent it.
+  fn send_raw(&mut self, next: MdiLine) -> Command {
+    tracing::info!("sending raw line '{}' (tick {})", next.line, next.tick);
+    self.serial.track(&next.line);
+
+    if let Some(client) = self.connected_clients.get_mut(&next.client) {
+      client.push_history(ClientHistoryEntry::SentCommand(ClientMessage {
+        tick: next.tick,
+        request: ClientMessageRequest::RawSerial(RawSerialRequest {
+          value: next.line.clone(),
+          force: false,
+        }),
+      }));
+    }
+
+    self.send_line(next.line, LineOrigin::Client(next.client, next.tick))
+  }
+
+  /// Sends a line to the controller, holding on to it until the controller has answered it.
+  fn send_line(&mut self, line: String, origin: LineOrigin) -> Command {
+    self.awaiting.push_back(SentLine {
+      line: line.clone(),
+      origin,
+    });
+    Command::Serial(SerialCommand::Raw(line))
+  }
+
+  /// Moves the state of the disconnected client holding the token over to the client with the
+  /// provided id, which keeps its own tick and authority. The history entries from `since` on are
+  /// sent to it with the next payload.
+  fn resume_client(&mut self, id: &str, request: &ResumeSessionRequest) -> Result<(), catalog::Failure> {
+    let previous = self
+      .connected_clients
+      .iter()
+      .find(|(_, client)| client.detached_at.is_some() && client.token == request.token)
+      .map(|(previous, _)| previous.clone())
+      .ok_or(catalog::Code::SessionNotResumable)?;
+
+    let mut resumed = self
+      .connected_clients
+      .remove(&previous)
+      .ok_or(catalog::Code::SessionNotResumable)?;
+
+    if let Some(current) = self.connected_clients.remove(id) {
+      resumed.tick = current.tick;
+      resumed.authority = current.authority;
+      resumed.can_operate = current.can_operate;
+    }
+
+    tracing::info!("client {id} resumed the session of {previous}");
+    resumed.detached_at = None;
+    resumed.delivered = request.since.clamp(resumed.history_start, resumed.history_end());
+    resumed.delivered_hash = None;
+    resumed.set_controller(id, self.controller());
+    resumed.invalidate();
+    self.connected_clients.insert(id.to_string(), resumed);
+    Ok(())
+  }
+
+  /// Returns the status query for the controller when one is due; see `KeepAliveConfiguration`.
+  fn poll(&mut self, now: std::time::Instant) -> Option<Command> {
+    let status = match &self.serial.connection {
+      SerialConnectionState::Idle(status) => status,
+      // Dry runs started without a connection have nothing to ask.
+      SerialConnectionState::SendingFile(queue, status)
+        if queue.dry_run.as_ref().map_or(true, |dry_run| dry_run.connected) =>
+      {
+        status
+      }
+      _ => return None,
+    };
+
+    // There is little reason to ask at all when nobody is around to see the result.
+    let unattended = self
+      .connected_clients
+      .values()
+      .all(|client| client.detached_at.is_some());
+    if self.keep_alive.pause_without_clients && unattended {
+      return None;
+    }
 
-      match serde_json::to_string(&ResponseKinds::State(client)) {
-        Ok(payload) => {
-          command_list.push(Command::Http(effects::http::Command::SendState(id.clone(), payload)));
+    let interval = self.keep_alive.interval(status.map(|(state, _)| state))?;
+
+    if self
+      .serial
+      .last_poll
+      .is_some_and(|last| now.duration_since(last) < interval)
+    {
+      return None;
+    }
+
+    tracing::debug!("asking controller for status");
+    self.serial.last_poll = Some(now);
+    Some(Command::Serial(SerialCommand::Status(self.serial.flavor())))
+  }
+
+  /// Returns the id of the client holding the control lock, if any.
+  fn controller(&self) -> Option<&String> {
+    self.control.as_ref().map(|lock| &lock.holder)
+  }
+
+  /// If we are in the middle of sending a file, this will stop doing so and add it to our job
+  /// history with the provided outcome. Returns the notification of the job having finished.
+  fn finish_job(&mut self, outcome: crate::jobs::JobOutcome) -> Option<Command> {
+    if !matches!(self.serial.connection, SerialConnectionState::SendingFile(_, _)) {
+      return None;
+    }
+
+    let mut notification = None;
+
+    // Dry runs leave the connection the way they found it.
+    let after = match &self.serial.connection {
+      SerialConnectionState::SendingFile(
+        FileQueue {
+          dry_run: Some(dry_run), ..
+        },
+        _,
+      ) if !dry_run.connected => SerialConnectionState::Disconnected,
+      connection => SerialConnectionState::Idle(connection.status()),
+    };
+
+    if let SerialConnectionState::SendingFile(queue, _) = std::mem::replace(&mut self.serial.connection, after) {
+      tracing::info!("job finished ({outcome}) after {} lines", queue.sent.len());
+      for marker in &queue.markers {
+        tracing::info!(
+          "job marker '{}' at line {:?} ({:?})",
+          marker.label,
+          marker.line,
+          marker.position
+        );
+      }
+
+      // Nothing was actually cut during a dry run; it is not worth remembering as a job.
+      if queue.dry_run.is_none() {
+        // Jobs that failed (e.g the controller alarmed, or went away) can be resumed where they were.
+        let position = self.serial.connection.status().map(|(_, position)| position.axes());
+        self.interrupted = match outcome {
+          crate::jobs::JobOutcome::Failed => Some(queue.progress(position)),
+          _ => None,
+        };
+
+        let record = queue.record(outcome);
+        for client in self.connected_clients.values_mut() {
+          client.set_last_job(Some(record.clone()));
         }
+
+        notification = Some(Command::Notify(effects::notify::Event::JobFinished {
+          job: record.clone(),
+        }));
+        let finished_at = record.finished_at;
+        self.jobs.record(record);
+        self.pruner.set_job_file(Some(finished_at));
+        self.last_job = Some(LastJob {
+          source: queue.source,
+          metadata: queue.metadata,
+          finished_at,
+        });
+      }
+    }
+
+    self.sync_upcoming();
+    self.sync_paused();
+    self.sync_job();
+    notification
+  }
+
+  /// Restarts the most recent job at a line of its file (where the first line is `1`), sending the
+  /// lines that restore the modal state of the controller first. Unless confirmed, nothing is sent;
+  /// the command sending a preview of those lines to the requesting client is returned instead.
+  fn run_from_line(&mut self, id: &str, line: usize, confirm: bool) -> Result<Option<Command>, catalog::Failure> {
+    let job = match self.last_job.as_ref() {
+      _ if matches!(self.serial.connection, SerialConnectionState::SendingFile(_, _)) => {
+        return Err(catalog::Code::JobActive.into());
+      }
+      None => return Err(catalog::Code::NoLastJob.into()),
+      Some(job) if line == 0 || line > job.source.len() => {
+        let detail = catalog::Failure::new(catalog::Code::LineOutOfRange)
+          .with("line", line)
+          .with("total", job.source.len());
+        return Err(detail);
+      }
+      Some(job) => job,
+    };
+
+    let start = line - 1;
+    let (preamble, warnings) = grbl::modal::ModalState::replay(&job.source[..start]).preamble();
+
+    if !confirm {
+      let preview = ResponseKinds::RunFromLine(RunFromLinePreview {
+        line,
+        total_lines: job.source.len(),
+        preamble,
+        warnings,
+        upcoming: &job.source[start..job.source.len().min(start + UPCOMING_LINES)],
+      });
+
+      return match serde_json::to_string(&preview) {
+        Ok(payload) => Ok(Some(Command::Http(effects::http::Command::SendState(
+          id.to_string(),
+          payload,
+        )))),
+        Err(error) => {
+          tracing::warn!("unable to serialize run from line preview - {error}");
+          Ok(None)
+        }
+      };
+    }
+
+    if !self.serial.available() {
+      return Err(catalog::Code::SerialNotIdle.into());
+    }
+
+    tracing::info!("running the last job from line {line}");
+    let mut queue = FileQueue::from_lines(
+      preamble
+        .iter()
+        .cloned()
+        .chain(job.source[start..].iter().cloned())
+        .collect(),
+    );
+    queue.source = job.source.clone();
+    queue.metadata = job.metadata.clone();
+    queue.skipped = start;
+    queue.preamble = preamble.len();
+    queue.wrap(self.serial.last_config.as_ref());
+    self.serial.connection = SerialConnectionState::SendingFile(queue, None);
+    Ok(None)
+  }
+
+  /// Applies the parts of our configuration that are held by the application itself. This happens
+  /// during `init`, and again whenever the configuration changes.
+  fn configure(&mut self, config: &Configuration) {
+    let broadcast_interval = config.timing.as_ref().map(|t| t.broadcast_interval).unwrap_or(2);
+    tracing::info!("configured using broadcast interval - {broadcast_interval}s");
+    self.broadcast_interval = std::time::Duration::from_secs(broadcast_interval);
+    let (tick, job_tick) = config
+      .timing
+      .as_ref()
+      .map(|t| (t.tick_interval_ms, t.job_tick_interval_ms))
+      .unwrap_or((default_tick_interval(), default_job_tick_interval()));
+    tracing::info!("configured using tick intervals - {tick}ms ({job_tick}ms while sending a file)");
+    self.tick_intervals = (
+      std::time::Duration::from_millis(tick),
+      std::time::Duration::from_millis(job_tick),
+    );
+    self.keep_alive = config.keep_alive.clone().unwrap_or_default();
+    self.retention = config.retention.clone();
+    self.pruner.configure(config.retention.clone());
+    let control = config.control.clone().unwrap_or_default();
+    self.control_timeout = control.idle_timeout.map(std::time::Duration::from_secs);
+    self.raw_during_job = control.raw_during_job;
+    self.snapshot = config.snapshot.clone();
+    self.max_upload_size = config.http.max_upload_size();
+  }
+
+  /// Restores the state held in a snapshot written before we last stopped.
+  fn restore(&mut self, snapshot: crate::snapshot::Snapshot) {
+    tracing::info!("restoring snapshot taken at {}", snapshot.taken_at);
+    // Snapshots taken before job files were timestamped are as old as the snapshot itself.
+    self.last_job = snapshot.job.map(|job| LastJob {
+      source: job.source,
+      metadata: job.metadata,
+      finished_at: job.finished_at.unwrap_or(snapshot.taken_at),
+    });
+    self
+      .pruner
+      .set_job_file(self.last_job.as_ref().map(|job| job.finished_at));
+
+    if let Some(progress) = snapshot.progress.as_ref() {
+      tracing::warn!(
+        "job {:?} was interrupted at line {} of {} (position {:?})",
+        progress.name,
+        progress.line,
+        progress.total,
+        progress.position,
+      );
+    }
+
+    self.interrupted = snapshot.progress;
+    self.serial.last_config = snapshot.serial;
+  }
+
+  /// Writes the state we restore on startup to disk, if we have been configured to.
+  fn write_snapshot(&mut self) {
+    let path = match self.snapshot.as_ref() {
+      Some(config) => std::path::PathBuf::from(&config.path),
+      None => return,
+    };
+
+    let position = self.serial.connection.status().map(|(_, position)| position.axes());
+
+    let (job, progress) = match &self.serial.connection {
+      SerialConnectionState::SendingFile(queue, _) if queue.dry_run.is_none() => {
+        self.interrupted = None;
+        let file = crate::snapshot::JobFile {
+          source: queue.source.clone(),
+          metadata: queue.metadata.clone(),
+          finished_at: None,
+        };
+        (Some(file), Some(queue.progress(position)))
+      }
+      _ => {
+        let file = self.last_job.as_ref().map(|job| crate::snapshot::JobFile {
+          source: job.source.clone(),
+          metadata: job.metadata.clone(),
+          finished_at: Some(job.finished_at),
+        });
+        (file, self.interrupted.clone())
+      }
+    };
+
+    let snapshot = crate::snapshot::Snapshot {
+      taken_at: chrono::Utc::now(),
+      serial: self.serial.last_config.clone(),
+      position,
+      job,
+      progress,
+    };
+
+    if let Err(error) = crate::snapshot::write(&path, &snapshot) {
+      tracing::warn!("unable to write snapshot to '{}' - {error}", path.display());
+    }
+  }
+
+  /// Applies a configuration that has changed while we are running. A different serial device is
+  /// only connected to while no job is being sent; reloading once the job is done will pick it up.
+  fn reconfigure(&mut self, config: Configuration) -> Vec<Command> {
+    self.configure(&config);
+    let mut cmds = vec![Command::Http(effects::http::Command::Reconfigure(Box::new(
+      config.http,
+    )))];
+
+    let serial = match config.serial {
+      Some(serial) if self.serial.last_config.as_ref() != Some(&serial) => serial,
+      _ => return cmds,
+    };
+
+    if matches!(self.serial.connection, SerialConnectionState::SendingFile(_, _)) {
+      tracing::warn!("serial configuration changed while sending a job, not applying it yet");
+      return cmds;
+    }
+
+    tracing::info!("serial configuration changed, reconnecting");
+    cmds.push(Command::Serial(SerialCommand::Configure(serial.clone())));
+    self.serial.last_config = Some(serial);
+    self.serial.connection = SerialConnectionState::PendingAttempt;
+
+    for client in self.connected_clients.values_mut() {
+      client.last_config = self.serial.last_config.clone();
+      client.invalidate();
+    }
+
+    cmds
+  }
+
+  /// Reports the state of the serial connection and the current job to our health checks.
+  fn report_health(&self) {
+    let serial = match &self.serial.connection {
+      SerialConnectionState::SendingFile(
+        FileQueue {
+          dry_run: Some(dry_run), ..
+        },
+        _,
+      ) => match dry_run.connected {
+        true => crate::health::SerialHealth::Idle,
+        false => crate::health::SerialHealth::Disconnected,
+      },
+      SerialConnectionState::Disconnected => crate::health::SerialHealth::Disconnected,
+      SerialConnectionState::PendingAttempt => crate::health::SerialHealth::Connecting,
+      SerialConnectionState::Idle(_) => crate::health::SerialHealth::Idle,
+      SerialConnectionState::SendingFile(_, _) => crate::health::SerialHealth::Sending,
+    };
+
+    let job = match &self.serial.connection {
+      SerialConnectionState::SendingFile(queue, _) if queue.dry_run.is_none() => Some(crate::health::JobHealth {
+        sent: queue.sent.len(),
+        total: queue.sent.len() + queue.pending.len(),
+        started_at: queue.started_at,
+      }),
+      _ => None,
+    };
+
+    self.health.report(crate::health::ApplicationHealth { serial, job });
+  }
+
+  /// Logs a summary of everything we are holding on to.
+  fn dump_state(&self) {
+    tracing::info!("serial connection: {:?}", self.serial.connection);
+    tracing::info!("serial configuration: {:?}", self.serial.last_config);
+    tracing::info!("{} chat messages, {} macros", self.chat.len(), self.macros.all().len());
+
+    for (id, client) in &self.connected_clients {
+      tracing::info!(
+        "client {id}: tick {}, {} history entries ({} delivered)",
+        client.tick,
+        client.history.len(),
+        client.delivered.saturating_sub(client.history_start),
+      );
+    }
+  }
+
+  /// There are a few times where we will want to append to a list of commands a "state refresh"
+  /// command for every client that is connected:
+  ///
+  /// 1. disconnect
+  /// 2. connect
+  /// 3. pending connect
+  /// 4. etc...
+  ///
+  /// Clients whose state has not changed since the last payload they were sent are skipped. When
+  /// every client is due the exact same payload, a single broadcast is sent instead.
+  #[inline]
+  fn add_statuses(&mut self, command_list: &mut Vec<Command>) {
+    // Clients waiting to be resumed are kept up to date, but are not sent anything.
+    let connected = self
+      .connected_clients
+      .values()
+      .filter(|client| client.detached_at.is_none())
+      .count();
+    let mut payloads = std::collections::HashMap::<String, Vec<&String>>::new();
+
+    for (id, client) in &mut self.connected_clients {
+      client.set_serial_available(self.serial.available());
+
+      if client.detached_at.is_some() {
+        continue;
+      }
+
+      match client.payload() {
+        Ok(Some(payload)) => payloads.entry(payload).or_default().push(id),
+        Ok(None) => (),
         Err(error) => {
           tracing::warn!("uanble to serialize client state - {error}");
         }
       }
     }
+
+    for (payload, ids) in payloads {
+      if ids.len() > 1 && ids.len() == connected {
+        command_list.push(Command::Http(effects::http::Command::Broadcast(payload)));
+        continue;
+      }
+
+      for id in ids {
+        command_list.push(Command::Http(effects::http::Command::SendState(
+          id.clone(),
+          payload.clone(),
+        )));
+      }
+    }
   }
 }
 
@@ -283,75 +2211,201 @@ impl crate::eff::Application for Application {
   type Command = Command;
   type Flags = Configuration;
 
-  fn init(self, flags: Self::Flags) -> (Self, Option<Vec<Self::Command>>) {
-    if let Some(config) = flags.serial {
+  fn init(&mut self, flags: Self::Flags) -> Option<Vec<Self::Command>> {
+    self.configure(&flags);
+
+    let restored = flags
+      .snapshot
+      .as_ref()
+      .map(|config| crate::snapshot::load(&config.path));
+    match restored {
+      Some(Ok(Some(snapshot))) => self.restore(snapshot),
+      Some(Ok(None)) | None => (),
+      Some(Err(error)) => tracing::warn!("unable to restore snapshot - {error}"),
+    }
+
+    // The serial configuration from our file takes precedence over the one we were last using.
+    if let Some(config) = flags.serial.or_else(|| self.serial.last_config.take()) {
       let config_cmd = Command::Serial(SerialCommand::Configure(config.clone()));
-      let mut next = self;
-      next.serial = DerivedSerialState {
+      self.serial = DerivedSerialState {
         last_config: Some(config),
-        connection: SerialConnectionState::default(),
+        ..DerivedSerialState::default()
       };
       tracing::info!("sending initial serial configuration");
-      return (next, Some(vec![config_cmd]));
+      return Some(vec![config_cmd]);
     }
 
-    (self, None)
+    None
   }
 
-  fn update(self, message: Self::Message) -> (Self, Option<Vec<Self::Command>>) {
-    let mut next = self;
+  fn subscriptions(&self) -> Vec<crate::eff::Subscription<Self::Message>> {
+    // The serial ticks are actually the maxiumum frequency that _we_ will be sending commands to
+    // the serial connection. The serial effect manager is responsible for inbound traffic from the
+    // connection. While a file is being sent, we tick faster so the controller is not left waiting.
+    let tick = match self.serial.connection {
+      SerialConnectionState::SendingFile(..) => self.tick_intervals.1,
+      _ => self.tick_intervals.0,
+    };
+    let mut subscriptions = vec![];
+
+    // Without a controller or anybody connected, there is nothing for a tick to send, expire or
+    // release; rather than waking up for nothing, the ticker is paused until one of them shows up.
+    let idle =
+      self.connected_clients.is_empty() && matches!(self.serial.connection, SerialConnectionState::Disconnected);
+    if !idle {
+      subscriptions.push(crate::eff::Subscription::Interval("serial-tick", tick, || {
+        Message::Tick
+      }));
+    }
+
+    // The broadcast ticks are used to determine when to publish events to our websockets. Without
+    // anybody to publish to, the ticker is paused until somebody connects.
+    if !self.connected_clients.is_empty() {
+      subscriptions.push(crate::eff::Subscription::Interval(
+        "broadcast",
+        self.broadcast_interval,
+        || Message::Broadcast,
+      ));
+    }
+
+    if let Some(retention) = self.retention.as_ref() {
+      let interval = std::time::Duration::from_secs(retention.interval);
+      subscriptions.push(crate::eff::Subscription::Interval("retention", interval, || {
+        Message::Prune
+      }));
+    }
+
+    if let Some(snapshot) = self.snapshot.as_ref() {
+      let interval = std::time::Duration::from_secs(snapshot.interval);
+      subscriptions.push(crate::eff::Subscription::Interval("snapshot", interval, || {
+        Message::Snapshot
+      }));
+    }
+
+    subscriptions
+  }
+
+  fn update(&mut self, message: Self::Message) -> Option<Vec<Self::Command>> {
+    match message {
+      kind @ Message::DisconnectedSerial | kind @ Message::ConnectedSerial => {
+        let serial_available = matches!(kind, Message::ConnectedSerial);
+        let mut cmds = vec![];
+
+        // The only job that can be running as we connect is a dry run started without a connection.
+        if serial_available {
+          cmds.extend(self.finish_job(crate::jobs::JobOutcome::Cancelled));
+        }
+
+        if !serial_available {
+          // Failed attempts to connect are not worth waking anybody up over; losing a connection is.
+          let connected = matches!(
+            self.serial.connection,
+            SerialConnectionState::Idle(_) | SerialConnectionState::SendingFile(_, _)
+          );
+          cmds.extend(self.finish_job(crate::jobs::JobOutcome::Failed));
+
+          if connected {
+            cmds.push(Command::Notify(effects::notify::Event::SerialDisconnected));
+          }
+
+          self.sync_overrides(None);
+          self.sync_firmware(None);
+          self.probing = None;
+          self.serial.work_offset = None;
+          self.serial.work_position = false;
+          self.serial.relative = false;
+          self.serial.last_poll = None;
+          self.serial.settings.clear();
+
+          let held = self.queued_raw.len() + self.mdi.len();
+          if held > 0 {
+            tracing::warn!("dropping {held} held raw lines");
+            self.queued_raw.clear();
+            self.mdi.clear();
+          }
 
-    match message {
-      kind @ Message::DisconnectedSerial | kind @ Message::ConnectedSerial => {
-        let serial_available = matches!(kind, Message::ConnectedSerial);
+          self.awaiting.clear();
+        }
 
         // Store the state on the application state itself. This will be used as new clients
         // connect so they have a fresh connection value without having to rely on these messages
         // being received.
-        next.serial.connection = if serial_available {
+        self.serial.connection = if serial_available {
           tracing::info!("serial connection available + idle");
-          SerialConnectionState::Idle(None, None)
+          SerialConnectionState::Idle(None)
         } else {
           tracing::warn!("serial connection disconnect");
           SerialConnectionState::Disconnected
         };
 
-        // If we have no clients to also update, we're done.
-        if next.connected_clients.is_empty() {
-          return (next, None);
+        // Our ticks (which normally report our health) may be paused from here on.
+        self.report_health();
+
+        // Every time we establish a connection, send along whatever startup sequence was configured
+        // for the device; these are recorded in client history like any other line we send.
+        let startup = self
+          .serial
+          .last_config
+          .as_ref()
+          .filter(|_| serial_available)
+          .map(|config| config.on_connect().to_vec())
+          .unwrap_or_default();
+
+        for line in startup {
+          tracing::info!("sending startup line '{line}'");
+          self.serial.track(&line);
+          for client in self.connected_clients.values_mut() {
+            client.record_sent(&line);
+          }
+
+          cmds.push(self.send_line(line, LineOrigin::Internal));
         }
 
-        let mut cmds = vec![];
-        next.add_statuses(&mut cmds);
-        return (next, Some(cmds));
+        self.add_statuses(&mut cmds);
+        return Some(cmds);
       }
 
-      Message::Http(effects::http::Message::FileUpload(file_contents)) => {
-        if !next.serial.available() {
+      Message::Http(effects::http::Message::FileUpload(file_contents, metadata)) => {
+        if !self.serial.available() {
           tracing::warn!("was not ready to handle a file upload");
-          return (next, None);
+          return None;
         }
 
         tracing::info!("has uploaded file ({file_contents:?})");
-        let queue = FileQueue::from_str(&file_contents);
-        next.serial.connection = SerialConnectionState::SendingFile(queue, None);
-        return (next, None);
+        let mut queue = FileQueue::from_str(&file_contents);
+        queue.metadata = metadata;
+        queue.wrap(self.serial.last_config.as_ref());
+        self.serial.connection = SerialConnectionState::SendingFile(queue, None);
+        return None;
       }
 
       Message::Http(effects::http::Message::ClientDisconnected(id)) => {
         tracing::debug!("client {id} disconnected");
-        next.connected_clients.remove(&id);
+
+        if let Some(client) = self.connected_clients.get_mut(&id) {
+          client.detached_at = Some(std::time::Instant::now());
+        }
+
+        // Nobody is promoted automatically; another client needs to explicitly take control.
+        if self.controller() == Some(&id) {
+          tracing::info!("operator client {id} disconnected, releasing control");
+          self.set_controller(None);
+
+          let mut cmds = vec![];
+          self.add_statuses(&mut cmds);
+          return Some(cmds);
+        }
       }
 
       // When a client sends us data, we receive it as a raw string and are left to determine what
       // to do with it ourselves.
       Message::Http(effects::http::Message::ClientData(id, data)) => {
-        let maybe_client = next.connected_clients.get_mut(&id);
+        let maybe_client = self.connected_clients.get_mut(&id);
 
         if maybe_client.is_none() {
           tracing::warn!("unable to find client to associate with received data");
 
-          return (next, None);
+          return None;
         }
 
         // Now that we have proven this is a valid request, we know we're going to be creating some
@@ -364,24 +2418,19 @@ impl crate::eff::Application for Application {
             tracing::warn!("unable to parse client data - {error}");
 
             // Create the response that we'll send back to the client.
-            let response = &ResponseKinds::Response(ClientResponse {
-              tick: 0,
-              status: "failed".into(),
-            });
+            let failure = catalog::Failure::new(catalog::Code::ParseError).with("error", error);
+            let response = &ResponseKinds::Response(ClientResponse::failed(0, failure));
 
             // Immediately return a command that will let our client know we have received their
             // request.
             match serde_json::to_string(&response) {
               Ok(res) => {
-                return (
-                  next,
-                  Some(vec![Command::Http(effects::http::Command::SendState(id.clone(), res))]),
-                );
+                return Some(vec![Command::Http(effects::http::Command::SendState(id.clone(), res))]);
               }
               Err(error) => tracing::warn!("unable to serialize error response! - {error}"),
             }
 
-            return (next, None);
+            return None;
           }
           Ok(p) => p,
         };
@@ -390,21 +2439,80 @@ impl crate::eff::Application for Application {
 
         // Immediately update the tick on our client; any state messages published from now on
         // should reflect that we are in sync.
-        connected_client.tick = new_tick;
+        if connected_client.tick != new_tick {
+          connected_client.tick = new_tick;
+          connected_client.invalidate();
+        }
 
         let mut cmds = vec![];
+        let mut failure = None;
         let mut update_configs = false;
+        let mut refresh_all = true;
+        let mut release_mdi = false;
 
         // Update the "tick" that we're using based on the message provided
         tracing::debug!("has parsed client data - {parsed:?} (tick: {new_tick})");
 
+        let held_elsewhere = self.control.as_ref().is_some_and(|lock| lock.holder != id);
+        let forbidden = match &parsed.request {
+          ClientMessageRequest::TakeOver | ClientMessageRequest::AcquireControl if !connected_client.can_operate => {
+            Some(catalog::Failure::new(catalog::Code::ObserverCannotOperate))
+          }
+          request if !connected_client.allows(request.required_authority()) => Some(
+            catalog::Failure::new(catalog::Code::InsufficientAuthority).with("authority", request.required_authority()),
+          ),
+          ClientMessageRequest::TakeOver if self.terminal.is_some() => {
+            Some(catalog::Failure::new(catalog::Code::ControlHeldByTerminal))
+          }
+          ClientMessageRequest::AcquireControl if held_elsewhere => {
+            Some(catalog::Failure::new(catalog::Code::ControlHeldElsewhere))
+          }
+          request if request.is_control() && connected_client.role != ClientRole::Operator => {
+            Some(catalog::Failure::new(catalog::Code::NotOperator))
+          }
+          _ => None,
+        };
+
+        if let Some(failure) = forbidden {
+          tracing::warn!("refusing request from client '{id}' - {}", failure.code.as_str());
+          let response = &ResponseKinds::Response(ClientResponse::failed(new_tick, failure));
+
+          return match serde_json::to_string(&response) {
+            Ok(res) => Some(vec![Command::Http(effects::http::Command::SendState(id.clone(), res))]),
+            Err(error) => {
+              tracing::warn!("unable to serialize forbidden response - {error}");
+              None
+            }
+          };
+        }
+
+        // Anything the operator sends to the machine keeps their lock from going idle.
+        if parsed.request.is_control() {
+          if let Some(lock) = self.control.as_mut() {
+            lock.last_active = std::time::Instant::now();
+          }
+        }
+
         match &parsed.request {
+          ClientMessageRequest::TakeOver | ClientMessageRequest::AcquireControl => {
+            tracing::info!("client {id} is taking control");
+            self.set_controller(Some(id.clone()));
+          }
+
+          ClientMessageRequest::ReleaseControl => {
+            if self.controller() == Some(&id) {
+              tracing::info!("client {id} released control");
+              self.set_controller(None);
+            }
+          }
+
           ClientMessageRequest::Configuration(configuration) => {
             // Create an attempt to configure our serial connection and make note of it on our
             // internal, mutable state.
+            cmds.extend(self.finish_job(crate::jobs::JobOutcome::Cancelled));
             cmds.push(Command::Serial(SerialCommand::Configure(configuration.clone())));
-            next.serial.last_config = Some(configuration.clone());
-            next.serial.connection = SerialConnectionState::PendingAttempt;
+            self.serial.last_config = Some(configuration.clone());
+            self.serial.connection = SerialConnectionState::PendingAttempt;
             update_configs = true;
           }
 
@@ -413,22 +2521,425 @@ impl crate::eff::Application for Application {
             cmds.push(Command::Serial(SerialCommand::Control(true)));
           }
 
+          ClientMessageRequest::FeedHold => {
+            tracing::info!("client has requested a feed hold");
+            cmds.push(Command::Serial(SerialCommand::Realtime(grbl::Command::FeedHold)));
+          }
+
+          ClientMessageRequest::CycleStart => {
+            tracing::info!("client has requested a cycle start");
+            cmds.push(Command::Serial(SerialCommand::Realtime(grbl::Command::CycleStart)));
+          }
+
+          ClientMessageRequest::Override(adjustment) => {
+            let command = adjustment.command(self.overrides);
+            tracing::info!("client has requested an override adjustment ({adjustment:?}) - {command}");
+            cmds.push(Command::Serial(SerialCommand::Realtime(command)));
+          }
+
           ClientMessageRequest::CloseSerial => {
             tracing::info!("client has requested to close the serial connection");
+            cmds.extend(self.finish_job(crate::jobs::JobOutcome::Cancelled));
             cmds.push(Command::Serial(SerialCommand::Control(false)));
           }
 
           ClientMessageRequest::RawSerial(inner) => {
-            cmds.push(Command::Serial(SerialCommand::Raw(inner.value.clone())));
-            // Add this interaction to our history
-            connected_client.history.push(ClientHistoryEntry::SentCommand(parsed));
+            let job_active = matches!(self.serial.connection, SerialConnectionState::SendingFile(_, _));
+            let line = MdiLine {
+              client: id.clone(),
+              tick: new_tick,
+              line: inner.value.clone(),
+            };
+
+            match (
+              self.serial.check_limits(&inner.value),
+              job_active,
+              inner.force,
+              self.raw_during_job,
+            ) {
+              (Err(detail), _, _, _) => failure = Some(detail),
+              // Forced lines go out right away; their answers are told apart from those of the job
+              // by the order they were sent in.
+              (Ok(()), true, true, _) => {
+                tracing::info!("forcing raw line '{}' in between the lines of the job", inner.value);
+                cmds.push(self.send_raw(line));
+              }
+              (Ok(()), false, _, _) if !self.serial.available() => {
+                failure = Some(catalog::Code::SerialUnavailable.into());
+              }
+              (Ok(()), false, _, _) => {
+                self.mdi.push_back(line);
+                release_mdi = true;
+              }
+              (Ok(()), true, false, RawDuringJob::Queue) => {
+                tracing::info!("holding raw line '{}' until the current job is done", inner.value);
+                self.queued_raw.push(line);
+              }
+              (Ok(()), true, false, RawDuringJob::Reject) => {
+                tracing::warn!("refusing raw line '{}' while a job is active", inner.value);
+                failure = Some(catalog::Code::RawDuringJob.into());
+              }
+            }
+          }
+
+          ClientMessageRequest::ResyncState(resync) => {
+            tracing::info!("client has requested a state resync");
+            connected_client.set_serial_available(self.serial.available());
+
+            match connected_client.snapshot(resync.history) {
+              Ok(payload) => cmds.push(Command::Http(effects::http::Command::SendState(id.clone(), payload))),
+              Err(error) => tracing::warn!("unable to serialize client snapshot - {error}"),
+            }
+
+            // The snapshot is all this client needs; nothing has changed for anyone else.
+            refresh_all = false;
+          }
+
+          ClientMessageRequest::MarkEvent(mark) => {
+            let position = self.serial.connection.status().map(|(_, position)| position);
+            let mut marker = JobMarker {
+              label: mark.label.clone(),
+              line: None,
+              position,
+              marked_at: chrono::Utc::now(),
+            };
+
+            // Markers made while a file is being sent are kept with that job, along with the line we
+            // had most recently sent.
+            if let SerialConnectionState::SendingFile(queue, _) = &mut self.serial.connection {
+              marker.line = Some(queue.sent.len());
+              queue.markers.push(marker.clone());
+            }
+
+            tracing::info!("recorded marker {marker:?}");
+            for client in self.connected_clients.values_mut() {
+              client.push_history(ClientHistoryEntry::Marker(marker.clone()));
+            }
+          }
+
+          ClientMessageRequest::HistoryPage(page) => {
+            let limit = page.limit.min(MAX_HISTORY_PAGE);
+
+            match connected_client.history_page(page.before, limit) {
+              Ok(payload) => cmds.push(Command::Http(effects::http::Command::SendState(id.clone(), payload))),
+              Err(error) => tracing::warn!("unable to serialize history page - {error}"),
+            }
+
+            // Nothing has changed for anyone; the page is all this client needs.
+            refresh_all = false;
+          }
+
+          ClientMessageRequest::BeginUpload(begin) => {
+            if begin.size == 0 || begin.size > self.max_upload_size {
+              tracing::warn!("refusing websocket upload of {} bytes", begin.size);
+              failure = Some(catalog::Code::UploadTooLarge.into());
+            } else {
+              tracing::info!("client has begun uploading {} bytes ({:?})", begin.size, begin.metadata);
+              connected_client.begin_upload(Some(PendingUpload {
+                metadata: begin.metadata.clone(),
+                size: begin.size,
+                received: 0,
+                next: 0,
+                content: String::with_capacity(begin.size),
+              }));
+            }
+          }
+
+          ClientMessageRequest::UploadChunk(chunk) => {
+            if let Err(detail) = connected_client.receive_chunk(chunk) {
+              tracing::warn!("refusing upload chunk {} - {detail:?}", chunk.seq);
+              failure = Some(detail);
+            }
+          }
+
+          ClientMessageRequest::FinishUpload => match (&self.serial.connection, connected_client.upload.as_ref()) {
+            (_, None) => failure = Some(catalog::Code::UploadNotStarted.into()),
+            (_, Some(upload)) if upload.received != upload.size => {
+              failure = Some(
+                catalog::Failure::new(catalog::Code::UploadIncomplete)
+                  .with("received", upload.received)
+                  .with("size", upload.size),
+              );
+            }
+            (SerialConnectionState::SendingFile(_, _), _) => failure = Some(catalog::Code::JobActive.into()),
+            (connection, _) if !connection.available() => {
+              failure = Some(catalog::Code::SerialUnavailable.into());
+            }
+            (connection, Some(_)) => {
+              let status = connection.status();
+
+              if let Some(upload) = connected_client.upload.take() {
+                tracing::info!("client has finished uploading {} bytes", upload.received);
+                let mut queue = FileQueue::from_str(&upload.content);
+                queue.metadata = upload.metadata;
+                queue.wrap(self.serial.last_config.as_ref());
+                self.serial.connection = SerialConnectionState::SendingFile(queue, status);
+              }
+
+              connected_client.invalidate();
+            }
+          },
+
+          ClientMessageRequest::ResumeSession(resume) => {
+            if let Err(detail) = self.resume_client(&id, resume) {
+              failure = Some(detail);
+            }
+          }
+
+          ClientMessageRequest::SetPreferences(preferences) => {
+            tracing::info!("client has updated its preferences - {preferences:?}");
+            connected_client.set_preferences(*preferences);
+
+            // Nothing has changed for anyone else.
+            refresh_all = false;
+          }
+
+          ClientMessageRequest::ChatMessage(chat) if chat.content.chars().count() > CHAT_MAX_LENGTH => {
+            tracing::warn!("refusing chat message of {} bytes", chat.content.len());
+            failure = Some(catalog::Code::InvalidRequest.into());
+          }
+
+          ClientMessageRequest::ChatMessage(chat) => {
+            // Messages are attributed to the client that sent them, never to whoever it claims to be.
+            let entry = ChatEntry {
+              author: id.clone(),
+              content: chat.content.clone(),
+              sent_at: chrono::Utc::now(),
+            };
+
+            // Relay the message to everyone, including the sender, so all clients agree on order.
+            match serde_json::to_string(&ResponseKinds::Chat(&entry)) {
+              Ok(payload) => cmds.push(Command::Http(effects::http::Command::Broadcast(payload))),
+              Err(error) => tracing::warn!("unable to serialize chat message - {error}"),
+            }
+
+            if self.chat.len() >= CHAT_CAPACITY {
+              self.chat.pop_front();
+            }
+
+            self.chat.push_back(entry);
+            refresh_all = false;
+          }
+
+          ClientMessageRequest::SaveMacro(save) => {
+            tracing::info!("client has saved macro '{}'", save.name);
+            self.macros.save(save.name.clone(), save.content.clone());
+
+            // Let every client know about the new macro.
+            match serde_json::to_string(&ResponseKinds::Macros(MacrosPayload {
+              macros: self.macros.all(),
+            })) {
+              Ok(payload) => cmds.push(Command::Http(effects::http::Command::Broadcast(payload))),
+              Err(error) => tracing::warn!("unable to serialize macros - {error}"),
+            }
+
+            refresh_all = false;
+          }
+
+          ClientMessageRequest::RunMacro(run) => match self.macros.get(&run.name) {
+            // Macros are sent exactly like uploaded files, which means they can only be started
+            // while the connection is idle.
+            Some(content) if self.serial.available() => {
+              tracing::info!("client has requested to run macro '{}'", run.name);
+              let mut queue = FileQueue::from_str(content);
+              queue.metadata.name = Some(run.name.clone());
+              self.serial.connection = SerialConnectionState::SendingFile(queue, None);
+            }
+            Some(_) => {
+              tracing::warn!("unable to run macro '{}' without an idle connection", run.name);
+              failure = Some(catalog::Code::MacroNotIdle.into());
+            }
+            None => {
+              tracing::warn!("client requested unknown macro '{}'", run.name);
+              failure = Some(catalog::Failure::new(catalog::Code::MacroNotFound).with("name", &run.name));
+            }
+          },
+
+          ClientMessageRequest::StepMode(step_mode) => {
+            if let SerialConnectionState::SendingFile(queue, _) = &mut self.serial.connection {
+              tracing::info!("client has set step mode to {}", step_mode.enabled);
+              queue.steps = step_mode.enabled.then_some(0);
+              self.sync_upcoming();
+            } else {
+              failure = Some(catalog::Code::NoActiveJob.into());
+            }
+          }
+
+          ClientMessageRequest::RunFromLine(run) => match self.run_from_line(&id, run.line, run.confirm) {
+            Ok(preview) => cmds.extend(preview),
+            Err(detail) => failure = Some(detail),
+          },
+
+          ClientMessageRequest::ResumeFromLine(resume) => {
+            let resumed = match self.interrupted.as_ref() {
+              None => Err(catalog::Code::NoInterruptedJob.into()),
+              Some(progress) => {
+                let line = resume.line.unwrap_or(progress.line.max(1));
+                tracing::info!("client has requested to resume the interrupted job at line {line}");
+                self.run_from_line(&id, line, resume.confirm)
+              }
+            };
+
+            match resumed {
+              Ok(preview) => cmds.extend(preview),
+              Err(detail) => failure = Some(detail),
+            }
+          }
+
+          ClientMessageRequest::Jog(jog) => match &self.serial.connection {
+            SerialConnectionState::SendingFile(_, _) => {
+              failure = Some(catalog::Code::JogDuringJob.into());
+            }
+            _ if !self.serial.available() => {
+              failure = Some(catalog::Code::SerialUnavailable.into());
+            }
+            _ => {
+              let mapping = self
+                .serial
+                .last_config
+                .as_ref()
+                .map(|config| config.display())
+                .unwrap_or_default();
+              let line = jog.line(mapping, self.serial.firmware());
+
+              match self.serial.check_limits(&line) {
+                Err(detail) => failure = Some(detail),
+                Ok(()) => {
+                  tracing::info!("client has requested a jog - {line}");
+                  connected_client.record_sent(&line);
+                  cmds.push(self.send_line(line, LineOrigin::Client(id.clone(), new_tick)));
+                }
+              }
+            }
+          },
+
+          ClientMessageRequest::Probe(probe) => match &self.serial.connection {
+            SerialConnectionState::SendingFile(_, _) => {
+              failure = Some(catalog::Code::ProbeDuringJob.into());
+            }
+            _ if !self.serial.available() => {
+              failure = Some(catalog::Code::SerialUnavailable.into());
+            }
+            _ => {
+              let mapping = self
+                .serial
+                .last_config
+                .as_ref()
+                .map(|config| config.display())
+                .unwrap_or_default();
+
+              match probe.lines(mapping) {
+                None => failure = Some(catalog::Code::InvalidRequest.into()),
+                Some((lines, offset)) => {
+                  tracing::info!("client has requested a probe - {lines:?} (offset {offset:?})");
+                  for line in &lines {
+                    self.serial.track(line);
+                    connected_client.record_sent(line);
+                  }
+                  for line in lines {
+                    cmds.push(self.send_line(line, LineOrigin::Client(id.clone(), new_tick)));
+                  }
+                  self.probing = Some(PendingProbe { offset });
+                }
+              }
+            }
+          },
+
+          ClientMessageRequest::StartJob(start) => match &self.serial.connection {
+            SerialConnectionState::SendingFile(_, _) => {
+              failure = Some(catalog::Code::JobActive.into());
+            }
+            _ if !self.serial.available() && !start.dry_run => {
+              failure = Some(catalog::Code::SerialUnavailable.into());
+            }
+            connection => {
+              tracing::info!(
+                "client has started a job ({} bytes, dry run: {}, transform: {:?})",
+                start.content.len(),
+                start.dry_run,
+                start.transform
+              );
+              let lines = start.content.lines().map(String::from).collect::<Vec<String>>();
+              let lines = match start.transform.as_ref() {
+                Some(transform) => transform.apply(&lines),
+                None => Ok(lines),
+              };
+
+              match lines {
+                Err(error) => {
+                  tracing::warn!("unable to transform job - {error}");
+                  failure = Some(catalog::Failure::new(catalog::Code::TransformFailed).with("error", error));
+                }
+                Ok(lines) => {
+                  let mut queue = FileQueue::from_lines(lines);
+                  queue.metadata = start.metadata.clone();
+                  queue.dry_run = start.dry_run.then(|| DryRun::new(connection.available()));
+
+                  if !start.skip_sequences {
+                    queue.wrap(self.serial.last_config.as_ref());
+                  }
+
+                  let status = connection.status();
+                  self.serial.connection = SerialConnectionState::SendingFile(queue, status);
+                }
+              }
+            }
+          },
+
+          ClientMessageRequest::ResumeJob => match &mut self.serial.connection {
+            SerialConnectionState::SendingFile(queue, _) => match queue.paused.take() {
+              Some(pause) => {
+                tracing::info!("client has resumed the job paused at '{}'", pause.line);
+                self.sync_paused();
+              }
+              None => failure = Some(catalog::Code::JobNotPaused.into()),
+            },
+            _ => failure = Some(catalog::Code::NoActiveJob.into()),
+          },
+
+          ClientMessageRequest::ReadSettings => match self.queue_settings(&id, new_tick, "$$".into()) {
+            Ok(()) => release_mdi = true,
+            Err(detail) => failure = Some(detail),
+          },
+
+          ClientMessageRequest::WriteSetting(WriteSettingRequest { value, .. }) if !value.is_finite() => {
+            failure = Some(catalog::Code::InvalidRequest.into());
+          }
+
+          ClientMessageRequest::WriteSetting(write) => {
+            let line = grbl::settings::line(write.id, write.value);
+            match self.queue_settings(&id, new_tick, line) {
+              Ok(()) => release_mdi = true,
+              Err(detail) => failure = Some(detail),
+            }
           }
+
+          ClientMessageRequest::CancelJob => match &self.serial.connection {
+            SerialConnectionState::SendingFile(queue, _) => {
+              tracing::info!("client has cancelled the job");
+
+              // Lines already sent are buffered by the controller; only a reset keeps them from running.
+              if queue.dry_run.is_none() {
+                cmds.push(Command::Serial(SerialCommand::Reset(self.serial.flavor())));
+              }
+
+              cmds.extend(self.finish_job(crate::jobs::JobOutcome::Cancelled));
+            }
+            _ => failure = Some(catalog::Code::NoActiveJob.into()),
+          },
+
+          ClientMessageRequest::Step => match &mut self.serial.connection {
+            SerialConnectionState::SendingFile(FileQueue { steps: Some(steps), .. }, _) => {
+              *steps += 1;
+            }
+            _ => failure = Some(catalog::Code::StepModeOff.into()),
+          },
         };
 
         // Create the response that we'll send back to the client.
-        let response = &ResponseKinds::Response(ClientResponse {
-          tick: new_tick,
-          status: "ok".into(),
+        let response = &ResponseKinds::Response(match failure {
+          Some(failure) => ClientResponse::failed(new_tick, failure),
+          None => ClientResponse::ok(new_tick),
         });
 
         // Immediately return a command that will let our client know we have received their
@@ -440,48 +2951,210 @@ impl crate::eff::Application for Application {
           Err(error) => tracing::warn!("unable to serialize - {error}"),
         }
 
+        // Raw lines go out as soon as the controller has answered the one sent before them.
+        if release_mdi {
+          cmds.extend(self.send_mdi());
+        }
+
         // If this request involved updating our serial config, update clients so the ui may
         // render the latest connection values.
         if update_configs {
-          for mut client in next.connected_clients.values_mut() {
-            client.last_config = next.serial.last_config.clone();
+          for client in self.connected_clients.values_mut() {
+            client.last_config = self.serial.last_config.clone();
+            client.invalidate();
           }
         }
 
         // Now, we _also_ want to send along a fresh set of state updates since we know we're about
         // to be disconnecting from, and attempting to connect to a new serial device.
-        next.add_statuses(&mut cmds);
+        if refresh_all {
+          self.add_statuses(&mut cmds);
+        }
 
-        return (next, Some(cmds));
+        return Some(cmds);
       }
 
       // When clients connect, create an entry for them.
-      Message::Http(effects::http::Message::ClientConnected(id)) => {
+      Message::Http(effects::http::Message::ClientConnected(id, authority)) => {
         tracing::debug!("has new client, updating hash");
         // Populate this new client with the latest connection state available to us.
-        let connected_client = DerivedClientState {
-          serial_available: next.serial.available(),
-          last_config: next.serial.last_config.clone(),
+        let history_limit = self
+          .retention
+          .as_ref()
+          .and_then(|retention| retention.history.as_ref())
+          .and_then(|policy| policy.count());
+        // Operators (and admins) take control when nobody else has it; everyone else starts out
+        // observing.
+        let can_operate = authority.allows(effects::http::Authority::Operator);
+        if can_operate && self.control.is_none() {
+          tracing::info!("client {id} is now the operator");
+          self.set_controller(Some(id.clone()));
+        }
+
+        let mut connected_client = DerivedClientState {
+          can_operate,
+          authority: Some(authority),
+          serial_available: self.serial.available(),
+          last_config: self.serial.last_config.clone(),
+          history_limit,
+          token: uuid::Uuid::new_v4().simple().to_string(),
           ..DerivedClientState::default()
         };
+        connected_client.set_controller(&id, self.controller());
+        if let SerialConnectionState::SendingFile(queue, _) = &self.serial.connection {
+          connected_client.set_upcoming(queue.upcoming());
+          connected_client.set_paused(queue.paused.as_ref());
+          connected_client.set_job(Some(&queue.progress(None)));
+        }
+        connected_client.set_position(self.display_position());
+        connected_client.set_last_job(self.jobs.last());
+        connected_client.set_overrides(self.overrides);
+        connected_client.set_firmware(self.serial.firmware.as_ref());
+        connected_client.set_probe(self.last_probe);
+
+        let hello = ResponseKinds::Hello(Hello {
+          protocol_version: PROTOCOL_VERSION,
+          server_version: option_env!("COSTANZA_VERSION").unwrap_or("dev"),
+          capabilities: CAPABILITIES,
+          encodings: effects::http::ENCODINGS,
+          profile: self.serial.last_config.as_ref(),
+          firmware: self.serial.firmware.as_ref(),
+          state: connected_client.view(0, false),
+          chat: &self.chat,
+          macros: self.macros.all(),
+          settings: grbl::settings::describe(&self.serial.settings),
+          interrupted: self.interrupted.as_ref(),
+          resume_token: &connected_client.token,
+        });
+
+        // Immediately send the hello frame along to our new client.
+        let cmds = match serde_json::to_string(&hello) {
+          Ok(payload) => vec![Command::Http(effects::http::Command::SendState(id.clone(), payload))],
+          Err(error) => {
+            tracing::warn!("unable to serialize hello frame - {error}");
+            vec![]
+          }
+        };
 
-        next.connected_clients.insert(id, connected_client);
+        self.connected_clients.insert(id, connected_client);
+        return Some(cmds);
       }
 
       Message::Serial(data) => {
         tracing::debug!("has serial data - {data}");
+        let mut cmds = vec![];
+        let mut reply = None;
+        let mut freed = false;
 
-        match data.parse::<grbl::Response>() {
+        // Controllers announce what they are running every time they reset, which is not a response
+        // to anything we have sent.
+        if let Some(detected) = firmware::detect(&data) {
+          tracing::info!("controller is running {} {}", detected.name, detected.version);
+          self.sync_firmware(Some(detected));
+
+          // Whatever the controller had not yet answered was discarded along with the rest of its
+          // buffers.
+          self.awaiting.clear();
+        }
+
+        let response = self.serial.firmware().parse(&data);
+
+        match &response {
           Ok(inner) => {
-            if let SerialConnectionState::SendingFile(queue, _) = &mut next.serial.connection {
-              queue.update(&inner);
+            // The controller answers lines in the order they were sent, so every answer is for the
+            // oldest line still waiting on one.
+            let answer = matches!(inner, grbl::Response::Ok | grbl::Response::Error(_));
+            let answered = answer.then(|| self.awaiting.pop_front()).flatten();
+            if answer && answered.is_none() {
+              tracing::warn!("controller answered a line we have no record of sending - '{data}'");
+            }
+            freed = answered.is_some();
+
+            // The controller does not repeat a setting back once it has accepted a change to it.
+            let written = answered
+              .as_ref()
+              .filter(|_| matches!(inner, grbl::Response::Ok))
+              .and_then(|sent| grbl::settings::parse(&sent.line).ok());
+            if let Some((id, value)) = written {
+              self.serial.settings.insert(id, value);
+              self.serial.settings_changed = true;
+            }
+
+            match answered {
+              Some(SentLine {
+                origin: LineOrigin::Job,
+                ..
+              }) => {
+                if let SerialConnectionState::SendingFile(queue, _) = &mut self.serial.connection {
+                  queue.update(inner);
+                }
+              }
+              Some(SentLine {
+                line,
+                origin: LineOrigin::Client(client, tick),
+              }) => {
+                tracing::info!("line '{line}' (tick {tick}) answered with '{data}'");
+                reply = Some((client, tick));
+              }
+              Some(SentLine {
+                origin: LineOrigin::Internal,
+                ..
+              })
+              | None => (),
+            }
+
+            if let grbl::Response::Error(detail) = inner {
+              tracing::warn!("controller refused a line - {detail}");
+            }
+
+            // A line answered while the controller was sitting still may have set it moving (or
+            // cleared its alarm); ask what it is doing now rather than waiting out the interval.
+            let state = self.serial.connection.status().map(|(state, _)| state);
+            if matches!(inner, grbl::Response::Ok | grbl::Response::Error(_)) && !KeepAliveConfiguration::active(state)
+            {
+              self.serial.last_poll = None;
             }
 
             // For now, persist this status message on our application. Eventually we will want to
             // build this into the connection enum itself somehow; even idle connections should
             // have a status.
-            if let grbl::Response::Status(state, pos) = inner {
-              next.serial.connection.update_status((state, pos));
+            if let grbl::Response::Status(status) = inner {
+              self.serial.connection.update_status((status.state, status.position));
+              self.serial.work_position = status.work_position;
+              self.sync_position();
+
+              if status.work_offset.is_some() {
+                self.serial.work_offset = status.work_offset;
+              }
+
+              if status.overrides.is_some() {
+                self.sync_overrides(status.overrides);
+              }
+            }
+
+            if let grbl::Response::Probe(position, touched) = inner {
+              cmds.extend(self.finish_probe(*position, *touched));
+            }
+
+            if let grbl::Response::Setting(id, value) = inner {
+              self.serial.settings.insert(*id, *value);
+              self.serial.settings_changed = true;
+            }
+
+            // Settings are listed a line at a time; clients are sent them once the listing is done.
+            if matches!(inner, grbl::Response::Ok | grbl::Response::Error(_)) {
+              cmds.extend(self.sync_settings());
+            }
+
+            if let grbl::Response::Alarm(code) = inner {
+              tracing::warn!("controller reported alarm {code}");
+              // The controller discards the lines it has buffered when it raises an alarm, without
+              // answering them.
+              self.awaiting.clear();
+              // Nothing more of the job will be accepted until the alarm is cleared; it can be
+              // resumed from where it was once it has been.
+              cmds.extend(self.finish_job(crate::jobs::JobOutcome::Failed));
+              cmds.push(Command::Notify(effects::notify::Event::Alarm { code: *code }));
             }
 
             tracing::info!("parsed grbl response = {inner:?}");
@@ -491,112 +3164,344 @@ impl crate::eff::Application for Application {
           }
         }
 
-        if !next.connected_clients.is_empty() {
-          let mut cmds = vec![];
+        if let Some(session) = self.terminal.as_ref() {
+          cmds.push(Command::Terminal(effects::terminal::TerminalCommand::Write(
+            session.clone(),
+            data.clone(),
+          )));
+        }
 
+        if !self.connected_clients.is_empty() {
           // Add this serial message to all of our connected clients.
-          for (id, client) in &mut next.connected_clients {
-            client.history.push(ClientHistoryEntry::ReceivedData(ReceivedDataEntry {
+          for (id, client) in self.connected_clients.iter_mut() {
+            let reply_to = reply.as_ref().filter(|(sender, _)| sender == id).map(|(_, tick)| *tick);
+
+            if !client.preferences.wants(response.as_ref().ok(), reply_to) {
+              continue;
+            }
+
+            client.push_history(ClientHistoryEntry::ReceivedData(ReceivedDataEntry {
               content: data.clone(),
+              reply_to,
             }));
+          }
+        }
+
+        // The next raw line can go out once everything sent before it has been answered.
+        if freed {
+          cmds.extend(self.send_mdi());
+        }
+
+        if !self.connected_clients.is_empty() {
+          self.add_statuses(&mut cmds);
+        }
+
+        return Some(cmds);
+      }
+
+      Message::Terminal(effects::terminal::TerminalEvent::Attached(session)) => {
+        if self.terminal.is_some() {
+          tracing::warn!("refusing terminal session '{session}', another is already attached");
+          let close = effects::terminal::TerminalCommand::Close(session, TERMINAL_BUSY.into());
+          return Some(vec![Command::Terminal(close)]);
+        }
+
+        tracing::info!("terminal session '{session}' attached, taking control");
+        let greeting = match self.serial.connection {
+          SerialConnectionState::SendingFile(_, _) => format!("% attached (job paused), '{TERMINAL_QUIT}' to detach"),
+          _ => format!("% attached, '{TERMINAL_QUIT}' to detach"),
+        };
+        self.set_controller(Some(terminal_holder(&session)));
+        self.terminal = Some(session.clone());
+
+        let mut cmds = vec![Command::Terminal(effects::terminal::TerminalCommand::Write(
+          session, greeting,
+        ))];
+        self.add_statuses(&mut cmds);
+        return Some(cmds);
+      }
+
+      Message::Terminal(effects::terminal::TerminalEvent::Line(session, line)) => {
+        if self.terminal.as_ref() != Some(&session) {
+          return None;
+        }
+
+        if line.trim() == TERMINAL_QUIT {
+          let close = effects::terminal::TerminalCommand::Close(session.clone(), "% detached".into());
+          return Some(vec![Command::Terminal(close)]);
+        }
+
+        if let Some(lock) = self.control.as_mut() {
+          lock.last_active = std::time::Instant::now();
+        }
+
+        self.serial.track(&line);
+        for client in self.connected_clients.values_mut() {
+          client.record_sent(&line);
+        }
+
+        return Some(vec![self.send_line(line, LineOrigin::Internal)]);
+      }
+
+      Message::Terminal(effects::terminal::TerminalEvent::Detached(session)) => {
+        if self.terminal.as_ref() != Some(&session) {
+          return None;
+        }
+
+        tracing::info!("terminal session '{session}' detached, releasing control");
+        self.terminal = None;
+
+        if self.controller() == Some(&terminal_holder(&session)) {
+          self.set_controller(None);
+        }
+
+        let mut cmds = vec![];
+        self.add_statuses(&mut cmds);
+        return Some(cmds);
+      }
+
+      // The pendant is next to the machine; whoever is pressing its keys can see what they are doing,
+      // so it is not subject to the control lock. Jogs are refused while a job is being sent, or a
+      // terminal session is talking to the controller.
+      Message::Input(event) => {
+        let line = match event {
+          effects::input::InputEvent::FeedHold => {
+            tracing::info!("pendant has requested a feed hold");
+            return Some(vec![Command::Serial(SerialCommand::Realtime(grbl::Command::FeedHold))]);
+          }
+          effects::input::InputEvent::CycleStart => {
+            tracing::info!("pendant has requested a cycle start");
+            return Some(vec![Command::Serial(SerialCommand::Realtime(
+              grbl::Command::CycleStart,
+            ))]);
+          }
+          effects::input::InputEvent::Jog(_, _) if !self.serial.available() || self.terminal.is_some() => {
+            tracing::warn!("ignoring pendant jog, the machine is busy");
+            return None;
+          }
+          effects::input::InputEvent::Jog([x, y, z], feed) => {
+            let mapping = self
+              .serial
+              .last_config
+              .as_ref()
+              .map(|config| config.display())
+              .unwrap_or_default();
+            JogRequest { x, y, z, feed }.line(mapping, self.serial.firmware())
+          }
+        };
+
+        if let Err(detail) = self.serial.check_limits(&line) {
+          tracing::warn!("ignoring pendant jog - {}", detail.render());
+          return None;
+        }
+
+        tracing::info!("pendant has requested a jog - {line}");
+        let mut cmds = vec![];
+        for client in self.connected_clients.values_mut() {
+          client.record_sent(&line);
+        }
+
+        cmds.push(self.send_line(line, LineOrigin::Internal));
+        self.add_statuses(&mut cmds);
+        return Some(cmds);
+      }
+
+      Message::Broadcast => {
+        // If we have never broadcast before, just update our reference and send anything we have.
+        if self.last_broadcast.is_none() {
+          self.last_broadcast = Some(std::time::Instant::now());
+          return None;
+        }
+
+        let last_broadcast = self.last_broadcast.unwrap();
+        let now = std::time::Instant::now();
+
+        // If we've broadcasted to our clients recently, just skip.
+        if now.duration_since(last_broadcast) < self.broadcast_interval {
+          return None;
+        }
+
+        self.last_broadcast = Some(now);
+
+        // We don't need to continue if we have no connected clients.
+        if self.connected_clients.is_empty() {
+          return None;
+        }
+
+        let mut cmds = Vec::with_capacity(10);
+        self.add_statuses(&mut cmds);
+        tracing::debug!(
+          "sending {} state commands to {} clients",
+          cmds.len(),
+          self.connected_clients.len()
+        );
+        return Some(cmds);
+      }
+
+      // Stop sending whatever job we were in the middle of before we go.
+      // Asking a second time skips waiting on the http server.
+      Message::Shutdown if self.stopping => {
+        tracing::warn!("shutdown requested while draining, exiting immediately");
+        return Some(vec![Command::Exit]);
+      }
+
+      Message::Shutdown => {
+        tracing::info!("shutdown requested, draining http server");
+        #[cfg(unix)]
+        crate::systemd::stopping();
+        let finished = self.finish_job(crate::jobs::JobOutcome::Cancelled);
+        // Stopping on purpose does not interrupt anything; make sure our snapshot says so.
+        self.write_snapshot();
+        self.stopping = true;
+        let reason = effects::http::RESTARTING.to_string();
+        let mut cmds = vec![Command::Http(effects::http::Command::Shutdown(reason))];
+        cmds.extend(finished);
+        return Some(cmds);
+      }
 
-            match serde_json::to_string(&ResponseKinds::State(client)) {
-              Ok(payload) => {
-                let response_command = Command::Http(effects::http::Command::SendState(id.clone(), payload));
-                cmds.push(response_command);
-              }
-              Err(error) => tracing::warn!("unable to serialize payload - {error}"),
-            }
-          }
+      // The serial effect is running alongside the http server by now; we are ready.
+      Message::Http(effects::http::Message::Listening) => {
+        tracing::info!("http server listening");
+        #[cfg(unix)]
+        crate::systemd::ready();
+      }
 
-          return (next, Some(cmds));
-        }
+      Message::Http(effects::http::Message::Stopped) => {
+        tracing::info!("http server stopped, exiting");
+        return Some(vec![Command::Exit]);
       }
 
-      Message::Broadcast => {
-        // If we have never broadcast before, just update our reference and send anything we have.
-        if next.last_broadcast.is_none() {
-          next.last_broadcast = Some(std::time::Instant::now());
-          return (next, None);
-        }
+      Message::Reload => {
+        tracing::info!("reload requested, reading configuration");
+        return Some(vec![Command::Watcher(effects::watcher::WatcherCommand::Reload)]);
+      }
 
-        let last_broadcast = next.last_broadcast.unwrap();
-        let now = std::time::Instant::now();
+      Message::ConfigurationChanged(config) => {
+        tracing::info!("configuration changed, applying");
+        return Some(self.reconfigure(*config));
+      }
 
-        // If we've broadcasted to our clients recently, just skip.
-        if now.duration_since(last_broadcast).as_secs() < 2 {
-          return (next, None);
-        }
+      Message::DumpState => self.dump_state(),
 
-        next.last_broadcast = Some(now);
+      Message::Prune => {
+        let report = self.pruner.prune(false);
+        tracing::debug!("retention pruned {report:?}");
 
-        // We don't need to continue if we have no connected clients.
-        if next.connected_clients.is_empty() {
-          return (next, None);
+        if report.job_files > 0 {
+          self.last_job = None;
         }
-
-        tracing::debug!("has {} clients to send heartbeats to", next.connected_clients.len());
-        let mut cmds = Vec::with_capacity(10);
-        next.add_statuses(&mut cmds);
-        return (next, Some(cmds));
       }
 
+      Message::Snapshot => self.write_snapshot(),
+
       Message::Tick => {
         let mut cmds = vec![];
+        self.report_health();
+
+        // Forget the clients that lost their connection and have not come back in time.
+        self.connected_clients.retain(|id, client| {
+          let expired = client.detached_at.is_some_and(|at| at.elapsed() > RESUME_WINDOW);
+          if expired {
+            tracing::info!("client {id} did not resume in time, forgetting it");
+          }
+          !expired
+        });
+
+        // Release the control lock of an operator that has not sent anything in a while.
+        let idle = self
+          .control
+          .as_ref()
+          .zip(self.control_timeout)
+          .is_some_and(|(lock, timeout)| lock.last_active.elapsed() > timeout);
+
+        if idle {
+          tracing::info!("releasing control from idle operator");
+          self.set_controller(None);
+          self.add_statuses(&mut cmds);
+        }
+
+        cmds.extend(self.poll(std::time::Instant::now()));
 
         // Start by seeing if we are sending a file over. If so, we will attempt to take the next
         // line off the contents and push a raw serial cmd onto our return vector.
-        if let SerialConnectionState::SendingFile(mut queue, status) = next.serial.connection {
-          next.serial.connection = match queue.next() {
+        if let SerialConnectionState::SendingFile(queue, _) = &mut self.serial.connection {
+          // The job is paused while a terminal session is talking to the controller.
+          if self.terminal.is_some() {
+            return Some(cmds);
+          }
+
+          let stepping = queue.steps.is_some();
+          let now = std::time::Instant::now();
+
+          // During a dry run, nothing is going to answer our lines but us.
+          if queue.waiting && queue.dry_run.as_ref().is_some_and(|dry_run| dry_run.done(now)) {
+            queue.update(&grbl::Response::Ok);
+          }
+
+          match queue.next() {
             FileQueueNext::Ready(next_line) => {
-              // We have a line, grab the contents and create a raw serial command for it.
-              tracing::info!("sending next file line '{next_line:?}'");
-              cmds.push(Command::Serial(SerialCommand::Raw(next_line.clone())));
-
-              for (_, mut client) in &mut next.connected_clients {
-                client.history.push(ClientHistoryEntry::SentCommand(ClientMessage {
-                  tick: 0,
-                  request: ClientMessageRequest::RawSerial(RawSerialRequest {
-                    value: next_line.clone(),
-                  }),
-                }));
+              for client in self.connected_clients.values_mut() {
+                client.record_sent(&next_line);
               }
 
-              // TODO: our lines iterator trims the newline off the rest of our lines. There is
-              // probably a way to do this so we hold into the original iterator instead of
-              // manipulating it back and forth between iterator and concrete string.
-              SerialConnectionState::SendingFile(queue, status)
+              match queue.dry_run.as_mut() {
+                Some(dry_run) => {
+                  tracing::info!("dry running next file line '{next_line:?}'");
+                  dry_run.take(&next_line, now);
+                }
+                None => {
+                  // We have a line, grab the contents and create a raw serial command for it.
+                  tracing::info!("sending next file line '{next_line:?}'");
+                  self.serial.track(&next_line);
+                  self.awaiting.push_back(SentLine {
+                    line: next_line.clone(),
+                    origin: LineOrigin::Job,
+                  });
+
+                  // TODO: our lines iterator trims the newline off the rest of our lines. There is
+                  // probably a way to do this so we hold into the original iterator instead of
+                  // manipulating it back and forth between iterator and concrete string.
+                  cmds.push(Command::Serial(SerialCommand::Raw(next_line)));
+                }
+              }
             }
-            FileQueueNext::Waiting => SerialConnectionState::SendingFile(queue, status),
+            FileQueueNext::Paused(pause) => {
+              tracing::info!(
+                "job paused at '{}' ({:?}), waiting on the operator",
+                pause.line,
+                pause.reason
+              );
+              self.sync_paused();
+            }
+            FileQueueNext::Waiting => (),
             FileQueueNext::Done => {
               tracing::info!("file queue exhausted, moving to idle");
-              SerialConnectionState::Idle(None, status)
+              cmds.extend(self.finish_job(crate::jobs::JobOutcome::Completed));
             }
-          };
-
-          return (next, Some(cmds));
-        }
-
-        if let SerialConnectionState::Idle(last_ping, _) = next.serial.connection {
-          let now = std::time::Instant::now();
-          let mut is_old = last_ping.is_none();
-
-          if let Some(ping) = last_ping {
-            is_old = now.duration_since(ping).as_secs() > 3;
           }
 
-          if is_old {
-            tracing::info!("sending new ping to serial");
-            next.serial.connection = SerialConnectionState::Idle(Some(now), None);
-            cmds.push(Command::Serial(SerialCommand::Status));
+          if stepping {
+            self.sync_upcoming();
           }
+
+          self.sync_job();
+          return Some(cmds);
         }
 
-        return (next, Some(cmds));
+        // Anything held while the job was being sent can go out now that it is done.
+        let held = self.queued_raw.len();
+        if self.serial.available() && held > 0 {
+          tracing::info!("releasing {held} held raw lines");
+          self.mdi.extend(self.queued_raw.drain(..));
+        }
+
+        cmds.extend(self.send_mdi());
+
+        return Some(cmds);
       }
     }
 
-    (next, None)
+    None
   }
 }
 
@@ -606,6 +3511,44 @@ impl crate::eff::EffectCommandFilter for SerialFilter {
   fn sendable(&self, c: &Self::Command) -> bool {
     matches!(c, Command::Serial(_))
   }
+
+  fn priority(&self, command: &Self::Command) -> crate::eff::Priority {
+    command.priority()
+  }
+}
+
+#[cfg(unix)]
+struct SignalFilter {}
+#[cfg(unix)]
+impl crate::eff::EffectCommandFilter for SignalFilter {
+  type Command = Command;
+
+  fn sendable(&self, command: &Self::Command) -> bool {
+    matches!(command, Command::Exit)
+  }
+}
+
+#[cfg(unix)]
+struct SignalMap {}
+#[cfg(unix)]
+impl effects::signals::SignalMap for SignalMap {
+  type Command = Command;
+  type Message = Message;
+
+  fn translate(&self, original: Self::Command) -> Option<effects::signals::SignalCommand> {
+    match original {
+      Command::Exit => Some(effects::signals::SignalCommand::Exit),
+      _ => None,
+    }
+  }
+
+  fn message(&self, signal: effects::signals::Signal) -> Option<Self::Message> {
+    Some(match signal {
+      effects::signals::Signal::Interrupt | effects::signals::Signal::Terminate => Message::Shutdown,
+      effects::signals::Signal::Hangup => Message::Reload,
+      effects::signals::Signal::User1 => Message::DumpState,
+    })
+  }
 }
 
 struct SerialParser {}
@@ -613,30 +3556,130 @@ impl effects::serial::OuputParser for SerialParser {
   type Message = Message;
 
   fn parse(&self, bytes: &[u8]) -> Option<(Self::Message, usize)> {
-    let appearance = String::from_utf8_lossy(bytes);
+    // Lines are found among the bytes rather than among the characters they decode to; bytes that
+    // are not valid utf8 (e.g noise on the line) are replaced when decoded, which would otherwise
+    // throw off how many bytes the line took up.
+    let (line, taken) = effects::serial::frame(bytes)?;
+    Some((Message::Serial(String::from_utf8_lossy(line).into_owned()), taken))
+  }
 
-    if let Some(boundary) = appearance.find('\n') {
-      if !appearance.is_char_boundary(boundary) {
-        tracing::warn!("newline appeared at strange location in utf8 byte array, ignoring");
-        return None;
-      }
+  fn flush(&self, bytes: &[u8]) -> Option<Self::Message> {
+    let line = effects::serial::unterminated(bytes);
+    Some(Message::Serial(String::from_utf8_lossy(line).into_owned()))
+  }
+}
+
+struct TerminalFilter {}
+impl crate::eff::EffectCommandFilter for TerminalFilter {
+  type Command = Command;
+
+  fn sendable(&self, command: &Self::Command) -> bool {
+    matches!(command, Command::Terminal(_))
+  }
+}
+
+#[derive(Clone)]
+struct TerminalMap {}
+impl effects::terminal::TerminalMap for TerminalMap {
+  type Command = Command;
+  type Message = Message;
 
-      let full = appearance.chars().take(boundary).collect::<String>();
-      return Some((Message::Serial(full), boundary + 1));
+  fn translate(&self, original: Self::Command) -> Option<effects::terminal::TerminalCommand> {
+    match original {
+      Command::Terminal(inner) => Some(inner),
+      _ => None,
     }
+  }
 
-    None
+  fn message(&self, event: effects::terminal::TerminalEvent) -> Self::Message {
+    Message::Terminal(event)
   }
 }
 
-struct TickFilter {}
-impl crate::eff::EffectCommandFilter for TickFilter {
+struct InputFilter {}
+impl crate::eff::EffectCommandFilter for InputFilter {
   type Command = Command;
-  fn sendable(&self, _: &Self::Command) -> bool {
+
+  /// The input effect does not receive any commands.
+  fn sendable(&self, _command: &Self::Command) -> bool {
     false
   }
 }
 
+struct InputMap {}
+impl effects::input::InputMap for InputMap {
+  type Command = Command;
+  type Message = Message;
+
+  fn message(&self, event: effects::input::InputEvent) -> Self::Message {
+    Message::Input(event)
+  }
+}
+
+struct NotifyFilter {}
+impl crate::eff::EffectCommandFilter for NotifyFilter {
+  type Command = Command;
+
+  fn sendable(&self, command: &Self::Command) -> bool {
+    matches!(command, Command::Notify(_))
+  }
+}
+
+struct NotifyMap {}
+impl effects::notify::NotifyMap for NotifyMap {
+  type Command = Command;
+  type Message = Message;
+
+  fn translate(&self, original: Self::Command) -> Option<effects::notify::NotifyCommand> {
+    match original {
+      Command::Notify(event) => Some(effects::notify::NotifyCommand::Deliver(event)),
+      _ => None,
+    }
+  }
+}
+
+struct WatcherFilter {}
+impl crate::eff::EffectCommandFilter for WatcherFilter {
+  type Command = Command;
+
+  fn sendable(&self, command: &Self::Command) -> bool {
+    matches!(command, Command::Watcher(_))
+  }
+}
+
+struct WatcherMap {}
+impl effects::watcher::WatcherMap for WatcherMap {
+  type Command = Command;
+  type Message = Message;
+
+  fn translate(&self, original: Self::Command) -> Option<effects::watcher::WatcherCommand> {
+    match original {
+      Command::Watcher(inner) => Some(inner),
+      _ => None,
+    }
+  }
+
+  /// Configurations that cannot be parsed, or that have errors, are not applied; we keep running
+  /// with whatever we had.
+  fn message(&self, contents: String) -> Option<Self::Message> {
+    let config = crate::overrides::load::<Configuration>(&contents)
+      .map_err(|error| tracing::warn!("unable to parse changed configuration - {error}"))
+      .ok()?;
+
+    let validation = validate(&config);
+    for issue in validation.issues() {
+      tracing::warn!("{issue}");
+    }
+
+    if validation.has_errors() {
+      tracing::warn!("changed configuration is invalid, ignoring");
+      return None;
+    }
+
+    Some(Message::ConfigurationChanged(Box::new(config)))
+  }
+}
+
 struct HttpFilter {}
 impl crate::eff::EffectCommandFilter for HttpFilter {
   type Command = Command;
@@ -660,11 +3703,17 @@ impl effects::serial::SerialCommandMap<SerialCommand> for SerialMap {
     Some(match serial_command {
       SerialCommand::Control(inner) => effects::serial::SerialCommand::Control(inner),
       SerialCommand::Configure(config) => effects::serial::SerialCommand::Configure(config),
-      SerialCommand::Raw(data) => effects::serial::SerialCommand::Data(SerialCommand::Raw(data)),
-      SerialCommand::Status => effects::serial::SerialCommand::Data(SerialCommand::Status),
+      data @ (SerialCommand::Raw(_)
+      | SerialCommand::Status(_)
+      | SerialCommand::Reset(_)
+      | SerialCommand::Realtime(_)) => effects::serial::SerialCommand::Data(data),
     })
   }
 
+  fn priority(&self, command: &Self::Command) -> crate::eff::Priority {
+    command.priority()
+  }
+
   fn disconnected(&self) -> Self::Message {
     Message::DisconnectedSerial
   }
@@ -674,38 +3723,263 @@ impl effects::serial::SerialCommandMap<SerialCommand> for SerialMap {
   }
 }
 
-pub async fn run(config: Configuration) -> io::Result<()> {
-  // Create all of our effect managers
-  let mut serial_effects = effects::serial::Serial::new(None, SerialParser {});
-  let mut http_effects = effects::http::Http::new(config.http.clone());
+/// The schema of a single line of our message journal, used when building the serial transcript of
+/// a debug bundle.
+#[derive(Deserialize)]
+struct JournalLine {
+  elapsed: u64,
+  message: Message,
+}
+
+/// Extracts a line of the serial transcript from a line of our message journal; the data received
+/// from the controller and the raw lines sent by clients. Lines sent from files and macros are not
+/// journaled, and will be missing.
+fn transcript_line(line: &str) -> Option<String> {
+  let JournalLine { elapsed, message } = serde_json::from_str(line).ok()?;
+
+  match message {
+    Message::Serial(data) => Some(format!("{elapsed:>10} << {data}")),
+    Message::Http(effects::http::Message::ClientData(_, data)) => {
+      match serde_json::from_str::<ClientMessage>(&data).ok()?.request {
+        ClientMessageRequest::RawSerial(raw) => Some(format!("{elapsed:>10} >> {}", raw.value)),
+        _ => None,
+      }
+    }
+    _ => None,
+  }
+}
+
+/// Prepares the diagnostics used to build debug bundles.
+fn diagnostics(
+  config: &Configuration,
+  logs: Option<crate::diagnostics::LogBuffer>,
+) -> crate::Result<crate::diagnostics::Diagnostics> {
+  let journal = config.journal.as_ref().map(std::path::PathBuf::from);
+  crate::diagnostics::Diagnostics::new(config, logs, journal, transcript_line)
+}
+
+/// Builds a debug bundle from outside of a running server. Only what has been persisted (e.g the
+/// message journal) is available; logs are only included in bundles built by a running server.
+pub fn bundle_debug(config: &Configuration) -> crate::Result<Vec<u8>> {
+  diagnostics(config, None)?.bundle()
+}
+
+/// Returns the path of the unix socket terminal sessions attach through, if one is configured.
+pub fn terminal_socket(config: &Configuration) -> Option<&str> {
+  config.terminal.as_ref().map(|terminal| terminal.socket.as_str())
+}
+
+/// Returns the configuration of our logs.
+pub fn logging(config: &Configuration) -> crate::logging::LoggingConfiguration {
+  config.logging.clone().unwrap_or_default()
+}
+
+/// Returns every file we persist data into, along with the migrations of its format.
+fn stores(config: &Configuration) -> Vec<crate::migrations::Store<'_>> {
+  let mut stores = vec![];
+
+  if let Some(path) = config.jobs.as_ref() {
+    stores.push(crate::migrations::Store {
+      name: "jobs",
+      path: std::path::Path::new(path),
+      migrations: crate::jobs::MIGRATIONS,
+    });
+  }
+
+  if let Some(path) = config.macros.as_ref().and_then(|macros| macros.path.as_ref()) {
+    stores.push(crate::migrations::Store {
+      name: "macros",
+      path: std::path::Path::new(path),
+      migrations: crate::macros::MIGRATIONS,
+    });
+  }
+
+  if let Some(snapshot) = config.snapshot.as_ref() {
+    stores.push(crate::migrations::Store {
+      name: "snapshot",
+      path: std::path::Path::new(&snapshot.path),
+      migrations: crate::snapshot::MIGRATIONS,
+    });
+  }
+
+  stores
+}
+
+/// Returns the migrations that would be applied to our persisted data the next time we run, after
+/// verifying that each of them can be applied.
+pub fn check_migrations(config: &Configuration) -> crate::Result<Vec<crate::migrations::PendingMigration>> {
+  let mut pending = vec![];
+
+  for store in stores(config) {
+    pending.extend(store.check()?);
+  }
+
+  Ok(pending)
+}
+
+/// Checks the configuration for the issues its types cannot express on their own, returning every
+/// issue found rather than stopping at the first.
+pub fn validate(config: &Configuration) -> crate::validation::Validation {
+  let mut validation = crate::validation::Validation::default();
+
+  config.http.validate(&mut validation);
+
+  if let Some(serial) = config.serial.as_ref() {
+    serial.validate(&mut validation);
+  }
+
+  if let Some(timing) = config.timing.as_ref() {
+    if timing.broadcast_interval == 0 {
+      validation.error("timing.broadcast_interval", "must be greater than zero");
+    }
+
+    if timing.tick_interval_ms == 0 {
+      validation.error("timing.tick_interval_ms", "must be greater than zero");
+    }
 
-  // The serial ticks are actually the maxiumum frequency that _we_ will be sending commands to the
-  // serial connection. The `serial_effects` manager is responsible for inbound traffic from the
-  // connection.
-  let mut serial_ticks = effects::ticker::Ticker::new(std::time::Duration::from_millis(50));
+    if timing.job_tick_interval_ms == 0 {
+      validation.error("timing.job_tick_interval_ms", "must be greater than zero");
+    }
+  }
+
+  if let Some(keep_alive) = config.keep_alive.as_ref() {
+    if keep_alive.interval == 0 {
+      validation.error("keep_alive.interval", "must be greater than zero");
+    }
+
+    if keep_alive.active_interval_ms == 0 {
+      validation.error("keep_alive.active_interval_ms", "must be greater than zero");
+    }
+  }
+
+  if let Some(terminal) = config.terminal.as_ref() {
+    if terminal.socket.is_empty() {
+      validation.error("terminal.socket", "must not be empty");
+    }
+  }
+
+  if let Some(logging) = config.logging.as_ref() {
+    logging.validate(&mut validation);
+  }
+
+  if let Some(input) = config.input.as_ref() {
+    input.validate(&mut validation);
+  }
+
+  if let Some(notify) = config.notify.as_ref() {
+    notify.validate(&mut validation);
+  }
 
-  // Create the ticker that will be used to create event which we can use to determine when to
-  // publish events to our websockets.
-  let broadcast_interval = config.timing.as_ref().map(|t| t.broadcast_interval).unwrap_or(2);
-  tracing::info!("configured using broadcast interval - {broadcast_interval}s");
-  let mut broadcast_ticks = effects::ticker::Ticker::new(std::time::Duration::from_secs(broadcast_interval));
+  if let Some(snapshot) = config.snapshot.as_ref() {
+    if snapshot.interval == 0 {
+      validation.error("snapshot.interval", "must be greater than zero");
+    }
+  }
+
+  for store in stores(config) {
+    let parent = store.path.parent().filter(|parent| !parent.as_os_str().is_empty());
+
+    if let Some(parent) = parent.filter(|parent| !parent.is_dir()) {
+      validation.error(store.name, format!("'{}' is not a directory", parent.display()));
+    }
+  }
+
+  validation
+}
+
+/// Runs the application. When the path our configuration was read from is provided, it is watched
+/// for changes (and read again on `SIGHUP`), which are applied without restarting.
+pub async fn run(
+  config: Configuration,
+  source: Option<std::path::PathBuf>,
+  logs: crate::diagnostics::LogBuffer,
+) -> crate::Result<()> {
+  for store in stores(&config) {
+    store.migrate()?;
+  }
+
+  let jobs = match config.jobs.as_ref() {
+    Some(path) => crate::jobs::JobHistory::open(path)?,
+    None => crate::jobs::JobHistory::default(),
+  };
+
+  let capture = effects::http::Capture::new(config.http.capture_path());
+  let pruner = crate::retention::Pruner::new(jobs.clone(), config.journal.clone().map(Into::into), capture);
+
+  let macros = crate::macros::MacroLibrary::open(&config.macros.clone().unwrap_or_default())?;
+
+  let health = crate::health::Health::default();
 
   // Create the main effect runtime using a default application state
-  let mut runtime = crate::eff::EffectRuntime::new(Application::default());
+  let mut runtime = crate::eff::EffectRuntime::new(Application {
+    jobs: jobs.clone(),
+    pruner: pruner.clone(),
+    macros,
+    health: health.clone(),
+    ..Application::default()
+  });
 
-  // Register the side effect managers
-  runtime.register(&mut serial_ticks, TickFilter {})?;
-  runtime.register(&mut broadcast_ticks, TickFilter {})?;
+  // Create all of our effect managers
+  let mut serial_effects = effects::serial::Serial::new(None, SerialParser {});
+  // Everything we persist can be downloaded by admins.
+  let mut downloads = effects::http::Downloads::default();
+  for store in stores(&config) {
+    downloads.insert(store.name, store.path);
+  }
+  if let Some(path) = config.journal.as_ref() {
+    downloads.insert("journal", path);
+  }
+
+  let diagnostics = diagnostics(&config, Some(logs))?;
+  let mut http_effects = effects::http::Http::new(
+    config.http.clone(),
+    runtime.metrics(),
+    jobs,
+    diagnostics,
+    downloads,
+    health,
+    pruner,
+  );
 
+  // Register the side effect managers
   runtime.register(&mut serial_effects, SerialFilter {})?;
   runtime.register(&mut http_effects, HttpFilter {})?;
 
+  #[cfg(unix)]
+  let mut signal_effects = effects::signals::Signals::new();
+  #[cfg(unix)]
+  runtime.register(&mut signal_effects, SignalFilter {})?;
+
+  #[cfg(unix)]
+  let mut terminal_effects = config.terminal.clone().map(effects::terminal::Terminal::new);
+  #[cfg(unix)]
+  if let Some(terminal_effects) = terminal_effects.as_mut() {
+    runtime.register(terminal_effects, TerminalFilter {})?;
+  }
+
+  let mut input_effects = config.input.clone().map(effects::input::Input::new);
+  if let Some(input_effects) = input_effects.as_mut() {
+    runtime.register(input_effects, InputFilter {})?;
+  }
+
+  let mut notify_effects = config.notify.clone().map(effects::notify::Notify::new);
+  if let Some(notify_effects) = notify_effects.as_mut() {
+    runtime.register(notify_effects, NotifyFilter {})?;
+  }
+
+  let mut watcher_effects = source.map(effects::watcher::Watcher::new);
+  if let Some(watcher_effects) = watcher_effects.as_mut() {
+    runtime.register(watcher_effects, WatcherFilter {})?;
+  }
+
+  if let Some(path) = config.journal.as_ref() {
+    tracing::info!("recording message journal to '{path}'");
+    runtime.journal(path)?;
+  }
+
   // Run all.
-  runtime
+  let running = runtime
     .run(config.clone())
-    // Separate message creation for tick/broadcst.
-    .race(broadcast_ticks.run(|| Message::Broadcast))
-    .race(serial_ticks.run(|| Message::Tick))
     // Provide the unique application events for connection and disconnection.
     .race(serial_effects.run(SerialMap {}))
     .race(http_effects.run(
@@ -714,6 +3988,184 @@ pub async fn run(config: Configuration) -> io::Result<()> {
         _ => None,
       },
       Message::Http,
-    ))
-    .await
+    ));
+
+  // The signals effect resolves once the application has decided to exit.
+  #[cfg(unix)]
+  let running = running.race(signal_effects.run(SignalMap {}));
+
+  // Without a socket configured, there is nothing for the terminal effect to do.
+  #[cfg(unix)]
+  let running = running.race(async {
+    match terminal_effects {
+      Some(terminal_effects) => terminal_effects.run(TerminalMap {}).await,
+      None => futures_lite::future::pending().await,
+    }
+  });
+
+  // Without a device configured, there is nothing for the input effect to do.
+  let running = running.race(async {
+    match input_effects {
+      Some(input_effects) => input_effects.run(InputMap {}).await,
+      None => futures_lite::future::pending().await,
+    }
+  });
+
+  // Without any sinks configured, there is nothing for the notify effect to do.
+  let running = running.race(async {
+    match notify_effects {
+      Some(notify_effects) => notify_effects.run(NotifyMap {}).await,
+      None => futures_lite::future::pending().await,
+    }
+  });
+
+  // Without a configuration file, there is nothing for the watcher effect to do.
+  let running = running.race(async {
+    match watcher_effects {
+      Some(watcher_effects) => watcher_effects.run(WatcherMap {}).await,
+      None => futures_lite::future::pending().await,
+    }
+  });
+
+  running.await
+}
+
+/// Feeds the messages recorded in a journal back through a fresh application. No effects are
+/// registered, so the commands produced by the application are not sent anywhere; this is meant
+/// for reproducing the state an application ended up in.
+pub async fn replay<P>(config: Configuration, journal: P, speed: f64) -> crate::Result<()>
+where
+  P: AsRef<std::path::Path>,
+{
+  // The snapshot belongs to the application that recorded the journal; restoring (or overwriting)
+  // it here would make our replay depend on whatever that application last wrote.
+  let config = Configuration {
+    snapshot: None,
+    ..config
+  };
+  let runtime = crate::eff::EffectRuntime::new(Application::default());
+  runtime.replay(config, journal, speed).await
+}
+
+/// A populated application used by the benchmarks to measure how expensive it is to publish client
+/// state. This is not meant to be used outside of them.
+#[cfg(feature = "fixtures")]
+pub struct BenchFixture {
+  application: Application,
+  tick: u32,
+}
+
+#[cfg(feature = "fixtures")]
+impl BenchFixture {
+  /// Creates an application with an idle serial connection and `clients` connected clients that
+  /// each have `history` entries.
+  pub fn new(clients: usize, history: usize) -> Self {
+    let mut application = Application {
+      serial: DerivedSerialState {
+        connection: SerialConnectionState::Idle(None),
+        ..DerivedSerialState::default()
+      },
+      ..Application::default()
+    };
+
+    for index in 0..clients {
+      let id = format!("client-{index}");
+      crate::eff::Application::update(
+        &mut application,
+        Message::Http(effects::http::Message::ClientConnected(
+          id.clone(),
+          effects::http::Authority::Admin,
+        )),
+      );
+
+      if let Some(client) = application.connected_clients.get_mut(&id) {
+        for line in 0..history {
+          client.push_history(ClientHistoryEntry::ReceivedData(ReceivedDataEntry {
+            content: format!("[MSG:line {line}]"),
+            reply_to: None,
+          }));
+        }
+      }
+    }
+
+    Self { application, tick: 0 }
+  }
+
+  /// Sends a request from a single client, which refreshes the state of every client. Returns the
+  /// amount of commands produced.
+  pub fn client_request(&mut self) -> usize {
+    self.tick += 1;
+    let data = serde_json::json!({ "tick": self.tick, "request": { "kind": "retry_serial" } }).to_string();
+    let message = Message::Http(effects::http::Message::ClientData("client-0".into(), data));
+    crate::eff::Application::update(&mut self.application, message).map_or(0, |cmds| cmds.len())
+  }
+
+  /// Sends a broadcast to every client. Returns the amount of commands produced.
+  pub fn broadcast(&mut self) -> usize {
+    let last_broadcast = std::time::Instant::now() - std::time::Duration::from_secs(10);
+    self.application.last_broadcast = Some(last_broadcast);
+    crate::eff::Application::update(&mut self.application, Message::Broadcast).map_or(0, |cmds| cmds.len())
+  }
+}
+
+/// The parsers controller output goes through, used by the property tests to check how they hold up
+/// against arbitrary (and arbitrarily split) reads. This is not meant to be used outside of them.
+#[cfg(feature = "fixtures")]
+#[derive(Default)]
+pub struct ParserFixture {
+  buffer: Vec<u8>,
+}
+
+#[cfg(feature = "fixtures")]
+impl ParserFixture {
+  /// Adds a read to the buffer the way our serial connection does, returning the lines it completed.
+  pub fn read(&mut self, bytes: &[u8]) -> Vec<String> {
+    self.buffer.extend_from_slice(bytes);
+    effects::serial::drain(&SerialParser {}, &mut self.buffer)
+      .into_iter()
+      .filter_map(|message| match message {
+        Message::Serial(line) => Some(line),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// Returns what is left in the buffer, waiting on the rest of its line.
+  pub fn remainder(&self) -> &[u8] {
+    &self.buffer
+  }
+
+  /// Flushes what is left in the buffer the way our serial connection does once nothing else has
+  /// arrived for a while, returning it as a line.
+  pub fn flush(&mut self) -> Option<String> {
+    match effects::serial::flush(&SerialParser {}, &mut self.buffer) {
+      Some(Message::Serial(line)) => Some(line),
+      _ => None,
+    }
+  }
+
+  /// Parses a line as a status report.
+  pub fn status(line: &str) -> Option<ParsedStatus> {
+    match line.parse::<grbl::Response>() {
+      Ok(grbl::Response::Status(status)) => Some(ParsedStatus {
+        state: format!("{:?}", status.state),
+        position: status.position.axes(),
+        work_offset: status.work_offset.map(|offset| offset.axes()),
+        overrides: status
+          .overrides
+          .map(|overrides| [overrides.feed, overrides.rapid, overrides.spindle]),
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// What the property tests compare of a parsed status report.
+#[cfg(feature = "fixtures")]
+#[derive(Debug)]
+pub struct ParsedStatus {
+  pub state: String,
+  pub position: [f32; 3],
+  pub work_offset: Option<[f32; 3]>,
+  pub overrides: Option<[u16; 3]>,
 }
@@ -0,0 +1,389 @@
+//! The self-test boots the application with the simulator standing in for the controller and a
+//! scripted client standing in for the ui (connected as an admin, without going through auth), and
+//! then walks through what every install needs to be able to do. Besides the `self-test` subcommand,
+//! this is what our integration tests (in `tests/`) run.
+
+use super::{Application, Command, Configuration, Message, SerialFilter, SerialMap, SerialParser};
+use crate::effects;
+use crate::error::{Error, Result};
+use async_std::channel;
+use futures_lite::future::FutureExt;
+use std::sync::{atomic, Arc};
+
+/// The id of our scripted client.
+const CLIENT: &str = "self-test";
+
+/// How long any single stage may take.
+const STAGE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
+
+/// The file streamed during the `stream` stage.
+const PROGRAM: &str = "G21\nG90\nG0 X10 Y10\nG0 X0 Y0\n";
+
+/// The file uploaded during the `upload` stage.
+const UPLOAD: &str = "G21\nG91\nG1 X5 Y5 F1000\nG1 X-5 Y-5\nG90\n";
+
+/// The setting changed during the `settings` stage (the step idle delay), and what it is changed to.
+const SETTING: (u16, f32) = (1, 50.0);
+
+/// A move beyond the travel of the simulator, which triggers a soft limit alarm.
+const BEYOND_TRAVEL: &str = "G0 X5000";
+
+/// How a single stage of the self-test went.
+#[derive(Debug)]
+pub struct Stage {
+  /// What the stage exercises.
+  pub name: &'static str,
+
+  /// How long the stage took when it passed, or why it failed.
+  pub outcome: std::result::Result<std::time::Duration, String>,
+}
+
+/// The stages the self-test ran; the self-test stops at the first stage that fails.
+#[derive(Debug, Default)]
+pub struct SelfTestReport {
+  /// Every stage that was run, in the order they were run in.
+  pub stages: Vec<Stage>,
+}
+
+impl SelfTestReport {
+  /// Whether or not every stage passed.
+  pub fn passed(&self) -> bool {
+    !self.stages.is_empty() && self.stages.iter().all(|stage| stage.outcome.is_ok())
+  }
+
+  /// Runs a stage, recording how it went. Returns whether or not it passed.
+  async fn stage<F>(&mut self, name: &'static str, stage: F) -> bool
+  where
+    F: std::future::Future<Output = std::result::Result<(), String>>,
+  {
+    let started = std::time::Instant::now();
+    let timeout = async {
+      async_std::task::sleep(STAGE_TIMEOUT).await;
+      Err(format!("timed out after {}s", STAGE_TIMEOUT.as_secs()))
+    };
+    let outcome = stage.race(timeout).await.map(|_| started.elapsed());
+    let passed = outcome.is_ok();
+    self.stages.push(Stage { name, outcome });
+    passed
+  }
+}
+
+impl std::fmt::Display for SelfTestReport {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    for stage in &self.stages {
+      match &stage.outcome {
+        Ok(elapsed) => writeln!(formatter, "[ ok ] {} ({}ms)", stage.name, elapsed.as_millis())?,
+        Err(reason) => writeln!(formatter, "[fail] {} - {reason}", stage.name)?,
+      }
+    }
+
+    match self.passed() {
+      true => write!(formatter, "self-test passed"),
+      false => write!(formatter, "self-test failed"),
+    }
+  }
+}
+
+/// Only the commands meant for clients are sent to our scripted client.
+struct ScenarioFilter {}
+impl crate::eff::EffectCommandFilter for ScenarioFilter {
+  type Command = Command;
+
+  fn sendable(&self, command: &Self::Command) -> bool {
+    matches!(command, Command::Http(_))
+  }
+}
+
+/// The scripted client, which talks to the application the same way the http effect does: over the
+/// channels of the effect runtime, rather than through an actual websocket.
+struct Scenario {
+  /// The commands the application sends our client; the sender is handed to the effect runtime.
+  commands: (channel::Receiver<Command>, Option<channel::Sender<Command>>),
+
+  /// The messages our client sends the application; the receiver is handed to the effect runtime.
+  messages: (channel::Sender<Message>, Option<channel::Receiver<Message>>),
+
+  /// The tick of the most recent request; every request is given the next one, which the application
+  /// includes in its response to it.
+  tick: u32,
+
+  /// The id our client is currently connected as; clients are given a new id when they reconnect.
+  client: String,
+}
+
+impl crate::eff::Effect for Scenario {
+  type Message = Message;
+  type Command = Command;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
+    Ok((msg_out, cmd_in))
+  }
+}
+
+impl Scenario {
+  fn new() -> Self {
+    let commands = channel::unbounded();
+    let messages = channel::unbounded();
+
+    Self {
+      commands: (commands.1, Some(commands.0)),
+      messages: (messages.0, Some(messages.1)),
+      tick: 0,
+      client: CLIENT.into(),
+    }
+  }
+
+  /// Sends a message from our client to the application.
+  async fn send(&self, message: effects::http::Message) -> std::result::Result<(), String> {
+    let message = Message::Http(message);
+    self.messages.0.send(message).await.map_err(|error| error.to_string())
+  }
+
+  /// Waits for a payload sent to our client that matches the predicate.
+  async fn expect<P>(&self, predicate: P) -> std::result::Result<serde_json::Value, String>
+  where
+    P: Fn(&serde_json::Value) -> bool,
+  {
+    loop {
+      let payload = match self.commands.0.recv().await.map_err(|error| error.to_string())? {
+        Command::Http(effects::http::Command::SendState(id, payload)) if id == self.client => payload,
+        Command::Http(effects::http::Command::Broadcast(payload)) => payload,
+        _ => continue,
+      };
+
+      let frame = serde_json::from_str(&payload).map_err(|error| format!("invalid payload - {error}"))?;
+      if predicate(&frame) {
+        return Ok(frame);
+      }
+    }
+  }
+
+  /// Sends a request, failing unless the application accepts it.
+  async fn request(&mut self, request: serde_json::Value) -> std::result::Result<(), String> {
+    self.tick += 1;
+    let tick = self.tick;
+    let data = serde_json::json!({ "tick": tick, "request": request }).to_string();
+    self
+      .send(effects::http::Message::ClientData(self.client.clone(), data))
+      .await?;
+
+    let response = self
+      .expect(|frame| frame["kind"] == "response" && frame["tick"] == tick)
+      .await?;
+
+    match response["status"].as_str() {
+      Some("ok") => Ok(()),
+      _ => Err(format!("request refused - {}", response["detail"])),
+    }
+  }
+
+  /// Waits for the controller to send a line containing `needle`.
+  async fn received(&self, needle: &str) -> std::result::Result<(), String> {
+    self
+      .expect(|frame| {
+        let history = frame["history"]
+          .as_array()
+          .or_else(|| frame["state"]["history"].as_array());
+        history.into_iter().flatten().any(|entry| {
+          entry["history_kind"] == "received_data"
+            && entry["content"].as_str().is_some_and(|line| line.contains(needle))
+        })
+      })
+      .await
+      .map(|_| ())
+  }
+
+  async fn connect(&mut self) -> std::result::Result<(), String> {
+    let authority = effects::http::Authority::Admin;
+    self
+      .send(effects::http::Message::ClientConnected(self.client.clone(), authority))
+      .await?;
+    self.expect(|frame| frame["kind"] == "hello").await.map(|_| ())
+  }
+
+  async fn configure(&mut self, device: &str) -> std::result::Result<(), String> {
+    let configuration = serde_json::json!({
+      "kind": "configuration",
+      "device": device,
+      "baud": 115200,
+      "on_connect": [],
+    });
+    self.request(configuration).await?;
+
+    self
+      .expect(|frame| frame["kind"] == "state" && frame["serial_available"] == true)
+      .await
+      .map(|_| ())
+  }
+
+  /// Changes a setting, expecting it to be listed with its new value once the settings are read.
+  async fn settings(&mut self) -> std::result::Result<(), String> {
+    let (id, value) = SETTING;
+    self
+      .request(serde_json::json!({ "kind": "write_setting", "id": id, "value": value }))
+      .await?;
+    self.request(serde_json::json!({ "kind": "read_settings" })).await?;
+
+    // The change is sent on its own before the listing is.
+    let frame = self
+      .expect(|frame| frame["kind"] == "settings" && frame["settings"].as_array().is_some_and(|all| all.len() > 1))
+      .await?;
+    let listed = frame["settings"]
+      .as_array()
+      .into_iter()
+      .flatten()
+      .find(|setting| setting["id"] == id)
+      .and_then(|setting| setting["value"].as_f64());
+
+    match listed {
+      Some(listed) if listed as f32 == value => Ok(()),
+      other => Err(format!("setting {id} listed as {other:?}, expected {value}")),
+    }
+  }
+
+  /// Waits for the job with the provided name to finish, failing unless it completed.
+  async fn finished(jobs: &crate::jobs::JobHistory, name: &str) -> std::result::Result<(), String> {
+    loop {
+      if let Some(record) = jobs.last().filter(|record| record.name.as_deref() == Some(name)) {
+        return match record.outcome {
+          crate::jobs::JobOutcome::Completed => Ok(()),
+          other => Err(format!("job finished as '{other}'")),
+        };
+      }
+
+      async_std::task::sleep(std::time::Duration::from_millis(100)).await;
+    }
+  }
+
+  async fn upload(&mut self, jobs: &crate::jobs::JobHistory) -> std::result::Result<(), String> {
+    let metadata = effects::http::UploadMetadata {
+      name: Some("self-test upload".into()),
+      material: None,
+    };
+    self
+      .send(effects::http::Message::FileUpload(UPLOAD.into(), metadata))
+      .await?;
+
+    Self::finished(jobs, "self-test upload").await
+  }
+
+  async fn stream(&mut self, jobs: &crate::jobs::JobHistory) -> std::result::Result<(), String> {
+    let request = serde_json::json!({ "kind": "start_job", "content": PROGRAM, "name": "self-test" });
+    self.request(request).await?;
+    Self::finished(jobs, "self-test").await
+  }
+
+  async fn alarm(&mut self) -> std::result::Result<(), String> {
+    self
+      .request(serde_json::json!({ "kind": "raw_serial", "value": BEYOND_TRAVEL }))
+      .await?;
+    self.received("ALARM:").await?;
+    self
+      .request(serde_json::json!({ "kind": "raw_serial", "value": "$X" }))
+      .await?;
+    self.received("Unlocked").await
+  }
+
+  /// Drops our client and connects again (under a new id, as the http effect does), expecting the
+  /// control lock it held to have been released and picked up by the new connection.
+  async fn disconnect(&mut self) -> std::result::Result<(), String> {
+    self
+      .send(effects::http::Message::ClientDisconnected(self.client.clone()))
+      .await?;
+
+    self.client = format!("{CLIENT}-reconnected");
+    let authority = effects::http::Authority::Admin;
+    self
+      .send(effects::http::Message::ClientConnected(self.client.clone(), authority))
+      .await?;
+
+    let hello = self.expect(|frame| frame["kind"] == "hello").await?;
+    if hello["state"]["controller"] != self.client.as_str() {
+      return Err(format!(
+        "control not released - held by {}",
+        hello["state"]["controller"]
+      ));
+    }
+
+    self
+      .request(serde_json::json!({ "kind": "raw_serial", "value": "?" }))
+      .await
+  }
+
+  /// Runs every stage, stopping at the first that fails.
+  async fn run(mut self, device: String, jobs: crate::jobs::JobHistory) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+
+    let _ = report.stage("connect", self.connect()).await
+      && report.stage("configure", self.configure(&device)).await
+      && report.stage("settings", self.settings()).await
+      && report.stage("upload", self.upload(&jobs)).await
+      && report.stage("stream", self.stream(&jobs)).await
+      && report.stage("alarm", self.alarm()).await
+      && report.stage("disconnect", self.disconnect()).await;
+
+    report
+  }
+}
+
+/// Runs the self-test. Only the timing, keep-alive and control settings of the configuration are
+/// used; nothing is persisted, no server is started and the configured serial device is left alone.
+pub async fn self_test(config: Configuration) -> Result<SelfTestReport> {
+  // There are no pseudo-terminals to simulate the controller behind outside of unix.
+  #[cfg(unix)]
+  let simulator = crate::simulator::Simulator::open()?;
+  #[cfg(not(unix))]
+  let simulator = crate::simulator::Simulator::listen("127.0.0.1:0")?;
+  let device = simulator
+    .device()
+    .ok_or_else(|| Error::ChannelClosed("simulator terminal has no name".into()))?;
+
+  let running = Arc::new(atomic::AtomicBool::new(true));
+  let simulating = {
+    let running = running.clone();
+    std::thread::spawn(move || simulator.run(running))
+  };
+
+  let jobs = crate::jobs::JobHistory::default();
+  let mut runtime = crate::eff::EffectRuntime::new(Application {
+    jobs: jobs.clone(),
+    ..Application::default()
+  });
+
+  let mut serial_effects = effects::serial::Serial::new(None, SerialParser {});
+  let mut scenario = Scenario::new();
+  runtime.register(&mut serial_effects, SerialFilter {})?;
+  runtime.register(&mut scenario, ScenarioFilter {})?;
+
+  let flags = Configuration {
+    serial: None,
+    journal: None,
+    jobs: None,
+    retention: None,
+    macros: None,
+    terminal: None,
+    ..config
+  };
+
+  let report = async { Ok(scenario.run(device, jobs).await) }
+    .race(async {
+      runtime.run(flags).await?;
+      Err(Error::ChannelClosed("application stopped during self-test".into()))
+    })
+    .race(async {
+      serial_effects.run(SerialMap {}).await?;
+      Err(Error::ChannelClosed(
+        "serial connection stopped during self-test".into(),
+      ))
+    })
+    .await;
+
+  running.store(false, atomic::Ordering::Relaxed);
+  if let Ok(Err(error)) = simulating.join() {
+    tracing::warn!("simulator failed - {error}");
+  }
+
+  report
+}
@@ -0,0 +1,73 @@
+//! The client side of terminal sessions: an interactive prompt that writes each line entered to the
+//! unix socket of a running server, and prints whatever the server writes back (the data received
+//! from the controller). Entering `%quit` (or ctrl-d) detaches.
+
+use crate::error::{Error, Result};
+use std::io::{BufRead, Write};
+
+/// The name of the file (in the home directory) our prompt history is persisted to.
+const HISTORY_FILE: &str = ".costanza_history";
+
+/// Attaches to the terminal socket at the provided path, returning once the session has ended.
+pub fn attach<P>(socket: P) -> Result<()>
+where
+  P: AsRef<std::path::Path>,
+{
+  let mut stream = std::os::unix::net::UnixStream::connect(socket.as_ref())?;
+  let reader = stream.try_clone()?;
+
+  let mut editor = rustyline::Editor::<()>::new()?;
+  let history = std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(HISTORY_FILE));
+
+  if let Some(path) = history.as_ref() {
+    // A missing history file just means this is our first session.
+    let _ = editor.load_history(path);
+  }
+
+  // Whatever the server writes is printed from its own thread so it shows up while we are waiting
+  // on the prompt.
+  let mut printer = editor.create_external_printer()?;
+  let output = std::thread::spawn(move || {
+    for line in std::io::BufReader::new(reader).lines() {
+      let line = match line {
+        Ok(line) => line,
+        Err(_) => break,
+      };
+
+      if rustyline::ExternalPrinter::print(&mut printer, format!("{line}\n")).is_err() {
+        break;
+      }
+    }
+  });
+
+  loop {
+    let line = match editor.readline("> ") {
+      Ok(line) => line,
+      Err(rustyline::error::ReadlineError::Interrupted) => continue,
+      Err(rustyline::error::ReadlineError::Eof) => break,
+      Err(error) => return Err(Error::Prompt(error)),
+    };
+
+    if !line.trim().is_empty() {
+      editor.add_history_entry(line.as_str());
+    }
+
+    if writeln!(stream, "{line}").is_err() || output.is_finished() {
+      break;
+    }
+
+    if line.trim() == "%quit" {
+      break;
+    }
+  }
+
+  if let Some(path) = history.as_ref() {
+    if let Err(error) = editor.save_history(path) {
+      eprintln!("unable to save history - {error}");
+    }
+  }
+
+  let _ = stream.shutdown(std::net::Shutdown::Both);
+  let _ = output.join();
+  Ok(())
+}
@@ -0,0 +1,320 @@
+//! A command line client for a running server, for automation and debugging without a browser. It
+//! connects to the websocket of the server with an api token (created from a signed in browser via
+//! `POST /auth/tokens`), and either runs a single command or opens an interactive console where
+//! every line entered is sent to the controller. Entering `%quit` (or ctrl-d) leaves the console.
+
+#![forbid(unsafe_code)]
+
+use clap::{Parser, Subcommand};
+use costanza::protocol::{ClientMessageRequest, ClientPreferences, RawSerialRequest, StartJobRequest, UploadMetadata};
+use futures_lite::future::FutureExt;
+
+/// The environment variable our api token is read from when not provided as an argument.
+const TOKEN_VARIABLE: &str = "COSTANZA_TOKEN";
+
+#[derive(Parser)]
+#[clap(version = option_env!("COSTANZA_VERSION").unwrap_or("dev"))]
+struct CommandLineArguments {
+  /// The websocket of the server.
+  #[clap(long, short, global = true, default_value = "ws://127.0.0.1:8081/ws")]
+  url: String,
+
+  /// The api token used to connect; read from `COSTANZA_TOKEN` when not provided.
+  #[clap(long, short, global = true)]
+  token: Option<String>,
+
+  /// What to do; without one, the console is opened.
+  #[clap(subcommand)]
+  command: Option<CommandLineCommand>,
+}
+
+#[derive(Subcommand)]
+enum CommandLineCommand {
+  /// Opens an interactive console; every line entered is sent to the controller, and every line the
+  /// controller sends back is printed.
+  Console {
+    /// Also print the status reports the controller sends while it is polled.
+    #[clap(long)]
+    statuses: bool,
+  },
+
+  /// Prints the state of the server.
+  Status {
+    /// Print the state as json.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Uploads a file over the websocket, a chunk at a time, and starts sending it.
+  Upload {
+    file: std::path::PathBuf,
+
+    /// The material that will be cut, recorded in the job history.
+    #[clap(long, short)]
+    material: Option<String>,
+  },
+
+  /// Starts, cancels or resumes jobs.
+  Job {
+    #[clap(subcommand)]
+    command: JobCommand,
+  },
+}
+
+#[derive(Subcommand)]
+enum JobCommand {
+  /// Starts sending a file in a single request.
+  Start {
+    file: std::path::PathBuf,
+
+    /// The material that will be cut, recorded in the job history.
+    #[clap(long, short)]
+    material: Option<String>,
+
+    /// Runs the job without sending any of it to the controller.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Sends only the lines of the file, without the lines configured to be sent before and after
+    /// every job.
+    #[clap(long)]
+    skip_sequences: bool,
+  },
+
+  /// Stops the job being sent.
+  Cancel,
+
+  /// Resumes a job waiting on the operator (e.g after a tool change).
+  Resume,
+}
+
+fn main() -> costanza::Result<()> {
+  if let Err(error) = dotenv::dotenv() {
+    eprintln!("no '.env' file found ({error})");
+  }
+  let arguments = CommandLineArguments::parse();
+
+  let token = match arguments.token.clone().or_else(|| std::env::var(TOKEN_VARIABLE).ok()) {
+    Some(token) => token,
+    None => {
+      eprintln!("an api token is required, either through '--token' or '{TOKEN_VARIABLE}'");
+      std::process::exit(2);
+    }
+  };
+
+  let accepted = async_std::task::block_on(async {
+    let mut client = costanza::Client::connect(&arguments.url, &token).await?;
+
+    let accepted = match arguments
+      .command
+      .unwrap_or(CommandLineCommand::Console { statuses: false })
+    {
+      CommandLineCommand::Console { statuses } => {
+        console(&mut client, statuses).await?;
+        true
+      }
+      CommandLineCommand::Status { json } => {
+        status(client.hello(), json)?;
+        true
+      }
+      CommandLineCommand::Upload { file, material } => {
+        let content = std::fs::read_to_string(&file)?;
+        let metadata = metadata(&file, material);
+        operate(&mut client).await? && report(&client.upload(&content, metadata).await?)
+      }
+      CommandLineCommand::Job { command } => {
+        let request = match command {
+          JobCommand::Start {
+            file,
+            material,
+            dry_run,
+            skip_sequences,
+          } => ClientMessageRequest::StartJob(StartJobRequest {
+            content: std::fs::read_to_string(&file)?,
+            dry_run,
+            transform: None,
+            skip_sequences,
+            metadata: metadata(&file, material),
+          }),
+          JobCommand::Cancel => ClientMessageRequest::CancelJob,
+          JobCommand::Resume => ClientMessageRequest::ResumeJob,
+        };
+
+        operate(&mut client).await? && report(&client.request(request).await?)
+      }
+    };
+
+    client.close().await?;
+    costanza::Result::Ok(accepted)
+  })?;
+
+  if !accepted {
+    std::process::exit(1);
+  }
+
+  Ok(())
+}
+
+/// The metadata recorded for a file, named after it.
+fn metadata(file: &std::path::Path, material: Option<String>) -> UploadMetadata {
+  UploadMetadata {
+    name: file.file_name().map(|name| name.to_string_lossy().into_owned()),
+    material,
+  }
+}
+
+/// Prints why a request was not accepted, returning whether or not it was.
+fn report(response: &costanza::protocol::ClientResponse) -> bool {
+  if costanza::client::accepted(response) {
+    return true;
+  }
+
+  let reason = response
+    .detail
+    .as_deref()
+    .or(response.code.as_deref())
+    .unwrap_or(response.status.as_str());
+  eprintln!("request refused - {reason}");
+  false
+}
+
+/// Acquires the control lock unless we already hold it, returning whether or not we do.
+async fn operate(client: &mut costanza::Client) -> costanza::Result<bool> {
+  if client.hello()["state"]["role"] == "operator" {
+    return Ok(true);
+  }
+
+  let response = client.request(ClientMessageRequest::AcquireControl).await?;
+  Ok(report(&response))
+}
+
+/// Prints the state from our hello frame.
+fn status(hello: &serde_json::Value, json: bool) -> costanza::Result<()> {
+  let state = &hello["state"];
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(state)?);
+    return Ok(());
+  }
+
+  println!("server:   {}", hello["server_version"].as_str().unwrap_or("unknown"));
+  println!(
+    "serial:   {}",
+    if state["serial_available"] == true {
+      "available"
+    } else {
+      "unavailable"
+    }
+  );
+  println!("operator: {}", state["controller"].as_str().unwrap_or("none"));
+
+  let position = &state["position"];
+  if let (Some(x), Some(y), Some(z)) = (position["x"].as_f64(), position["y"].as_f64(), position["z"].as_f64()) {
+    println!("position: x {x:.3}, y {y:.3}, z {z:.3}");
+  }
+
+  let job = &state["job"];
+  if let (Some(line), Some(total)) = (job["line"].as_u64(), job["total"].as_u64()) {
+    let name = job["name"].as_str().unwrap_or("unnamed");
+    println!("job:      {name} (line {line} of {total})");
+  }
+
+  if let Some(line) = state["paused"]["line"].as_str() {
+    println!("paused:   {line}");
+  }
+
+  let last = &state["last_job"];
+  if let Some(outcome) = last["outcome"].as_str() {
+    let name = last["name"].as_str().unwrap_or("unnamed");
+    println!("last job: {name} ({outcome})");
+  }
+
+  Ok(())
+}
+
+/// What the console is woken up by.
+enum Wake {
+  Frame(costanza::Result<Option<serde_json::Value>>),
+  Line(Option<String>),
+}
+
+/// Runs the interactive console until it is left (or the server closes our connection). Without the
+/// control lock, the console only shows what the controller sends; the lines entered are refused.
+async fn console(client: &mut costanza::Client, statuses: bool) -> costanza::Result<()> {
+  let preferences = ClientPreferences {
+    statuses,
+    verbose: true,
+  };
+  let request = ClientMessageRequest::SetPreferences(preferences);
+  report(&client.request(request).await?);
+  operate(client).await?;
+
+  let mut editor = rustyline::Editor::<()>::new()?;
+  let mut printer = editor.create_external_printer()?;
+
+  // The prompt blocks while waiting on a line, so it is run on its own thread; whatever the
+  // controller sends is printed through the editor so it shows up while we are waiting on it.
+  let (lines, entered) = async_std::channel::bounded::<String>(1);
+  std::thread::spawn(move || loop {
+    let line = match editor.readline("> ") {
+      Ok(line) => line,
+      Err(rustyline::error::ReadlineError::Interrupted) => continue,
+      Err(_) => break,
+    };
+
+    if !line.trim().is_empty() {
+      editor.add_history_entry(line.as_str());
+    }
+
+    if line.trim() == "%quit" || async_std::task::block_on(lines.send(line)).is_err() {
+      break;
+    }
+  });
+
+  let mut pending = std::collections::HashSet::new();
+
+  loop {
+    let frame = async { Wake::Frame(client.frame().await) };
+    let line = async { Wake::Line(entered.recv().await.ok()) };
+
+    match frame.race(line).await {
+      Wake::Frame(frame) => {
+        let frame = match frame? {
+          Some(frame) => frame,
+          None => {
+            eprintln!("connection closed by the server");
+            break;
+          }
+        };
+
+        match frame["kind"].as_str() {
+          Some("state") => {
+            for line in costanza::client::received(&frame) {
+              if rustyline::ExternalPrinter::print(&mut printer, format!("{line}\n")).is_err() {
+                break;
+              }
+            }
+          }
+          Some("response") => {
+            let response = serde_json::from_value::<costanza::protocol::ClientResponse>(frame)?;
+            if pending.remove(&response.tick) {
+              report(&response);
+            }
+          }
+          _ => (),
+        }
+      }
+      Wake::Line(Some(line)) if line.trim().is_empty() => (),
+      Wake::Line(Some(line)) => {
+        let request = ClientMessageRequest::RawSerial(RawSerialRequest {
+          value: line.trim().to_string(),
+          force: false,
+        });
+        pending.insert(client.send(request).await?);
+      }
+      Wake::Line(None) => break,
+    }
+  }
+
+  Ok(())
+}
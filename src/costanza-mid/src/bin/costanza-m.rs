@@ -1,30 +1,250 @@
 #![forbid(unsafe_code)]
 
-use clap::Parser;
-use std::io;
+use clap::{CommandFactory, Parser, Subcommand};
 use tracing_subscriber::prelude::*;
 
 #[derive(Parser)]
 #[clap(version = option_env!("COSTANZA_VERSION").unwrap_or("dev"))]
 struct CommandLineArguments {
-  #[clap(long, short)]
-  config: String,
+  /// Our configuration; required by every command other than `list-ports` and `send`.
+  #[clap(long, short, global = true)]
+  config: Option<String>,
+
+  /// When provided, the messages recorded in this journal will be replayed through the application
+  /// instead of running the normal effect runtimes.
+  #[clap(long)]
+  replay: Option<String>,
+
+  /// A multiplier applied to the original timing between messages during a replay.
+  #[clap(long, default_value_t = 1.0)]
+  replay_speed: f64,
+
+  /// When provided, the migrations that would be applied to our persisted data are verified and
+  /// printed instead of running the application.
+  #[clap(long)]
+  check_migrations: bool,
+
+  /// What to do; without one, the server is run.
+  #[clap(subcommand)]
+  command: Option<CommandLineCommand>,
 }
 
-fn main() -> io::Result<()> {
+#[derive(Subcommand)]
+enum CommandLineCommand {
+  /// Runs the server; this is what happens when no command is provided.
+  Serve,
+
+  /// Validates the configuration and prints any issues with it. Exits with a non-zero status if any
+  /// of them are errors.
+  CheckConfig {
+    /// Print the issues as json.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Lists the serial devices found on this machine.
+  ListPorts,
+
+  /// Sends lines straight to a serial device (without running the server), printing whatever the
+  /// device responds with. Each line waits on its `ok` before the next is sent. Exits with a
+  /// non-zero status if any of them are answered with an error.
+  Send {
+    /// The serial device to send to, e.g `/dev/ttyUSB0` (or `COM3` on windows).
+    #[clap(long, short)]
+    device: String,
+
+    #[clap(long, short, default_value_t = 115200)]
+    baud: u32,
+
+    /// The amount of seconds to wait on the answer to each line.
+    #[clap(long, default_value_t = 10)]
+    timeout: u64,
+
+    /// The lines to send, e.g `$I` or `G0 X10`.
+    #[clap(required = true)]
+    lines: Vec<String>,
+  },
+
+  /// Writes an archive containing the (redacted) configuration, version and recent message journal
+  /// that can be attached to bug reports. Bundles with logs can be downloaded from a running server
+  /// at `/api/diagnostics/bundle`.
+  BundleDebug {
+    /// Where the archive will be written.
+    #[clap(long, short, default_value = "costanza-debug.tar.gz")]
+    output: String,
+  },
+
+  /// Runs the application against a simulated controller, walking through connecting, configuring,
+  /// streaming a small file and clearing an alarm. Exits with a non-zero status if any of it fails.
+  SelfTest,
+
+  /// Opens an interactive prompt connected directly to the serial connection of a running server.
+  /// While attached, the server holds the control lock and pauses any job being sent. Enter `%quit`
+  /// to detach.
+  #[cfg(unix)]
+  Attach {
+    /// The unix socket to attach through; defaults to the socket in our configuration.
+    #[clap(long, short)]
+    socket: Option<String>,
+  },
+}
+
+fn main() -> costanza::Result<()> {
   if let Err(error) = dotenv::dotenv() {
     eprintln!("no '.env' file found ({error})");
   }
   let arguments = CommandLineArguments::parse();
-  let config_contents = std::fs::read_to_string(&arguments.config)?;
-  let config = toml::from_str::<costanza::Configuration>(config_contents.as_str())?;
+
+  // Talking to serial devices directly does not involve our configuration at all.
+  match arguments.command.as_ref() {
+    Some(CommandLineCommand::ListPorts) => {
+      let ports = costanza::list_ports()?;
+
+      if ports.is_empty() {
+        println!("no serial devices found");
+      }
+
+      for port in ports {
+        println!("{port}");
+      }
+
+      return Ok(());
+    }
+    Some(CommandLineCommand::Send {
+      device,
+      baud,
+      timeout,
+      lines,
+    }) => {
+      let timeout = std::time::Duration::from_secs(*timeout);
+      let accepted = costanza::send_lines(device, *baud, lines, timeout, |line| println!("{line}"))?;
+
+      if !accepted {
+        std::process::exit(1);
+      }
+
+      return Ok(());
+    }
+    _ => (),
+  }
+
+  let config_path = match arguments.config.as_ref() {
+    Some(path) => path.clone(),
+    None => CommandLineArguments::command()
+      .error(
+        clap::error::ErrorKind::MissingRequiredArgument,
+        "a configuration is required (--config <CONFIG>)",
+      )
+      .exit(),
+  };
+  let config_contents = std::fs::read_to_string(&config_path)?;
+  let config = costanza::load_configuration::<costanza::Configuration>(config_contents.as_str())?;
+
+  let validation = costanza::validate(&config);
+
+  if let Some(CommandLineCommand::CheckConfig { json }) = arguments.command.as_ref() {
+    if *json {
+      println!("{}", serde_json::to_string_pretty(&validation)?);
+    } else if validation.issues().is_empty() {
+      println!("configuration is valid");
+    } else {
+      for issue in validation.issues() {
+        println!("{issue}");
+      }
+    }
+
+    if validation.has_errors() {
+      std::process::exit(1);
+    }
+
+    return Ok(());
+  }
+
+  // Recent logs are held on to so they can be included in debug bundles.
+  let logs = costanza::LogBuffer::default();
+  let logging = costanza::logging(&config);
+  let json = logging.format() == costanza::LogFormat::Json;
+
+  let mut layers: Vec<Box<dyn tracing_subscriber::Layer<tracing_subscriber::Registry> + Send + Sync>> =
+    vec![tracing_subscriber::fmt::layer()
+      .with_ansi(false)
+      .with_writer(logs.clone())
+      .boxed()];
+
+  // Our output and log files share a format; the lines held for debug bundles are always human.
+  layers.push(if json {
+    tracing_subscriber::fmt::layer().json().boxed()
+  } else {
+    tracing_subscriber::fmt::layer().boxed()
+  });
+
+  if let Some(appender) = logging.appender()? {
+    let file = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(appender);
+    layers.push(if json { file.json().boxed() } else { file.boxed() });
+  }
 
   tracing_subscriber::registry()
-    .with(tracing_subscriber::fmt::layer())
-    .with(tracing_subscriber::EnvFilter::from_default_env())
+    .with(layers)
+    .with(logging.filter())
     .init();
 
   tracing::event!(tracing::Level::INFO, "configuration ready, running application");
   tracing::event!(tracing::Level::DEBUG, "{config:?}");
-  async_std::task::block_on(costanza::run(config))
+
+  if let Some(CommandLineCommand::BundleDebug { output }) = arguments.command.as_ref() {
+    std::fs::write(output, costanza::bundle_debug(&config)?)?;
+    println!("wrote debug bundle to '{output}'");
+    return Ok(());
+  }
+
+  #[cfg(unix)]
+  if let Some(CommandLineCommand::Attach { socket }) = arguments.command.as_ref() {
+    let socket = socket
+      .as_deref()
+      .or_else(|| costanza::terminal_socket(&config))
+      .ok_or_else(|| costanza::Error::Unconfigured("no terminal socket configured".into()))?;
+    return costanza::attach(socket);
+  }
+
+  if let Some(CommandLineCommand::SelfTest) = arguments.command.as_ref() {
+    let report = async_std::task::block_on(costanza::self_test(config))?;
+    println!("{report}");
+
+    if !report.passed() {
+      std::process::exit(1);
+    }
+
+    return Ok(());
+  }
+
+  if arguments.check_migrations {
+    let pending = costanza::check_migrations(&config)?;
+
+    if pending.is_empty() {
+      println!("no pending migrations");
+    }
+
+    for migration in pending {
+      println!("pending: {migration}");
+    }
+
+    return Ok(());
+  }
+
+  if let Some(journal) = arguments.replay {
+    tracing::event!(tracing::Level::INFO, "replaying journal '{journal}'");
+    return async_std::task::block_on(costanza::replay(config, journal, arguments.replay_speed));
+  }
+
+  // Rather than half-starting with a configuration we know is bad, refuse to run at all.
+  for issue in validation.issues() {
+    tracing::event!(tracing::Level::WARN, "{issue}");
+  }
+
+  if validation.has_errors() {
+    tracing::event!(tracing::Level::ERROR, "invalid configuration (see 'check-config')");
+    std::process::exit(1);
+  }
+
+  async_std::task::block_on(costanza::run(config, Some(config_path.into()), logs))
 }
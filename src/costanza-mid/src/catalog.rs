@@ -0,0 +1,500 @@
+//! Every error a user may be shown is given a stable code here, along with the names of the
+//! parameters that fill in its message. Clients are sent the code and parameters (never only the
+//! english message), and can fetch this catalog from `/api/errors` to localize or document them.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The area of the application an error belongs to.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+  /// Malformed or unknown requests.
+  Request,
+
+  /// The control lock.
+  Control,
+
+  /// The serial connection.
+  Serial,
+
+  /// Jobs, macros and the file queue.
+  Job,
+
+  /// Uploaded files.
+  Upload,
+
+  /// Sessions and authentication.
+  Auth,
+
+  /// Errors and alarms reported by the controller itself.
+  Grbl,
+
+  /// The camera proxied by our server.
+  Camera,
+}
+
+/// The stable identifier of every error in the catalog. Codes are only ever added; a code that is
+/// no longer used stays in the catalog so older clients can still render it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+  ParseError,
+  InvalidRequest,
+  RequestTimeout,
+  NotFound,
+  RateLimited,
+  ObserverCannotOperate,
+  ControlHeldByTerminal,
+  ControlHeldElsewhere,
+  NotOperator,
+  SerialUnavailable,
+  SerialNotIdle,
+  JobActive,
+  RawDuringJob,
+  JogDuringJob,
+  NoActiveJob,
+  StepModeOff,
+  NoLastJob,
+  LineOutOfRange,
+  MacroNotFound,
+  MacroNotIdle,
+  UploadMissingType,
+  UploadInvalidType,
+  UploadTooLarge,
+  UploadInvalidEncoding,
+  SessionNotFound,
+  UserNotFound,
+  AuthCodeMissing,
+  AuthProviderFailed,
+  InsufficientAuthority,
+  CsrfMismatch,
+  GrblError,
+  GrblAlarm,
+  CameraUnavailable,
+  ProbeDuringJob,
+  JobNotPaused,
+  BeyondLimits,
+  PositionUnknown,
+  TransformFailed,
+  NoInterruptedJob,
+  SessionNotResumable,
+  UploadNotStarted,
+  UploadOutOfOrder,
+  UploadIncomplete,
+}
+
+/// The catalog entry of a single code.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+  /// The identifier sent to clients.
+  pub code: &'static str,
+
+  /// Where the error comes from.
+  pub category: Category,
+
+  /// The http status used when the error is returned from a route.
+  pub status: u16,
+
+  /// The english message, with `{name}` slots for each parameter.
+  pub message: &'static str,
+
+  /// The names of the parameters provided alongside the code.
+  pub params: &'static [&'static str],
+}
+
+impl Code {
+  /// Every code, in the order they are listed by `/api/errors`.
+  pub const ALL: &'static [Code] = &[
+    Self::ParseError,
+    Self::InvalidRequest,
+    Self::RequestTimeout,
+    Self::NotFound,
+    Self::RateLimited,
+    Self::ObserverCannotOperate,
+    Self::ControlHeldByTerminal,
+    Self::ControlHeldElsewhere,
+    Self::NotOperator,
+    Self::SerialUnavailable,
+    Self::SerialNotIdle,
+    Self::JobActive,
+    Self::RawDuringJob,
+    Self::JogDuringJob,
+    Self::NoActiveJob,
+    Self::StepModeOff,
+    Self::NoLastJob,
+    Self::LineOutOfRange,
+    Self::MacroNotFound,
+    Self::MacroNotIdle,
+    Self::UploadMissingType,
+    Self::UploadInvalidType,
+    Self::UploadTooLarge,
+    Self::UploadInvalidEncoding,
+    Self::SessionNotFound,
+    Self::UserNotFound,
+    Self::AuthCodeMissing,
+    Self::AuthProviderFailed,
+    Self::InsufficientAuthority,
+    Self::CsrfMismatch,
+    Self::GrblError,
+    Self::GrblAlarm,
+    Self::CameraUnavailable,
+    Self::ProbeDuringJob,
+    Self::JobNotPaused,
+    Self::BeyondLimits,
+    Self::PositionUnknown,
+    Self::TransformFailed,
+    Self::NoInterruptedJob,
+    Self::SessionNotResumable,
+    Self::UploadNotStarted,
+    Self::UploadOutOfOrder,
+    Self::UploadIncomplete,
+  ];
+
+  /// Returns the catalog entry of this code.
+  pub fn entry(self) -> Entry {
+    let (code, category, status, message, params): (_, _, _, _, &'static [&'static str]) = match self {
+      Self::ParseError => (
+        "parse_error",
+        Category::Request,
+        422,
+        "unable to parse request - {error}",
+        &["error"],
+      ),
+      Self::InvalidRequest => (
+        "invalid_request",
+        Category::Request,
+        422,
+        "the request is not valid",
+        &[],
+      ),
+      Self::RequestTimeout => (
+        "request_timeout",
+        Category::Request,
+        504,
+        "the request was not answered in time",
+        &[],
+      ),
+      Self::NotFound => ("not_found", Category::Request, 404, "not found", &[]),
+      Self::RateLimited => (
+        "rate_limited",
+        Category::Request,
+        429,
+        "too many requests; slow down and try again",
+        &[],
+      ),
+      Self::ObserverCannotOperate => (
+        "observer_cannot_operate",
+        Category::Control,
+        403,
+        "observers cannot take control",
+        &[],
+      ),
+      Self::ControlHeldByTerminal => (
+        "control_held_by_terminal",
+        Category::Control,
+        403,
+        "control is held by an attached terminal",
+        &[],
+      ),
+      Self::ControlHeldElsewhere => (
+        "control_held_elsewhere",
+        Category::Control,
+        403,
+        "control is held by another client",
+        &[],
+      ),
+      Self::NotOperator => (
+        "not_operator",
+        Category::Control,
+        403,
+        "only the operator may control the machine",
+        &[],
+      ),
+      Self::SerialUnavailable => (
+        "serial_unavailable",
+        Category::Serial,
+        503,
+        "the machine is not connected",
+        &[],
+      ),
+      Self::SerialNotIdle => ("serial_not_idle", Category::Serial, 503, "the machine is not idle", &[]),
+      Self::JobActive => ("job_active", Category::Job, 409, "a job is already active", &[]),
+      Self::RawDuringJob => (
+        "raw_during_job",
+        Category::Job,
+        409,
+        "raw lines cannot be sent while a job is active",
+        &[],
+      ),
+      Self::JogDuringJob => (
+        "jog_during_job",
+        Category::Job,
+        409,
+        "the machine cannot be jogged during a job",
+        &[],
+      ),
+      Self::NoActiveJob => (
+        "no_active_job",
+        Category::Job,
+        409,
+        "there is no job to step through",
+        &[],
+      ),
+      Self::StepModeOff => ("step_mode_off", Category::Job, 409, "step mode is not on", &[]),
+      Self::NoLastJob => ("no_last_job", Category::Job, 404, "there is no job to run from", &[]),
+      Self::LineOutOfRange => (
+        "line_out_of_range",
+        Category::Job,
+        404,
+        "line {line} is outside of the job ({total} lines)",
+        &["line", "total"],
+      ),
+      Self::MacroNotFound => (
+        "macro_not_found",
+        Category::Job,
+        404,
+        "no macro named '{name}'",
+        &["name"],
+      ),
+      Self::MacroNotIdle => (
+        "macro_not_idle",
+        Category::Job,
+        409,
+        "macros can only be run while the machine is idle",
+        &[],
+      ),
+      Self::UploadMissingType => (
+        "upload_missing_type",
+        Category::Upload,
+        422,
+        "the upload is missing a content type",
+        &[],
+      ),
+      Self::UploadInvalidType => (
+        "upload_invalid_type",
+        Category::Upload,
+        422,
+        "only text files can be uploaded",
+        &[],
+      ),
+      Self::UploadTooLarge => (
+        "upload_too_large",
+        Category::Upload,
+        422,
+        "the upload is empty or too large",
+        &[],
+      ),
+      Self::UploadInvalidEncoding => (
+        "upload_invalid_encoding",
+        Category::Upload,
+        422,
+        "the upload is not valid utf-8",
+        &[],
+      ),
+      Self::SessionNotFound => ("session_not_found", Category::Auth, 404, "no session was found", &[]),
+      Self::UserNotFound => (
+        "user_not_found",
+        Category::Auth,
+        404,
+        "the user could not be found",
+        &[],
+      ),
+      Self::AuthCodeMissing => (
+        "auth_code_missing",
+        Category::Auth,
+        404,
+        "the sign in is missing its code",
+        &[],
+      ),
+      Self::AuthProviderFailed => (
+        "auth_provider_failed",
+        Category::Auth,
+        500,
+        "the sign in provider could not be reached",
+        &[],
+      ),
+      Self::InsufficientAuthority => (
+        "insufficient_authority",
+        Category::Auth,
+        403,
+        "this requires the '{authority}' authority",
+        &["authority"],
+      ),
+      Self::CsrfMismatch => (
+        "csrf_mismatch",
+        Category::Auth,
+        403,
+        "the request is missing a valid csrf token",
+        &[],
+      ),
+      Self::GrblError => (
+        "grbl_error",
+        Category::Grbl,
+        500,
+        "the controller reported error {number}",
+        &["number"],
+      ),
+      Self::GrblAlarm => (
+        "grbl_alarm",
+        Category::Grbl,
+        500,
+        "the controller reported alarm {number}",
+        &["number"],
+      ),
+      Self::CameraUnavailable => (
+        "camera_unavailable",
+        Category::Camera,
+        502,
+        "the camera could not be reached",
+        &[],
+      ),
+      Self::ProbeDuringJob => (
+        "probe_during_job",
+        Category::Job,
+        409,
+        "the machine cannot probe during a job",
+        &[],
+      ),
+      Self::JobNotPaused => (
+        "job_not_paused",
+        Category::Job,
+        409,
+        "the job is not waiting to be resumed",
+        &[],
+      ),
+      Self::BeyondLimits => (
+        "beyond_limits",
+        Category::Serial,
+        422,
+        "the move would take the {axis} axis to {target}, beyond its travel limits",
+        &["axis", "target"],
+      ),
+      Self::PositionUnknown => (
+        "position_unknown",
+        Category::Serial,
+        409,
+        "the machine position is not known yet; moves cannot be checked against its travel limits",
+        &[],
+      ),
+      Self::TransformFailed => (
+        "transform_failed",
+        Category::Job,
+        422,
+        "the file could not be transformed - {error}",
+        &["error"],
+      ),
+      Self::NoInterruptedJob => (
+        "no_interrupted_job",
+        Category::Job,
+        404,
+        "there is no interrupted job to resume",
+        &[],
+      ),
+      Self::SessionNotResumable => (
+        "session_not_resumable",
+        Category::Auth,
+        404,
+        "there is no disconnected client to resume with that token",
+        &[],
+      ),
+      Self::UploadNotStarted => (
+        "upload_not_started",
+        Category::Upload,
+        409,
+        "no upload has been started",
+        &[],
+      ),
+      Self::UploadOutOfOrder => (
+        "upload_out_of_order",
+        Category::Upload,
+        409,
+        "expected chunk {expected} of the upload",
+        &["expected"],
+      ),
+      Self::UploadIncomplete => (
+        "upload_incomplete",
+        Category::Upload,
+        422,
+        "only {received} of the {size} bytes of the upload have been received",
+        &["received", "size"],
+      ),
+    };
+
+    Entry {
+      code,
+      category,
+      status,
+      message,
+      params,
+    }
+  }
+
+  /// Returns the identifier sent to clients.
+  pub fn as_str(self) -> &'static str {
+    self.entry().code
+  }
+
+  /// Returns the code with the provided identifier.
+  pub fn lookup(code: &str) -> Option<Self> {
+    Self::ALL.iter().copied().find(|known| known.as_str() == code)
+  }
+}
+
+impl Serialize for Code {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: serde::Serializer,
+  {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+/// A code along with the values of its parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+  /// What went wrong.
+  pub code: Code,
+
+  /// The values filling in the message of the code.
+  pub params: BTreeMap<&'static str, String>,
+}
+
+impl Failure {
+  /// Returns a failure without any parameters.
+  pub fn new(code: Code) -> Self {
+    Self {
+      code,
+      params: BTreeMap::default(),
+    }
+  }
+
+  /// Sets the value of a parameter.
+  pub fn with<V>(mut self, name: &'static str, value: V) -> Self
+  where
+    V: std::fmt::Display,
+  {
+    self.params.insert(name, value.to_string());
+    self
+  }
+
+  /// Returns the english message of the code, with its parameters filled in.
+  pub fn render(&self) -> String {
+    self
+      .params
+      .iter()
+      .fold(self.code.entry().message.to_string(), |message, (name, value)| {
+        message.replace(&format!("{{{name}}}"), value)
+      })
+  }
+}
+
+impl From<Code> for Failure {
+  fn from(code: Code) -> Self {
+    Self::new(code)
+  }
+}
+
+/// Returns every entry of the catalog.
+pub fn entries() -> Vec<Entry> {
+  Code::ALL.iter().map(|code| code.entry()).collect()
+}
@@ -0,0 +1,190 @@
+//! A websocket client for a running server, authenticated with an api token (see `POST
+//! /auth/tokens`). Requests and responses are the ones defined in our `protocol` module; the frames
+//! carrying state are left as json, since callers tend to only care about a few of their fields.
+
+use crate::error::{Error, Result};
+use crate::protocol::{
+  BeginUploadRequest, ClientMessage, ClientMessageRequest, ClientResponse, UploadChunkRequest, UploadMetadata,
+};
+use async_tungstenite::tungstenite;
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use futures::{SinkExt, StreamExt};
+
+/// The most bytes sent in a single chunk of an upload.
+const UPLOAD_CHUNK_SIZE: usize = 16 * 1024;
+
+/// An open connection to the websocket of a running server.
+pub struct Client {
+  websocket: async_tungstenite::WebSocketStream<async_tungstenite::async_std::ConnectStream>,
+
+  /// The tick of the most recent request we sent.
+  tick: u32,
+
+  /// The first frame we were sent, with a complete snapshot of our state.
+  hello: serde_json::Value,
+}
+
+impl Client {
+  /// Connects to the websocket at the provided address (e.g `ws://127.0.0.1:8081/ws`), returning
+  /// once the server has greeted us.
+  pub async fn connect(addr: &str, token: &str) -> Result<Self> {
+    let mut request = addr.into_client_request().map_err(failure)?;
+    let value = tungstenite::http::HeaderValue::from_str(&format!("Bearer {}", token.trim()))
+      .map_err(|error| Error::Websocket(format!("invalid token - {error}")))?;
+    request.headers_mut().insert("Authorization", value);
+
+    let websocket = match async_tungstenite::async_std::connect_async(request).await {
+      Ok((websocket, _)) => websocket,
+      // The server responds to upgrades it cannot authenticate as if there were nothing there.
+      Err(tungstenite::Error::Http(response)) if matches!(response.status().as_u16(), 401 | 403 | 404) => {
+        let message = format!("connection refused ({}); is the token valid?", response.status());
+        return Err(Error::Websocket(message));
+      }
+      Err(error) => return Err(failure(error)),
+    };
+
+    let mut client = Self {
+      websocket,
+      tick: 0,
+      hello: serde_json::Value::Null,
+    };
+    client.hello = client.expect(|frame| frame["kind"] == "hello").await?;
+    Ok(client)
+  }
+
+  /// The hello frame we were greeted with.
+  pub fn hello(&self) -> &serde_json::Value {
+    &self.hello
+  }
+
+  /// Waits on the next frame sent to us, returning `None` once the server has closed the connection.
+  pub async fn frame(&mut self) -> Result<Option<serde_json::Value>> {
+    while let Some(message) = self.websocket.next().await {
+      match message.map_err(failure)? {
+        tungstenite::Message::Text(text) => return serde_json::from_str(&text).map(Some).map_err(Error::from),
+        tungstenite::Message::Close(_) => break,
+        _ => continue,
+      }
+    }
+
+    Ok(None)
+  }
+
+  /// Sends a request without waiting on its response, returning the tick its response will have.
+  pub async fn send(&mut self, request: ClientMessageRequest) -> Result<u32> {
+    self.tick += 1;
+    let message = ClientMessage {
+      tick: self.tick,
+      request,
+    };
+    let payload = serde_json::to_string(&message)?;
+    self
+      .websocket
+      .send(tungstenite::Message::Text(payload))
+      .await
+      .map_err(failure)?;
+    Ok(self.tick)
+  }
+
+  /// Sends a request and waits on its response. Any other frame received in the meantime is dropped.
+  pub async fn request(&mut self, request: ClientMessageRequest) -> Result<ClientResponse> {
+    let tick = self.send(request).await?;
+    let frame = self
+      .expect(|frame| frame["kind"] == "response" && frame["tick"] == tick)
+      .await?;
+    serde_json::from_value(frame).map_err(Error::from)
+  }
+
+  /// Uploads a file a chunk at a time and starts sending it, returning the first response that was
+  /// not accepted (or the response to `FinishUpload`).
+  pub async fn upload(&mut self, content: &str, metadata: UploadMetadata) -> Result<ClientResponse> {
+    let begin = BeginUploadRequest {
+      size: content.len(),
+      metadata,
+    };
+    let response = self.request(ClientMessageRequest::BeginUpload(begin)).await?;
+    if !accepted(&response) {
+      return Ok(response);
+    }
+
+    for (seq, data) in (0..).zip(chunks(content, UPLOAD_CHUNK_SIZE)) {
+      let chunk = UploadChunkRequest {
+        seq,
+        data: data.to_string(),
+      };
+      let response = self.request(ClientMessageRequest::UploadChunk(chunk)).await?;
+      if !accepted(&response) {
+        return Ok(response);
+      }
+    }
+
+    self.request(ClientMessageRequest::FinishUpload).await
+  }
+
+  /// Closes the connection.
+  pub async fn close(mut self) -> Result<()> {
+    self.websocket.close(None).await.map_err(failure)
+  }
+
+  /// Waits on the first frame matching the provided predicate.
+  async fn expect<P>(&mut self, predicate: P) -> Result<serde_json::Value>
+  where
+    P: Fn(&serde_json::Value) -> bool,
+  {
+    loop {
+      match self.frame().await? {
+        Some(frame) if predicate(&frame) => return Ok(frame),
+        Some(_) => continue,
+        None => return Err(Error::Websocket("connection closed by the server".into())),
+      }
+    }
+  }
+}
+
+/// Whether or not a response reports its request as accepted.
+pub fn accepted(response: &ClientResponse) -> bool {
+  response.status == "ok"
+}
+
+/// Returns every line received from the controller in the history of a state (or hello) frame.
+pub fn received(frame: &serde_json::Value) -> Vec<&str> {
+  let history = frame["history"]
+    .as_array()
+    .or_else(|| frame["state"]["history"].as_array());
+
+  history
+    .into_iter()
+    .flatten()
+    .filter(|entry| entry["history_kind"] == "received_data")
+    .filter_map(|entry| entry["content"].as_str())
+    .collect()
+}
+
+/// Splits text into chunks of at most `size` bytes (or a single character, when it is larger),
+/// without splitting any character.
+fn chunks(text: &str, size: usize) -> impl Iterator<Item = &str> {
+  let mut rest = text;
+
+  std::iter::from_fn(move || {
+    if rest.is_empty() {
+      return None;
+    }
+
+    let mut end = size.min(rest.len());
+    while !rest.is_char_boundary(end) {
+      end -= 1;
+    }
+    if end == 0 {
+      end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+    }
+
+    let (chunk, remainder) = rest.split_at(end);
+    rest = remainder;
+    Some(chunk)
+  })
+}
+
+/// Wraps a failure of the websocket itself.
+fn failure(error: tungstenite::Error) -> Error {
+  Error::Websocket(error.to_string())
+}
@@ -0,0 +1,210 @@
+//! This module contains the debug bundle: a single archive with everything needed to look into a
+//! problem reported by someone else (logs, configuration, version, recent messages, and the serial
+//! transcript). Anything that looks like a secret is redacted before it is added.
+
+use crate::error::Result;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// The amount of log lines held on to by a `LogBuffer`.
+const LOG_CAPACITY: usize = 2_000;
+
+/// The amount of the most recent journal lines included in a bundle.
+const TIMELINE_CAPACITY: usize = 500;
+
+/// Configuration keys containing any of these are considered secret.
+const SECRET_KEYS: &[&str] = &["secret", "password", "token", "cookie", "authorization"];
+
+/// What secrets are replaced with.
+const REDACTED: &str = "[redacted]";
+
+/// A cheaply cloneable handle to the most recent log lines written by this process. This is meant
+/// to be installed as the writer of a `tracing_subscriber` fmt layer.
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<String>>>);
+
+impl LogBuffer {
+  /// Returns every line currently held, oldest first.
+  fn lines(&self) -> Vec<String> {
+    self.with(|lines| lines.iter().cloned().collect())
+  }
+
+  /// Runs the closure against our locked lines.
+  fn with<F, T>(&self, apply: F) -> T
+  where
+    F: FnOnce(&mut VecDeque<String>) -> T,
+  {
+    let mut lines = match self.0.lock() {
+      Ok(lines) => lines,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    apply(&mut lines)
+  }
+}
+
+impl std::io::Write for LogBuffer {
+  fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+    let contents = String::from_utf8_lossy(buffer);
+
+    self.with(|lines| {
+      for line in contents.lines() {
+        if lines.len() >= LOG_CAPACITY {
+          lines.pop_front();
+        }
+
+        lines.push_back(line.to_string());
+      }
+    });
+
+    Ok(buffer.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for LogBuffer {
+  type Writer = Self;
+
+  fn make_writer(&'a self) -> Self::Writer {
+    self.clone()
+  }
+}
+
+/// Everything needed to build a debug bundle. This is created once at startup and shared with
+/// anything that wants to produce a bundle.
+#[derive(Clone)]
+pub struct Diagnostics {
+  /// The configuration we are running with, as json, with secrets redacted.
+  config: String,
+
+  /// The values that were redacted from our configuration; these are also scrubbed from anything
+  /// else we add to the bundle.
+  secrets: Vec<String>,
+
+  /// The log lines of the running process, if we have them.
+  logs: Option<LogBuffer>,
+
+  /// The message journal, if one is being recorded.
+  journal: Option<std::path::PathBuf>,
+
+  /// Extracts a line of the serial transcript from a single journal line, if it has one.
+  transcript: fn(&str) -> Option<String>,
+}
+
+impl Diagnostics {
+  /// Prepares diagnostics for the provided configuration.
+  pub(crate) fn new<C>(
+    config: &C,
+    logs: Option<LogBuffer>,
+    journal: Option<std::path::PathBuf>,
+    transcript: fn(&str) -> Option<String>,
+  ) -> Result<Self>
+  where
+    C: serde::Serialize,
+  {
+    let mut value = serde_json::to_value(config)?;
+    let mut secrets = vec![];
+    redact(&mut value, &mut secrets);
+
+    Ok(Self {
+      config: serde_json::to_string_pretty(&value)?,
+      secrets,
+      logs,
+      journal,
+      transcript,
+    })
+  }
+
+  /// Builds the gzipped tarball containing everything we know.
+  pub fn bundle(&self) -> Result<Vec<u8>> {
+    let encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+
+    let version = format!(
+      "version: {}\nos: {}\narch: {}\ncreated_at: {}\n",
+      option_env!("COSTANZA_VERSION").unwrap_or("dev"),
+      std::env::consts::OS,
+      std::env::consts::ARCH,
+      chrono::Utc::now().to_rfc3339(),
+    );
+    append(&mut archive, "version.txt", &version)?;
+    append(&mut archive, "config.json", &self.config)?;
+
+    let logs = match self.logs.as_ref() {
+      Some(logs) => logs.lines().join("\n"),
+      None => "logs are only available in bundles created by a running server.".to_string(),
+    };
+    append(&mut archive, "logs.txt", &self.scrub(logs))?;
+
+    let timeline = self.timeline()?;
+    let transcript = timeline
+      .iter()
+      .filter_map(|line| (self.transcript)(line))
+      .collect::<Vec<String>>();
+    append(&mut archive, "timeline.jsonl", &self.scrub(timeline.join("\n")))?;
+    append(&mut archive, "serial.txt", &self.scrub(transcript.join("\n")))?;
+
+    Ok(archive.into_inner()?.finish()?)
+  }
+
+  /// Returns the most recent lines of our journal, oldest first.
+  fn timeline(&self) -> Result<Vec<String>> {
+    let contents = match self.journal.as_ref().map(std::fs::read_to_string) {
+      Some(Ok(contents)) => contents,
+      Some(Err(error)) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+      Some(Err(error)) => return Err(error.into()),
+      None => return Ok(vec![]),
+    };
+
+    let lines = contents.lines().collect::<Vec<&str>>();
+    let start = lines.len().saturating_sub(TIMELINE_CAPACITY);
+    Ok(lines[start..].iter().map(|line| line.to_string()).collect())
+  }
+
+  /// Replaces every secret in the provided contents.
+  fn scrub(&self, contents: String) -> String {
+    self
+      .secrets
+      .iter()
+      .fold(contents, |contents, secret| contents.replace(secret.as_str(), REDACTED))
+  }
+}
+
+/// Replaces the values of any secret-looking keys, collecting the originals.
+pub(crate) fn redact(value: &mut serde_json::Value, secrets: &mut Vec<String>) {
+  match value {
+    serde_json::Value::Object(map) => {
+      for (key, value) in map.iter_mut() {
+        let secret = SECRET_KEYS.iter().any(|name| key.to_lowercase().contains(name));
+
+        match value {
+          serde_json::Value::String(inner) if secret => {
+            if !inner.is_empty() {
+              secrets.push(std::mem::replace(inner, REDACTED.to_string()));
+            }
+          }
+          other => redact(other, secrets),
+        }
+      }
+    }
+    serde_json::Value::Array(values) => values.iter_mut().for_each(|value| redact(value, secrets)),
+    _ => (),
+  }
+}
+
+/// Adds a file with the provided contents to the archive.
+fn append<W>(archive: &mut tar::Builder<W>, path: &str, contents: &str) -> Result<()>
+where
+  W: std::io::Write,
+{
+  let mut header = tar::Header::new_gnu();
+  header.set_size(contents.len() as u64);
+  header.set_mode(0o644);
+  header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+  header.set_cksum();
+  archive.append_data(&mut header, path, contents.as_bytes())?;
+  Ok(())
+}
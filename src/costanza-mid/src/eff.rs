@@ -2,9 +2,11 @@
 //! "glue" that, when given a bunch of different channels, and an application, will attempt to
 //! continuously reach for the next command and "publish" it to the application.
 
+use crate::error::{Error, Result};
 use async_std::channel;
 use async_std::stream::StreamExt;
-use std::io;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
 
 /// The idea of this `EffectCommandFilter` is to be able to use a single `Command` type from the
 /// application, but associate each effect manager with a filter that can be used to determine
@@ -17,9 +19,23 @@ pub trait EffectCommandFilter {
   type Command;
 
   fn sendable(&self, command: &Self::Command) -> bool;
+
+  /// Commands with a `High` priority are published to their effect before any `Normal` ones that
+  /// were returned from the same update.
+  fn priority(&self, _command: &Self::Command) -> Priority {
+    Priority::Normal
+  }
+}
+
+/// Some commands (e.g stopping a machine) cannot afford to wait behind others. Effects that hold
+/// on to commands before acting on them are expected to act on `High` priority commands first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+  Normal,
+  High,
 }
 
-pub type UnbindResult<M, C> = io::Result<(channel::Receiver<M>, channel::Sender<C>)>;
+pub type UnbindResult<M, C> = Result<(channel::Receiver<M>, channel::Sender<C>)>;
 
 pub trait Effect {
   type Message;
@@ -28,101 +44,277 @@ pub trait Effect {
   fn detach(&mut self) -> UnbindResult<Self::Message, Self::Command>;
 }
 
-/// The main application trait. _Heavily_ inspired by the Elm architecture.
+/// Subscriptions are the way an application declares the recurring sources of messages it is
+/// interested in. The runtime is responsible for starting and stopping them as the list returned
+/// by `Application::subscriptions` changes.
+pub enum Subscription<M> {
+  /// Produces a message every time the interval elapses. The first field is a key that uniquely
+  /// identifies the subscription between updates.
+  Interval(&'static str, std::time::Duration, fn() -> M),
+}
+
+impl<M> Subscription<M> {
+  /// Returns the key that identifies this subscription.
+  fn key(&self) -> &'static str {
+    match self {
+      Subscription::Interval(key, _, _) => key,
+    }
+  }
+}
+
+/// The main application trait. _Heavily_ inspired by the Elm architecture, although the state is
+/// updated in place rather than being moved through every update; applications with large amounts
+/// of state should not need to pay for moving it around on every message.
 pub trait Application {
   type Message;
   type Command;
   type Flags;
 
-  fn init(self, flags: Self::Flags) -> (Self, Option<Vec<Self::Command>>)
-  where
-    Self: Sized;
+  fn init(&mut self, flags: Self::Flags) -> Option<Vec<Self::Command>>;
 
-  fn update(self, message: Self::Message) -> (Self, Option<Vec<Self::Command>>)
-  where
-    Self: Sized;
+  fn update(&mut self, message: Self::Message) -> Option<Vec<Self::Command>>;
+
+  /// Returns the subscriptions the application is currently interested in. This is evaluated after
+  /// `init` and every `update`, which allows the application to change them based on its state.
+  fn subscriptions(&self) -> Vec<Subscription<Self::Message>> {
+    vec![]
+  }
+}
+
+/// A subscription that the runtime has started, along with the channel its messages arrive on and
+/// the one its ticker is reconfigured through.
+struct ActiveSubscription<M> {
+  key: &'static str,
+  interval: std::time::Duration,
+
+  /// Subscriptions that are no longer requested are paused rather than stopped, and resumed if they
+  /// are requested again.
+  paused: bool,
+  messages: channel::Receiver<M>,
+  commands: channel::Sender<crate::effects::ticker::TickerCommand>,
+}
+
+impl<M> ActiveSubscription<M> {
+  /// Sends a command to the ticker of this subscription. A ticker that is gone has already stopped
+  /// sending messages, which the runtime notices on its own.
+  async fn command(&self, command: crate::effects::ticker::TickerCommand) {
+    tracing::debug!("subscription '{}' - {command:?}", self.key);
+
+    if let Err(error) = self.commands.send(command).await {
+      tracing::warn!("unable to reconfigure subscription '{}' - {error}", self.key);
+    }
+  }
+}
+
+/// The journal is an optional record of every message the runtime has received, written as lines
+/// of json that include the amount of milliseconds elapsed since the journal was opened (and when
+/// they were received, used when pruning). These can be fed back through an application using
+/// `EffectRuntime::replay`.
+struct Journal<M> {
+  file: std::fs::File,
+  opened: std::time::Instant,
+  serialize: fn(u64, &M) -> serde_json::Result<String>,
+}
+
+/// The schema of a single journal line when it is being written.
+#[derive(Serialize)]
+struct JournalRecord<'a, M> {
+  at: chrono::DateTime<chrono::Utc>,
+  elapsed: u64,
+  message: &'a M,
+}
+
+/// The schema of a single journal line when it is being read back during a replay.
+#[derive(Deserialize)]
+struct JournalEntry<M> {
+  elapsed: u64,
+  message: M,
+}
+
+impl<M> Journal<M> {
+  /// Appends the message to our journal file. Failures here are not terminal to the runtime.
+  fn record(&mut self, message: &M) {
+    let elapsed = self.opened.elapsed().as_millis() as u64;
+
+    let result = (self.serialize)(elapsed, message)
+      .map_err(Error::from)
+      .and_then(|line| writeln!(self.file, "{line}").map_err(Error::from));
+
+    if let Err(error) = result {
+      tracing::warn!("unable to write message to journal - {error}");
+    }
+  }
 }
 
 /// Each effect runtime will provide us a pair of sender/receiver channels and a filter that we can
-/// use to determine what commands belong to what channels.
+/// use to determine what commands belong to what channels. The last field is a name for the effect
+/// that is used when reporting metrics.
 struct EffectChannels<M, C>(
   channel::Receiver<M>,
   channel::Sender<C>,
   Box<dyn EffectCommandFilter<Command = C>>,
+  &'static str,
 );
 
+/// Returns the name of a type without its module path or generic parameters, e.g `Http` for
+/// `costanza::effects::http::Http<..>`.
+fn short_type_name<T>() -> &'static str {
+  let full = std::any::type_name::<T>();
+  let without_generics = full.split('<').next().unwrap_or(full);
+  without_generics.rsplit("::").next().unwrap_or(without_generics)
+}
+
 pub struct EffectRuntime<M, C, A, S>
 where
   A: Application<Message = M, Command = C, Flags = S>,
 {
   channels: Vec<EffectChannels<M, C>>,
+  subscriptions: Vec<ActiveSubscription<M>>,
+  journal: Option<Journal<M>>,
+  metrics: crate::metrics::Metrics,
   application: A,
 }
 
 impl<M, C, A, S> EffectRuntime<M, C, A, S>
 where
   A: Application<Message = M, Command = C, Flags = S>,
-  M: std::fmt::Debug,
+  M: std::fmt::Debug + Send + 'static,
   C: std::fmt::Debug,
 {
   pub fn new(a: A) -> Self {
     Self {
       application: a,
       channels: vec![],
+      subscriptions: vec![],
+      journal: None,
+      metrics: crate::metrics::Metrics::default(),
     }
   }
 
-  pub fn register<E, F>(&mut self, effect: &mut E, filter: F) -> io::Result<()>
+  /// Returns a handle to the metrics collected by this runtime while it runs.
+  pub fn metrics(&self) -> crate::metrics::Metrics {
+    self.metrics.clone()
+  }
+
+  /// Opens (or creates) a file at the provided path that every message received by the runtime
+  /// will be appended to.
+  pub fn journal<P>(&mut self, path: P) -> Result<()>
+  where
+    P: AsRef<std::path::Path>,
+    M: Serialize,
+  {
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+
+    self.journal = Some(Journal {
+      file,
+      opened: std::time::Instant::now(),
+      serialize: |elapsed, message| {
+        let at = chrono::Utc::now();
+        serde_json::to_string(&JournalRecord { at, elapsed, message })
+      },
+    });
+
+    Ok(())
+  }
+
+  pub fn register<E, F>(&mut self, effect: &mut E, filter: F) -> Result<()>
   where
     E: Effect<Message = M, Command = C>,
     F: EffectCommandFilter<Command = C> + 'static,
   {
     let (s, r) = effect.detach()?;
-    self.channels.push(EffectChannels(s, r, Box::new(filter)));
+    self
+      .channels
+      .push(EffectChannels(s, r, Box::new(filter), short_type_name::<E>()));
     Ok(())
   }
 
-  pub async fn run(self, flags: S) -> io::Result<()> {
-    let mut cursor = self.init(flags).await?;
+  pub async fn run(mut self, flags: S) -> Result<()> {
+    self.init(flags).await?;
+
+    // Frames time out when there is nothing to receive, so a runtime that stops petting the
+    // watchdog is one that is stuck.
+    #[cfg(unix)]
+    let mut watchdog = crate::systemd::Watchdog::from_env();
 
     loop {
-      cursor = match cursor.frame().await {
-        Ok(next) => next,
-        Err(error) => {
-          tracing::error!("effect runtime terminal failure - {error}");
-          break;
-        }
+      if let Err(error) = self.frame().await {
+        tracing::error!("effect runtime terminal failure - {error}");
+        break;
+      }
+
+      #[cfg(unix)]
+      if let Some(watchdog) = watchdog.as_mut() {
+        watchdog.pet();
       }
     }
 
     Ok(())
   }
 
-  /// Wrapps the `Application` init with our command publishing.
-  async fn init(self, flags: S) -> io::Result<Self> {
-    let (application, mut cmds) = self.application.init(flags);
+  /// Instead of pulling messages from our effect channels, this will read the messages recorded in
+  /// a journal and apply them to the application in order. The `speed` is a multiplier used on the
+  /// original timing between messages; anything at or below zero will not wait at all.
+  ///
+  /// Subscriptions are not started during a replay; any messages they produced will have been
+  /// recorded into the journal itself.
+  pub async fn replay<P>(mut self, flags: S, path: P, speed: f64) -> Result<()>
+  where
+    P: AsRef<std::path::Path>,
+    M: serde::de::DeserializeOwned,
+  {
+    let contents = std::fs::read_to_string(path)?;
+    self.start(flags).await?;
+    let mut last_elapsed = 0;
+
+    for (index, line) in contents.lines().enumerate() {
+      let entry = serde_json::from_str::<JournalEntry<M>>(line).map_err(|error| {
+        tracing::error!("unable to parse journal line {index} - {error}");
+        error
+      })?;
+
+      if speed > 0.0 {
+        let delay = std::time::Duration::from_millis(entry.elapsed.saturating_sub(last_elapsed));
+        async_std::task::sleep(delay.div_f64(speed)).await;
+      }
 
-    let mut next = Self {
-      application,
-      channels: self.channels,
-    };
+      last_elapsed = entry.elapsed;
+      self.apply(entry.message).await?;
+    }
+
+    tracing::info!("journal replay complete");
+    Ok(())
+  }
 
-    if let Some(command_list) = cmds.take() {
-      next.publish_cmds(command_list).await?;
+  /// Wrapps the `Application` init with our command publishing.
+  async fn init(&mut self, flags: S) -> Result<()> {
+    self.start(flags).await?;
+    self.sync_subscriptions().await;
+    Ok(())
+  }
+
+  /// Initializes the application and publishes whatever commands it returns.
+  async fn start(&mut self, flags: S) -> Result<()> {
+    if let Some(command_list) = self.application.init(flags) {
+      self.publish_cmds(command_list).await?;
     }
 
-    Ok(next)
+    Ok(())
   }
 
   #[inline]
-  async fn frame(self) -> io::Result<Self> {
+  async fn frame(&mut self) -> Result<()> {
     // Create a list of futures that we will try to take the next ready and drop the rest. It is
     // not immediately clear right now if this can race or not (i.e: two futures ready at the same
     // time).
     let mut future_list = futures::stream::FuturesUnordered::new();
-    let borrowed_iter = &self.channels;
-    for EffectChannels(message_receiver, _, _) in borrowed_iter {
-      future_list.push(message_receiver.recv());
+    let receivers = self
+      .channels
+      .iter()
+      .map(|EffectChannels(message_receiver, _, _, name)| (*name, message_receiver))
+      .chain(self.subscriptions.iter().map(|active| (active.key, &active.messages)));
+    for (source, message_receiver) in receivers {
+      future_list.push(async move { (source, message_receiver.recv().await) });
     }
     let timeout_dur = std::time::Duration::from_millis(100);
     let message_result = async_std::future::timeout(timeout_dur, future_list.next()).await;
@@ -132,58 +324,143 @@ where
       // No-op path
       Err(error) => {
         tracing::trace!("timeout on message channel receiving - {error:?}");
-        return Ok(Self {
-          application: self.application,
-          channels: self.channels,
-        });
+        return Ok(());
       }
 
       // Unknown path
       Ok(None) => {
         tracing::trace!("empty message received from future unordered stream, maybe over?");
-        return Ok(Self {
-          application: self.application,
-          channels: self.channels,
-        });
+        return Ok(());
       }
 
       // Sad path
-      Ok(Some(Err(error))) => {
+      Ok(Some((_, Err(error)))) => {
         tracing::error!("failed receive from a channel - {error}");
-        return Err(io::Error::new(io::ErrorKind::Other, format!("{error}")));
+        return Err(Error::ChannelClosed(format!("{error}")));
       }
 
       // Happy path
-      Ok(Some(Ok(message))) => message,
+      Ok(Some((source, Ok(message)))) => {
+        self.metrics.received(source);
+        message
+      }
     };
 
+    for EffectChannels(message_receiver, cmd_sink, _, name) in &self.channels {
+      self.metrics.depth(name, message_receiver.len(), Some(cmd_sink.len()));
+    }
+    for active in &self.subscriptions {
+      self.metrics.depth(active.key, active.messages.len(), None);
+    }
+
+    if let Some(journal) = self.journal.as_mut() {
+      journal.record(&msg);
+    }
+
+    self.apply(msg).await?;
+    self.sync_subscriptions().await;
+    Ok(())
+  }
+
+  /// Sends a single message through the application's update, publishing the commands it returns.
+  async fn apply(&mut self, msg: M) -> Result<()> {
     tracing::debug!("applying message '{msg:?}' update to application");
-    let (new_state, mut cmd) = self.application.update(msg);
+    let started = std::time::Instant::now();
+    let cmds = self.application.update(msg);
+    self.metrics.observe_update(started.elapsed());
 
-    let mut next = Self {
-      application: new_state,
-      channels: self.channels,
-    };
+    if let Some(command_list) = cmds {
+      self.publish_cmds(command_list).await?;
+    }
 
-    if let Some(command_list) = cmd.take() {
-      next.publish_cmds(command_list).await?;
+    Ok(())
+  }
+
+  /// Compares the subscriptions currently requested by the application against the ones we have
+  /// running; any that are no longer requested are paused, any whose interval changed are told of
+  /// their new one, and any that were never started are.
+  async fn sync_subscriptions(&mut self) {
+    use crate::effects::ticker::TickerCommand;
+
+    let mut requested = self.application.subscriptions();
+
+    for active in self.subscriptions.iter_mut() {
+      let interval = requested.iter().find_map(|subscription| match subscription {
+        Subscription::Interval(key, interval, _) if *key == active.key => Some(*interval),
+        Subscription::Interval(..) => None,
+      });
+
+      match interval {
+        None if !active.paused => {
+          active.paused = true;
+          active.command(TickerCommand::Pause).await;
+        }
+        None => (),
+        Some(interval) => {
+          if interval != active.interval {
+            active.interval = interval;
+            active.command(TickerCommand::SetInterval(interval)).await;
+          }
+
+          if active.paused {
+            active.paused = false;
+            active.command(TickerCommand::Resume).await;
+          }
+        }
+      }
     }
 
-    Ok(next)
+    requested.retain(|subscription| !self.subscriptions.iter().any(|active| active.key == subscription.key()));
+
+    for subscription in requested {
+      match subscription {
+        Subscription::Interval(key, interval, producer) => {
+          tracing::debug!("starting interval subscription '{key}' ({interval:?})");
+          let mut ticker = crate::effects::ticker::Ticker::<M>::new(interval);
+          let (messages, commands) = match Effect::detach(&mut ticker) {
+            Ok(channels) => channels,
+            Err(error) => {
+              tracing::warn!("unable to start subscription '{key}' - {error}");
+              continue;
+            }
+          };
+          // Tickers stop on their own once we (and with us, their channels) are gone.
+          async_std::task::spawn(async move {
+            if let Err(error) = ticker.run(producer).await {
+              tracing::debug!("subscription '{key}' stopped - {error}");
+            }
+          });
+          self.subscriptions.push(ActiveSubscription {
+            key,
+            interval,
+            paused: false,
+            messages,
+            commands,
+          });
+        }
+      }
+    }
   }
 
   /// Given a mutable borrow to an instance of this runtime and a vector of commands to publish,
   /// this function will attempt to iterate over them and figure out who to send to and how to send
   /// them.
-  async fn publish_cmds(&mut self, command_list: Vec<C>) -> io::Result<()> {
-    for cmd in command_list {
+  async fn publish_cmds(&mut self, command_list: Vec<C>) -> Result<()> {
+    let (urgent, normal): (Vec<C>, Vec<C>) = command_list.into_iter().partition(|cmd| {
+      self
+        .channels
+        .iter()
+        .any(|EffectChannels(_, _, filter, _)| filter.sendable(cmd) && filter.priority(cmd) == Priority::High)
+    });
+
+    for cmd in urgent.into_iter().chain(normal) {
       #[cfg(debug_assertions)]
       let serialized = format!("{cmd:?}");
 
       #[cfg(debug_assertions)]
       let mut sent = false;
 
-      for EffectChannels(_, cmd_sink, filter) in self.channels.iter() {
+      for EffectChannels(_, cmd_sink, filter, _) in self.channels.iter() {
         let sendable = filter.sendable(&cmd);
 
         #[cfg(debug_assertions)]
@@ -196,7 +473,8 @@ where
         // Attempt to send the command.
         if let Err(error) = cmd_sink.send(cmd).await {
           tracing::warn!("failed sending command to sink - {error}");
-          return Err(io::Error::new(io::ErrorKind::Other, "closed-sink"));
+          self.metrics.publish_failed();
+          return Err(Error::ChannelClosed("closed-sink".into()));
         }
 
         #[cfg(debug_assertions)]
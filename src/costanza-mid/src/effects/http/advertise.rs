@@ -0,0 +1,62 @@
+//! Advertises our server over mdns (as a `_costanza._tcp` service), so clients on the same network
+//! can find it without being configured with its address. Advertising is best effort; failing to do
+//! so never prevents the server from running.
+
+/// The service type clients browse for.
+const SERVICE_TYPE: &str = "_costanza._tcp.local.";
+
+/// A registered advertisement, withdrawn when dropped.
+pub(super) struct Advertisement {
+  daemon: mdns_sd::ServiceDaemon,
+  fullname: String,
+}
+
+impl Drop for Advertisement {
+  fn drop(&mut self) {
+    if let Err(error) = self.daemon.unregister(&self.fullname) {
+      tracing::warn!("unable to withdraw mdns advertisement - {error}");
+    }
+
+    if let Err(error) = self.daemon.shutdown() {
+      tracing::warn!("unable to stop mdns daemon - {error}");
+    }
+  }
+}
+
+/// Advertises the first of our listeners under the provided instance name, along with the path of
+/// our websocket. Every address of the machine is advertised, since listeners are usually bound to
+/// the unspecified address.
+pub(super) fn advertise(name: &str, listeners: &[std::net::TcpListener]) -> Option<Advertisement> {
+  let port = match listeners.first().map(std::net::TcpListener::local_addr) {
+    Some(Ok(addr)) => addr.port(),
+    Some(Err(error)) => {
+      tracing::warn!("unable to determine the port to advertise - {error}");
+      return None;
+    }
+    None => return None,
+  };
+
+  let properties = std::collections::HashMap::from([
+    ("path".to_string(), "/ws".to_string()),
+    ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+  ]);
+
+  let advertised = mdns_sd::ServiceDaemon::new().and_then(|daemon| {
+    let host = format!("{}.local.", name.replace(|c: char| !c.is_ascii_alphanumeric(), "-"));
+    let service = mdns_sd::ServiceInfo::new(SERVICE_TYPE, name, &host, "", port, Some(properties))?.enable_addr_auto();
+    let fullname = service.get_fullname().to_string();
+    daemon.register(service)?;
+    Ok(Advertisement { daemon, fullname })
+  });
+
+  match advertised {
+    Ok(advertisement) => {
+      tracing::info!("advertising '{}' over mdns on port {port}", advertisement.fullname);
+      Some(advertisement)
+    }
+    Err(error) => {
+      tracing::warn!("unable to advertise over mdns - {error}");
+      None
+    }
+  }
+}
@@ -0,0 +1,176 @@
+//! These routes mirror the websocket control protocol for scripts and other automation that would
+//! rather not hold a websocket open. Every request is handled by a client that is registered with
+//! our proxy for the duration of the request, so the application treats it exactly like a websocket
+//! client sending the same request (including the control lock).
+
+use super::{liveness, shared_state, utils, Command, Message};
+use crate::catalog;
+use async_std::channel;
+
+/// How long we wait on the application to answer a request before giving up.
+const EXCHANGE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The tick used for the single request sent by each exchange.
+const EXCHANGE_TICK: u32 = 1;
+
+/// A client registered with our proxy and the application while a single request is handled.
+struct Exchange {
+  /// The id of our client.
+  id: String,
+
+  /// Where we receive the frames the application sends our client.
+  receiver: channel::Receiver<Command>,
+
+  /// The channel used to send messages to the application.
+  messages: channel::Sender<Message>,
+}
+
+impl Exchange {
+  /// Registers a new client on behalf of the user that made the request, returning it along with
+  /// the hello frame the application greeted it with.
+  async fn open(request: &tide::Request<shared_state::SharedState>) -> tide::Result<(Self, serde_json::Value)> {
+    let state = request.state();
+    let authority = match utils::session_id(request) {
+      None => None,
+      Some(id) => state.authority(id).await,
+    }
+    .ok_or_else(|| utils::error(catalog::Code::NotFound))?;
+
+    let (sender, receiver) = channel::unbounded();
+    let id = uuid::Uuid::new_v4().to_string();
+    tracing::info!("opening api client {id}");
+    state
+      .registration
+      .send((id.clone(), sender, liveness::Liveness::new()))
+      .await?;
+    state
+      .messages
+      .send(Message::ClientConnected(id.clone(), authority))
+      .await?;
+
+    let exchange = Self {
+      id,
+      receiver,
+      messages: state.messages.clone(),
+    };
+
+    match exchange.frame(|frame| frame["kind"] == "hello").await {
+      Ok(hello) => Ok((exchange, hello)),
+      Err(error) => {
+        exchange.close().await;
+        Err(error)
+      }
+    }
+  }
+
+  /// Sends a request of the provided kind to the application, returning its response.
+  async fn send(&self, kind: &str, request: serde_json::Value) -> tide::Result<serde_json::Value> {
+    let mut request = match request {
+      serde_json::Value::Object(fields) => fields,
+      serde_json::Value::Null => serde_json::Map::new(),
+      _ => return Err(utils::error(catalog::Code::InvalidRequest)),
+    };
+    request.insert("kind".to_string(), kind.into());
+
+    let data = serde_json::json!({ "tick": EXCHANGE_TICK, "request": request }).to_string();
+    self.messages.send(Message::ClientData(self.id.clone(), data)).await?;
+
+    self
+      .frame(|frame| frame["kind"] == "response" && frame["tick"] == EXCHANGE_TICK)
+      .await
+  }
+
+  /// Waits for the first frame sent to our client matching the provided predicate.
+  async fn frame<P>(&self, predicate: P) -> tide::Result<serde_json::Value>
+  where
+    P: Fn(&serde_json::Value) -> bool,
+  {
+    let receive = async {
+      loop {
+        let data = match self.receiver.recv().await? {
+          Command::SendState(_, data) | Command::Broadcast(data) => data,
+          // Responses to requests that were already made are still delivered while draining.
+          Command::Shutdown(_) | Command::Reconfigure(_) => continue,
+        };
+
+        match serde_json::from_str::<serde_json::Value>(&data) {
+          Ok(frame) if predicate(&frame) => return Ok(frame),
+          Ok(_) => continue,
+          Err(error) => tracing::warn!("unable to parse frame sent to api client - {error}"),
+        }
+      }
+    };
+
+    async_std::future::timeout(EXCHANGE_TIMEOUT, receive)
+      .await
+      .map_err(|_| utils::error(catalog::Code::RequestTimeout))?
+  }
+
+  /// Lets the application know our client is gone; our proxy drops it once the receiver is dropped.
+  async fn close(self) {
+    tracing::info!("closing api client {}", self.id);
+
+    if let Err(error) = self.messages.send(Message::ClientDisconnected(self.id)).await {
+      tracing::warn!("unable to disconnect api client - {error}");
+    }
+  }
+}
+
+/// Sends a single request through a new exchange, converting the response into an http response.
+async fn exchange(
+  request: &tide::Request<shared_state::SharedState>,
+  kind: &str,
+  body: serde_json::Value,
+) -> tide::Result {
+  let (exchange, _) = Exchange::open(request).await?;
+  let response = exchange.send(kind, body).await;
+  exchange.close().await;
+  let response = response?;
+
+  let status = match response["code"].as_str() {
+    None => 200,
+    Some(code) => catalog::Code::lookup(code).map_or(422, |code| code.entry().status),
+  };
+
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(status).body(body).build())
+}
+
+/// route: returns every entry of the error catalog. This is public so clients can load it before
+/// signing in.
+pub(super) async fn errors(_request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  tide::Body::from_json(&catalog::entries()).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: returns the state the application would send a newly connected websocket client.
+pub(super) async fn state(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let (exchange, hello) = Exchange::open(&request).await?;
+  exchange.close().await;
+  tide::Body::from_json(&hello["state"]).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: sends a `raw_serial` request, e.g `{"value": "$H"}`.
+pub(super) async fn raw_serial(mut request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let body = request
+    .body_json::<serde_json::Value>()
+    .await
+    .map_err(|error| tide::Error::from_str(422, format!("invalid request - {error}")))?;
+
+  exchange(&request, "raw_serial", body).await
+}
+
+/// route: sends a `start_job` request, e.g `{"content": "G0 X10", "name": "sign.nc"}`. The same size
+/// limit applies as for uploads.
+pub(super) async fn start_job(mut request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let size = request.len().unwrap_or(0);
+  if size > request.state().max_upload_size() {
+    tracing::warn!("invalid request size - {size}");
+    return Err(utils::error(catalog::Code::UploadTooLarge));
+  }
+
+  let body = request
+    .body_json::<serde_json::Value>()
+    .await
+    .map_err(|error| tide::Error::from_str(422, format!("invalid request - {error}")))?;
+
+  exchange(&request, "start_job", body).await
+}
@@ -1,4 +1,5 @@
-use super::{constants, sec, shared_state, utils};
+use super::{constants, csrf, oauth, sec, shared_state, utils};
+use crate::catalog;
 use serde::Serialize;
 
 /// The json-serializable response structure for our identify endpoint.
@@ -15,6 +16,9 @@ struct AuthIdentifyResponse {
 
   /// Optionally-included information about the user if we found one.
   session: Option<sec::AuthIdentifyResponseUserInfo>,
+
+  /// The token state-changing requests must provide in our csrf header.
+  csrf_token: Option<String>,
 }
 
 impl Default for AuthIdentifyResponse {
@@ -23,6 +27,7 @@ impl Default for AuthIdentifyResponse {
       ok: false,
       timestamp: chrono::Utc::now(),
       session: None,
+      csrf_token: None,
       version: "unknown".to_string(),
     }
   }
@@ -31,54 +36,80 @@ impl Default for AuthIdentifyResponse {
 /// route: oauth flow redirect.
 pub(super) async fn start(request: tide::Request<shared_state::SharedState>) -> tide::Result {
   tracing::info!("initializing oauth redirect");
-  let destination = request.state().config.oauth.redirect_uri().map_err(|error| {
+  let provider = request.state().config.provider().ok_or_else(|| {
+    tracing::warn!("no oauth provider is configured");
+    utils::error(catalog::Code::AuthProviderFailed)
+  })?;
+
+  let destination = provider.redirect_uri().await.map_err(|error| {
     tracing::warn!("{}", error);
-    tide::Error::from_str(500, "bad-oauth")
+    utils::error(catalog::Code::AuthProviderFailed)
   })?;
 
   Ok(tide::Redirect::temporary(destination).into())
 }
 
-/// route: oauth token -> user information exchange. also creates a redis session entry and returns
-/// a cookie to the browser.
-pub(super) async fn complete(request: tide::Request<shared_state::SharedState>) -> tide::Result {
-  let code = request
-    .url()
-    .query_pairs()
-    .find_map(|(k, v)| if k == "code" { Some(v) } else { None })
-    .ok_or_else(|| tide::Error::from_str(404, "no-code"))?;
-
-  let oauth = &request.state().config.oauth;
-
+/// Swaps a code for the information and roles of the user that signed in through Auth0.
+async fn auth_zero_sign_in(
+  oauth: &oauth::AuthZeroConfig,
+  code: &str,
+) -> tide::Result<(oauth::ManagementUserInfoResponse, Vec<oauth::UserRole>)> {
   // Swap our code for a token and load the basic user information it provides for us.
   let user = oauth.fetch_initial_user_info(&code).await.map_err(|error| {
     tracing::warn!("unable to fetch initial user info - {}", error);
-    tide::Error::from_str(500, "bad-oauth")
+    utils::error(catalog::Code::AuthProviderFailed)
   })?;
 
   if user.email_verified.is_none() {
     tracing::warn!("user email not verified for sub '{}'", user.sub);
-    return Err(tide::Error::from_str(404, "user-not-found"));
+    return Err(utils::error(catalog::Code::UserNotFound));
   }
 
   // Fetch the Auth0 roles for this user.
   let roles = oauth.fetch_user_roles(&user.sub).await.map_err(|error| {
     tracing::warn!("unable to fetch user roles - {}", error);
-    tide::Error::from_str(500, "bad-roles-listing")
+    utils::error(catalog::Code::AuthProviderFailed)
   })?;
 
-  // TODO: should non-admins be allowed to see info?
-  if !roles.iter().any(|role| role.is_admin()) {
-    tracing::warn!("user not admin, skippping cookie setting (roles {:?})", roles);
-    return Err(tide::Error::from_str(404, "user-not-found"));
-  }
-
   // Fetch the complete user information available to us from the auth0 api.
   let user = oauth.fetch_detailed_user_info(&user.sub).await.map_err(|error| {
     tracing::warn!("unable to load complete user information from auth0 - {error}");
     error
   })?;
 
+  Ok((user, roles))
+}
+
+/// route: oauth token -> user information exchange. also creates a session entry and returns
+/// a cookie to the browser.
+pub(super) async fn complete(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let code = request
+    .url()
+    .query_pairs()
+    .find_map(|(k, v)| if k == "code" { Some(v) } else { None })
+    .ok_or_else(|| utils::error(catalog::Code::AuthCodeMissing))?;
+
+  let (user, roles) = match request.state().config.provider() {
+    Some(oauth::Provider::AuthZero(oauth)) => auth_zero_sign_in(oauth, &code).await?,
+    Some(oauth::Provider::Oidc(oidc)) => oidc.sign_in(&code).await.map_err(|error| {
+      tracing::warn!("unable to sign in through openid connect provider - {error}");
+      utils::error(catalog::Code::AuthProviderFailed)
+    })?,
+    None => {
+      tracing::warn!("no oauth provider is configured");
+      return Err(utils::error(catalog::Code::AuthProviderFailed));
+    }
+  };
+
+  // Observers may sign in, but what they can do is limited by the application.
+  if sec::Authority::from_roles(&roles, request.state().config.roles.as_ref()).is_none() {
+    tracing::warn!(
+      "user neither admin nor observer, skippping cookie setting (roles {:?})",
+      roles
+    );
+    return Err(utils::error(catalog::Code::UserNotFound));
+  }
+
   // Create a serializable representation of our user information
   let session_data = sec::AuthIdentifyResponseUserInfo { user, roles };
   let session_id = uuid::Uuid::new_v4().to_string();
@@ -87,27 +118,21 @@ pub(super) async fn complete(request: tide::Request<shared_state::SharedState>)
     error
   })?;
 
-  // Perist that user information into our redis storage.
-  let command = kramer::Command::Strings(kramer::StringCommand::Set(
-    kramer::Arity::One((&session_id, &serialized_session)),
-    None,
-    kramer::Insertion::Always,
-  ));
-
-  request.state().command(command).await.map_err(|error| {
-    tracing::warn!("unable to persist session information - {error}");
-    error
-  })?;
+  // Perist that user information into our session store.
+  let session_ttl = request.state().config.session.session_ttl();
+  let sessions = &request.state().sessions;
+  sessions
+    .put(&session_id, &serialized_session, Some(session_ttl))
+    .await
+    .map_err(|error| {
+      tracing::warn!("unable to persist session information - {error}");
+      error
+    })?;
 
   // Create our json web token, including the unique identifier we generated for this session.
-  let jwt = sec::Claims::for_sub(&session_id).encode(&request.state().config.session.jwt_secret)?;
-  let cookie = format!(
-    "{}={}; {}; Domain={}",
-    constants::COOKIE_NAME,
-    jwt,
-    constants::COOKIE_SET_FLAGS,
-    &request.state().config.domain
-  );
+  let session = &request.state().config.session;
+  let jwt = sec::Claims::for_sub(&session_id, session.token_ttl()).encode(&session.jwt_secret)?;
+  let cookie = utils::session_cookie(&request.state().config, &jwt);
 
   // TODO - determine where to send the user. Once the web UI is created, we will send the user to some login page
   // where an attempt will be made to fetch identity information using the newly-set cookie.
@@ -119,6 +144,100 @@ pub(super) async fn complete(request: tide::Request<shared_state::SharedState>)
   Ok(response)
 }
 
+/// route: issues a new token for the session in our cookie, extending the session. Tokens that have
+/// expired are accepted here, as long as their session has not.
+pub(super) async fn refresh(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let secret = &request.state().config.session.jwt_secret;
+  let claims = request
+    .cookie(constants::COOKIE_NAME)
+    .and_then(|cookie| sec::Claims::decode_expired(&cookie.value(), secret).ok())
+    .ok_or_else(|| utils::error(catalog::Code::SessionNotFound))?;
+
+  let cookie = utils::renew_session(request.state(), &claims.oid).await?;
+  Ok(tide::Response::builder(204).header("Set-Cookie", cookie).build())
+}
+
+/// The payload returned when an api token is created; the token is not available afterwards.
+#[derive(Debug, Serialize)]
+struct CreatedToken {
+  /// The token, which is provided through an `Authorization: Bearer` header.
+  token: String,
+
+  /// When the token expires, if ever.
+  expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// route: creates an api token acting on behalf of the user signed in through our cookie. Tokens can
+/// be used by scripts and other non-browser clients in place of the cookie.
+pub(super) async fn create_token(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let state = request.state();
+  let claims = utils::cookie_claims(&request).ok_or_else(|| utils::error(catalog::Code::SessionNotFound))?;
+  let session_data = state.user_from_session(&claims.oid).await.ok_or_else(|| {
+    tracing::warn!("unable to load session data for claims {}", claims.oid);
+    utils::error(catalog::Code::SessionNotFound)
+  })?;
+
+  let token = sec::api_token();
+  let ttl = state.config.session.api_token_ttl.map(std::time::Duration::from_secs);
+  let serialized = serde_json::to_string(&session_data)?;
+  state
+    .sessions
+    .put(&sec::api_token_key(&token), &serialized, ttl)
+    .await?;
+  tracing::info!("created api token for '{}'", session_data.user.user_id);
+
+  let expires_at = ttl
+    .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+    .map(|ttl| chrono::Utc::now() + ttl);
+  let body = tide::Body::from_json(&CreatedToken { token, expires_at })?;
+  Ok(tide::Response::builder(201).body(body).build())
+}
+
+/// route: revokes the api token provided through the `Authorization` header.
+pub(super) async fn revoke_token(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let token = utils::bearer_token(&request).ok_or_else(|| utils::error(catalog::Code::SessionNotFound))?;
+  request.state().sessions.delete(&sec::api_token_key(&token)).await?;
+  Ok(tide::Response::new(204))
+}
+
+/// The payload of our roles route.
+#[derive(Debug, Serialize)]
+struct RolesResponse<'a> {
+  /// The authority of the user, which determines what they may do.
+  authority: sec::Authority,
+
+  /// The names of the roles the authority was derived from.
+  roles: Vec<&'a str>,
+
+  /// Whether or not the user may control the machine.
+  can_operate: bool,
+
+  /// Whether or not the user may change how the machine (and costanza) is set up.
+  can_administer: bool,
+}
+
+/// route: returns the authority of the user that made the request, so clients can tailor what they
+/// offer to it.
+pub(super) async fn roles(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let state = request.state();
+  let session_data = match utils::session_id(&request) {
+    Some(id) => state.user_from_session(id).await,
+    None => None,
+  }
+  .ok_or_else(|| utils::error(catalog::Code::SessionNotFound))?;
+
+  let authority = sec::Authority::from_roles(&session_data.roles, state.config.roles.as_ref())
+    .ok_or_else(|| utils::error(catalog::Code::UserNotFound))?;
+
+  tide::Body::from_json(&RolesResponse {
+    authority,
+    roles: session_data.roles.iter().map(|role| role.name()).collect(),
+    can_operate: authority.allows(sec::Authority::Operator),
+    can_administer: authority.allows(sec::Authority::Admin),
+  })
+  .map(|body| tide::Response::builder(200).body(body).build())
+}
+
 /// route: return user darta based on the information available to us in the cookie.
 pub(super) async fn identify(request: tide::Request<shared_state::SharedState>) -> tide::Result {
   let claims = utils::cookie_claims(&request);
@@ -133,16 +252,26 @@ pub(super) async fn identify(request: tide::Request<shared_state::SharedState>)
   if let Some(claims) = claims {
     let session_data = request.state().user_from_session(&claims.oid).await.ok_or_else(|| {
       tracing::warn!("unable to load session data for claims {}", claims.oid);
-      tide::Error::from_str(404, "no-session")
+      utils::error(catalog::Code::SessionNotFound)
     })?;
 
-    if session_data.roles.iter().any(|role| role.is_admin()) {
+    if sec::Authority::from_roles(&session_data.roles, request.state().config.roles.as_ref()).is_some() {
       res.ok = true;
       res.session = Some(session_data);
     }
   }
 
-  tide::Body::from_json(&res).map(|bod| tide::Response::builder(200).body(bod).build())
+  // The csrf token is kept for as long as the browser holds on to it, so other tabs keep working.
+  let csrf_token = csrf::cookie_token(&request).unwrap_or_else(csrf::token);
+  let csrf_cookie = csrf::cookie(&request.state().config, &csrf_token);
+  res.csrf_token = Some(csrf_token);
+
+  tide::Body::from_json(&res).map(|bod| {
+    tide::Response::builder(200)
+      .header("Set-Cookie", csrf_cookie)
+      .body(bod)
+      .build()
+  })
 }
 
 /// route: clear the cookie and redirect users back to the ui.
@@ -152,11 +281,7 @@ pub(super) async fn end(request: tide::Request<shared_state::SharedState>) -> ti
   if let Some(inner) = claims {
     tracing::debug!("attempting to delete session for '{}'", inner.oid);
 
-    if let Err(error) = request
-      .state()
-      .command(kramer::Command::Del::<&str, &str>(kramer::Arity::One(&inner.oid)))
-      .await
-    {
+    if let Err(error) = request.state().sessions.delete(&inner.oid).await {
       tracing::error!("unable to dleete session data - '{error}'");
     }
   }
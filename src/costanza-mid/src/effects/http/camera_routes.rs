@@ -0,0 +1,98 @@
+//! Proxies a camera (e.g an mjpeg webcam on the same network as the machine) so the ui can embed a
+//! view of the machine without the camera being exposed directly, or being served from another
+//! origin than the ui itself.
+
+use super::{sec, shared_state, utils};
+use crate::catalog;
+use serde::{Deserialize, Serialize};
+
+/// How long we wait on the camera to start answering before giving up.
+const CAMERA_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The configuration of our camera.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(super) struct CameraConfiguration {
+  /// Where the camera streams from, e.g `http://192.168.1.20:8080/?action=stream`.
+  url: String,
+
+  /// Where a single frame can be requested from, e.g `http://192.168.1.20:8080/?action=snapshot`.
+  /// Without it, snapshots are not available.
+  #[serde(default)]
+  snapshot_url: Option<String>,
+}
+
+impl CameraConfiguration {
+  /// Records an error for each url we would be unable to request.
+  pub(super) fn validate(&self, validation: &mut crate::validation::Validation) {
+    validation.url("http.camera.url", &self.url);
+
+    if let Some(url) = self.snapshot_url.as_ref() {
+      validation.url("http.camera.snapshot_url", url);
+    }
+  }
+}
+
+/// Returns the camera configuration, failing with a "not found" error when we have none.
+fn camera(request: &tide::Request<shared_state::SharedState>) -> tide::Result<&CameraConfiguration> {
+  request
+    .state()
+    .config
+    .camera
+    .as_ref()
+    .ok_or_else(|| utils::error(catalog::Code::NotFound))
+}
+
+/// Requests the url from our camera, failing if it does not start answering in time (or answers
+/// with anything other than a success).
+async fn fetch(url: &str) -> tide::Result<surf::Response> {
+  let response = async_std::future::timeout(CAMERA_TIMEOUT, surf::get(url))
+    .await
+    .map_err(|_| {
+      tracing::warn!("camera at '{url}' did not answer in time");
+      utils::error(catalog::Code::CameraUnavailable)
+    })?
+    .map_err(|error| {
+      tracing::warn!("unable to request camera at '{url}' - {error}");
+      utils::error(catalog::Code::CameraUnavailable)
+    })?;
+
+  if !response.status().is_success() {
+    tracing::warn!("camera at '{url}' answered with {}", response.status());
+    return Err(utils::error(catalog::Code::CameraUnavailable));
+  }
+
+  Ok(response)
+}
+
+/// Builds the response relaying the body of our camera's response.
+fn relay(mut upstream: surf::Response) -> tide::Response {
+  let mut response = tide::Response::new(200);
+
+  if let Some(mime) = upstream.content_type() {
+    response.set_content_type(mime);
+  }
+
+  response.insert_header("Cache-Control", "no-store");
+  response.set_body(upstream.take_body());
+  response
+}
+
+/// route: returns a single frame from the camera.
+pub(super) async fn snapshot(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_authority(&request, sec::Authority::Observer).await?;
+  let url = camera(&request)?
+    .snapshot_url
+    .as_deref()
+    .ok_or_else(|| utils::error(catalog::Code::NotFound))?;
+
+  fetch(url).await.map(relay)
+}
+
+/// route: relays the stream of the camera for as long as the client keeps reading it. Unlike event
+/// streams, these are not counted as in-flight when shutting down; they never finish on their own,
+/// and are simply dropped along with the server.
+pub(super) async fn stream(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_authority(&request, sec::Authority::Observer).await?;
+  let url = camera(&request)?.url.as_str();
+  fetch(url).await.map(relay)
+}
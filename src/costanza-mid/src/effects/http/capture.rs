@@ -0,0 +1,221 @@
+//! Wire-level capture of websocket traffic. When enabled by an admin, every frame sent to or
+//! received from websocket clients (optionally only a single client) is appended to a json lines
+//! file so mismatches between the ui and this server can be looked into after the fact.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// The amount of bytes written to a capture file before capturing is stopped, unless the admin
+/// starting the capture asks for something else.
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Which way a captured frame was travelling.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Direction {
+  /// Sent by a client to us.
+  Inbound,
+
+  /// Sent by us to a client.
+  Outbound,
+}
+
+/// The options an admin can provide when starting a capture.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub(super) struct CaptureOptions {
+  /// When provided, only the frames of this client are captured.
+  client: Option<String>,
+
+  /// The amount of bytes to write before stopping.
+  max_bytes: Option<u64>,
+}
+
+/// What is reported to admins about the current capture.
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct CaptureStatus {
+  /// Whether or not frames are currently being captured.
+  active: bool,
+
+  /// The options the current (or most recent) capture was started with.
+  options: Option<CaptureOptions>,
+
+  /// The amount of bytes written by the current (or most recent) capture.
+  bytes_written: u64,
+}
+
+/// The schema of a single line in our capture file.
+#[derive(Serialize)]
+struct CaptureLine<'a> {
+  /// When the frame was captured.
+  at: chrono::DateTime<chrono::Utc>,
+
+  /// The client the frame belongs to.
+  client: &'a str,
+
+  /// Which way the frame was travelling.
+  direction: Direction,
+
+  /// The frame itself; json frames are embedded as-is (after redaction).
+  frame: serde_json::Value,
+}
+
+/// A capture that has been started.
+struct Active {
+  /// The file frames are written to.
+  file: std::fs::File,
+
+  /// The options this capture was started with.
+  options: CaptureOptions,
+}
+
+/// The values shared behind a `Capture` handle.
+#[derive(Default)]
+struct Inner {
+  /// The current capture, if frames are being captured.
+  active: Option<Active>,
+
+  /// The options of the most recent capture.
+  options: Option<CaptureOptions>,
+
+  /// The amount of bytes written by the most recent capture.
+  bytes_written: u64,
+}
+
+/// A cheaply cloneable handle shared by every websocket connection that writes frames into the
+/// capture file while a capture is active.
+#[derive(Clone)]
+pub(crate) struct Capture {
+  /// Where frames are written.
+  path: std::path::PathBuf,
+
+  /// The state of our capture.
+  inner: Arc<Mutex<Inner>>,
+}
+
+impl Capture {
+  /// Creates the (inactive) capture handle that will write to the provided path.
+  pub(crate) fn new<P>(path: P) -> Self
+  where
+    P: Into<std::path::PathBuf>,
+  {
+    Self {
+      path: path.into(),
+      inner: Arc::new(Mutex::new(Inner::default())),
+    }
+  }
+
+  /// Starts capturing frames, replacing the contents of any previous capture. Frames are appended
+  /// to the file so that it can be pruned in place while we are capturing.
+  pub(super) fn start(&self, options: CaptureOptions) -> std::io::Result<CaptureStatus> {
+    std::fs::File::create(&self.path)?;
+    let file = std::fs::OpenOptions::new().append(true).open(&self.path)?;
+    tracing::info!(
+      "starting websocket capture into '{}' ({options:?})",
+      self.path.display()
+    );
+
+    Ok(self.with(|inner| {
+      inner.options = Some(options.clone());
+      inner.bytes_written = 0;
+      inner.active = Some(Active { file, options });
+      status(inner)
+    }))
+  }
+
+  /// Stops capturing frames.
+  pub(super) fn stop(&self) -> CaptureStatus {
+    self.with(|inner| {
+      if inner.active.take().is_some() {
+        tracing::info!("stopped websocket capture after {} bytes", inner.bytes_written);
+      }
+
+      status(inner)
+    })
+  }
+
+  /// Returns the state of the current capture.
+  pub(super) fn status(&self) -> CaptureStatus {
+    self.with(|inner| status(inner))
+  }
+
+  /// Writes a frame into the capture file, if a capture of this client is active. Anything that
+  /// looks like auth material is redacted from json frames before they are written.
+  pub(super) fn record(&self, client: &str, direction: Direction, frame: &str) {
+    self.with(|inner| {
+      let active = match inner.active.as_mut() {
+        Some(active) if active.options.client.as_ref().is_none_or(|only| only == client) => active,
+        _ => return,
+      };
+
+      let frame = match serde_json::from_str::<serde_json::Value>(frame) {
+        Ok(mut value) => {
+          crate::diagnostics::redact(&mut value, &mut vec![]);
+          value
+        }
+        Err(_) => serde_json::Value::String(frame.to_string()),
+      };
+
+      let line = CaptureLine {
+        at: chrono::Utc::now(),
+        client,
+        direction,
+        frame,
+      };
+
+      let written = serde_json::to_string(&line)
+        .map_err(std::io::Error::from)
+        .and_then(|line| writeln!(active.file, "{line}").map(|_| line.len() as u64 + 1));
+
+      let max_bytes = active.options.max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+
+      match written {
+        Ok(amount) => inner.bytes_written += amount,
+        Err(error) => {
+          tracing::warn!("unable to write websocket capture, stopping - {error}");
+          inner.active = None;
+          return;
+        }
+      }
+
+      if inner.bytes_written >= max_bytes {
+        tracing::warn!("websocket capture reached {max_bytes} bytes, stopping");
+        inner.active = None;
+      }
+    })
+  }
+
+  /// Removes the oldest frames of the capture file that fall outside of a policy, holding off any
+  /// frame being captured until it is done. When `dry_run` is true, nothing is removed. Returns the
+  /// amount of frames that were (or would be) removed.
+  pub(crate) fn prune(&self, policy: &crate::retention::RetentionPolicy, dry_run: bool) -> usize {
+    self.with(|_| {
+      crate::retention::prune_lines(&self.path, policy, dry_run).unwrap_or_else(|error| {
+        tracing::warn!("unable to prune websocket capture - {error}");
+        0
+      })
+    })
+  }
+
+  /// Runs the closure against our locked state.
+  fn with<F, T>(&self, apply: F) -> T
+  where
+    F: FnOnce(&mut Inner) -> T,
+  {
+    let mut inner = match self.inner.lock() {
+      Ok(inner) => inner,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    apply(&mut inner)
+  }
+}
+
+/// Builds the status reported to admins.
+fn status(inner: &Inner) -> CaptureStatus {
+  CaptureStatus {
+    active: inner.active.is_some(),
+    options: inner.options.clone(),
+    bytes_written: inner.bytes_written,
+  }
+}
@@ -1,20 +1,113 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+/// The fewest bytes a secret used to sign json web tokens may have.
+const MIN_JWT_SECRET_LEN: usize = 32;
 
 /// The session store config
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub(super) struct SessionStoreConfiguration {
   /// A secret that will be used to sign JWT tokens.
   pub(super) jwt_secret: String,
 
+  /// Which backend sessions are persisted in. Defaults to redis when an address is provided, and to
+  /// memory otherwise.
+  #[serde(default)]
+  store: Option<super::session_store::SessionBackend>,
+
   /// The address that we can find redis at. Used for storing user data.
-  pub(super) redis_addr: String,
+  #[serde(default)]
+  pub(super) redis_addr: Option<String>,
+
+  /// The file sessions are written to when using the file backend.
+  #[serde(default)]
+  pub(super) path: Option<String>,
+
+  /// The most connections to redis that will be open at once.
+  #[serde(default)]
+  pool_size: Option<usize>,
+
+  /// The amount of seconds a redis connection may sit idle before it is checked before being used.
+  #[serde(default)]
+  health_check_interval: Option<u64>,
+
+  /// The amount of seconds a session lasts without being used.
+  #[serde(default)]
+  session_ttl: Option<u64>,
+
+  /// The amount of seconds each json web token is valid for. Tokens are reissued while a session is
+  /// being used, and can be refreshed through `/auth/refresh` for as long as the session lasts.
+  #[serde(default)]
+  token_ttl: Option<u64>,
+
+  /// The amount of seconds api tokens are valid for. Api tokens do not expire unless provided.
+  #[serde(default)]
+  pub(super) api_token_ttl: Option<u64>,
+}
+
+impl SessionStoreConfiguration {
+  /// Returns which backend sessions are persisted in.
+  pub(super) fn backend(&self) -> super::session_store::SessionBackend {
+    match (self.store, self.redis_addr.as_ref()) {
+      (Some(backend), _) => backend,
+      (None, Some(_)) => super::session_store::SessionBackend::Redis,
+      (None, None) => super::session_store::SessionBackend::Memory,
+    }
+  }
+
+  /// Returns the most connections to redis that will be open at once.
+  pub(super) fn pool_size(&self) -> usize {
+    self.pool_size.unwrap_or(4).max(1)
+  }
+
+  /// Returns how long a redis connection may sit idle before it is checked before being used.
+  pub(super) fn health_check_interval(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.health_check_interval.unwrap_or(30))
+  }
+
+  /// Returns how long a session lasts without being used.
+  pub(super) fn session_ttl(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.session_ttl.unwrap_or(60 * 60 * 24 * 7))
+  }
+
+  /// Returns how long each json web token is valid for.
+  pub(super) fn token_ttl(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.token_ttl.unwrap_or(60 * 60))
+  }
+
+  /// Records the issues with our secret and the backend sessions are persisted in.
+  fn validate(&self, validation: &mut crate::validation::Validation) {
+    if self.jwt_secret.len() < MIN_JWT_SECRET_LEN {
+      validation.error(
+        "http.session.jwt_secret",
+        format!("must be at least {MIN_JWT_SECRET_LEN} bytes long"),
+      );
+    }
+
+    match self.backend() {
+      super::session_store::SessionBackend::Redis => match self.redis_addr.as_deref() {
+        None | Some("") => validation.error("http.session.redis_addr", "required by the redis session store"),
+        Some(addr) if !addr.contains(':') => {
+          validation.error("http.session.redis_addr", format!("'{addr}' is not a 'host:port' pair"))
+        }
+        Some(_) => (),
+      },
+      super::session_store::SessionBackend::File if self.path.is_none() => {
+        validation.error("http.session.path", "required by the file session store")
+      }
+      _ => (),
+    }
+
+    if self.token_ttl() > self.session_ttl() {
+      validation.warning("http.session.token_ttl", "tokens outlive the sessions they belong to");
+    }
+  }
 }
 
 /// The main configuration schema for the http effect runtime.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Configuration {
-  /// The address to bind our tcp stream to.
-  pub(super) addr: String,
+  /// The address (or list of addresses) to bind our tcp listeners to.
+  pub(super) addr: super::listeners::ListenAddresses,
 
   /// The maxiumum amount of bytes to accept for file uploads.
   pub(super) max_upload_size: usize,
@@ -28,6 +121,168 @@ pub struct Configuration {
   /// Configuration used for authentication.
   pub(super) session: SessionStoreConfiguration,
 
-  /// Configuration used for authorization.
-  pub(super) oauth: super::oauth::AuthZeroConfig,
+  /// Configuration used for authorization when users sign in through Auth0.
+  #[serde(default)]
+  pub(super) oauth: Option<super::oauth::AuthZeroConfig>,
+
+  /// Configuration used for authorization when users sign in through any other OpenID Connect
+  /// provider. Takes precedence over `oauth`.
+  #[serde(default)]
+  pub(super) oidc: Option<super::oidc::OidcConfiguration>,
+
+  /// Where websocket frames are written while an admin is capturing them.
+  #[serde(default)]
+  capture_path: Option<String>,
+
+  /// The amount of seconds between the pings sent to each websocket client.
+  #[serde(default)]
+  ping_interval: Option<u64>,
+
+  /// The amount of seconds a websocket client may go without answering (or sending anything)
+  /// before it is considered gone.
+  #[serde(default)]
+  pong_deadline: Option<u64>,
+
+  /// How many messages each websocket client may send at once, and how quickly that allowance is
+  /// refilled. Messages beyond it are refused.
+  #[serde(default)]
+  client_rate_limit: Option<super::rate_limit::RateLimit>,
+
+  /// How many files each session may upload at once, and how quickly that allowance is refilled.
+  #[serde(default)]
+  upload_rate_limit: Option<super::rate_limit::RateLimit>,
+
+  /// The amount of seconds we wait for in-flight requests to finish when shutting down.
+  #[serde(default)]
+  shutdown_timeout: Option<u64>,
+
+  /// When provided, every request is appended to this file in the combined log format.
+  #[serde(default)]
+  pub(super) access_log: Option<String>,
+
+  /// When provided, the ui bundle is served from this directory.
+  #[serde(default)]
+  pub(super) static_dir: Option<String>,
+
+  /// When provided, the authority granted by each Auth0 role (by name). Roles that are not listed
+  /// grant nothing. Without it, roles are matched by name.
+  #[serde(default)]
+  pub(super) roles: Option<std::collections::HashMap<String, super::sec::Authority>>,
+
+  /// Which dependencies the health check considers critical.
+  #[serde(default)]
+  pub(super) health: Option<super::health_routes::HealthConfiguration>,
+
+  /// When provided, the camera watching the machine is proxied through our server.
+  #[serde(default)]
+  pub(super) camera: Option<super::camera_routes::CameraConfiguration>,
+
+  /// Whether or not requests made with an api token skip csrf validation. Defaults to true.
+  #[serde(default)]
+  csrf_exempt_tokens: Option<bool>,
+
+  /// When provided, our server is advertised over mdns under this name (e.g `shop router`).
+  #[serde(default)]
+  pub(super) advertise: Option<String>,
+}
+
+impl Configuration {
+  /// Records every issue with our configuration that prevents (or will likely interfere with)
+  /// running the http server.
+  pub(crate) fn validate(&self, validation: &mut crate::validation::Validation) {
+    self.addr.validate(validation);
+    self.session.validate(validation);
+
+    if self.max_upload_size == 0 {
+      validation.error("http.max_upload_size", "must be greater than zero");
+    }
+
+    validation.url("http.auth_complete_uri", &self.auth_complete_uri);
+
+    if let Some(oauth) = self.oauth.as_ref() {
+      oauth.validate(validation);
+    }
+
+    if let Some(oidc) = self.oidc.as_ref() {
+      oidc.validate(validation);
+    }
+
+    if self.provider().is_none() {
+      validation.warning(
+        "http.oauth",
+        "no provider is configured; users will be unable to sign in",
+      );
+    }
+
+    if let Some(camera) = self.camera.as_ref() {
+      camera.validate(validation);
+    }
+
+    if let Some(directory) = self.static_dir.as_ref() {
+      if !std::path::Path::new(directory).is_dir() {
+        validation.error("http.static_dir", format!("'{directory}' is not a directory"));
+      }
+    }
+
+    if self.advertise.as_ref().is_some_and(|name| name.trim().is_empty()) {
+      validation.error("http.advertise", "must not be empty");
+    }
+
+    if self.ping_interval() >= self.pong_deadline() {
+      validation.warning("http.pong_deadline", "clients will be dropped before they are pinged");
+    }
+  }
+
+  /// Returns the provider users sign in through, if one is configured.
+  pub(super) fn provider(&self) -> Option<super::oauth::Provider<'_>> {
+    match (self.oidc.as_ref(), self.oauth.as_ref()) {
+      (Some(oidc), _) => Some(super::oauth::Provider::Oidc(oidc)),
+      (None, Some(oauth)) => Some(super::oauth::Provider::AuthZero(oauth)),
+      (None, None) => None,
+    }
+  }
+
+  /// Returns where websocket frames are written while an admin is capturing them.
+  pub(crate) fn capture_path(&self) -> &str {
+    self.capture_path.as_deref().unwrap_or("costanza-capture.jsonl")
+  }
+
+  /// Returns the maximum amount of bytes accepted for a single file, however it is uploaded.
+  pub(crate) fn max_upload_size(&self) -> usize {
+    self.max_upload_size
+  }
+
+  /// Returns the time between the pings sent to each websocket client.
+  pub(super) fn ping_interval(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.ping_interval.unwrap_or(15))
+  }
+
+  /// Returns how long a websocket client may go without answering before it is considered gone.
+  pub(super) fn pong_deadline(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.pong_deadline.unwrap_or(45))
+  }
+
+  /// Returns whether or not requests made with an api token skip csrf validation.
+  pub(super) fn csrf_exempt_tokens(&self) -> bool {
+    self.csrf_exempt_tokens.unwrap_or(true)
+  }
+
+  /// Returns how long we wait for in-flight requests to finish when shutting down.
+  pub(super) fn shutdown_timeout(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.shutdown_timeout.unwrap_or(10))
+  }
+
+  /// Returns the limit applied to the messages of each websocket client.
+  pub(super) fn client_rate_limit(&self) -> super::rate_limit::RateLimit {
+    self
+      .client_rate_limit
+      .unwrap_or(super::rate_limit::RateLimit::new(30, 10.0))
+  }
+
+  /// Returns the limit applied to the uploads of each session.
+  pub(super) fn upload_rate_limit(&self) -> super::rate_limit::RateLimit {
+    self
+      .upload_rate_limit
+      .unwrap_or(super::rate_limit::RateLimit::new(5, 0.1))
+  }
 }
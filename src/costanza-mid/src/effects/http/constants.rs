@@ -1,11 +1,23 @@
 /// The name of our session cookie used within our `Set-Cookie` headers.
 pub(super) const COOKIE_NAME: &str = "_costanza_session";
 
-/// When setting the cookie, these flags are used alongside the actual value.
+/// When setting the cookie, these flags are used alongside the actual value (and its `Max-Age`).
 #[cfg(debug_assertions)]
-pub(super) const COOKIE_SET_FLAGS: &str = "Max-Age=3600; Path=/; SameSite=Strict; HttpOnly";
+pub(super) const COOKIE_SET_FLAGS: &str = "Path=/; SameSite=Strict; HttpOnly";
 #[cfg(not(debug_assertions))]
-pub(super) const COOKIE_SET_FLAGS: &str = "Max-Age=3600; Path=/; SameSite=Strict; HttpOnly; Secure";
+pub(super) const COOKIE_SET_FLAGS: &str = "Path=/; SameSite=Strict; HttpOnly; Secure";
+
+/// The name of the cookie holding the csrf token that state-changing requests must echo back.
+pub(super) const CSRF_COOKIE_NAME: &str = "_costanza_csrf";
+
+/// The header state-changing requests provide the csrf token in.
+pub(super) const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// When setting the csrf cookie, these flags are used alongside its value. It is readable by scripts.
+#[cfg(debug_assertions)]
+pub(super) const CSRF_COOKIE_SET_FLAGS: &str = "Path=/; SameSite=Strict";
+#[cfg(not(debug_assertions))]
+pub(super) const CSRF_COOKIE_SET_FLAGS: &str = "Path=/; SameSite=Strict; Secure";
 
 /// When clearing a cookie, these flags are sent.
 #[cfg(debug_assertions)]
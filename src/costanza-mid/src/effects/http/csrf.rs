@@ -0,0 +1,91 @@
+//! Double-submit csrf protection. `/auth/identify` issues a random token in a cookie that scripts
+//! on our own origin can read; every state-changing request made with our session cookie must echo
+//! that token back in a header, which other origins cannot do.
+
+use super::{constants, shared_state, utils};
+use crate::catalog;
+
+/// Requests made with these methods do not change anything, and are never checked.
+const SAFE_METHODS: &[tide::http::Method] = &[
+  tide::http::Method::Get,
+  tide::http::Method::Head,
+  tide::http::Method::Options,
+];
+
+/// Generates a new, random csrf token.
+pub(super) fn token() -> String {
+  uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Returns the csrf token held in the cookie of a request, if any.
+pub(super) fn cookie_token(request: &tide::Request<shared_state::SharedState>) -> Option<String> {
+  request
+    .cookie(constants::CSRF_COOKIE_NAME)
+    .map(|cookie| cookie.value().to_string())
+    .filter(|value| !value.is_empty())
+}
+
+/// Returns the `Set-Cookie` value holding a csrf token. Unlike our session cookie, this one is not
+/// `HttpOnly` since the ui needs to read it.
+pub(super) fn cookie(config: &super::Configuration, token: &str) -> String {
+  format!(
+    "{}={}; Max-Age={}; {}; Domain={}",
+    constants::CSRF_COOKIE_NAME,
+    token,
+    config.session.session_ttl().as_secs(),
+    constants::CSRF_COOKIE_SET_FLAGS,
+    &config.domain
+  )
+}
+
+/// Compares two tokens without returning early, so the time taken does not reveal how much of a
+/// guess was right.
+fn matches(expected: &str, provided: &str) -> bool {
+  expected.len() == provided.len()
+    && expected
+      .bytes()
+      .zip(provided.bytes())
+      .fold(0, |difference, (left, right)| difference | (left ^ right))
+      == 0
+}
+
+/// The middleware registered in front of every route.
+#[derive(Clone, Default)]
+pub(super) struct CsrfProtection {}
+
+#[tide::utils::async_trait]
+impl tide::Middleware<shared_state::SharedState> for CsrfProtection {
+  async fn handle(
+    &self,
+    request: tide::Request<shared_state::SharedState>,
+    next: tide::Next<'_, shared_state::SharedState>,
+  ) -> tide::Result {
+    if SAFE_METHODS.contains(&request.method()) {
+      return Ok(next.run(request).await);
+    }
+
+    // Api tokens are never sent by the browser on its own, so requests made with one cannot be forged.
+    if utils::bearer_token(&request).is_some() && request.state().config.csrf_exempt_tokens() {
+      return Ok(next.run(request).await);
+    }
+
+    // Without our session cookie there is nothing to forge; the route will refuse the request itself.
+    if request.cookie(constants::COOKIE_NAME).is_none() {
+      return Ok(next.run(request).await);
+    }
+
+    let provided = request.header(constants::CSRF_HEADER).map(|values| values.as_str());
+
+    match (cookie_token(&request), provided) {
+      (Some(expected), Some(provided)) if matches(&expected, provided) => Ok(next.run(request).await),
+      _ => {
+        tracing::warn!(
+          "refusing '{} {}' without a matching csrf token",
+          request.method(),
+          request.url().path()
+        );
+        Err(utils::error(catalog::Code::CsrfMismatch))
+      }
+    }
+  }
+}
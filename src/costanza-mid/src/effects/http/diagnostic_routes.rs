@@ -0,0 +1,61 @@
+use super::{capture, shared_state, utils};
+
+/// The amount of clients returned by the `clients` route.
+const SLOWEST_CLIENT_LIMIT: usize = 10;
+
+/// route: builds a debug bundle (logs, redacted configuration, version, recent messages and the
+/// serial transcript) that can be attached to bug reports.
+pub(super) async fn bundle(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+
+  let archive = request.state().diagnostics.bundle().map_err(|error| {
+    tracing::warn!("unable to build debug bundle - {error}");
+    tide::Error::from_str(500, "unable to build debug bundle")
+  })?;
+
+  Ok(
+    tide::Response::builder(200)
+      .content_type("application/gzip")
+      .header("Content-Disposition", "attachment; filename=\"costanza-debug.tar.gz\"")
+      .body(archive)
+      .build(),
+  )
+}
+
+/// route: returns the websocket clients we have spent the most time blocked on while sending, along
+/// with the amount of data sent to each. A client on a bad connection will show up at the top.
+pub(super) async fn clients(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+  let clients = request.state().metrics.slowest_clients(SLOWEST_CLIENT_LIMIT);
+  tide::Body::from_json(&clients).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: returns the state of the websocket capture.
+pub(super) async fn capture_status(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+  tide::Body::from_json(&request.state().capture.status()).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: starts capturing websocket frames, replacing any previous capture. The capture file can
+/// be downloaded as `capture`.
+pub(super) async fn start_capture(mut request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+
+  let options = request
+    .body_json::<capture::CaptureOptions>()
+    .await
+    .map_err(|error| tide::Error::from_str(422, format!("invalid capture options - {error}")))?;
+
+  let status = request.state().capture.start(options).map_err(|error| {
+    tracing::warn!("unable to start websocket capture - {error}");
+    tide::Error::from_str(500, "unable to start capture")
+  })?;
+
+  tide::Body::from_json(&status).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: stops capturing websocket frames.
+pub(super) async fn stop_capture(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+  tide::Body::from_json(&request.state().capture.stop()).map(|body| tide::Response::builder(200).body(body).build())
+}
@@ -0,0 +1,84 @@
+use super::{shared_state, utils};
+use crate::catalog;
+use async_std::io::prelude::{ReadExt, SeekExt};
+
+/// route: lists the files available for download, along with their sizes.
+pub(super) async fn list(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+  tide::Body::from_json(&request.state().downloads.list()).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: streams a single file from disk. A single `Range` is honored, allowing large files to be
+/// downloaded in parts (or resumed).
+pub(super) async fn download(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+
+  let name = request.param("name")?;
+  let download = request
+    .state()
+    .downloads
+    .get(name)
+    .ok_or_else(|| utils::error(catalog::Code::NotFound))?;
+
+  let mut file = async_std::fs::File::open(&download.path).await?;
+  let length = file.metadata().await?.len();
+
+  let range = match request.header("Range") {
+    None => None,
+    Some(values) => match parse_range(values.last().as_str(), length) {
+      Some(range) => Some(range),
+      None => {
+        return Ok(
+          tide::Response::builder(416)
+            .header("Content-Range", format!("bytes */{length}"))
+            .build(),
+        );
+      }
+    },
+  };
+
+  let (start, end) = range.unwrap_or((0, length));
+  file.seek(std::io::SeekFrom::Start(start)).await?;
+  let reader = async_std::io::BufReader::new(file.take(end - start));
+
+  let mut response = tide::Response::builder(if range.is_some() { 206 } else { 200 })
+    .content_type("application/octet-stream")
+    .header("Accept-Ranges", "bytes")
+    .header(
+      "Content-Disposition",
+      format!("attachment; filename=\"{}\"", download.name),
+    )
+    .body(tide::Body::from_reader(reader, Some((end - start) as usize)));
+
+  if range.is_some() {
+    response = response.header("Content-Range", format!("bytes {start}-{}/{length}", end - 1));
+  }
+
+  Ok(response.build())
+}
+
+/// Parses the value of a `Range` header containing a single range of bytes into the start
+/// (inclusive) and end (exclusive) offsets into a file of the provided length. Returns `None` when
+/// the range cannot be satisfied; multiple ranges are not supported.
+fn parse_range(header: &str, length: u64) -> Option<(u64, u64)> {
+  let (start, end) = header.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+  let (start, end) = match (start.trim(), end.trim()) {
+    // The last `n` bytes of the file.
+    ("", suffix) => {
+      let suffix = suffix.parse::<u64>().ok()?;
+      (length.saturating_sub(suffix), length)
+    }
+    (start, "") => (start.parse().ok()?, length),
+    (start, end) => (
+      start.parse().ok()?,
+      end.parse::<u64>().ok()?.saturating_add(1).min(length),
+    ),
+  };
+
+  if start >= end {
+    return None;
+  }
+
+  Some((start, end))
+}
@@ -0,0 +1,90 @@
+//! The files on disk that admins are allowed to download (message journals, job records, backups,
+//! etc...). Only files registered here, along with any migration backups made of them, can be
+//! served; nothing is resolved from the request path itself.
+
+/// A single downloadable file.
+#[derive(Debug, Clone, serde::Serialize)]
+pub(super) struct Download {
+  /// The name the file is requested by.
+  pub(super) name: String,
+
+  /// The size of the file, in bytes.
+  pub(super) size: u64,
+
+  /// Where the file lives.
+  #[serde(skip)]
+  pub(super) path: std::path::PathBuf,
+}
+
+/// The files available for download, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Downloads(std::collections::BTreeMap<String, std::path::PathBuf>);
+
+impl Downloads {
+  /// Registers a file (and its backups) for download under the provided name.
+  pub fn insert<N, P>(&mut self, name: N, path: P)
+  where
+    N: Into<String>,
+    P: Into<std::path::PathBuf>,
+  {
+    self.0.insert(name.into(), path.into());
+  }
+
+  /// Returns every file that currently exists on disk. Backups are named after the file they were
+  /// made from, e.g `jobs.v1.bak`.
+  pub(super) fn list(&self) -> Vec<Download> {
+    let mut downloads = vec![];
+
+    for (name, path) in &self.0 {
+      if let Ok(metadata) = std::fs::metadata(path) {
+        downloads.push(Download {
+          name: name.clone(),
+          size: metadata.len(),
+          path: path.clone(),
+        });
+      }
+
+      let (parent, prefix) = match (path.parent(), path.file_name().and_then(|name| name.to_str())) {
+        (Some(parent), Some(prefix)) => (parent, prefix),
+        _ => continue,
+      };
+
+      let parent = if parent.as_os_str().is_empty() {
+        std::path::Path::new(".")
+      } else {
+        parent
+      };
+
+      let entries = match std::fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(error) => {
+          tracing::warn!("unable to list backups of '{name}' - {error}");
+          continue;
+        }
+      };
+
+      for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_name = entry.file_name();
+        let suffix = match file_name.to_str().and_then(|file_name| file_name.strip_prefix(prefix)) {
+          Some(suffix) if suffix.starts_with(".v") && suffix.ends_with(".bak") => suffix.to_string(),
+          _ => continue,
+        };
+
+        if let Ok(metadata) = entry.metadata() {
+          downloads.push(Download {
+            name: format!("{name}{suffix}"),
+            size: metadata.len(),
+            path: entry.path(),
+          });
+        }
+      }
+    }
+
+    downloads
+  }
+
+  /// Returns the file with the provided name, if it exists.
+  pub(super) fn get(&self, name: &str) -> Option<Download> {
+    self.list().into_iter().find(|download| download.name == name)
+  }
+}
@@ -0,0 +1,59 @@
+//! The encodings websocket frames can be sent in. Every payload is produced by the application as
+//! json; clients that would rather save on bandwidth can ask for MessagePack when they connect
+//! (e.g `/ws?encoding=msgpack`), in which case payloads are transcoded here on their way out (and
+//! binary frames transcoded back into json on their way in).
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// The name of every encoding we support, advertised to clients in the hello frame.
+pub const ENCODINGS: &[&str] = &["json", "msgpack"];
+
+/// The encoding used for the frames of a single websocket connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Encoding {
+  /// Frames are sent as json text.
+  #[default]
+  Json,
+
+  /// Frames are sent as binary MessagePack.
+  Msgpack,
+}
+
+impl Encoding {
+  /// Builds the frame sending the provided json payload to a client.
+  pub(super) fn encode(&self, payload: String) -> Result<tide_websockets::Message> {
+    match self {
+      Self::Json => Ok(tide_websockets::Message::Text(payload)),
+      Self::Msgpack => {
+        let value = serde_json::from_str::<serde_json::Value>(&payload)?;
+        rmp_serde::to_vec_named(&value)
+          .map(tide_websockets::Message::Binary)
+          .map_err(|error| Error::Encoding(format!("unable to encode msgpack frame - {error}")))
+      }
+    }
+  }
+
+  /// Returns the json payload of a frame received from a client, if it has one. Text frames are
+  /// always accepted as json, regardless of encoding.
+  pub(super) fn decode(&self, message: tide_websockets::Message) -> Result<Option<String>> {
+    match (self, message) {
+      (_, tide_websockets::Message::Text(payload)) => Ok(Some(payload)),
+      (Self::Msgpack, tide_websockets::Message::Binary(bytes)) => {
+        let value = rmp_serde::from_slice::<serde_json::Value>(&bytes)
+          .map_err(|error| Error::Encoding(format!("unable to decode msgpack frame - {error}")))?;
+        Ok(Some(serde_json::to_string(&value)?))
+      }
+      _ => Ok(None),
+    }
+  }
+}
+
+/// The options clients may provide in the query string when opening a websocket.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct WebsocketOptions {
+  /// The encoding frames should be sent in.
+  #[serde(default)]
+  pub(super) encoding: Encoding,
+}
@@ -0,0 +1,86 @@
+//! Some embedded browsers and reverse proxies handle server-sent events better than websockets. The
+//! `/events` route registers a client exactly like a websocket does and streams it the same payloads;
+//! since the stream only flows one way, control requests are made through the json api routes.
+
+use super::{capture, liveness, shared_state, utils, Command, Message};
+use crate::catalog;
+use async_std::channel;
+use futures_lite::FutureExt;
+
+/// The name of the events carrying payloads. Using the default name lets browsers handle them with
+/// `EventSource.onmessage`.
+const PAYLOAD_EVENT: &str = "message";
+
+/// The name of the events we send to keep the stream (and any proxies along the way) alive.
+const PING_EVENT: &str = "ping";
+
+/// route: streams the payloads the application sends this client as `text/event-stream`.
+pub(super) async fn events(
+  request: tide::Request<shared_state::SharedState>,
+  events: tide::sse::Sender,
+) -> tide::Result<()> {
+  let state = request.state();
+  let authority = match utils::session_id(&request) {
+    None => None,
+    Some(id) => state.authority(id).await,
+  }
+  .ok_or_else(|| utils::error(catalog::Code::NotFound))?;
+
+  let _guard = state.inflight.track();
+  let (sender, receiver) = channel::unbounded();
+  let liveness = liveness::Liveness::new();
+  let id = uuid::Uuid::new_v4().to_string();
+  tracing::info!("event stream client {id} connected");
+
+  state.registration.send((id.clone(), sender, liveness.clone())).await?;
+  state
+    .messages
+    .send(Message::ClientConnected(id.clone(), authority))
+    .await?;
+
+  let ping_interval = state.config.ping_interval();
+
+  loop {
+    let ping = async {
+      async_std::task::sleep(ping_interval).await;
+      Ok(None)
+    };
+    let command = async { receiver.recv().await.map(Some) };
+
+    let (name, data) = match command.race(ping).await {
+      Ok(Some(Command::SendState(_, data) | Command::Broadcast(data))) => (PAYLOAD_EVENT, data),
+      Ok(Some(Command::Shutdown(reason))) => {
+        tracing::info!("closing event stream client {id} - {reason}");
+        break;
+      }
+      Ok(Some(Command::Reconfigure(_))) => continue,
+      Ok(None) => (PING_EVENT, String::new()),
+      Err(error) => {
+        tracing::warn!("event stream client {id} channel closed - {error}");
+        break;
+      }
+    };
+
+    let started = std::time::Instant::now();
+    if let Err(error) = events.send(name, &data, None).await {
+      tracing::info!("event stream client {id} went away - {error}");
+      break;
+    }
+
+    // Nothing flows back from the client; being able to write to it is all we have to go on.
+    liveness.touch();
+
+    if name == PAYLOAD_EVENT {
+      state.capture.record(&id, capture::Direction::Outbound, &data);
+      state.metrics.client_sent(&id, data.len(), started.elapsed());
+    }
+  }
+
+  state.metrics.client_disconnected(&id);
+
+  if !liveness.reaped() {
+    state.messages.send(Message::ClientDisconnected(id)).await?;
+  }
+
+  Ok(())
+}
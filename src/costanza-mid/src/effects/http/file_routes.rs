@@ -1,52 +1,63 @@
-use super::{shared_state, utils};
+use super::{sec, shared_state, utils};
+use crate::catalog;
 
 /// route: attempts to parse the request body as a raw utf-8 string and pass the contents over the
 /// outbound message channel to be picked up by the concrete application runtime.
 pub(super) async fn upload(mut request: tide::Request<shared_state::SharedState>) -> tide::Result {
-  let claims = utils::cookie_claims(&request);
+  utils::require_authority(&request, sec::Authority::Operator).await?;
+  let session_id = utils::session_id(&request);
 
-  if claims.is_none() {
-    tracing::warn!("missing claims on request to upload file");
+  if session_id.is_none() {
+    tracing::warn!("missing session on request to upload file");
     return Ok(tide::Response::new(404));
   }
 
-  let claims = claims.unwrap();
-  let session_data = request.state().user_from_session(&claims.oid).await.ok_or_else(|| {
-    tracing::warn!("unable to load session data for claims {}", claims.oid);
-    tide::Error::from_str(404, "no-session")
+  let session_id = session_id.unwrap();
+  if !request.state().uploads.take(&session_id) {
+    tracing::warn!("rate limiting upload from session '{session_id}'");
+    return Err(utils::error(catalog::Code::RateLimited));
+  }
+
+  let session_data = request.state().user_from_session(&session_id).await.ok_or_else(|| {
+    tracing::warn!("unable to load session data for session {session_id}");
+    utils::error(catalog::Code::SessionNotFound)
   })?;
 
   let content_type = request
     .content_type()
-    .ok_or_else(|| tide::Error::from_str(422, "missing-filetype"))?;
+    .ok_or_else(|| utils::error(catalog::Code::UploadMissingType))?;
 
   if content_type.basetype() != "text" {
     tracing::warn!("invalid upload type - {content_type:?}");
-    return Err(tide::Error::from_str(422, "invalid-filetype"));
+    return Err(utils::error(catalog::Code::UploadInvalidType));
   }
 
   let size = request.len().unwrap_or(0);
-  if size == 0 || size > request.state().config.max_upload_size {
+  if size == 0 || size > request.state().max_upload_size() {
     tracing::warn!("invalid request size - {size}");
-    return Err(tide::Error::from_str(422, "file-too-large"));
+    return Err(utils::error(catalog::Code::UploadTooLarge));
   }
 
   tracing::info!("file upload initiated by '{}'", session_data.user.user_id,);
   let bytes = request.body_bytes().await?;
   let raw = String::from_utf8(bytes).map_err(|error| {
     tracing::warn!("unable to interpret upload as valid utf8-string: {error}");
-    tide::Error::from_str(422, "invalid-file")
+    utils::error(catalog::Code::UploadInvalidEncoding)
   })?;
   tracing::info!("raw byte contents as string - '{raw:?}'");
 
+  // The name and material of the file are optional details provided through the query string that
+  // are only used for record keeping.
+  let metadata = request.query::<super::UploadMetadata>().unwrap_or_default();
+
   request
     .state()
     .messages
-    .send(super::Message::FileUpload(raw))
+    .send(super::Message::FileUpload(raw, metadata))
     .await
     .map_err(|error| {
       tracing::warn!("unable to interpret upload as valid utf8-string: {error}");
-      tide::Error::from_str(422, "invalid-file")
+      utils::error(catalog::Code::UploadInvalidEncoding)
     })?;
 
   Ok(tide::Response::new(200))
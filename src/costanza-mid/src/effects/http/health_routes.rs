@@ -0,0 +1,92 @@
+//! The health check consulted by load balancers and service managers. Unlike `/status`, which only
+//! tells whether our server is answering, this reports on the things the application depends on.
+
+use super::shared_state;
+use crate::health;
+use serde::{Deserialize, Serialize};
+
+/// How long we wait on the session store to answer before considering it unreachable.
+const SESSION_PING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The things we depend on that can be considered critical.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Dependency {
+  /// The serial connection to the controller.
+  Serial,
+
+  /// The store our sessions are persisted in (e.g redis).
+  Sessions,
+}
+
+/// The configuration of our health check.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub(super) struct HealthConfiguration {
+  /// The dependencies we cannot do without; while any of them are down, the health check responds
+  /// with a `503`. Without any, it always responds with a `200`.
+  #[serde(default)]
+  critical: Vec<Dependency>,
+}
+
+/// The schema of our health check response.
+#[derive(Serialize)]
+struct HealthResponse {
+  /// Whether or not every critical dependency is up.
+  healthy: bool,
+
+  /// The current time of our server.
+  time: std::time::SystemTime,
+
+  /// The amount of seconds since we started.
+  uptime: u64,
+
+  /// The state of the serial connection.
+  serial: health::SerialHealth,
+
+  /// The job currently being sent, if any.
+  job: Option<health::JobHealth>,
+
+  /// Whether or not our session store answered.
+  sessions: bool,
+}
+
+/// route: reports on the serial connection, the session store and the current job.
+pub(super) async fn health(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let state = request.state();
+  let application = state.health.current();
+
+  let sessions = match async_std::future::timeout(SESSION_PING_TIMEOUT, state.sessions.ping()).await {
+    Ok(Ok(())) => true,
+    Ok(Err(error)) => {
+      tracing::warn!("session store health check failed - {error}");
+      false
+    }
+    Err(_) => {
+      tracing::warn!("session store health check timed out");
+      false
+    }
+  };
+
+  let critical = state
+    .config
+    .health
+    .as_ref()
+    .map(|health| health.critical.as_slice())
+    .unwrap_or_default();
+  let healthy = critical.iter().all(|dependency| match dependency {
+    Dependency::Serial => application.serial.connected(),
+    Dependency::Sessions => sessions,
+  });
+
+  let response = HealthResponse {
+    healthy,
+    time: std::time::SystemTime::now(),
+    uptime: state.health.uptime().as_secs(),
+    serial: application.serial,
+    job: application.job,
+    sessions,
+  };
+  let status = if healthy { 200 } else { 503 };
+
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(status).body(body).build())
+}
@@ -0,0 +1,54 @@
+use super::{sec, shared_state, utils};
+use serde::Deserialize;
+
+/// The most jobs returned by a single request to our job listing.
+const MAX_JOBS: usize = 500;
+
+/// The query parameters accepted by our job listing.
+#[derive(Deserialize, Default)]
+struct JobQuery {
+  /// The amount of the most recent jobs to return.
+  limit: Option<usize>,
+}
+
+/// route: returns the most recently finished jobs as json, newest first (e.g `/api/jobs?limit=10`).
+pub(super) async fn list_jobs(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_authority(&request, sec::Authority::Observer).await?;
+  let query = request.query::<JobQuery>().unwrap_or_default();
+  let jobs = request
+    .state()
+    .jobs
+    .recent(query.limit.unwrap_or(MAX_JOBS).min(MAX_JOBS));
+  tide::Body::from_json(&jobs).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// route: exports every recorded job as a csv file.
+pub(super) async fn export_jobs(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+  Ok(csv_response("jobs.csv", request.state().jobs.jobs_csv()))
+}
+
+/// route: exports the amount of jobs and machine-hours per day as a csv file.
+pub(super) async fn export_stats(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+  Ok(csv_response("stats.csv", request.state().jobs.stats_csv()))
+}
+
+/// route: reports the amount of data that would be removed by the next pruning, without removing
+/// anything.
+pub(super) async fn retention_preview(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  utils::require_admin(&request).await?;
+
+  let report = request.state().pruner.prune(true);
+
+  tide::Body::from_json(&report).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Builds a response that browsers will download as a file with the provided name.
+fn csv_response(filename: &str, body: String) -> tide::Response {
+  tide::Response::builder(200)
+    .content_type("text/csv")
+    .header("Content-Disposition", format!("attachment; filename=\"{filename}\""))
+    .body(body)
+    .build()
+}
@@ -0,0 +1,95 @@
+//! Resolves the configured addresses and binds a tcp listener to each of them.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// The addresses our server listens on; either a single address or a list of them. Listening on
+/// both ipv4 and ipv6 is done by listing both (e.g `["0.0.0.0:8081", "[::]:8081"]`).
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub(super) enum ListenAddresses {
+  /// A single address (or `hostname:port`).
+  Single(String),
+
+  /// Any amount of addresses, each of which will be listened on.
+  Many(Vec<String>),
+}
+
+impl ListenAddresses {
+  /// Returns the configured addresses, without resolving them.
+  fn iter(&self) -> impl Iterator<Item = &String> {
+    let addresses = match self {
+      Self::Single(address) => std::slice::from_ref(address),
+      Self::Many(addresses) => addresses.as_slice(),
+    };
+
+    addresses.iter()
+  }
+
+  /// Records an error for every address that is not a `host:port` pair we could resolve.
+  pub(super) fn validate(&self, validation: &mut crate::validation::Validation) {
+    if self.iter().next().is_none() {
+      validation.error("http.addr", "no addresses to listen on");
+    }
+
+    for address in self.iter() {
+      if let Err(error) = std::net::ToSocketAddrs::to_socket_addrs(address.as_str()) {
+        validation.error("http.addr", format!("unable to resolve '{address}' ({error})"));
+      }
+    }
+  }
+}
+
+/// Resolves every configured address, returning a listener bound to each distinct socket address.
+/// Hostnames may resolve to more than one address (e.g `localhost`), in which case each of them is
+/// bound.
+pub(super) async fn bind(addresses: &ListenAddresses) -> Result<Vec<std::net::TcpListener>> {
+  let mut resolved = vec![];
+
+  for address in addresses.iter() {
+    let socket_addrs = async_std::net::ToSocketAddrs::to_socket_addrs(address.as_str())
+      .await
+      .map_err(|error| Error::Address(format!("unable to resolve '{address}' - {error}")))?;
+
+    for socket_addr in socket_addrs {
+      if !resolved.contains(&socket_addr) {
+        resolved.push(socket_addr);
+      }
+    }
+  }
+
+  if resolved.is_empty() {
+    return Err(Error::Address("no addresses to listen on".into()));
+  }
+
+  let mut listeners = Vec::with_capacity(resolved.len());
+
+  for socket_addr in resolved {
+    let listener =
+      listen(socket_addr).map_err(|error| Error::Address(format!("unable to listen on '{socket_addr}' - {error}")))?;
+
+    tracing::info!("listening on {}", listener.local_addr()?);
+    listeners.push(listener);
+  }
+
+  Ok(listeners)
+}
+
+/// Binds a listener to a single address. Ipv6 listeners are restricted to ipv6 traffic so that
+/// they do not conflict with an ipv4 listener on the same port.
+fn listen(socket_addr: std::net::SocketAddr) -> std::io::Result<std::net::TcpListener> {
+  let socket = socket2::Socket::new(
+    socket2::Domain::for_address(socket_addr),
+    socket2::Type::STREAM,
+    Some(socket2::Protocol::TCP),
+  )?;
+
+  if socket_addr.is_ipv6() {
+    socket.set_only_v6(true)?;
+  }
+
+  socket.set_reuse_address(true)?;
+  socket.bind(&socket_addr.into())?;
+  socket.listen(1024)?;
+  Ok(socket.into())
+}
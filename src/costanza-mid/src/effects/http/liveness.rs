@@ -0,0 +1,62 @@
+//! Websocket clients on flaky networks (e.g a tablet that loses wifi) can leave half-open
+//! connections behind that never error. Every connection is pinged periodically and shares its
+//! `Liveness` with the proxy task, which reaps the clients that stop answering.
+
+use std::sync::{Arc, Mutex};
+
+/// The values shared behind a `Liveness` handle.
+struct Inner {
+  /// When we last heard anything from the client.
+  last_seen: std::time::Instant,
+
+  /// Whether or not the proxy has given up on the client.
+  reaped: bool,
+}
+
+/// A cheaply cloneable handle to when a single websocket client was last heard from.
+#[derive(Clone)]
+pub(super) struct Liveness(Arc<Mutex<Inner>>);
+
+impl Liveness {
+  /// Creates the liveness of a client that has just connected.
+  pub(super) fn new() -> Self {
+    Self(Arc::new(Mutex::new(Inner {
+      last_seen: std::time::Instant::now(),
+      reaped: false,
+    })))
+  }
+
+  /// Records having heard from the client (a pong, or any other frame).
+  pub(super) fn touch(&self) {
+    self.with(|inner| inner.last_seen = std::time::Instant::now())
+  }
+
+  /// Returns how long it has been since we heard from the client.
+  pub(super) fn silence(&self) -> std::time::Duration {
+    self.with(|inner| inner.last_seen.elapsed())
+  }
+
+  /// Marks the client as having been given up on.
+  pub(super) fn reap(&self) {
+    self.with(|inner| inner.reaped = true)
+  }
+
+  /// Returns whether or not the client has been given up on; the application has already been told
+  /// about it, and the connection should be closed.
+  pub(super) fn reaped(&self) -> bool {
+    self.with(|inner| inner.reaped)
+  }
+
+  /// Runs the closure against our locked values.
+  fn with<F, T>(&self, apply: F) -> T
+  where
+    F: FnOnce(&mut Inner) -> T,
+  {
+    let mut inner = match self.0.lock() {
+      Ok(inner) => inner,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    apply(&mut inner)
+  }
+}
@@ -3,26 +3,93 @@
 //! This module contains the types related to side effects associated with http connections to our
 //! server.
 
+use crate::catalog;
+use crate::error::{Error, Result};
 use async_std::{channel, sync};
 use futures_lite::{FutureExt, StreamExt};
-use serde::Serialize;
-use std::io;
+use serde::{Deserialize, Serialize};
 
 /// The `auth_routes` module defines the routes responsible for authenticating users.
 mod auth_routes;
 
+/// The `api_routes` mirror the websocket control protocol for scripts.
+mod api_routes;
+
+/// The `event_routes` stream state to clients as server-sent events.
+mod event_routes;
+
+/// The `static_routes` serve the ui bundle.
+mod static_routes;
+
+/// Tracing and access logging of every request.
+mod request_log;
+
+/// Token buckets limiting how much each client may send.
+mod rate_limit;
+
+/// Draining in-flight requests when shutting down.
+mod shutdown;
+
+/// The `health_routes` report on the things we depend on.
+mod health_routes;
+
+/// The pool of connections to redis.
+mod redis_pool;
+
+/// The backends sessions can be persisted in.
+mod session_store;
+
+/// Renewing sessions while they are being used.
+mod renewal;
+
+/// Double-submit csrf protection of state-changing routes.
+mod csrf;
+
 /// The `file_routes` deals with uploading files.
 mod file_routes;
 
+/// The `job_routes` deal with exporting the history of jobs.
+mod job_routes;
+
+/// The `camera_routes` proxy the camera watching the machine.
+mod camera_routes;
+
+/// The `diagnostic_routes` deal with building debug bundles.
+mod diagnostic_routes;
+
+/// The `download_routes` deal with streaming files from disk.
+mod download_routes;
+
+/// The files that may be downloaded.
+mod downloads;
+
+/// Capturing websocket frames to disk.
+mod capture;
+
+/// The encodings websocket frames can be sent in.
+mod encoding;
+
+/// Tracking when websocket clients were last heard from.
+mod liveness;
+
 /// Contains configuration structure.
 mod configuration;
 
+/// Resolving and binding the addresses we listen on.
+mod listeners;
+
+/// Advertising our server over mdns.
+mod advertise;
+
 /// Cookie and other compile-time constants.
 mod constants;
 
 /// Types related to Auth0 (current recommended oauth provider)
 mod oauth;
 
+/// Signing in through generic OpenID Connect providers.
+mod oidc;
+
 /// Cookie + JWT related types.
 mod sec;
 
@@ -32,33 +99,67 @@ mod shared_state;
 /// General utility functionality.
 mod utils;
 
+pub(crate) use capture::Capture;
 pub use configuration::Configuration;
+pub use downloads::Downloads;
+pub use encoding::ENCODINGS;
+pub use sec::Authority;
+pub use shutdown::RESTARTING;
 
 /// The command type here represents effects that a concrete `eff::Application` can send into our
 /// web runtime.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Command {
   /// When the concrete application runtime needs to send a payload to a connected websocket,
   /// this command will be returned which contains the id of a client and the payload to send.
   SendState(String, String),
+
+  /// Sends the same payload to every connected websocket.
+  Broadcast(String),
+
+  /// Stops accepting connections and closes every websocket with the provided reason. Once the
+  /// requests that were in flight have finished (or the shutdown timeout elapsed), a `Stopped`
+  /// message is sent back.
+  Shutdown(String),
+
+  /// Applies the parts of a new configuration that can change while we are running (the size and
+  /// rate of uploads). Everything else (e.g our addresses and sessions) requires a restart.
+  Reconfigure(Box<Configuration>),
+}
+
+/// Optional details provided by users when uploading a file.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct UploadMetadata {
+  /// The name of the file.
+  pub name: Option<String>,
+
+  /// The material that will be cut.
+  pub material: Option<String>,
 }
 
 /// The message type here are the possible messages produced by this effect runtime that are
 /// consumed by the concrete application runtime.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Message {
-  /// A message that will be sent to the concrete application runtime containing a client id.
-  ClientConnected(String),
+  /// A message that will be sent to the concrete application runtime containing a client id, and the
+  /// authority of the user that opened it.
+  ClientConnected(String, Authority),
 
   /// A message that will be sent to the concrete application runtime containing a client id and
   /// any data that was received by that client.
   ClientData(String, String),
 
   /// When a file is uploaded, we will...
-  FileUpload(String),
+  FileUpload(String, UploadMetadata),
 
   /// A message that will be sent to the concrete application runtime containing a client id.
   ClientDisconnected(String),
+
+  /// Our listeners have been bound, and we are accepting connections.
+  Listening,
+
+  /// The server has stopped after being asked to shut down.
+  Stopped,
 }
 
 /// The `Http` effect  is responsible for creating a server runtime and passing message/command
@@ -72,21 +173,57 @@ pub struct Http<C, M> {
 
   /// The channel pair used to send messages to the application runtime.
   messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
+
+  /// The metrics of the effect runtime, rendered by our `/metrics` route.
+  metrics: crate::metrics::Metrics,
+
+  /// The history of jobs run by the application, exported by our job routes.
+  jobs: crate::jobs::JobHistory,
+
+  /// Used to build debug bundles for admins.
+  diagnostics: crate::diagnostics::Diagnostics,
+
+  /// The files admins may download.
+  downloads: Downloads,
+
+  /// The health reported by the application, served by our health check.
+  health: crate::health::Health,
+
+  /// Used to preview what the next pruning would remove, and holds the websocket capture.
+  pruner: crate::retention::Pruner,
 }
 
 impl<C, M> Http<C, M>
 where
   M: std::fmt::Debug,
 {
-  /// Return a new http effect manager based on a provided configuration.
-  pub fn new(config: Configuration) -> Self {
+  /// Return a new http effect manager based on a provided configuration and the metrics handle of
+  /// the effect runtime it will be registered with, along with the job history, diagnostics, health
+  /// and pruner of the application and the files admins may download.
+  pub fn new(
+    config: Configuration,
+    metrics: crate::metrics::Metrics,
+    jobs: crate::jobs::JobHistory,
+    diagnostics: crate::diagnostics::Diagnostics,
+    mut downloads: Downloads,
+    health: crate::health::Health,
+    pruner: crate::retention::Pruner,
+  ) -> Self {
     let commands = channel::unbounded();
+    downloads.insert("capture", config.capture_path());
+
     let messages = channel::unbounded();
 
     Self {
       config,
       commands: (commands.1, Some(commands.0)),
       messages: (messages.0, Some(messages.1)),
+      metrics,
+      jobs,
+      diagnostics,
+      downloads,
+      health,
+      pruner,
     }
   }
 
@@ -97,7 +234,7 @@ where
   ///    command type provided by this module.
   /// 2. allowing consumers to map messages from the internal runtime here into their own
   ///    application message domain.
-  pub async fn run<CM, MM>(self, command_mapper: CM, message_mapper: MM) -> io::Result<()>
+  pub async fn run<CM, MM>(self, command_mapper: CM, message_mapper: MM) -> Result<()>
   where
     CM: Fn(C) -> Option<Command>,
     MM: Fn(Message) -> M,
@@ -108,7 +245,16 @@ where
     let command_proxy = channel::unbounded();
 
     // Create the underlying server runtime and execute in in a separate task.
-    let runtime = ServerRuntime::new(self.config, (message_proxy.0.clone(), command_proxy.1));
+    let runtime = ServerRuntime {
+      config: self.config,
+      channels: (message_proxy.0.clone(), command_proxy.1),
+      metrics: self.metrics.clone(),
+      jobs: self.jobs.clone(),
+      diagnostics: self.diagnostics.clone(),
+      downloads: self.downloads.clone(),
+      health: self.health.clone(),
+      pruner: self.pruner.clone(),
+    };
     async_std::task::spawn(async move { runtime.run().await });
 
     // Our main "thread" here will be concerned with pulling messages from what is sent from the
@@ -133,7 +279,7 @@ where
           .0
           .recv()
           .await
-          .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("failed http command proxy - {error}")))?;
+          .map_err(|error| Error::ChannelClosed(format!("failed http command proxy - {error}")))?;
 
         // The consumer has a chance to return a `None` based on the command, similar to the filter
         // function provided to the generic types provided by the `eff` module.
@@ -142,10 +288,10 @@ where
             .0
             .send(inner)
             .await
-            .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("failed http command proxy - {error}")))?;
+            .map_err(|error| Error::ChannelClosed(format!("failed http command proxy - {error}")))?;
         }
 
-        Ok(()) as io::Result<()>
+        Ok(()) as Result<()>
       };
 
       // The message half of our proxy will attempt to pull messages from the channel provided to
@@ -156,14 +302,14 @@ where
           .1
           .recv()
           .await
-          .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("failed http message receive - {error}")))?;
+          .map_err(|error| Error::ChannelClosed(format!("failed http message receive - {error}")))?;
         self
           .messages
           .0
           .send(message_mapper(message))
           .await
-          .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("failed http message receive - {error}")))?;
-        Ok(()) as io::Result<()>
+          .map_err(|error| Error::ChannelClosed(format!("failed http message receive - {error}")))?;
+        Ok(()) as Result<()>
       };
 
       if let Err(error) = command_handler.race(message_handler).await {
@@ -172,7 +318,7 @@ where
       }
     }
 
-    Err(io::Error::new(io::ErrorKind::Other, "http-runtime failure"))
+    Err(Error::ChannelClosed("http-runtime failure".into()))
   }
 }
 
@@ -180,18 +326,10 @@ impl<C, M> crate::eff::Effect for Http<C, M> {
   type Message = M;
   type Command = C;
 
-  fn detach(&mut self) -> io::Result<(channel::Receiver<Self::Message>, channel::Sender<Self::Command>)> {
-    let cmd_in = self
-      .commands
-      .1
-      .take()
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "already taken"))?;
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
 
-    let msg_out = self
-      .messages
-      .1
-      .take()
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "already taken"))?;
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
 
     Ok((msg_out, cmd_in))
   }
@@ -205,47 +343,62 @@ struct Heartbeat {
 }
 
 /// route: returns the system time. can be used as a health check endpoint.
-async fn heartbeat(request: tide::Request<shared_state::SharedState>) -> tide::Result {
-  let shared_state::SharedState {
-    span,
-    config: _,
-    redis: _,
-    messages: _,
-    registration: _,
-  } = request.state();
-  let span = tracing::span!(parent: span, tracing::Level::INFO, "heartbeat");
-  tracing::event!(parent: &span, tracing::Level::INFO, "returning basic status info");
+async fn heartbeat(_request: tide::Request<shared_state::SharedState>) -> tide::Result {
   tide::Body::from_json(&Heartbeat {
     time: std::time::SystemTime::now(),
   })
   .map(|body| tide::Response::builder(200).body(body).build())
 }
 
+/// route: returns the effect runtime metrics in the prometheus text exposition format.
+async fn metrics(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let body = request.state().metrics.render();
+  Ok(
+    tide::Response::builder(200)
+      .content_type("text/plain; version=0.0.4")
+      .body(body)
+      .build(),
+  )
+}
+
 /// route: the main websocket connection consumed by the ui.
 async fn ws(
   request: tide::Request<shared_state::SharedState>,
   mut connection: tide_websockets::WebSocketConnection,
 ) -> tide::Result<()> {
   let state = request.state();
-  let authority = match utils::cookie_claims(&request) {
-    None => return Err(tide::Error::from_str(404, "not-found")),
-    Some(claims) => state.authority(claims.oid).await,
+  let authority = match utils::session_id(&request) {
+    None => return Err(utils::error(catalog::Code::NotFound)),
+    Some(id) => state.authority(id).await,
   };
 
-  if authority != Some(sec::Authority::Admin) {
-    tracing::warn!("non-admin attempt to open websocket, refusing");
-    return Err(tide::Error::from_str(404, "not-found"));
-  }
+  // Observers are allowed to connect; the application decides what each client may do.
+  let authority = authority.ok_or_else(|| {
+    tracing::warn!("unauthorized attempt to open websocket, refusing");
+    utils::error(catalog::Code::NotFound)
+  })?;
 
   let span = tracing::span!(parent: &state.span, tracing::Level::INFO, "websocket");
   let _ = span.enter();
+  let _guard = state.inflight.track();
+
+  let encoding = request
+    .query::<encoding::WebsocketOptions>()
+    .unwrap_or_default()
+    .encoding;
 
   let (sender, receiver) = channel::unbounded();
+  let liveness = liveness::Liveness::new();
 
   tracing::info!("websocket client connected");
   let id = uuid::Uuid::new_v4().to_string();
-  state.messages.send(Message::ClientConnected(id.clone())).await?;
-  state.registration.send((id.clone(), sender)).await?;
+  // Register the client with our proxy before the application hears about it; the application will
+  // immediately attempt to send its hello frame.
+  state.registration.send((id.clone(), sender, liveness.clone())).await?;
+  state
+    .messages
+    .send(Message::ClientConnected(id.clone(), authority))
+    .await?;
 
   /// During our interval, we'll either be receiving string data from the connection, or a command
   /// to send into the connection. We'll race these two effects and perform the correct action
@@ -256,42 +409,83 @@ async fn ws(
 
     /// Wraps the effect runtime message. Is ultimately mapped into a `Message::ClientData` kind.
     Message(String),
+
+    /// It is time to ping the client.
+    Ping,
   }
 
+  let ping_interval = state.config.ping_interval();
+  let mut next_ping = std::time::Instant::now() + ping_interval;
+  let mut bucket = state.config.client_rate_limit().bucket();
+
   loop {
+    let ping = async {
+      async_std::task::sleep(next_ping.saturating_duration_since(std::time::Instant::now())).await;
+      Ok(Some(FrameResult::Ping))
+    };
+
     let application_input = async {
       // Attempt to receive any client-bound command sent from the application runtime.
       match receiver.recv().await {
         Err(error) => {
           tracing::warn!("unable to receive inside websocket - {error}");
-          Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("unable to receive command - {error}"),
-          ))
+          Err(Error::ChannelClosed(format!("unable to receive command - {error}")))
         }
         Ok(command) => Ok(Some(FrameResult::Command(command))),
       }
     };
 
     let client_input = async {
-      match connection.next().await {
-        None => Err(io::Error::new(io::ErrorKind::Other, "end-of-stream")),
-        Some(Ok(tide_websockets::Message::Text(data))) => {
-          tracing::info!("has data from websocket - {data}");
-          Ok(Some(FrameResult::Message(data)))
-        }
-        Some(Ok(_)) => Ok(None),
+      let next = connection.next().await;
+
+      // Anything at all from the client means it is still there.
+      if let Some(Ok(_)) = next.as_ref() {
+        liveness.touch();
+      }
+
+      match next {
+        None => Err(Error::Websocket("end-of-stream".into())),
+        Some(Ok(message)) => match encoding.decode(message)? {
+          Some(data) => {
+            tracing::info!("has data from websocket - {data}");
+            state.capture.record(&id, capture::Direction::Inbound, &data);
+            Ok(Some(FrameResult::Message(data)))
+          }
+          None => Ok(None),
+        },
         Some(Err(error)) => {
           tracing::warn!("failed reading from client websocket - {error}");
-          Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("unable to receive from client - {error}"),
-          ))
+          Err(Error::Websocket(format!("unable to receive from client - {error}")))
         }
       }
     };
 
-    match client_input.race(application_input).await {
+    match client_input.race(application_input).race(ping).await {
+      Ok(Some(FrameResult::Ping)) => {
+        next_ping = std::time::Instant::now() + ping_interval;
+        if let Err(error) = connection.send(tide_websockets::Message::Ping(vec![])).await {
+          tracing::warn!("unable to ping client - {error}");
+          break;
+        }
+      }
+      // Messages beyond the client's allowance are answered here instead of queuing up in front of
+      // the application (and ultimately the serial connection).
+      Ok(Some(FrameResult::Message(data))) if !bucket.take() => {
+        tracing::warn!("rate limiting websocket client '{id}'");
+        let response = rate_limited(&data);
+        state.capture.record(&id, capture::Direction::Outbound, &response);
+        let frame = match encoding.encode(response) {
+          Ok(frame) => frame,
+          Err(error) => {
+            tracing::warn!("unable to encode frame for client - {error}");
+            continue;
+          }
+        };
+        if let Err(error) = connection.send(frame).await {
+          tracing::warn!("unable to send rate limit response to client - {error}");
+          break;
+        }
+      }
       Ok(Some(FrameResult::Message(data))) => {
         if let Err(error) = request
           .state()
@@ -303,11 +497,35 @@ async fn ws(
           break;
         }
       }
+      // Broadcasts are split up into individual `SendState` commands by our proxy, which also
+      // handles reconfiguration itself.
+      Ok(Some(FrameResult::Command(Command::Broadcast(_) | Command::Reconfigure(_)))) => (),
+      Ok(Some(FrameResult::Command(Command::Shutdown(reason)))) => {
+        tracing::info!("closing websocket client '{id}' - {reason}");
+        let frame = tide_websockets::tungstenite::protocol::CloseFrame {
+          code: tide_websockets::tungstenite::protocol::frame::coding::CloseCode::Restart,
+          reason: reason.into(),
+        };
+        if let Err(error) = connection.send(tide_websockets::Message::Close(Some(frame))).await {
+          tracing::warn!("unable to send close frame to client - {error}");
+        }
+        break;
+      }
       Ok(Some(FrameResult::Command(Command::SendState(_, data)))) => {
-        if let Err(error) = connection.send_string(data).await {
+        state.capture.record(&id, capture::Direction::Outbound, &data);
+        let frame = match encoding.encode(data) {
+          Ok(frame) => frame,
+          Err(error) => {
+            tracing::warn!("unable to encode frame for client - {error}");
+            continue;
+          }
+        };
+        let (bytes, started) = (frame_len(&frame), std::time::Instant::now());
+        if let Err(error) = connection.send(frame).await {
           tracing::warn!("unable to send serialized command to client - {error}");
           break;
         }
+        state.metrics.client_sent(&id, bytes, started.elapsed());
       }
       Ok(None) => tracing::debug!("todo"),
       Err(error) => {
@@ -317,10 +535,44 @@ async fn ws(
     }
   }
 
-  state.messages.send(Message::ClientDisconnected(id.clone())).await?;
+  state.metrics.client_disconnected(&id);
+
+  // Clients that were reaped by our proxy have already been reported as disconnected.
+  if !liveness.reaped() {
+    state.messages.send(Message::ClientDisconnected(id.clone())).await?;
+  }
   Ok(())
 }
 
+/// Returns the response sent to a client whose message was refused for exceeding its rate limit,
+/// shaped like the responses of the application so clients can match it to their request's tick.
+fn rate_limited(data: &str) -> String {
+  let tick = serde_json::from_str::<serde_json::Value>(data)
+    .ok()
+    .and_then(|value| value["tick"].as_u64())
+    .unwrap_or(0);
+  let failure = catalog::Failure::new(catalog::Code::RateLimited);
+
+  serde_json::json!({
+    "kind": "response",
+    "tick": tick,
+    "status": "failed",
+    "code": failure.code,
+    "params": failure.params,
+    "detail": failure.render(),
+  })
+  .to_string()
+}
+
+/// Returns the size of a websocket frame's payload, in bytes.
+fn frame_len(frame: &tide_websockets::Message) -> usize {
+  match frame {
+    tide_websockets::Message::Text(payload) => payload.len(),
+    tide_websockets::Message::Binary(payload) => payload.len(),
+    _ => 0,
+  }
+}
+
 /// Internal to the module package, the `ServerRuntime` is responsible for creating the tide
 /// application, registering the routes and actually binding the tcp listener.
 struct ServerRuntime {
@@ -330,45 +582,118 @@ struct ServerRuntime {
   /// A pair of channels that are proxied in the `Http` effect manager and forwarded along from/to
   /// the concrete application runtime.
   channels: (channel::Sender<Message>, channel::Receiver<Command>),
+
+  /// The metrics of the effect runtime.
+  metrics: crate::metrics::Metrics,
+
+  /// The history of jobs run by the application.
+  jobs: crate::jobs::JobHistory,
+
+  /// Used to build debug bundles.
+  diagnostics: crate::diagnostics::Diagnostics,
+
+  /// The files admins may download.
+  downloads: Downloads,
+
+  /// The health reported by the application.
+  health: crate::health::Health,
+
+  /// Used to preview what the next pruning would remove.
+  pruner: crate::retention::Pruner,
 }
 
 impl ServerRuntime {
-  /// Creates a new runtime from a configuration and a pair of proxied message/command channels.
-  fn new(
-    config: configuration::Configuration,
-    channels: (channel::Sender<Message>, channel::Receiver<Command>),
-  ) -> Self {
-    Self { config, channels }
-  }
-
   /// Responsible for registering all of our `tide` application routes
-  async fn run(self) -> io::Result<()> {
+  async fn run(self) -> Result<()> {
     let span = tracing::span!(tracing::Level::INFO, "http/web");
     let _ = span.enter();
 
     let (reg_sender, reg_receiver) = channel::unbounded();
+    let (stop_sender, stop_receiver) = channel::bounded(1);
+    let inflight = shutdown::InFlight::default();
+    let stopped = self.channels.0.clone();
+    let upload_size = sync::Arc::new(std::sync::atomic::AtomicUsize::new(self.config.max_upload_size));
+    let uploads = rate_limit::Limiter::new(self.config.upload_rate_limit());
 
     let mut app = tide::with_state(shared_state::SharedState {
       config: self.config.clone(),
-      redis: async_std::sync::Arc::new(async_std::sync::Mutex::new(None)),
+      sessions: session_store::open(&self.config.session)?,
       messages: self.channels.0.clone(),
       registration: reg_sender,
+      metrics: self.metrics.clone(),
+      jobs: self.jobs.clone(),
+      diagnostics: self.diagnostics.clone(),
+      downloads: self.downloads.clone(),
+      capture: self
+        .pruner
+        .capture()
+        .unwrap_or_else(|| capture::Capture::new(self.config.capture_path())),
+      pruner: self.pruner.clone(),
+      uploads: uploads.clone(),
+      upload_size: upload_size.clone(),
+      health: self.health.clone(),
+      inflight: inflight.clone(),
       span,
     });
+    app.with(inflight.clone());
+    app.with(request_log::RequestLog::new(self.config.access_log.as_deref())?);
+    app.with(csrf::CsrfProtection::default());
+    app.with(renewal::SessionRenewal::default());
+
     app.at("/status").get(heartbeat);
+    app.at("/healthz").get(health_routes::health);
+    app.at("/metrics").get(metrics);
     app.at("/ws").with(tide_websockets::WebSocket::new(ws)).get(heartbeat);
 
+    app.at("/events").get(tide::sse::endpoint(event_routes::events));
+
     app.at("/auth/start").get(auth_routes::start);
     app.at("/auth/end").get(auth_routes::end);
     app.at("/auth/complete").get(auth_routes::complete);
     app.at("/auth/identify").get(auth_routes::identify);
+    app.at("/auth/refresh").post(auth_routes::refresh);
+    app.at("/auth/roles").get(auth_routes::roles);
+    app
+      .at("/auth/tokens")
+      .post(auth_routes::create_token)
+      .delete(auth_routes::revoke_token);
     app.at("/upload").post(file_routes::upload);
+    app.at("/camera/snapshot").get(camera_routes::snapshot);
+    app.at("/camera/stream").get(camera_routes::stream);
+    app.at("/api/jobs").get(job_routes::list_jobs);
+    app.at("/api/jobs/export.csv").get(job_routes::export_jobs);
+    app.at("/api/stats/export.csv").get(job_routes::export_stats);
+    app.at("/api/retention/preview").get(job_routes::retention_preview);
+    app.at("/api/diagnostics/bundle").get(diagnostic_routes::bundle);
+    app.at("/api/diagnostics/clients").get(diagnostic_routes::clients);
+    app
+      .at("/api/diagnostics/capture")
+      .get(diagnostic_routes::capture_status)
+      .post(diagnostic_routes::start_capture)
+      .delete(diagnostic_routes::stop_capture);
+    app.at("/api/errors").get(api_routes::errors);
+    app.at("/api/state").get(api_routes::state);
+    app.at("/api/serial/raw").post(api_routes::raw_serial);
+    app.at("/api/job/start").post(api_routes::start_job);
+    app.at("/api/downloads").get(download_routes::list);
+    app.at("/api/downloads/:name").get(download_routes::download);
+
+    // Everything not matched by the routes above belongs to the ui.
+    if let Some(root) = self.config.static_dir.as_ref() {
+      tracing::info!("serving ui bundle from '{root}'");
+      app.at("/").get(static_routes::serve);
+      app.at("/*path").get(static_routes::serve);
+    }
 
     // Our proxy task/future here is responsible for managing the mapping of client ids with a
     // channel that can be used to send them `Command`s.
+    let pong_deadline = self.config.pong_deadline();
+    let ping_interval = self.config.ping_interval();
+
     let proxy_task = async {
-      let (_, commands) = self.channels;
-      let clients: std::collections::HashMap<String, channel::Sender<Command>> = std::collections::HashMap::new();
+      let (messages, commands) = self.channels;
+      let clients: std::collections::HashMap<String, (channel::Sender<Command>, liveness::Liveness)> =
+        std::collections::HashMap::new();
       let locked = sync::Arc::new(sync::Mutex::new(clients));
 
       loop {
@@ -387,19 +712,62 @@ impl ServerRuntime {
             Ok(c) => c,
           };
 
+          let mut clients = clients.lock().await;
+
+          // The websocket handler of a client that has gone away drops its receiver, closing the
+          // channel we would send it commands through.
+          clients.retain(|id, (sender, _)| {
+            if sender.is_closed() {
+              tracing::debug!("removing disconnected client {id}");
+            }
+
+            !sender.is_closed()
+          });
+
           // Match on the command to get access to the underlying id that we want to send to, and
           // then send the command to that client.
           match &command {
             Command::SendState(id, _) => {
               tracing::info!("received state publish command - {id}");
-              let clients = clients.lock().await;
 
-              if let Some(sender) = clients.get(id) {
+              if let Some((sender, _)) = clients.get(id) {
                 if let Err(error) = sender.send(command.clone()).await {
                   tracing::warn!("failed comand propagation - {error}");
                 }
               }
             }
+
+            // Every client is told to close, after which our listener stops accepting connections.
+            Command::Shutdown(_) => {
+              tracing::info!("shutting down, closing {} clients", clients.len());
+
+              for (sender, _) in clients.values() {
+                if let Err(error) = sender.send(command.clone()).await {
+                  tracing::warn!("failed shutdown propagation - {error}");
+                }
+              }
+
+              if stop_sender.try_send(()).is_err() {
+                tracing::warn!("server already shutting down");
+              }
+            }
+
+            Command::Reconfigure(config) => {
+              tracing::info!("applying new upload limits");
+              upload_size.store(config.max_upload_size, std::sync::atomic::Ordering::Relaxed);
+              uploads.reconfigure(config.upload_rate_limit());
+            }
+
+            // Each websocket handler only deals with payloads addressed to it.
+            Command::Broadcast(payload) => {
+              tracing::info!("received broadcast command for {} clients", clients.len());
+
+              for (id, (sender, _)) in clients.iter() {
+                if let Err(error) = sender.send(Command::SendState(id.clone(), payload.clone())).await {
+                  tracing::warn!("failed broadcast propagation - {error}");
+                }
+              }
+            }
           }
 
           Ok(())
@@ -412,10 +780,10 @@ impl ServerRuntime {
           let clients = locked.clone();
 
           match reg_receiver.recv().await {
-            Ok((id, sender)) => {
+            Ok((id, sender, liveness)) => {
               tracing::info!("has new client - {id}");
               let mut clients = clients.lock().await;
-              clients.insert(id, sender);
+              clients.insert(id, (sender, liveness));
               Ok(())
             }
             Err(error) => {
@@ -425,7 +793,36 @@ impl ServerRuntime {
           }
         };
 
-        if let Err(error) = cmd.race(rec).await {
+        // Finally, we periodically look for clients that have stopped answering our pings. Dropping
+        // their sender closes the channel their websocket handler is waiting on.
+        let reap = async {
+          async_std::task::sleep(ping_interval).await;
+          let mut clients = locked.lock().await;
+          clients.retain(|_, (sender, _)| !sender.is_closed());
+
+          let silent = clients
+            .iter()
+            .filter(|(_, (_, liveness))| liveness.silence() > pong_deadline)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<String>>();
+
+          for id in silent {
+            tracing::warn!("client {id} missed its pong deadline, reaping");
+
+            if let Some((_, liveness)) = clients.remove(&id) {
+              liveness.reap();
+            }
+
+            if let Err(error) = messages.send(Message::ClientDisconnected(id)).await {
+              tracing::warn!("unable to notify application of reaped client - {error}");
+              return Err(channel::RecvError);
+            }
+          }
+
+          Ok(())
+        };
+
+        if let Err(error) = cmd.race(rec).race(reap).await {
           tracing::warn!("breaking server command loop - {error}");
           break;
         }
@@ -434,6 +831,44 @@ impl ServerRuntime {
       Ok(())
     };
 
-    app.listen(&self.config.addr).race(proxy_task).await
+    let listeners = listeners::bind(&self.config.addr).await?;
+    let advertisement = self
+      .config
+      .advertise
+      .as_deref()
+      .and_then(|name| advertise::advertise(name, &listeners));
+    stopped
+      .send(Message::Listening)
+      .await
+      .map_err(|error| Error::ChannelClosed(format!("unable to report listening - {error}")))?;
+    let shutdown_timeout = self.config.shutdown_timeout();
+
+    // Dropping the listener stops accepting connections; the tasks handling the connections that
+    // were already accepted keep running while we drain them.
+    let listener = async {
+      let listening = async { app.listen(listeners).await.map_err(Error::from) };
+      let stopping = async {
+        stop_receiver
+          .recv()
+          .await
+          .map_err(|error| Error::ChannelClosed(format!("unable to receive shutdown - {error}")))
+      };
+      listening.race(stopping).await?;
+      drop(advertisement);
+
+      tracing::info!("no longer accepting connections, draining in-flight requests");
+      if inflight.drained(shutdown_timeout).await {
+        tracing::info!("finished draining in-flight requests");
+      }
+
+      stopped
+        .send(Message::Stopped)
+        .await
+        .map_err(|error| Error::ChannelClosed(format!("unable to report shutdown - {error}")))?;
+
+      // The application decides when we actually exit; our proxy keeps running until then.
+      futures_lite::future::pending().await
+    };
+    listener.race(proxy_task).await
   }
 }
@@ -1,7 +1,8 @@
-//! Auth0 type definitions and some wrapping structs that are json serializable.
+//! Auth0 type definitions and some wrapping structs that are json serializable, along with the
+//! `Provider` users sign in through (Auth0, or any OpenID Connect provider).
 
+use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
-use std::io::{Error, ErrorKind, Result};
 
 #[allow(clippy::missing_docs_in_private_items)]
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -11,10 +12,37 @@ pub struct UserRole {
 }
 
 impl UserRole {
+  /// Returns a role that only has a name, as found in the claims of an OpenID Connect user.
+  pub(super) fn named(name: &str) -> Self {
+    Self {
+      id: name.to_string(),
+      name: name.to_string(),
+    }
+  }
+
+  /// The name of the role, as it is configured in Auth0.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
   /// Will return if the given rule is should be consider an "admin" role.
   pub fn is_admin(&self) -> bool {
     self.name.split(':').any(|part| part.starts_with("admin"))
   }
+
+  /// Will return if the given role should be considered an "operator" role, which may control the
+  /// machine but not change how it is set up.
+  pub fn is_operator(&self) -> bool {
+    self.name.split(':').any(|part| part.starts_with("operator"))
+  }
+
+  /// Will return if the given role should be considered a read-only "observer" role.
+  pub fn is_observer(&self) -> bool {
+    self
+      .name
+      .split(':')
+      .any(|part| part.starts_with("observer") || part.starts_with("viewer"))
+  }
 }
 
 #[allow(clippy::missing_docs_in_private_items)]
@@ -50,6 +78,45 @@ pub struct ManagementUserInfoResponse {
   email_verified: bool,
 }
 
+impl ManagementUserInfoResponse {
+  /// Returns the information of a user from their standard OpenID Connect claims, as long as the
+  /// claims include a subject.
+  pub(super) fn from_claims(claims: &serde_json::Value) -> Option<Self> {
+    let text = |name: &str| claims.get(name).and_then(|value| value.as_str()).map(String::from);
+
+    Some(Self {
+      user_id: text("sub")?,
+      name: text("name"),
+      picture: text("picture"),
+      email: text("email"),
+      nickname: text("preferred_username").or_else(|| text("nickname")),
+      email_verified: claims
+        .get("email_verified")
+        .and_then(|value| value.as_bool())
+        .unwrap_or_default(),
+    })
+  }
+}
+
+/// The identity provider users sign in through.
+pub(super) enum Provider<'a> {
+  /// Auth0, with roles loaded from its management api.
+  AuthZero(&'a AuthZeroConfig),
+
+  /// Any OpenID Connect provider, with roles read from the claims of the user.
+  Oidc(&'a super::oidc::OidcConfiguration),
+}
+
+impl Provider<'_> {
+  /// Returns the url that users will be sent to at the start of an oauth exchange.
+  pub(super) async fn redirect_uri(&self) -> Result<String> {
+    match self {
+      Self::AuthZero(config) => config.redirect_uri(),
+      Self::Oidc(config) => config.redirect_uri().await,
+    }
+  }
+}
+
 #[allow(clippy::missing_docs_in_private_items)]
 #[derive(Debug, Serialize)]
 pub struct AuthCodeRequest {
@@ -62,7 +129,7 @@ pub struct AuthCodeRequest {
 }
 
 #[allow(clippy::missing_docs_in_private_items)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuthZeroConfig {
   auth_client_id: String,
   auth_client_secret: String,
@@ -73,6 +140,12 @@ pub struct AuthZeroConfig {
 }
 
 impl AuthZeroConfig {
+  /// Records an error for every url that is not absolute.
+  pub(super) fn validate(&self, validation: &mut crate::validation::Validation) {
+    validation.url("http.oauth.redirect_uri", &self.redirect_uri);
+    validation.url("http.oauth.domain", &self.domain);
+  }
+
   /// Returns the url that is used for exchanging codes for tokens.
   #[inline]
   pub fn token_uri(&self) -> Result<String> {
@@ -89,12 +162,12 @@ impl AuthZeroConfig {
       .body_json(&self.auth_token_payload(code)?)
       .map_err(|error| {
         tracing::warn!("unable to serialize auth token payload - {}", error);
-        Error::new(ErrorKind::Other, "bad-token-serialize")
+        Error::OAuth("bad-token-serialize".into())
       })?
       .await
       .map_err(|error| {
         tracing::warn!("unable to request token for code - {}", error);
-        Error::new(ErrorKind::Other, "bad-code-exchange-request")
+        Error::OAuth("bad-code-exchange-request".into())
       })?;
 
     let tok = response
@@ -102,7 +175,7 @@ impl AuthZeroConfig {
       .await
       .map_err(|error| {
         tracing::warn!("unable to parse token exchange response - {}", error);
-        Error::new(ErrorKind::Other, "bad-code-exchange-request")
+        Error::OAuth("bad-code-exchange-request".into())
       })
       .map(|body| body.access_token)?;
 
@@ -111,12 +184,12 @@ impl AuthZeroConfig {
       .await
       .map_err(|error| {
         tracing::warn!("unable to parse token exchange response - {}", error);
-        Error::new(ErrorKind::Other, "bad-code-exchange-request")
+        Error::OAuth("bad-code-exchange-request".into())
       })?;
 
     res.body_json::<UserInfo>().await.map_err(|error| {
       tracing::warn!("unable to parse token exchange response - {}", error);
-      Error::new(ErrorKind::Other, "bad-code-exchange-request")
+      Error::OAuth("bad-code-exchange-request".into())
     })
   }
 
@@ -131,11 +204,11 @@ impl AuthZeroConfig {
       .await
       .map_err(|error| {
         tracing::warn!("unable to parse user info response - {}", error);
-        Error::new(ErrorKind::Other, format!("{}", error))
+        Error::OAuth(format!("{}", error))
       })?;
 
     if response.status() != surf::StatusCode::Ok {
-      return Err(Error::new(ErrorKind::Other, "not-ok-response"));
+      return Err(Error::OAuth("not-ok-response".into()));
     }
 
     response
@@ -143,7 +216,7 @@ impl AuthZeroConfig {
       .await
       .map_err(|error| {
         tracing::warn!("unable to parse response - {}", error);
-        Error::new(ErrorKind::Other, format!("{}", error))
+        Error::OAuth(format!("{}", error))
       })
   }
 
@@ -158,14 +231,14 @@ impl AuthZeroConfig {
       .await
       .map_err(|error| {
         tracing::warn!("unable to parse user info response - {}", error);
-        Error::new(ErrorKind::Other, format!("{}", error))
+        Error::OAuth(format!("{}", error))
       })?;
 
     tracing::debug!("request for roles completed - {}", response.status());
 
     response.body_json::<Vec<UserRole>>().await.map_err(|error| {
       tracing::warn!("unable to parse user role response - {}", error);
-      Error::new(ErrorKind::Other, format!("{}", error))
+      Error::OAuth(format!("{}", error))
     })
   }
 
@@ -175,16 +248,16 @@ impl AuthZeroConfig {
       .body_json(&self.manage_token_payload()?)
       .map_err(|error| {
         tracing::warn!("failed serializing management token payload - {}", error);
-        Error::new(ErrorKind::Other, "bad-management-payload")
+        Error::OAuth("bad-management-payload".into())
       })?
       .await
       .map_err(|error| {
         tracing::warn!("failed management token response - {}", error);
-        Error::new(ErrorKind::Other, "bad-management-response")
+        Error::OAuth("bad-management-response".into())
       })?;
 
     if response.status() != surf::StatusCode::Ok {
-      return Err(Error::new(ErrorKind::Other, "not-ok-response"));
+      return Err(Error::OAuth("not-ok-response".into()));
     }
 
     response
@@ -192,7 +265,7 @@ impl AuthZeroConfig {
       .await
       .map_err(|error| {
         tracing::warn!("unable to parse response - {}", error);
-        Error::new(ErrorKind::Other, format!("{}", error))
+        Error::OAuth(format!("{}", error))
       })
       .map(|b| b.access_token)
   }
@@ -239,7 +312,7 @@ impl AuthZeroConfig {
     )
     .map_err(|error| {
       tracing::warn!("unable to build redirect uri - {}", error);
-      Error::new(ErrorKind::Other, "bad-oauth-redirect-uri")
+      Error::OAuth("bad-oauth-redirect-uri".into())
     })
     .map(|url| url.to_string())
   }
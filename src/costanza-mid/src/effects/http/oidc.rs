@@ -0,0 +1,214 @@
+//! Signing in through any OpenID Connect provider (e.g Keycloak or Authentik on a self-hosted
+//! network). Endpoints are found through the discovery document of the issuer, and roles are read
+//! from the claims of the user rather than a management api.
+
+use super::oauth;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// The scopes requested when none are configured.
+const DEFAULT_SCOPES: &str = "openid profile email";
+
+/// The claim roles are read from when none is configured.
+const DEFAULT_ROLES_CLAIM: &str = "roles";
+
+/// The configuration of an OpenID Connect provider.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(super) struct OidcConfiguration {
+  /// The issuer url; its discovery document is found at `/.well-known/openid-configuration`.
+  issuer: String,
+
+  /// The id of our client, as registered with the provider.
+  client_id: String,
+
+  /// The secret of our client.
+  client_secret: String,
+
+  /// Where the provider sends users back to (our `/auth/complete` route).
+  redirect_uri: String,
+
+  /// The scopes requested when signing in.
+  #[serde(default)]
+  scopes: Option<String>,
+
+  /// A dot separated path to the claim holding the roles of a user (e.g `realm_access.roles` for
+  /// Keycloak, or `groups` for Authentik). The claim may be a list of names or a single name.
+  #[serde(default)]
+  roles_claim: Option<String>,
+}
+
+/// The parts of the discovery document we use.
+#[derive(Debug, Deserialize)]
+struct Discovery {
+  /// Where users are sent to sign in.
+  authorization_endpoint: String,
+
+  /// Where codes are exchanged for tokens.
+  token_endpoint: String,
+
+  /// Where the claims of a user are loaded from.
+  userinfo_endpoint: String,
+}
+
+/// The parts of the token endpoint response we use.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  /// Used to load the claims of the user from the userinfo endpoint.
+  access_token: String,
+
+  /// Some providers only include roles in the claims of the id token.
+  #[serde(default)]
+  id_token: Option<String>,
+}
+
+/// The form sent to the token endpoint.
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Debug, Serialize)]
+struct TokenRequest<'a> {
+  grant_type: &'a str,
+  code: &'a str,
+  redirect_uri: &'a str,
+  client_id: &'a str,
+  client_secret: &'a str,
+}
+
+impl OidcConfiguration {
+  /// Records an error for every url that is not absolute.
+  pub(super) fn validate(&self, validation: &mut crate::validation::Validation) {
+    validation.url("http.oidc.issuer", &self.issuer);
+    validation.url("http.oidc.redirect_uri", &self.redirect_uri);
+  }
+
+  /// Loads the discovery document of our issuer.
+  async fn discover(&self) -> Result<Discovery> {
+    let url = format!("{}/.well-known/openid-configuration", self.issuer.trim_end_matches('/'));
+
+    let mut response = surf::get(&url).await.map_err(|error| {
+      tracing::warn!("unable to load discovery document from '{url}' - {error}");
+      Error::OAuth("bad-discovery-request".into())
+    })?;
+
+    response.body_json::<Discovery>().await.map_err(|error| {
+      tracing::warn!("unable to parse discovery document - {error}");
+      Error::OAuth("bad-discovery-response".into())
+    })
+  }
+
+  /// Returns the url that users will be sent to at the start of an oauth exchange.
+  pub(super) async fn redirect_uri(&self) -> Result<String> {
+    let discovery = self.discover().await?;
+
+    tide::http::Url::parse_with_params(
+      &discovery.authorization_endpoint,
+      &[
+        ("client_id", self.client_id.as_str()),
+        ("redirect_uri", self.redirect_uri.as_str()),
+        ("response_type", "code"),
+        ("scope", self.scopes.as_deref().unwrap_or(DEFAULT_SCOPES)),
+      ],
+    )
+    .map_err(|error| {
+      tracing::warn!("unable to build redirect uri - {}", error);
+      Error::OAuth("bad-oauth-redirect-uri".into())
+    })
+    .map(|url| url.to_string())
+  }
+
+  /// Exchanges a code for the information and roles of the user that signed in.
+  pub(super) async fn sign_in(&self, code: &str) -> Result<(oauth::ManagementUserInfoResponse, Vec<oauth::UserRole>)> {
+    let discovery = self.discover().await?;
+
+    let form = TokenRequest {
+      grant_type: "authorization_code",
+      code,
+      redirect_uri: &self.redirect_uri,
+      client_id: &self.client_id,
+      client_secret: &self.client_secret,
+    };
+    let body = surf::Body::from_form(&form).map_err(|error| {
+      tracing::warn!("unable to serialize token request - {error}");
+      Error::OAuth("bad-token-serialize".into())
+    })?;
+
+    let mut response = surf::post(&discovery.token_endpoint)
+      .body(body)
+      .await
+      .map_err(|error| {
+        tracing::warn!("unable to request token for code - {error}");
+        Error::OAuth("bad-code-exchange-request".into())
+      })?;
+
+    if response.status() != surf::StatusCode::Ok {
+      tracing::warn!("token endpoint responded with {}", response.status());
+      return Err(Error::OAuth("not-ok-response".into()));
+    }
+
+    let tokens = response.body_json::<TokenResponse>().await.map_err(|error| {
+      tracing::warn!("unable to parse token exchange response - {error}");
+      Error::OAuth("bad-code-exchange-request".into())
+    })?;
+
+    let mut response = surf::get(&discovery.userinfo_endpoint)
+      .header("Authorization", format!("Bearer {}", tokens.access_token))
+      .await
+      .map_err(|error| {
+        tracing::warn!("unable to request user info - {error}");
+        Error::OAuth("bad-userinfo-request".into())
+      })?;
+
+    let userinfo = response.body_json::<serde_json::Value>().await.map_err(|error| {
+      tracing::warn!("unable to parse user info response - {error}");
+      Error::OAuth("bad-userinfo-response".into())
+    })?;
+
+    // The claims of the userinfo endpoint take precedence over those of the id token.
+    let mut claims = match tokens.id_token.as_deref() {
+      Some(token) => self.id_token_claims(token)?,
+      None => serde_json::Map::new(),
+    };
+    if let serde_json::Value::Object(userinfo) = userinfo {
+      claims.extend(userinfo);
+    }
+    let claims = serde_json::Value::Object(claims);
+
+    let user = oauth::ManagementUserInfoResponse::from_claims(&claims)
+      .ok_or_else(|| Error::OAuth("missing-subject-claim".into()))?;
+
+    let path = self.roles_claim.as_deref().unwrap_or(DEFAULT_ROLES_CLAIM);
+    let roles = path
+      .split('.')
+      .try_fold(&claims, |value, key| value.get(key))
+      .map(|value| match value {
+        serde_json::Value::Array(names) => names.iter().filter_map(|name| name.as_str()).collect(),
+        serde_json::Value::String(name) => vec![name.as_str()],
+        _ => vec![],
+      })
+      .unwrap_or_default()
+      .into_iter()
+      .map(oauth::UserRole::named)
+      .collect::<Vec<oauth::UserRole>>();
+
+    tracing::debug!("found {} roles in the '{path}' claim", roles.len());
+
+    Ok((user, roles))
+  }
+
+  /// Returns the claims of an id token. The token was received directly from the token endpoint of
+  /// our issuer, so (as the spec allows) its signature is not checked; its audience and expiration are.
+  fn id_token_claims(&self, token: &str) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut validation = jsonwebtoken::Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.set_audience(&[&self.client_id]);
+
+    jsonwebtoken::decode::<serde_json::Map<String, serde_json::Value>>(
+      token,
+      &jsonwebtoken::DecodingKey::from_secret(&[]),
+      &validation,
+    )
+    .map(|data| data.claims)
+    .map_err(|error| {
+      tracing::warn!("unable to decode id token - {error}");
+      Error::OAuth("bad-id-token".into())
+    })
+  }
+}
@@ -0,0 +1,136 @@
+//! Token buckets used to keep a single misbehaving client from flooding the application. Each
+//! bucket holds up to `burst` tokens and is refilled at `per_second`; anything arriving at an empty
+//! bucket is refused rather than queued.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The amount of buckets a `Limiter` holds before it drops the ones that have refilled completely.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// The size and refill rate of a bucket.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub(super) struct RateLimit {
+  /// The most that can be accepted at once.
+  burst: u32,
+
+  /// How many tokens are added back every second.
+  per_second: f64,
+}
+
+impl RateLimit {
+  /// Creates a limit; used for our defaults.
+  pub(super) const fn new(burst: u32, per_second: f64) -> Self {
+    Self { burst, per_second }
+  }
+
+  /// Returns a full bucket with this limit.
+  pub(super) fn bucket(&self) -> TokenBucket {
+    TokenBucket {
+      limit: *self,
+      tokens: f64::from(self.burst),
+      updated: std::time::Instant::now(),
+    }
+  }
+}
+
+/// A single token bucket.
+#[derive(Debug)]
+pub(super) struct TokenBucket {
+  /// The size and refill rate of this bucket.
+  limit: RateLimit,
+
+  /// The tokens currently available.
+  tokens: f64,
+
+  /// When the tokens were last refilled.
+  updated: std::time::Instant,
+}
+
+impl TokenBucket {
+  /// Takes a token from the bucket, returning false when there are none.
+  pub(super) fn take(&mut self) -> bool {
+    self.refill();
+
+    if self.tokens < 1.0 {
+      return false;
+    }
+
+    self.tokens -= 1.0;
+    true
+  }
+
+  /// Adds back the tokens earned since the last refill.
+  fn refill(&mut self) {
+    let now = std::time::Instant::now();
+    let earned = now.duration_since(self.updated).as_secs_f64() * self.limit.per_second;
+    self.tokens = (self.tokens + earned).min(f64::from(self.limit.burst));
+    self.updated = now;
+  }
+
+  /// Whether or not the bucket has refilled completely, making it indistinguishable from a new one.
+  fn is_full(&mut self) -> bool {
+    self.refill();
+    self.tokens >= f64::from(self.limit.burst)
+  }
+}
+
+/// A cheaply cloneable set of buckets, keyed by whatever is being limited (e.g a session).
+#[derive(Clone)]
+pub(super) struct Limiter {
+  /// The limit of every bucket.
+  limit: Arc<RwLock<RateLimit>>,
+
+  /// The bucket of each key.
+  buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+impl Limiter {
+  /// Creates a limiter without any buckets.
+  pub(super) fn new(limit: RateLimit) -> Self {
+    Self {
+      limit: Arc::new(RwLock::new(limit)),
+      buckets: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Takes a token from the bucket of the key, returning false when there are none.
+  pub(super) fn take(&self, key: &str) -> bool {
+    let mut buckets = match self.buckets.lock() {
+      Ok(buckets) => buckets,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    if buckets.len() > PRUNE_THRESHOLD {
+      buckets.retain(|_, bucket| !bucket.is_full());
+    }
+
+    buckets
+      .entry(key.to_string())
+      .or_insert_with(|| self.limit().bucket())
+      .take()
+  }
+
+  /// Replaces the limit of every bucket. Existing buckets are dropped, starting everyone over with a
+  /// full bucket at the new limit.
+  pub(super) fn reconfigure(&self, limit: RateLimit) {
+    match self.limit.write() {
+      Ok(mut current) => *current = limit,
+      Err(poisoned) => *poisoned.into_inner() = limit,
+    }
+
+    match self.buckets.lock() {
+      Ok(mut buckets) => buckets.clear(),
+      Err(poisoned) => poisoned.into_inner().clear(),
+    }
+  }
+
+  /// Returns the current limit of every bucket.
+  fn limit(&self) -> RateLimit {
+    match self.limit.read() {
+      Ok(limit) => *limit,
+      Err(poisoned) => *poisoned.into_inner(),
+    }
+  }
+}
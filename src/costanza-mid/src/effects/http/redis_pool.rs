@@ -0,0 +1,205 @@
+//! A small pool of redis connections shared by every request. Checking a connection out waits for
+//! one of `pool_size` permits, reusing an idle connection when there is one (checking its health
+//! first when it has been idle a while) and connecting otherwise. Connections that fail a command
+//! are dropped instead of being returned, so the next checkout reconnects.
+
+use super::configuration::SessionStoreConfiguration;
+use crate::error::{Error, Result};
+use async_std::channel;
+use std::sync::{Arc, Mutex};
+
+/// The payload echoed back by healthy connections.
+const HEALTH_CHECK: &str = "costanza";
+
+/// An idle connection, along with when it was last used.
+type Idle = (async_std::net::TcpStream, std::time::Instant);
+
+/// The state shared by every clone of the pool and every connection checked out of it.
+struct Inner {
+  /// Where redis can be found.
+  addr: String,
+
+  /// How long a connection may sit idle before it is health checked on checkout.
+  health_check_interval: std::time::Duration,
+
+  /// The connections not currently checked out.
+  idle: Mutex<Vec<Idle>>,
+
+  /// One permit per connection the pool may have open; taken on checkout and returned on drop.
+  permits: (channel::Sender<()>, channel::Receiver<()>),
+}
+
+impl Inner {
+  /// Returns the idle connections.
+  fn idle(&self) -> std::sync::MutexGuard<'_, Vec<Idle>> {
+    match self.idle.lock() {
+      Ok(idle) => idle,
+      Err(poisoned) => poisoned.into_inner(),
+    }
+  }
+}
+
+/// A cheaply cloneable pool of redis connections.
+#[derive(Clone)]
+pub(super) struct RedisPool {
+  /// The state shared with the connections we check out.
+  inner: Arc<Inner>,
+}
+
+impl RedisPool {
+  /// Creates a pool of connections to the provided address without opening any of them; they are
+  /// opened as they are needed.
+  pub(super) fn new(addr: &str, config: &SessionStoreConfiguration) -> Self {
+    let size = config.pool_size();
+    let permits = channel::bounded(size);
+
+    for _ in 0..size {
+      // The channel was created with room for exactly this many permits.
+      let _ = permits.0.try_send(());
+    }
+
+    Self {
+      inner: Arc::new(Inner {
+        addr: addr.to_string(),
+        health_check_interval: config.health_check_interval(),
+        idle: Mutex::new(Vec::with_capacity(size)),
+        permits,
+      }),
+    }
+  }
+
+  /// Drops every idle connection, e.g after one of them was found to have been closed by redis.
+  pub(super) fn clear_idle(&self) {
+    self.inner.idle().clear();
+  }
+
+  /// Waits for a permit and returns a healthy connection, reusing an idle one when possible.
+  pub(super) async fn checkout(&self) -> Result<PooledConnection> {
+    self
+      .inner
+      .permits
+      .1
+      .recv()
+      .await
+      .map_err(|error| Error::SessionStore(format!("redis pool closed - {error}")))?;
+
+    // From here on, dropping the connection (even without a stream) returns the permit.
+    let mut connection = PooledConnection {
+      stream: None,
+      reused: false,
+      pool: self.inner.clone(),
+    };
+
+    loop {
+      let idle = self.inner.idle().pop();
+
+      let (stream, last_used) = match idle {
+        Some(idle) => idle,
+        None => break,
+      };
+
+      connection.stream = Some(stream);
+      connection.reused = true;
+
+      if last_used.elapsed() < self.inner.health_check_interval || connection.healthy().await {
+        return Ok(connection);
+      }
+
+      tracing::warn!("dropping unhealthy idle redis connection");
+    }
+
+    let stream = async_std::net::TcpStream::connect(&self.inner.addr)
+      .await
+      .map_err(|error| {
+        tracing::error!("failed establishing new connection to redis - {error}");
+        Error::SessionStore(format!("unable to connect - {error}"))
+      })?;
+
+    connection.stream = Some(stream);
+    connection.reused = false;
+    Ok(connection)
+  }
+}
+
+/// A connection checked out of the pool, returned to it when dropped.
+pub(super) struct PooledConnection {
+  /// The connection itself; cleared when it fails so it is not returned to the pool.
+  stream: Option<async_std::net::TcpStream>,
+
+  /// Whether or not the connection had been used before this checkout.
+  reused: bool,
+
+  /// The pool we were checked out of.
+  pool: Arc<Inner>,
+}
+
+impl PooledConnection {
+  /// Whether or not the connection had been used before this checkout. Failures on reused
+  /// connections are usually just redis having closed them while they were idle.
+  pub(super) fn reused(&self) -> bool {
+    self.reused
+  }
+
+  /// Executes a single command, dropping the connection if it fails.
+  pub(super) async fn execute<K, V>(&mut self, command: &kramer::Command<K, V>) -> Result<kramer::Response>
+  where
+    K: std::fmt::Display,
+    V: std::fmt::Display,
+  {
+    let stream = self
+      .stream
+      .as_mut()
+      .ok_or_else(|| Error::SessionStore("redis connection already failed".into()))?;
+
+    match kramer::execute(stream, command).await {
+      Ok(response) => Ok(response),
+      Err(error) => {
+        tracing::error!("unable to execute redis command - {error}");
+        self.stream = None;
+        Err(Error::SessionStore(format!("unable to execute command - {error}")))
+      }
+    }
+  }
+
+  /// Executes every command in order on this connection, without anything else being interleaved
+  /// between them. Stops at the first command that fails.
+  pub(super) async fn batch<K, V>(&mut self, commands: &[kramer::Command<K, V>]) -> Result<Vec<kramer::Response>>
+  where
+    K: std::fmt::Display,
+    V: std::fmt::Display,
+  {
+    let mut responses = Vec::with_capacity(commands.len());
+
+    for command in commands {
+      responses.push(self.execute(command).await?);
+    }
+
+    Ok(responses)
+  }
+
+  /// Whether or not the connection echoes back what we send it.
+  async fn healthy(&mut self) -> bool {
+    let echo = kramer::Command::Echo::<&str, &str>(HEALTH_CHECK);
+
+    match self.execute(&echo).await {
+      Ok(kramer::Response::Item(kramer::ResponseValue::String(value))) if value == HEALTH_CHECK => true,
+      Ok(other) => {
+        tracing::warn!("unexpected redis health check response - {other:?}");
+        self.stream = None;
+        false
+      }
+      Err(_) => false,
+    }
+  }
+}
+
+impl Drop for PooledConnection {
+  fn drop(&mut self) {
+    if let Some(stream) = self.stream.take() {
+      self.pool.idle().push((stream, std::time::Instant::now()));
+    }
+
+    // The permit channel is bounded to the pool size and we took one on checkout.
+    let _ = self.pool.permits.0.try_send(());
+  }
+}
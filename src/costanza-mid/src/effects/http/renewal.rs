@@ -0,0 +1,45 @@
+//! Sessions slide forward while they are being used: once half of the lifetime of the token in a
+//! request's cookie has passed, the response carries a new one (and the session is extended).
+
+use super::{constants, shared_state, utils};
+
+/// The middleware registered in front of every route.
+#[derive(Clone, Default)]
+pub(super) struct SessionRenewal {}
+
+#[tide::utils::async_trait]
+impl tide::Middleware<shared_state::SharedState> for SessionRenewal {
+  async fn handle(
+    &self,
+    request: tide::Request<shared_state::SharedState>,
+    next: tide::Next<'_, shared_state::SharedState>,
+  ) -> tide::Result {
+    let state = request.state().clone();
+    let halfway = state.config.session.token_ttl().as_secs() as i64 / 2;
+    let stale = utils::cookie_claims(&request).filter(|claims| claims.remaining() < halfway);
+
+    let mut response = next.run(request).await;
+
+    // Routes that set (or clear) the cookie themselves know better than we do.
+    let sets_session = response.header("Set-Cookie").is_some_and(|values| {
+      values
+        .iter()
+        .any(|value| value.as_str().starts_with(constants::COOKIE_NAME))
+    });
+
+    let claims = match stale {
+      Some(claims) if !sets_session => claims,
+      _ => return Ok(response),
+    };
+
+    match utils::renew_session(&state, &claims.oid).await {
+      Ok(cookie) => {
+        tracing::debug!("renewed session '{}'", claims.oid);
+        response.append_header("Set-Cookie", cookie);
+      }
+      Err(error) => tracing::warn!("unable to renew session '{}' - {error}", claims.oid),
+    }
+
+    Ok(response)
+  }
+}
@@ -0,0 +1,110 @@
+//! Every request is handled inside of its own tracing span (carrying the method, path, status,
+//! duration and session oid), and is optionally written to an access log in the "combined" format
+//! understood by most log tooling.
+
+use super::{shared_state, utils};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing::Instrument;
+
+/// The middleware registered in front of every route.
+#[derive(Clone, Default)]
+pub(super) struct RequestLog {
+  /// When provided, the file every request is appended to.
+  access_log: Option<Arc<Mutex<std::fs::File>>>,
+}
+
+impl RequestLog {
+  /// Creates the middleware, opening (or creating) the access log when a path is provided.
+  pub(super) fn new(access_log: Option<&str>) -> std::io::Result<Self> {
+    let access_log = match access_log {
+      Some(path) => {
+        tracing::info!("writing access log to '{path}'");
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Some(Arc::new(Mutex::new(file)))
+      }
+      None => None,
+    };
+
+    Ok(Self { access_log })
+  }
+
+  /// Appends a line to our access log, if we have one. Failures are not terminal to the request.
+  fn write(&self, line: &str) {
+    let access_log = match self.access_log.as_ref() {
+      Some(access_log) => access_log,
+      None => return,
+    };
+
+    let mut file = match access_log.lock() {
+      Ok(file) => file,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    if let Err(error) = writeln!(file, "{line}") {
+      tracing::warn!("unable to write access log - {error}");
+    }
+  }
+}
+
+#[tide::utils::async_trait]
+impl tide::Middleware<shared_state::SharedState> for RequestLog {
+  async fn handle(
+    &self,
+    request: tide::Request<shared_state::SharedState>,
+    next: tide::Next<'_, shared_state::SharedState>,
+  ) -> tide::Result {
+    let started = std::time::Instant::now();
+    let at = chrono::Local::now();
+    let oid = utils::session_id(&request);
+    let method = request.method();
+    let target = match request.url().query() {
+      Some(query) => format!("{}?{query}", request.url().path()),
+      None => request.url().path().to_string(),
+    };
+    // The combined format only includes the host of the client.
+    let remote = match request.remote() {
+      Some(remote) => remote
+        .parse::<std::net::SocketAddr>()
+        .map_or(remote.to_string(), |address| address.ip().to_string()),
+      None => "-".to_string(),
+    };
+    let version = request
+      .version()
+      .map_or("HTTP/1.1".to_string(), |version| version.to_string());
+    let referer = header(&request, "Referer");
+    let agent = header(&request, "User-Agent");
+
+    let span = tracing::info_span!(
+      "request",
+      %method,
+      path = request.url().path(),
+      oid = oid.as_deref().unwrap_or("-"),
+      status = tracing::field::Empty,
+      duration_ms = tracing::field::Empty,
+    );
+
+    let response = next.run(request).instrument(span.clone()).await;
+    let status = u16::from(response.status());
+    let duration = started.elapsed();
+    span.record("status", status);
+    span.record("duration_ms", duration.as_millis() as u64);
+    tracing::info!(parent: &span, "{method} {target} {status} ({}ms)", duration.as_millis());
+
+    let size = response.len().map_or("-".to_string(), |size| size.to_string());
+    self.write(&format!(
+      "{remote} - {} [{}] \"{method} {target} {version}\" {status} {size} \"{referer}\" \"{agent}\"",
+      oid.as_deref().unwrap_or("-"),
+      at.format("%d/%b/%Y:%H:%M:%S %z"),
+    ));
+
+    Ok(response)
+  }
+}
+
+/// Returns the value of a header for the access log, which uses `-` for missing values.
+fn header(request: &tide::Request<shared_state::SharedState>, name: &str) -> String {
+  request
+    .header(name)
+    .map_or("-".to_string(), |values| values.as_str().replace('"', "\\\""))
+}
@@ -2,17 +2,62 @@
 //! we store that information in our session + json web token.
 
 use super::oauth;
+use crate::error::Result;
 use serde::{Deserialize, Serialize};
 
 /// Based on the cookie provided to our http endpoints, the `Authority` here represents what access
-/// the user should be allowed to have.
-#[derive(PartialEq)]
-pub(super) enum Authority {
-  /// Currently, only an admin/non-admin distinction is made based on Auth0 management api role
-  /// associations.
+/// the user should be allowed to have. This is derived from Auth0 management api role associations.
+/// Each authority is allowed everything the ones before it are.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Authority {
+  /// Observers may watch everything that is happening, but not control anything.
+  #[serde(alias = "viewer")]
+  Observer,
+
+  /// Operators may control the machine, but not change how it (or costanza) is set up.
+  Operator,
+
+  /// Admins may do anything.
   Admin,
 }
 
+impl Authority {
+  /// Whether or not this authority is allowed what the required authority is.
+  pub fn allows(&self, required: Self) -> bool {
+    *self >= required
+  }
+
+  /// Returns the authority granted by the roles of a user. When a mapping of role names is
+  /// configured only those roles count; otherwise roles are matched by name (`admin`, `operator`
+  /// and `observer`/`viewer`).
+  pub(super) fn from_roles(
+    roles: &[oauth::UserRole],
+    mapping: Option<&std::collections::HashMap<String, Self>>,
+  ) -> Option<Self> {
+    roles
+      .iter()
+      .filter_map(|role| match mapping {
+        Some(mapping) => mapping.get(role.name()).copied(),
+        None if role.is_admin() => Some(Self::Admin),
+        None if role.is_operator() => Some(Self::Operator),
+        None if role.is_observer() => Some(Self::Observer),
+        None => None,
+      })
+      .max()
+  }
+}
+
+impl std::fmt::Display for Authority {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Self::Observer => write!(formatter, "observer"),
+      Self::Operator => write!(formatter, "operator"),
+      Self::Admin => write!(formatter, "admin"),
+    }
+  }
+}
+
 /// The inner type sent in our identify endpoint when a user is available.
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct AuthIdentifyResponseUserInfo {
@@ -23,6 +68,25 @@ pub(crate) struct AuthIdentifyResponseUserInfo {
   pub(crate) roles: Vec<oauth::UserRole>,
 }
 
+/// Api tokens are given this prefix, which makes them easy to recognize (e.g by secret scanners).
+const API_TOKEN_PREFIX: &str = "costanza_";
+
+/// Api tokens are stored in our session store under the hash of the token, with this prefix.
+const API_TOKEN_KEY_PREFIX: &str = "api-token:";
+
+/// Generates a new, random api token.
+pub(super) fn api_token() -> String {
+  let (first, second) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+  format!("{API_TOKEN_PREFIX}{}{}", first.simple(), second.simple())
+}
+
+/// Returns the key an api token is stored under in our session store. Only the hash of the token
+/// is stored, so the tokens themselves cannot be recovered from the store.
+pub(super) fn api_token_key(token: &str) -> String {
+  let digest = <sha2::Sha256 as sha2::Digest>::digest(token.as_bytes());
+  format!("{API_TOKEN_KEY_PREFIX}{digest:x}")
+}
+
 /// Information that is included in our JWT claims.
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Claims {
@@ -36,13 +100,14 @@ pub(crate) struct Claims {
 
 impl Claims {
   /// Given an id of a user, will return an instance of our claims with all other fields
-  /// populated.
-  pub fn for_sub<T>(oid: T) -> Self
+  /// populated, expiring after the provided lifetime.
+  pub fn for_sub<T>(oid: T, lifetime: std::time::Duration) -> Self
   where
     T: std::fmt::Display,
   {
-    let day = chrono::Utc::now()
-      .checked_add_signed(chrono::Duration::minutes(60))
+    let day = chrono::Duration::from_std(lifetime)
+      .ok()
+      .and_then(|lifetime| chrono::Utc::now().checked_add_signed(lifetime))
       .unwrap_or_else(chrono::Utc::now);
 
     let exp = day.timestamp() as usize;
@@ -56,26 +121,51 @@ impl Claims {
     }
   }
 
+  /// The amount of seconds until these claims expire; negative once they have.
+  pub fn remaining(&self) -> i64 {
+    self.exp as i64 - chrono::Utc::now().timestamp()
+  }
+
   /// Given the value of a jwt represented in some string-able type, will return the decoded
   /// representation.
-  pub fn decode<T, S>(target: &T, secret: &S) -> std::io::Result<Self>
+  pub fn decode<T, S>(target: &T, secret: &S) -> Result<Self>
+  where
+    T: std::fmt::Display,
+    S: std::convert::AsRef<str>,
+  {
+    Self::decode_with(target, secret, true)
+  }
+
+  /// Like `decode`, but accepts tokens that have expired. Only used when refreshing a session, which
+  /// is still checked against our session store.
+  pub fn decode_expired<T, S>(target: &T, secret: &S) -> Result<Self>
+  where
+    T: std::fmt::Display,
+    S: std::convert::AsRef<str>,
+  {
+    Self::decode_with(target, secret, false)
+  }
+
+  /// Decodes and verifies the signature of a token, optionally verifying its expiration.
+  fn decode_with<T, S>(target: &T, secret: &S, validate_exp: bool) -> Result<Self>
   where
     T: std::fmt::Display,
     S: std::convert::AsRef<str>,
   {
     let token = format!("{}", target);
     let key = jsonwebtoken::DecodingKey::from_secret(secret.as_ref().as_bytes());
-    let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.validate_exp = validate_exp;
     jsonwebtoken::decode::<Self>(token.as_str(), &key, &validation)
       .map_err(|error| {
         tracing::warn!("unable to decode token - {}", error);
-        std::io::Error::new(std::io::ErrorKind::Other, "bad-jwt")
+        error.into()
       })
       .map(|data| data.claims)
   }
 
   /// Encodes our claims into their string form.
-  pub fn encode<S>(&self, secret: S) -> std::io::Result<String>
+  pub fn encode<S>(&self, secret: S) -> Result<String>
   where
     S: std::convert::AsRef<str>,
   {
@@ -84,7 +174,7 @@ impl Claims {
 
     jsonwebtoken::encode(header, &self, &secret).map_err(|error| {
       tracing::warn!("unable to encode token - {}", error);
-      std::io::Error::new(std::io::ErrorKind::Other, "bad-jwt")
+      error.into()
     })
   }
 }
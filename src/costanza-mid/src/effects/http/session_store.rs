@@ -0,0 +1,285 @@
+//! Where sessions are persisted. Redis remains the default when an address is configured, but
+//! single-board deployments can keep sessions in-process instead, optionally persisted to a file so
+//! they survive restarts.
+
+use super::configuration::SessionStoreConfiguration;
+use super::redis_pool;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The backends sessions can be persisted in.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum SessionBackend {
+  /// A redis server, at `redis_addr`.
+  Redis,
+
+  /// The memory of our process; sessions are lost on restart.
+  Memory,
+
+  /// The memory of our process, written to the file at `path` whenever it changes.
+  File,
+}
+
+/// The operations every session backend supports. Sessions are opaque, serialized strings keyed by
+/// the id found in the claims of our cookie.
+#[tide::utils::async_trait]
+pub(super) trait SessionStore: Send + Sync {
+  /// Writes a session, which expires after `ttl` when one is provided.
+  async fn put(&self, id: &str, data: &str, ttl: Option<std::time::Duration>) -> Result<()>;
+
+  /// Returns a session, unless it does not exist or has expired.
+  async fn get(&self, id: &str) -> Result<Option<String>>;
+
+  /// Removes a session.
+  async fn delete(&self, id: &str) -> Result<()>;
+
+  /// Pushes the expiration of a session back to `ttl` from now. Returns false when the session does
+  /// not exist (or has already expired).
+  async fn renew(&self, id: &str, ttl: std::time::Duration) -> Result<bool>;
+
+  /// Checks that the backend can be reached. Backends in our own memory always can.
+  async fn ping(&self) -> Result<()> {
+    Ok(())
+  }
+}
+
+/// Opens the backend selected by our configuration.
+pub(super) fn open(config: &SessionStoreConfiguration) -> Result<Arc<dyn SessionStore>> {
+  match config.backend() {
+    SessionBackend::Redis => {
+      let addr = config
+        .redis_addr
+        .as_ref()
+        .ok_or_else(|| Error::SessionStore("the redis session store requires a 'redis_addr'".into()))?;
+      tracing::info!("storing sessions in redis at '{addr}'");
+      Ok(Arc::new(RedisSessionStore {
+        pool: redis_pool::RedisPool::new(addr, config),
+      }))
+    }
+    SessionBackend::Memory => {
+      tracing::info!("storing sessions in memory");
+      Ok(Arc::new(LocalSessionStore::default()))
+    }
+    SessionBackend::File => {
+      let path = config
+        .path
+        .as_ref()
+        .ok_or_else(|| Error::SessionStore("the file session store requires a 'path'".into()))?;
+      tracing::info!("storing sessions in '{path}'");
+      Ok(Arc::new(LocalSessionStore::open(path)?))
+    }
+  }
+}
+
+/// Sessions kept in redis.
+struct RedisSessionStore {
+  /// The connections we send commands through.
+  pool: redis_pool::RedisPool,
+}
+
+impl RedisSessionStore {
+  /// Executes redis commands back to back on a single connection from our pool. When a connection
+  /// that had been sitting idle fails (e.g redis was restarted), the idle connections are dropped and
+  /// the commands are retried once on a new connection.
+  async fn commands<K, V>(&self, commands: Vec<kramer::Command<K, V>>) -> Result<Vec<kramer::Response>>
+  where
+    K: std::fmt::Display,
+    V: std::fmt::Display,
+  {
+    let mut connection = self.pool.checkout().await?;
+
+    match connection.batch(&commands).await {
+      Err(error) if connection.reused() => {
+        tracing::warn!("redis connection failed, retrying on a new connection - {error}");
+        drop(connection);
+        self.pool.clear_idle();
+        self.pool.checkout().await?.batch(&commands).await
+      }
+      result => result,
+    }
+  }
+
+  /// Executes a single redis command on a connection from our pool.
+  async fn command<K, V>(&self, command: kramer::Command<K, V>) -> Result<kramer::Response>
+  where
+    K: std::fmt::Display,
+    V: std::fmt::Display,
+  {
+    self
+      .commands(vec![command])
+      .await?
+      .pop()
+      .ok_or_else(|| Error::SessionStore("missing redis response".into()))
+  }
+}
+
+#[tide::utils::async_trait]
+impl SessionStore for RedisSessionStore {
+  async fn put(&self, id: &str, data: &str, ttl: Option<std::time::Duration>) -> Result<()> {
+    let command = kramer::Command::Strings(kramer::StringCommand::Set(
+      kramer::Arity::One((id, data)),
+      ttl,
+      kramer::Insertion::Always,
+    ));
+
+    self.command(command).await.map(|_| ())
+  }
+
+  async fn get(&self, id: &str) -> Result<Option<String>> {
+    let command = kramer::Command::Strings::<&str, &str>(kramer::StringCommand::Get(kramer::Arity::One(id)));
+
+    match self.command(command).await? {
+      kramer::Response::Item(kramer::ResponseValue::String(inner)) => Ok(Some(inner)),
+      _ => Ok(None),
+    }
+  }
+
+  async fn delete(&self, id: &str) -> Result<()> {
+    let command = kramer::Command::Del::<&str, &str>(kramer::Arity::One(id));
+    self.command(command).await.map(|_| ())
+  }
+
+  async fn renew(&self, id: &str, ttl: std::time::Duration) -> Result<bool> {
+    let command = kramer::Command::Expire::<&str, &str>(id, ttl);
+
+    // Redis answers with the amount of keys it updated the expiration of.
+    match self.command(command).await? {
+      kramer::Response::Item(kramer::ResponseValue::Integer(updated)) => Ok(updated > 0),
+      _ => Ok(false),
+    }
+  }
+
+  async fn ping(&self) -> Result<()> {
+    self
+      .command(kramer::Command::Echo::<&str, &str>("ping"))
+      .await
+      .map(|_| ())
+  }
+}
+
+/// A single session held by a `LocalSessionStore`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Entry {
+  /// The serialized session.
+  data: String,
+
+  /// When the session expires, if ever.
+  expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Entry {
+  /// Whether or not the session has expired.
+  fn expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+    self.expires_at.is_some_and(|expires_at| expires_at <= now)
+  }
+}
+
+/// Sessions kept in the memory of our process, written to a file when we have one.
+#[derive(Default)]
+struct LocalSessionStore {
+  /// The sessions, keyed by id.
+  entries: Mutex<HashMap<String, Entry>>,
+
+  /// When provided, the file the sessions are written to whenever they change.
+  path: Option<std::path::PathBuf>,
+}
+
+impl LocalSessionStore {
+  /// Loads the (unexpired) sessions previously written to the file at the provided path.
+  fn open<P>(path: P) -> Result<Self>
+  where
+    P: AsRef<std::path::Path>,
+  {
+    let mut entries = match std::fs::read_to_string(&path) {
+      Ok(contents) => serde_json::from_str::<HashMap<String, Entry>>(&contents)?,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+      Err(error) => return Err(error.into()),
+    };
+
+    let now = chrono::Utc::now();
+    entries.retain(|_, entry| !entry.expired(now));
+    tracing::info!("loaded {} sessions", entries.len());
+
+    Ok(Self {
+      entries: Mutex::new(entries),
+      path: Some(path.as_ref().to_path_buf()),
+    })
+  }
+
+  /// Provides mutable access to our sessions, writing them to our file afterwards when we have one
+  /// and the closure reports having changed them.
+  fn with<F, R>(&self, apply: F) -> Result<R>
+  where
+    F: FnOnce(&mut HashMap<String, Entry>) -> (R, bool),
+  {
+    let mut entries = match self.entries.lock() {
+      Ok(entries) => entries,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    let (result, changed) = apply(&mut entries);
+
+    if let Some(path) = self.path.as_ref().filter(|_| changed) {
+      let staging = path.with_extension("tmp");
+      std::fs::write(&staging, serde_json::to_string(&*entries)?)?;
+      std::fs::rename(&staging, path)?;
+    }
+
+    Ok(result)
+  }
+}
+
+#[tide::utils::async_trait]
+impl SessionStore for LocalSessionStore {
+  async fn put(&self, id: &str, data: &str, ttl: Option<std::time::Duration>) -> Result<()> {
+    let now = chrono::Utc::now();
+    let expires_at = ttl
+      .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+      .map(|ttl| now + ttl);
+
+    self.with(|entries| {
+      // Expired sessions are only ever cleaned up while writing.
+      entries.retain(|_, entry| !entry.expired(now));
+      let entry = Entry {
+        data: data.to_string(),
+        expires_at,
+      };
+      entries.insert(id.to_string(), entry);
+      ((), true)
+    })
+  }
+
+  async fn get(&self, id: &str) -> Result<Option<String>> {
+    let now = chrono::Utc::now();
+
+    self.with(|entries| {
+      let data = entries
+        .get(id)
+        .filter(|entry| !entry.expired(now))
+        .map(|entry| entry.data.clone());
+      (data, false)
+    })
+  }
+
+  async fn delete(&self, id: &str) -> Result<()> {
+    self.with(|entries| ((), entries.remove(id).is_some()))
+  }
+
+  async fn renew(&self, id: &str, ttl: std::time::Duration) -> Result<bool> {
+    let now = chrono::Utc::now();
+    let expires_at = chrono::Duration::from_std(ttl).ok().map(|ttl| now + ttl);
+
+    self.with(
+      |entries| match entries.get_mut(id).filter(|entry| !entry.expired(now)) {
+        Some(entry) => {
+          entry.expires_at = expires_at;
+          (true, true)
+        }
+        None => (false, false),
+      },
+    )
+  }
+}
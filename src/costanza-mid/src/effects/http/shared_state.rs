@@ -1,8 +1,7 @@
 //! This module contains the main type that is shared across request tasks.
 
 use super::sec;
-use async_std::{channel, sync};
-use std::io;
+use async_std::channel;
 
 /// The `SharedState` here is a type that will be available to every request handler. This means
 /// that the fields on this struct should be safe to pass between threads.
@@ -11,49 +10,54 @@ pub struct SharedState {
   /// A reference to the configuration of the http effect runtime itself.
   pub(super) config: super::configuration::Configuration,
 
-  /// A "connection pool" of redis tcp streams that can be used.
-  pub(super) redis: sync::Arc<sync::Mutex<Option<async_std::net::TcpStream>>>,
+  /// Where sessions are persisted.
+  pub(super) sessions: std::sync::Arc<dyn super::session_store::SessionStore>,
 
   /// The top-level message channel that we can send directly into.
   pub(super) messages: channel::Sender<super::Message>,
 
   /// A pair of channels that will be used to "register" new clients with our effect runtime from
   /// individual websocket connections.
-  pub(super) registration: channel::Sender<(String, channel::Sender<super::Command>)>,
+  pub(super) registration: channel::Sender<(String, channel::Sender<super::Command>, super::liveness::Liveness)>,
+
+  /// The metrics of the effect runtime.
+  pub(super) metrics: crate::metrics::Metrics,
+
+  /// The history of jobs run by the application.
+  pub(super) jobs: crate::jobs::JobHistory,
+
+  /// Used to build debug bundles.
+  pub(super) diagnostics: crate::diagnostics::Diagnostics,
+
+  /// The files admins may download.
+  pub(super) downloads: super::Downloads,
+
+  /// Writes websocket frames to disk while an admin is capturing them.
+  pub(super) capture: super::capture::Capture,
+
+  /// Previews what the next pruning of our retention policies would remove.
+  pub(super) pruner: crate::retention::Pruner,
+
+  /// Limits how often each session may upload files.
+  pub(super) uploads: super::rate_limit::Limiter,
+
+  /// The most bytes accepted for a single upload; this may change as we are reconfigured.
+  pub(super) upload_size: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+
+  /// The most recent health reported by the application.
+  pub(super) health: crate::health::Health,
+
+  /// Everything still being handled, drained when shutting down.
+  pub(super) inflight: super::shutdown::InFlight,
 
   /// The tracing span.
   pub(super) span: tracing::Span,
 }
 
 impl SharedState {
-  /// Executes a redis command against our shared, mutex locked redis "pool".
-  pub(super) async fn command<K, V>(&self, command: kramer::Command<K, V>) -> io::Result<kramer::Response>
-  where
-    K: std::fmt::Display,
-    V: std::fmt::Display,
-  {
-    let mut redis = self.redis.lock().await;
-
-    let mut pulled_connection = match redis.take() {
-      Some(inner) => inner,
-      None => async_std::net::TcpStream::connect(&self.config.session.redis_addr)
-        .await
-        .map_err(|error| {
-          tracing::error!("failed establishing new connection to redis - {error}");
-          error
-        })?,
-    };
-
-    let output = kramer::execute(&mut pulled_connection, &command)
-      .await
-      .map_err(|error| {
-        tracing::error!("unable to execute redis command - {error}");
-        error
-      })?;
-
-    *redis = Some(pulled_connection);
-
-    Ok(output)
+  /// Returns the most bytes accepted for a single upload.
+  pub(super) fn max_upload_size(&self) -> usize {
+    self.upload_size.load(std::sync::atomic::Ordering::Relaxed)
   }
 
   /// Returns the authority level based on the session data provided by our cookie. This is
@@ -63,40 +67,30 @@ impl SharedState {
     T: std::fmt::Display,
   {
     let data = self.user_from_session(id).await?;
-
-    if data.roles.into_iter().any(|role| role.is_admin()) {
-      return Some(sec::Authority::Admin);
-    }
-
-    None
+    sec::Authority::from_roles(&data.roles, self.config.roles.as_ref())
   }
 
   /// This function is responsible for taking the unique id found in our session cookie and
-  /// returning the user data that we have previously stored in redis.
+  /// returning the user data that we have previously stored in our session store.
   pub(crate) async fn user_from_session<T>(&self, id: T) -> Option<sec::AuthIdentifyResponseUserInfo>
   where
     T: std::fmt::Display,
   {
-    // Look up our session by the uuid in our redis session store
+    // Look up our session by the uuid in our session store
     let serialized_id = format!("{id}");
-    let command =
-      kramer::Command::Strings::<&str, &str>(kramer::StringCommand::Get(kramer::Arity::One(&serialized_id)));
 
-    let response = self
-      .command(command)
+    let inner = self
+      .sessions
+      .get(&serialized_id)
       .await
       .map_err(|error| {
         tracing::error!("unable to fetch session info - {error}");
         error
       })
-      .ok()?;
+      .ok()??;
 
     // Attempt to deserialize as our user info structure.
-    if let kramer::Response::Item(kramer::ResponseValue::String(inner)) = response {
-      tracing::trace!("has session data - {inner:?}");
-      return serde_json::from_str(&inner).ok();
-    }
-
-    None
+    tracing::trace!("has session data - {inner:?}");
+    serde_json::from_str(&inner).ok()
   }
 }
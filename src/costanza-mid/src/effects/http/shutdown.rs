@@ -0,0 +1,71 @@
+//! Draining the server when the application shuts down. Every request, along with every websocket
+//! and event stream (which outlive the request that opened them), holds a guard while it is being
+//! handled; once we have stopped accepting connections we wait for the guards to be dropped.
+
+use std::sync::{atomic, Arc};
+
+/// How often we check whether everything has finished while draining.
+const DRAIN_POLL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// The close frame reason sent to websocket clients when the server is stopping.
+pub const RESTARTING: &str = "server restarting";
+
+/// A cheaply cloneable count of everything still being handled.
+#[derive(Clone, Default)]
+pub(super) struct InFlight {
+  /// The amount of guards that have not been dropped.
+  count: Arc<atomic::AtomicUsize>,
+}
+
+/// Held while a request, websocket or event stream is being handled.
+pub(super) struct Guard {
+  /// The count this guard was taken from.
+  count: Arc<atomic::AtomicUsize>,
+}
+
+impl Drop for Guard {
+  fn drop(&mut self) {
+    self.count.fetch_sub(1, atomic::Ordering::SeqCst);
+  }
+}
+
+impl InFlight {
+  /// Returns a guard counting as in-flight until it is dropped.
+  pub(super) fn track(&self) -> Guard {
+    self.count.fetch_add(1, atomic::Ordering::SeqCst);
+    Guard {
+      count: self.count.clone(),
+    }
+  }
+
+  /// Waits for every guard to be dropped, returning false if the timeout elapsed first.
+  pub(super) async fn drained(&self, timeout: std::time::Duration) -> bool {
+    let deadline = std::time::Instant::now() + timeout;
+
+    loop {
+      let remaining = self.count.load(atomic::Ordering::SeqCst);
+
+      if remaining == 0 {
+        return true;
+      }
+
+      if std::time::Instant::now() > deadline {
+        tracing::warn!("{remaining} requests still in flight after {}s", timeout.as_secs());
+        return false;
+      }
+
+      async_std::task::sleep(DRAIN_POLL).await;
+    }
+  }
+}
+
+#[tide::utils::async_trait]
+impl<S> tide::Middleware<S> for InFlight
+where
+  S: Clone + Send + Sync + 'static,
+{
+  async fn handle(&self, request: tide::Request<S>, next: tide::Next<'_, S>) -> tide::Result {
+    let _guard = self.track();
+    Ok(next.run(request).await)
+  }
+}
@@ -0,0 +1,62 @@
+//! Serving the ui bundle lets a single binary host everything; without a `static_dir`, the ui is
+//! expected to be served by something else.
+
+use super::shared_state;
+
+/// The document served for any path that is not a file, so the ui can handle its own routes.
+const INDEX: &str = "index.html";
+
+/// The `Cache-Control` of everything other than the index. The index is never cached, so a new
+/// deployment is picked up on the next load.
+const ASSET_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// route: serves a file from our static directory, or the index when the path does not look like
+/// a file.
+pub(super) async fn serve(request: tide::Request<shared_state::SharedState>) -> tide::Result {
+  let root = match request.state().config.static_dir.as_ref() {
+    Some(root) => std::path::Path::new(root),
+    None => return Ok(tide::Response::new(404)),
+  };
+
+  let requested = request.url().path().trim_start_matches('/');
+
+  // Unknown api routes should not look like they succeeded.
+  if requested.starts_with("api/") {
+    return Ok(tide::Response::new(404));
+  }
+
+  // Anything that would step outside of our directory is refused rather than resolved.
+  let relative = std::path::Path::new(requested);
+  if !relative
+    .components()
+    .all(|component| matches!(component, std::path::Component::Normal(_)))
+  {
+    tracing::warn!("refusing static path '{requested}'");
+    return Ok(tide::Response::new(404));
+  }
+
+  let path = root.join(relative);
+  let is_file = async_std::fs::metadata(&path)
+    .await
+    .map(|metadata| metadata.is_file())
+    .unwrap_or(false);
+
+  let (path, cache_control) = match (is_file, relative.extension()) {
+    (true, _) if relative != std::path::Path::new(INDEX) => (path, ASSET_CACHE_CONTROL),
+    // Missing files are not found; missing paths are routes of the ui.
+    (false, Some(_)) => return Ok(tide::Response::new(404)),
+    _ => (root.join(INDEX), "no-cache"),
+  };
+
+  let body = tide::Body::from_file(&path).await.map_err(|error| {
+    tracing::warn!("unable to serve static file '{}' - {error}", path.display());
+    tide::Error::from_str(404, "not-found")
+  })?;
+
+  Ok(
+    tide::Response::builder(200)
+      .header("Cache-Control", cache_control)
+      .body(body)
+      .build(),
+  )
+}
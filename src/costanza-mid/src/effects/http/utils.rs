@@ -3,6 +3,86 @@
 //! in the request that contains that.
 
 use super::{constants, sec, shared_state};
+use crate::catalog;
+
+/// Returns the route error of a catalog code, using the http status of its entry.
+pub(super) fn error(code: catalog::Code) -> tide::Error {
+  tide::Error::from_str(code.entry().status, code.as_str())
+}
+
+/// Ensures the request was made by a user with the admin authority, returning a "not found" error
+/// when it was not so we do not leak the existence of the route.
+pub(super) async fn require_admin(request: &tide::Request<shared_state::SharedState>) -> tide::Result<()> {
+  require_authority(request, sec::Authority::Admin).await.map(|_| ())
+}
+
+/// Ensures the request was made by a user with (at least) the provided authority, returning theirs.
+/// Like `require_admin`, a "not found" error is returned when it was not.
+pub(super) async fn require_authority(
+  request: &tide::Request<shared_state::SharedState>,
+  required: sec::Authority,
+) -> tide::Result<sec::Authority> {
+  let authority = match session_id(request) {
+    None => return Err(error(catalog::Code::NotFound)),
+    Some(id) => request.state().authority(id).await,
+  };
+
+  match authority {
+    Some(authority) if authority.allows(required) => Ok(authority),
+    _ => {
+      tracing::warn!(
+        "attempt to access '{}' without {required}, refusing",
+        request.url().path()
+      );
+      Err(error(catalog::Code::NotFound))
+    }
+  }
+}
+
+/// Extends a session and issues a new token for it, returning the `Set-Cookie` value holding the
+/// token. Fails when the session no longer exists.
+pub(super) async fn renew_session(state: &shared_state::SharedState, oid: &str) -> tide::Result<String> {
+  let session = &state.config.session;
+
+  if !state.sessions.renew(oid, session.session_ttl()).await? {
+    tracing::warn!("unable to renew missing session '{oid}'");
+    return Err(error(catalog::Code::SessionNotFound));
+  }
+
+  let jwt = sec::Claims::for_sub(oid, session.token_ttl()).encode(&session.jwt_secret)?;
+  Ok(session_cookie(&state.config, &jwt))
+}
+
+/// Returns the `Set-Cookie` value holding a token, which lasts as long as the session it belongs to.
+pub(super) fn session_cookie(config: &super::Configuration, jwt: &str) -> String {
+  format!(
+    "{}={}; Max-Age={}; {}; Domain={}",
+    constants::COOKIE_NAME,
+    jwt,
+    config.session.session_ttl().as_secs(),
+    constants::COOKIE_SET_FLAGS,
+    &config.domain
+  )
+}
+
+/// Returns the id of the session a request was made with. Api tokens provided through an
+/// `Authorization: Bearer` header take precedence over our cookie.
+pub(super) fn session_id(request: &tide::Request<shared_state::SharedState>) -> Option<String> {
+  match bearer_token(request) {
+    Some(token) => Some(sec::api_token_key(&token)),
+    None => cookie_claims(request).map(|claims| claims.oid),
+  }
+}
+
+/// Returns the api token provided through the `Authorization` header of a request, if any.
+pub(super) fn bearer_token(request: &tide::Request<shared_state::SharedState>) -> Option<String> {
+  request.header("Authorization").and_then(|values| {
+    values
+      .as_str()
+      .strip_prefix("Bearer ")
+      .map(|token| token.trim().to_string())
+  })
+}
 
 /// Returns the cookie responsible for holding our session from the request http header.
 pub(super) fn cookie_claims(request: &tide::Request<shared_state::SharedState>) -> Option<sec::Claims> {
@@ -0,0 +1,321 @@
+//! The input effect reads key presses from an input device (a usb jog pendant, or a numeric keypad)
+//! attached to the computer we are running on, turning the keys we have been configured with into
+//! actions for the application. This gives operators standing at the machine a physical way of
+//! jogging it, and of holding or resuming a job, without reaching for the ui.
+//!
+//! Devices are read through evdev (`/dev/input/event*`), which only exists on linux. Rather than
+//! depending on a wrapper library, the fixed-size `input_event` records are parsed directly.
+
+use crate::error::{Error, Result};
+use async_std::channel;
+use serde::{Deserialize, Serialize};
+
+/// How long we wait before opening the device again after it has gone away (e.g been unplugged).
+#[cfg(target_os = "linux")]
+const REOPEN_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The `type` of the events produced by keys.
+#[cfg(target_os = "linux")]
+const EV_KEY: u16 = 0x01;
+
+/// The `value` of a key event for a key being released.
+#[cfg(target_os = "linux")]
+const KEY_RELEASED: i32 = 0;
+
+/// The `value` of a key event for a key being held down long enough to repeat.
+#[cfg(target_os = "linux")]
+const KEY_REPEATED: i32 = 2;
+
+/// The size of the `timeval` every event starts with; two `long`s, which depends on our platform.
+#[cfg(target_os = "linux")]
+const TIMEVAL_SIZE: usize = 2 * std::mem::size_of::<std::ffi::c_long>();
+
+/// The size of a single `input_event`: the `timeval`, followed by a `u16` type, a `u16` code and
+/// an `i32` value.
+#[cfg(target_os = "linux")]
+const EVENT_SIZE: usize = TIMEVAL_SIZE + 8;
+
+/// The names of the keys most pendants and keypads have, along with their codes (see
+/// `linux/input-event-codes.h`). Any other key can be configured by its code.
+const KEY_NAMES: &[(&str, u16)] = &[
+  ("KEY_ESC", 1),
+  ("KEY_ENTER", 28),
+  ("KEY_KPASTERISK", 55),
+  ("KEY_SPACE", 57),
+  ("KEY_NUMLOCK", 69),
+  ("KEY_KP7", 71),
+  ("KEY_KP8", 72),
+  ("KEY_KP9", 73),
+  ("KEY_KPMINUS", 74),
+  ("KEY_KP4", 75),
+  ("KEY_KP5", 76),
+  ("KEY_KP6", 77),
+  ("KEY_KPPLUS", 78),
+  ("KEY_KP1", 79),
+  ("KEY_KP2", 80),
+  ("KEY_KP3", 81),
+  ("KEY_KP0", 82),
+  ("KEY_KPDOT", 83),
+  ("KEY_KPENTER", 96),
+  ("KEY_KPSLASH", 98),
+  ("KEY_UP", 103),
+  ("KEY_PAGEUP", 104),
+  ("KEY_LEFT", 105),
+  ("KEY_RIGHT", 106),
+  ("KEY_DOWN", 108),
+  ("KEY_PAGEDOWN", 109),
+];
+
+/// Returns the code of a key, configured either by name (e.g `KEY_KP8`) or by code (e.g `72`).
+fn key_code(key: &str) -> Option<u16> {
+  KEY_NAMES
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, code)| *code)
+    .or_else(|| key.parse().ok())
+}
+
+/// What a key does when it is pressed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAction {
+  JogXPlus,
+  JogXMinus,
+  JogYPlus,
+  JogYMinus,
+  JogZPlus,
+  JogZMinus,
+  FeedHold,
+  CycleStart,
+}
+
+/// The events sent to the application when a configured key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+  /// Moves the machine by the distance of each axis (in `X`, `Y`, `Z` order, as shown to
+  /// operators) at the provided feed rate.
+  Jog([f32; 3], f32),
+
+  FeedHold,
+  CycleStart,
+}
+
+impl InputAction {
+  /// Returns the direction of each axis moved by this action, if it is a jog.
+  #[cfg(target_os = "linux")]
+  fn jog(&self) -> Option<[f32; 3]> {
+    match self {
+      Self::JogXPlus => Some([1.0, 0.0, 0.0]),
+      Self::JogXMinus => Some([-1.0, 0.0, 0.0]),
+      Self::JogYPlus => Some([0.0, 1.0, 0.0]),
+      Self::JogYMinus => Some([0.0, -1.0, 0.0]),
+      Self::JogZPlus => Some([0.0, 0.0, 1.0]),
+      Self::JogZMinus => Some([0.0, 0.0, -1.0]),
+      Self::FeedHold | Self::CycleStart => None,
+    }
+  }
+}
+
+/// The configuration of the input effect.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Configuration {
+  /// The device keys are read from. Prefer the stable names under `/dev/input/by-id`, e.g
+  /// `/dev/input/by-id/usb-05a4_9881-event-kbd`, over `/dev/input/event*`.
+  pub device: String,
+
+  /// The distance (in millimeters) each jog moves the machine.
+  #[serde(default = "default_jog_distance")]
+  pub jog_distance: f32,
+
+  /// The feed rate (in millimeters per minute) of each jog.
+  #[serde(default = "default_jog_feed")]
+  pub jog_feed: f32,
+
+  /// The action of each key, by name (e.g `KEY_KP8`) or code.
+  #[serde(default)]
+  pub keys: std::collections::BTreeMap<String, InputAction>,
+}
+
+/// Used by serde when no jog distance is provided.
+fn default_jog_distance() -> f32 {
+  1.0
+}
+
+/// Used by serde when no jog feed rate is provided.
+fn default_jog_feed() -> f32 {
+  500.0
+}
+
+impl Configuration {
+  /// Returns the event sent to the application for an action.
+  #[cfg(target_os = "linux")]
+  fn event(&self, action: InputAction) -> InputEvent {
+    match (action, action.jog()) {
+      (_, Some(direction)) => InputEvent::Jog(direction.map(|axis| axis * self.jog_distance), self.jog_feed),
+      (InputAction::FeedHold, None) => InputEvent::FeedHold,
+      (_, None) => InputEvent::CycleStart,
+    }
+  }
+
+  /// Returns the action of every key, by code.
+  #[cfg(target_os = "linux")]
+  fn actions(&self) -> std::collections::HashMap<u16, InputAction> {
+    self
+      .keys
+      .iter()
+      .filter_map(|(key, action)| key_code(key).map(|code| (code, *action)))
+      .collect()
+  }
+
+  /// Records every issue with our device and keys.
+  pub(crate) fn validate(&self, validation: &mut crate::validation::Validation) {
+    if cfg!(not(target_os = "linux")) {
+      validation.warning("input", "input devices can only be read on linux");
+    }
+
+    if !std::path::Path::new(&self.device).exists() {
+      validation.warning("input.device", format!("'{}' does not exist (yet)", self.device));
+    }
+
+    for key in self.keys.keys().filter(|key| key_code(key).is_none()) {
+      validation.error("input.keys", format!("'{key}' is not a known key name or code"));
+    }
+
+    if self.jog_distance <= 0.0 {
+      validation.error("input.jog_distance", "must be greater than zero");
+    }
+
+    if self.jog_feed <= 0.0 {
+      validation.error("input.jog_feed", "must be greater than zero");
+    }
+  }
+}
+
+pub trait InputMap {
+  type Command;
+  type Message;
+
+  /// Defines the type of message that should be used when a configured key is pressed.
+  fn message(&self, event: InputEvent) -> Self::Message;
+}
+
+pub struct Input<C, M> {
+  config: Configuration,
+
+  /// The input effect does not receive any commands; this only exists to satisfy `detach`.
+  commands: (channel::Receiver<C>, Option<channel::Sender<C>>),
+  messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
+}
+
+impl<C, M> Input<C, M> {
+  pub fn new(config: Configuration) -> Self {
+    let commands = channel::unbounded();
+    let messages = channel::unbounded();
+
+    Self {
+      config,
+      commands: (commands.1, Some(commands.0)),
+      messages: (messages.0, Some(messages.1)),
+    }
+  }
+
+  /// Reads the device until our application goes away. A device that cannot be opened, or stops
+  /// being readable, is opened again after a short delay.
+  #[cfg(target_os = "linux")]
+  pub async fn run<T>(self, glue: T) -> Result<()>
+  where
+    T: InputMap<Command = C, Message = M>,
+  {
+    let actions = self.config.actions();
+    tracing::info!("reading {} keys from '{}'", actions.len(), self.config.device);
+
+    loop {
+      match self.read(&actions, &glue).await {
+        Err(Error::ChannelClosed(reason)) => return Err(Error::ChannelClosed(reason)),
+        Err(error) => tracing::warn!("unable to read '{}' - {error}", self.config.device),
+        Ok(()) => (),
+      }
+
+      async_std::task::sleep(REOPEN_DELAY).await;
+    }
+  }
+
+  /// There are no input devices for us to read outside of linux.
+  #[cfg(not(target_os = "linux"))]
+  pub async fn run<T>(self, _glue: T) -> Result<()>
+  where
+    T: InputMap<Command = C, Message = M>,
+  {
+    tracing::warn!(
+      "input devices can only be read on linux, ignoring '{}'",
+      self.config.device
+    );
+    futures_lite::future::pending().await
+  }
+
+  /// Opens the device and sends a message for every press of a configured key until it can no
+  /// longer be read.
+  #[cfg(target_os = "linux")]
+  async fn read<T>(&self, actions: &std::collections::HashMap<u16, InputAction>, glue: &T) -> Result<()>
+  where
+    T: InputMap<Command = C, Message = M>,
+  {
+    use async_std::io::ReadExt;
+
+    let mut device = async_std::fs::File::open(&self.config.device).await?;
+    tracing::info!("opened input device '{}'", self.config.device);
+    let mut event = [0u8; EVENT_SIZE];
+
+    loop {
+      device.read_exact(&mut event).await?;
+
+      let kind = u16::from_ne_bytes([event[TIMEVAL_SIZE], event[TIMEVAL_SIZE + 1]]);
+      let code = u16::from_ne_bytes([event[TIMEVAL_SIZE + 2], event[TIMEVAL_SIZE + 3]]);
+      let value = i32::from_ne_bytes([
+        event[TIMEVAL_SIZE + 4],
+        event[TIMEVAL_SIZE + 5],
+        event[TIMEVAL_SIZE + 6],
+        event[TIMEVAL_SIZE + 7],
+      ]);
+
+      if kind != EV_KEY || value == KEY_RELEASED {
+        continue;
+      }
+
+      let action = match actions.get(&code) {
+        Some(action) => *action,
+        None => {
+          tracing::debug!("ignoring unmapped key {code}");
+          continue;
+        }
+      };
+
+      // Holding a jog key down keeps the machine moving; anything else only happens once per press.
+      if value == KEY_REPEATED && action.jog().is_none() {
+        continue;
+      }
+
+      tracing::debug!("key {code} pressed ({action:?})");
+
+      let message = glue.message(self.config.event(action));
+
+      if let Err(error) = self.messages.0.send(message).await {
+        tracing::warn!("unable to send input message - {error}");
+        return Err(Error::ChannelClosed("input-send failure".into()));
+      }
+    }
+  }
+}
+
+impl<C, M> crate::eff::Effect for Input<C, M> {
+  type Message = M;
+  type Command = C;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
+
+    Ok((msg_out, cmd_in))
+  }
+}
@@ -1,8 +1,24 @@
 /// http module for the `tide`-based http api effects.
 pub mod http;
 
+/// input module for jog pendants and keypads attached to our host.
+pub mod input;
+
+/// notify module for delivering notifications to webhooks and chat services.
+pub mod notify;
+
 /// serial module for a serial connection related effects.
 pub mod serial;
 
+/// signals module for translating unix signals into messages.
+#[cfg(unix)]
+pub mod signals;
+
+/// terminal module for local sessions attached through a unix socket.
+pub mod terminal;
+
 /// A simple ticker effect runtime.
 pub mod ticker;
+
+/// watcher module for noticing changes to our configuration file.
+pub mod watcher;
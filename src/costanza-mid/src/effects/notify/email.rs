@@ -0,0 +1,139 @@
+//! Delivering events as emails, for shops without a chat service (or phones subscribed to ntfy).
+
+use super::Event;
+use crate::error::{Error, Result};
+use lettre::AsyncTransport;
+use serde::{Deserialize, Serialize};
+
+/// How the connection to the smtp server is secured.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum EmailSecurity {
+  /// The connection is upgraded to tls after connecting, usually on port `587`.
+  #[default]
+  Starttls,
+
+  /// The connection is made over tls from the start, usually on port `465`.
+  Tls,
+
+  /// Nothing is encrypted. This is only meant for relays on the same host or network.
+  None,
+}
+
+/// The smtp server emails are sent through, and who they are sent to.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(super) struct EmailConfiguration {
+  /// The host name of the smtp server.
+  host: String,
+
+  /// The port of the smtp server. When not provided, the default port of our security is used.
+  #[serde(default)]
+  port: Option<u16>,
+
+  #[serde(default)]
+  security: EmailSecurity,
+
+  /// The user we sign in to the smtp server as, if it requires it.
+  #[serde(default)]
+  username: Option<String>,
+
+  #[serde(default)]
+  password: Option<String>,
+
+  /// Who emails are sent from, e.g `Costanza <costanza@example.com>`.
+  from: String,
+
+  /// Who emails are sent to.
+  to: Vec<String>,
+}
+
+impl EmailConfiguration {
+  /// Records an error for every address we would be unable to send emails from (or to), and for
+  /// credentials that are only partially provided.
+  pub(super) fn validate(&self, index: usize, validation: &mut crate::validation::Validation) {
+    let field = format!("notify.sinks[{index}].email");
+
+    if self.host.is_empty() {
+      validation.error(format!("{field}.host"), "must not be empty");
+    }
+
+    if let Err(error) = self.from.parse::<lettre::message::Mailbox>() {
+      validation.error(
+        format!("{field}.from"),
+        format!("'{}' is not a valid address ({error})", self.from),
+      );
+    }
+
+    for address in &self.to {
+      if let Err(error) = address.parse::<lettre::message::Mailbox>() {
+        validation.error(
+          format!("{field}.to"),
+          format!("'{address}' is not a valid address ({error})"),
+        );
+      }
+    }
+
+    if self.to.is_empty() {
+      validation.error(format!("{field}.to"), "must contain at least one address");
+    }
+
+    if self.username.is_some() != self.password.is_some() {
+      validation.error(&field, "both a username and password are required to sign in");
+    }
+
+    if self.security == EmailSecurity::None && self.password.is_some() {
+      validation.warning(
+        format!("{field}.security"),
+        "credentials will be sent to the smtp server unencrypted",
+      );
+    }
+  }
+
+  /// Builds the transport used to reach our smtp server.
+  fn transport(&self) -> Result<lettre::AsyncSmtpTransport<lettre::AsyncStd1Executor>> {
+    type Transport = lettre::AsyncSmtpTransport<lettre::AsyncStd1Executor>;
+
+    let mut builder = match self.security {
+      EmailSecurity::Starttls => Transport::starttls_relay(&self.host),
+      EmailSecurity::Tls => Transport::relay(&self.host),
+      EmailSecurity::None => Ok(Transport::builder_dangerous(&self.host)),
+    }
+    .map_err(|error| Error::Notify(format!("unable to reach '{}' - {error}", self.host)))?;
+
+    if let Some(port) = self.port {
+      builder = builder.port(port);
+    }
+
+    if let Some((username, password)) = self.username.as_ref().zip(self.password.as_ref()) {
+      let credentials = lettre::transport::smtp::authentication::Credentials::new(username.clone(), password.clone());
+      builder = builder.credentials(credentials);
+    }
+
+    Ok(builder.build())
+  }
+}
+
+/// Sends the event to every recipient.
+pub(super) async fn send(config: &EmailConfiguration, event: &Event) -> Result<()> {
+  let invalid = |error: lettre::address::AddressError| Error::Notify(format!("invalid address - {error}"));
+
+  let mut message = lettre::Message::builder()
+    .from(config.from.parse().map_err(invalid)?)
+    .subject(format!("costanza: {}", event.title()))
+    .header(lettre::message::header::ContentType::TEXT_PLAIN);
+
+  for address in &config.to {
+    message = message.to(address.parse().map_err(invalid)?);
+  }
+
+  let message = message
+    .body(event.message())
+    .map_err(|error| Error::Notify(format!("unable to build email - {error}")))?;
+
+  config
+    .transport()?
+    .send(message)
+    .await
+    .map(|_| ())
+    .map_err(|error| Error::Notify(format!("unable to send email - {error}")))
+}
@@ -0,0 +1,278 @@
+//! The notify effect delivers the events an operator may want to hear about while they are away
+//! from the machine (a job finishing, an alarm, losing the controller) to the sinks we have been
+//! configured with: generic webhooks, Slack or Discord incoming webhooks, ntfy topics and email.
+//! Every delivery happens on its own task, so a slow (or unreachable) sink never holds up the
+//! others.
+
+use crate::error::{Error, Result};
+use async_std::channel;
+use serde::{Deserialize, Serialize};
+
+/// Sending events as emails.
+mod email;
+
+/// How long a single delivery may take before it is abandoned.
+const DELIVERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Something that happened which is worth telling an operator about.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum Event {
+  /// A job has finished, however it ended.
+  JobFinished { job: crate::jobs::JobRecord },
+
+  /// The controller has reported an alarm.
+  Alarm { code: u8 },
+
+  /// The serial connection to the controller has been lost.
+  SerialDisconnected,
+}
+
+impl Event {
+  /// Returns the kind of this event, used to determine which sinks it is delivered to.
+  fn kind(&self) -> EventKind {
+    match self {
+      Self::JobFinished { .. } => EventKind::JobFinished,
+      Self::Alarm { .. } => EventKind::Alarm,
+      Self::SerialDisconnected => EventKind::SerialDisconnected,
+    }
+  }
+
+  /// Returns a short title for this event.
+  fn title(&self) -> String {
+    match self {
+      Self::JobFinished { job } => format!("job {}", job.outcome),
+      Self::Alarm { code } => format!("alarm {code}"),
+      Self::SerialDisconnected => "machine disconnected".to_string(),
+    }
+  }
+
+  /// Returns the human-readable message sent to chat sinks.
+  fn message(&self) -> String {
+    match self {
+      Self::JobFinished { job } => format!(
+        "job '{}' {} after {} lines ({} minutes)",
+        job.name.as_deref().unwrap_or("untitled"),
+        job.outcome,
+        job.lines_sent,
+        job.finished_at.signed_duration_since(job.started_at).num_minutes(),
+      ),
+      Self::Alarm { code } => format!("the controller reported alarm {code}"),
+      Self::SerialDisconnected => "the serial connection to the controller was lost".to_string(),
+    }
+  }
+}
+
+/// The kinds of events sinks may subscribe to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+  JobFinished,
+  Alarm,
+  SerialDisconnected,
+}
+
+/// The services events can be delivered to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+  /// The event itself is posted as json.
+  Webhook,
+
+  /// A Slack incoming webhook.
+  Slack,
+
+  /// A Discord webhook.
+  Discord,
+
+  /// An ntfy topic, e.g `https://ntfy.sh/my-shop`.
+  Ntfy,
+
+  /// Emails sent through an smtp server.
+  Email,
+}
+
+/// A single place events are delivered to.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SinkConfiguration {
+  kind: SinkKind,
+
+  /// Where events are posted; required by every kind of sink other than email.
+  #[serde(default)]
+  url: Option<String>,
+
+  /// How emails are sent; required by email sinks.
+  #[serde(default)]
+  email: Option<email::EmailConfiguration>,
+
+  /// The kinds of events delivered to this sink. When empty, every event is.
+  #[serde(default)]
+  events: Vec<EventKind>,
+}
+
+impl SinkConfiguration {
+  /// Whether or not the event should be delivered to this sink.
+  fn wants(&self, event: &Event) -> bool {
+    self.events.is_empty() || self.events.contains(&event.kind())
+  }
+
+  /// Delivers the event to this sink.
+  async fn deliver(&self, event: &Event) -> Result<()> {
+    let delivery = async {
+      match (self.kind, self.email.as_ref(), self.url.as_deref()) {
+        (SinkKind::Email, Some(config), _) => email::send(config, event).await,
+        (SinkKind::Email, None, _) => Err(Error::Notify("missing email configuration".to_string())),
+        (kind, _, Some(url)) => post(kind, url, event).await,
+        (_, _, None) => Err(Error::Notify("missing url".to_string())),
+      }
+    };
+
+    async_std::future::timeout(DELIVERY_TIMEOUT, delivery)
+      .await
+      .map_err(|_| Error::Notify("timed out".to_string()))?
+  }
+
+  /// Records an error when we are missing (or have an invalid) destination.
+  pub(crate) fn validate(&self, index: usize, validation: &mut crate::validation::Validation) {
+    match (self.kind, self.email.as_ref(), self.url.as_ref()) {
+      (SinkKind::Email, Some(config), _) => config.validate(index, validation),
+      (SinkKind::Email, None, _) => {
+        validation.error(format!("notify.sinks[{index}].email"), "is required by email sinks")
+      }
+      (_, _, Some(url)) => validation.url(format!("notify.sinks[{index}].url"), url),
+      (_, _, None) => validation.error(format!("notify.sinks[{index}].url"), "is required"),
+    }
+  }
+}
+
+/// Posts the event to a webhook (or chat service) at the provided url, in the shape it expects.
+async fn post(kind: SinkKind, url: &str, event: &Event) -> Result<()> {
+  let request = match kind {
+    SinkKind::Slack => surf::post(url).body_json(&serde_json::json!({ "text": event.message() })),
+    SinkKind::Discord => surf::post(url).body_json(&serde_json::json!({ "content": event.message() })),
+    SinkKind::Ntfy => Ok(
+      surf::post(url)
+        .header("Title", format!("costanza: {}", event.title()))
+        .body_string(event.message()),
+    ),
+    SinkKind::Webhook | SinkKind::Email => surf::post(url).body_json(event),
+  }
+  .map_err(|error| Error::Notify(format!("unable to build request - {error}")))?;
+
+  let response = request.await.map_err(|error| Error::Notify(error.to_string()))?;
+
+  if !response.status().is_success() {
+    return Err(Error::Notify(format!("responded with {}", response.status())));
+  }
+
+  Ok(())
+}
+
+/// The configuration of the notify effect.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Configuration {
+  /// Every place events are delivered to.
+  #[serde(default)]
+  sinks: Vec<SinkConfiguration>,
+}
+
+impl Configuration {
+  /// Records every issue with our sinks.
+  pub(crate) fn validate(&self, validation: &mut crate::validation::Validation) {
+    for (index, sink) in self.sinks.iter().enumerate() {
+      sink.validate(index, validation);
+    }
+
+    if self.sinks.is_empty() {
+      validation.warning("notify.sinks", "no sinks are configured; nothing will be delivered");
+    }
+  }
+}
+
+/// The `NotifyCommand` type defined here refers to the commands that are specific to the notify
+/// effect.
+#[derive(Debug)]
+pub enum NotifyCommand {
+  /// Delivers the event to every sink interested in it.
+  Deliver(Event),
+}
+
+pub trait NotifyMap {
+  type Command;
+  type Message;
+
+  fn translate(&self, original: Self::Command) -> Option<NotifyCommand>;
+}
+
+pub struct Notify<C, M> {
+  config: Configuration,
+  commands: (channel::Receiver<C>, Option<channel::Sender<C>>),
+
+  /// The notify effect does not produce any messages; this only exists to satisfy `detach`.
+  messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
+}
+
+impl<C, M> Notify<C, M> {
+  pub fn new(config: Configuration) -> Self {
+    let commands = channel::unbounded();
+    let messages = channel::unbounded();
+
+    Self {
+      config,
+      commands: (commands.1, Some(commands.0)),
+      messages: (messages.0, Some(messages.1)),
+    }
+  }
+
+  pub async fn run<T>(self, glue: T) -> Result<()>
+  where
+    T: NotifyMap<Command = C, Message = M>,
+  {
+    tracing::info!("delivering notifications to {} sinks", self.config.sinks.len());
+
+    loop {
+      let command = self
+        .commands
+        .0
+        .recv()
+        .await
+        .map_err(|error| Error::ChannelClosed(format!("closed notify channel ({error})")))?;
+
+      let event = match glue.translate(command) {
+        Some(NotifyCommand::Deliver(event)) => event,
+        None => {
+          tracing::warn!("unable to map from external notify command to internal command");
+          continue;
+        }
+      };
+
+      for sink in self.config.sinks.iter().filter(|sink| sink.wants(&event)) {
+        let (sink, event) = (sink.clone(), event.clone());
+
+        async_std::task::spawn(async move {
+          match sink.deliver(&event).await {
+            Ok(()) => tracing::debug!("delivered '{}' to {:?} sink", event.title(), sink.kind),
+            Err(error) => tracing::warn!(
+              "unable to deliver '{}' to {:?} sink - {error}",
+              event.title(),
+              sink.kind
+            ),
+          }
+        });
+      }
+    }
+  }
+}
+
+impl<C, M> crate::eff::Effect for Notify<C, M> {
+  type Message = M;
+  type Command = C;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
+
+    Ok((msg_out, cmd_in))
+  }
+}
@@ -1,24 +1,293 @@
-//! The serial side effect wraps an underlying tty-looking serial connection. The effect manager
-//! will attempt to use a `SerialCommandMap` to both:
+//! The serial side effect wraps an underlying serial connection; a serial port on any platform we
+//! run on (e.g `/dev/ttyUSB0` or `COM3`), or a tcp socket for devices configured as
+//! `tcp://host:port` (e.g the simulator, or a serial-to-network bridge like ser2net). The effect
+//! manager will attempt to use a `SerialCommandMap` to both:
 //!
 //! 1. Create application-specific messages for connections and disconnect events.
 //! 2. Map an application-specific command into the generic command type defined here.
 
+use crate::error::{Error, Result};
 use async_std::channel;
 use serde::{Deserialize, Serialize};
 use std::io;
 
-#[derive(Deserialize, Debug, Serialize, Clone)]
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
 pub struct SerialConfiguration {
   device: String,
   baud: u32,
+
+  /// A list of lines that should be sent to the device every time a connection is established.
+  #[serde(default)]
+  on_connect: Vec<String>,
+
+  /// Lines sent before the lines of every job, e.g to warm the spindle up (`M3 S12000`, `G4 P10`).
+  #[serde(default)]
+  before_job: Vec<String>,
+
+  /// Lines sent once every line of a job has been, e.g to stop the coolant and park the machine.
+  #[serde(default)]
+  after_job: Vec<String>,
+
+  /// The firmware running on the controller; grbl when not provided.
+  #[serde(default)]
+  firmware: Option<crate::app::firmware::FirmwareFlavor>,
+
+  /// How the axes of this machine are presented to operators.
+  #[serde(default)]
+  display: AxisMapping,
+
+  /// How far each axis of this machine can travel. Jogs and raw moves that would go beyond these are
+  /// refused before they are sent, for machines without soft limits configured in their firmware.
+  #[serde(default)]
+  limits: Option<TravelLimits>,
+
+  /// The amount of milliseconds a write may take before the connection is considered stalled (e.g
+  /// a wedged usb adapter), torn down and re-established.
+  #[serde(default = "default_write_timeout")]
+  write_timeout_ms: u64,
+}
+
+fn default_write_timeout() -> u64 {
+  2000
+}
+
+/// How long each read (and write) waits on the port itself. Reads happen on every iteration of our
+/// loop, so this needs to stay short; writes stuck beyond it are caught by our write timeout.
+const PORT_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// How long we wait on a device reached over tcp to accept our connection.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The prefix of devices reached over tcp instead of opened as serial ports.
+pub const TCP_PREFIX: &str = "tcp://";
+
+/// The travel of a single axis, in machine coordinates (millimeters).
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct AxisLimits {
+  pub min: f32,
+  pub max: f32,
+}
+
+/// The travel of every axis of the machine. Axes without limits are not checked.
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct TravelLimits {
+  #[serde(default)]
+  x: Option<AxisLimits>,
+  #[serde(default)]
+  y: Option<AxisLimits>,
+  #[serde(default)]
+  z: Option<AxisLimits>,
+}
+
+impl TravelLimits {
+  /// Returns the first machine axis (and where it would end up) that a move to the provided target,
+  /// in machine coordinates, would take beyond its limits.
+  pub fn violation(&self, target: [f32; 3]) -> Option<(&'static str, f32)> {
+    ["X", "Y", "Z"]
+      .into_iter()
+      .zip([self.x, self.y, self.z])
+      .zip(target)
+      .find_map(|((axis, limits), value)| {
+        limits
+          .filter(|limits| value < limits.min || value > limits.max)
+          .map(|_| (axis, value))
+      })
+  }
+
+  /// Records an error for every axis whose minimum is not below its maximum.
+  fn validate(&self, validation: &mut crate::validation::Validation) {
+    for (axis, limits) in ["x", "y", "z"].into_iter().zip([self.x, self.y, self.z]) {
+      if let Some(limits) = limits.filter(|limits| limits.min >= limits.max) {
+        validation.error(
+          format!("serial.limits.{axis}"),
+          format!("min ({}) must be less than max ({})", limits.min, limits.max),
+        );
+      }
+    }
+  }
+}
+
+/// Some machines are wired with swapped or inverted axes relative to how operators think about
+/// them. The mapping is purely presentational; it is applied to the positions we show and the jogs
+/// we are asked for, never to the lines of a file.
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AxisMapping {
+  /// Whether the machine `X` axis is shown as `Y` (and vice versa).
+  #[serde(default)]
+  swap_xy: bool,
+
+  /// Whether the shown `X` axis is the negative of the machine axis.
+  #[serde(default)]
+  invert_x: bool,
+
+  /// Whether the shown `Y` axis is the negative of the machine axis.
+  #[serde(default)]
+  invert_y: bool,
+
+  /// Whether the shown `Z` axis is the negative of the machine axis.
+  #[serde(default)]
+  invert_z: bool,
+}
+
+impl AxisMapping {
+  /// Maps machine coordinates into the coordinates shown to operators.
+  pub fn display(&self, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    let (x, y) = if self.swap_xy { (y, x) } else { (x, y) };
+    [
+      sign(self.invert_x) * x,
+      sign(self.invert_y) * y,
+      sign(self.invert_z) * z,
+    ]
+  }
+
+  /// Maps coordinates shown to operators back into machine coordinates.
+  pub fn machine(&self, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    let (x, y, z) = (
+      sign(self.invert_x) * x,
+      sign(self.invert_y) * y,
+      sign(self.invert_z) * z,
+    );
+    let (x, y) = if self.swap_xy { (y, x) } else { (x, y) };
+    [x, y, z]
+  }
+}
+
+/// The multiplier applied to an axis that may be inverted.
+fn sign(inverted: bool) -> f32 {
+  if inverted {
+    -1.0
+  } else {
+    1.0
+  }
+}
+
+impl SerialConfiguration {
+  /// Returns the lines that should be written to the device after each successful connection.
+  pub fn on_connect(&self) -> &[String] {
+    &self.on_connect
+  }
+
+  /// Returns the lines sent before, and after, the lines of every job.
+  pub fn job_sequences(&self) -> (&[String], &[String]) {
+    (&self.before_job, &self.after_job)
+  }
+
+  /// Returns the firmware the controller is configured as running, if it is.
+  pub fn firmware(&self) -> Option<crate::app::firmware::FirmwareFlavor> {
+    self.firmware
+  }
+
+  /// Returns how the axes of this machine are presented to operators.
+  pub fn display(&self) -> AxisMapping {
+    self.display
+  }
+
+  /// Returns how far each axis of this machine can travel, when configured.
+  pub fn limits(&self) -> Option<&TravelLimits> {
+    self.limits.as_ref()
+  }
+
+  /// Returns how long a write may take before the connection is considered stalled.
+  pub fn write_timeout(&self) -> std::time::Duration {
+    std::time::Duration::from_millis(self.write_timeout_ms)
+  }
+
+  /// Records the issues with our device. A missing device is only a warning; controllers are often
+  /// plugged in after we have started, and connections can be retried by clients.
+  pub(crate) fn validate(&self, validation: &mut crate::validation::Validation) {
+    if self.device.is_empty() {
+      validation.error("serial.device", "must not be empty");
+    } else if !self.device.starts_with(TCP_PREFIX) && !device_exists(&self.device) {
+      validation.warning("serial.device", format!("'{}' does not exist", self.device));
+    }
+
+    if self.baud == 0 {
+      validation.error("serial.baud", "must be greater than zero");
+    }
+
+    if self.write_timeout_ms == 0 {
+      validation.error("serial.write_timeout_ms", "must be greater than zero");
+    }
+
+    if let Some(limits) = self.limits.as_ref() {
+      limits.validate(validation);
+    }
+  }
 }
 
 /// The output parser is the type that is used to produce the application-specific messages _from_
 /// serial data.
 pub trait OuputParser {
   type Message;
+
+  /// Parses the first message out of the provided data, returning it along with the amount of bytes
+  /// it took up (which is never zero), or nothing until the data holds a complete message.
   fn parse(&self, data: &[u8]) -> Option<(Self::Message, usize)>;
+
+  /// Parses whatever is left of the data once nothing else has arrived for a while, e.g a prompt
+  /// that is not followed by a line ending. By default, it is discarded.
+  fn flush(&self, _data: &[u8]) -> Option<Self::Message> {
+    None
+  }
+}
+
+/// How long the bytes following the last complete message may sit in our buffer, without anything
+/// else arriving, before they are flushed.
+const IDLE_FLUSH: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Finds the first line among bytes read from a serial connection, returning it without its line
+/// ending along with the amount of bytes taken up by both. Lines may end with `\n`, `\r\n` or a bare
+/// `\r`; a trailing `\r` is waited on, since the `\n` following it may not have been read yet.
+pub fn frame(bytes: &[u8]) -> Option<(&[u8], usize)> {
+  let boundary = bytes.iter().position(|byte| matches!(byte, b'\n' | b'\r'))?;
+
+  match (bytes[boundary], bytes.get(boundary + 1)) {
+    (b'\r', Some(b'\n')) => Some((&bytes[..boundary], boundary + 2)),
+    (b'\r', None) => None,
+    _ => Some((&bytes[..boundary], boundary + 1)),
+  }
+}
+
+/// Returns bytes that will not be followed by anything else without a trailing `\r`, if they have
+/// one; it is the ending of a line rather than part of it.
+pub fn unterminated(bytes: &[u8]) -> &[u8] {
+  bytes.strip_suffix(b"\r").unwrap_or(bytes)
+}
+
+/// Parses every complete message out of the buffer, leaving only whatever follows the last of them.
+pub fn drain<O>(parser: &O, buffer: &mut Vec<u8>) -> Vec<O::Message>
+where
+  O: OuputParser,
+{
+  let mut messages = vec![];
+  let mut taken = 0;
+
+  while let Some((message, bytes_taken)) = parser.parse(&buffer[taken..]) {
+    messages.push(message);
+    taken += bytes_taken;
+
+    if bytes_taken == 0 {
+      tracing::warn!("serial parser produced a message without taking any bytes, stopping");
+      break;
+    }
+  }
+
+  buffer.drain(..taken);
+  messages
+}
+
+/// Parses everything left in the buffer once nothing else has arrived for a while, emptying it.
+pub fn flush<O>(parser: &O, buffer: &mut Vec<u8>) -> Option<O::Message>
+where
+  O: OuputParser,
+{
+  if buffer.is_empty() {
+    return None;
+  }
+
+  let message = parser.flush(buffer);
+  buffer.clear();
+  message
 }
 
 pub struct Serial<C, M, O> {
@@ -29,11 +298,42 @@ pub struct Serial<C, M, O> {
   config: Option<SerialConfiguration>,
 }
 
+/// What is written to the serial connection for a piece of data. Whether (and how) something is
+/// terminated is up to each kind of data, rather than how it happens to be displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encoded {
+  /// A line, which is written followed by a newline.
+  Line(String),
+
+  /// Bytes written exactly as they are; realtime commands are not followed by a newline, and some
+  /// (e.g grbl's overrides) cannot be represented by utf-8 at all.
+  RawBytes(Vec<u8>),
+
+  /// Nothing is written.
+  None,
+}
+
+impl Encoded {
+  /// Returns the bytes written to the serial connection, if any.
+  fn into_bytes(self) -> Option<Vec<u8>> {
+    match self {
+      Self::Line(line) => Some(format!("{line}\n").into_bytes()),
+      Self::RawBytes(bytes) => Some(bytes),
+      Self::None => None,
+    }
+  }
+}
+
+/// Implemented by the data an application writes to the serial connection.
+pub trait Encode {
+  fn encode(&self) -> Encoded;
+}
+
 /// The `SerialCommand` type defined here refers to types that are uniquely related to the serial
 /// effect management; they are more specific than the general application config.
 pub enum SerialCommand<D>
 where
-  D: std::fmt::Display,
+  D: Encode,
 {
   Control(bool),
   Configure(SerialConfiguration),
@@ -42,13 +342,19 @@ where
 
 pub trait SerialCommandMap<D>
 where
-  D: std::fmt::Display,
+  D: Encode,
 {
   type Command;
   type Message;
 
   fn translate(&self, original: Self::Command) -> Option<SerialCommand<D>>;
 
+  /// Data from commands with a `High` priority will be written to the serial connection before
+  /// any `Normal` priority data that is waiting to be written.
+  fn priority(&self, _command: &Self::Command) -> crate::eff::Priority {
+    crate::eff::Priority::Normal
+  }
+
   /// Defines the type of message that should be used when we lose a serial connection.
   fn disconnected(&self) -> Self::Message;
 
@@ -73,56 +379,68 @@ where
     }
   }
 
-  pub async fn run<T, D>(mut self, glue: T) -> io::Result<()>
+  pub async fn run<T, D>(mut self, glue: T) -> Result<()>
   where
     T: SerialCommandMap<D, Command = C, Message = M>,
-    D: std::fmt::Display,
+    D: Encode,
   {
     let mut port = None;
     let mut is_connected = false;
     let mut manual_disconnect = false;
 
+    // When we last read anything, used to flush output that never receives a line ending.
+    let mut last_read = std::time::Instant::now();
+
+    // Data waiting to be written to the serial port, split by priority.
+    let mut urgent = std::collections::VecDeque::new();
+    let mut pending = std::collections::VecDeque::new();
+
     loop {
-      // Check to see if we have anything waiting to be sent into our serial port, or if we have a
-      // configuration command that can be extrapolated from the original command.
-      let sendable_command = match self.commands.0.try_recv() {
-        Err(error) if error.is_empty() => None,
-        Err(error) => {
-          let message = format!("closed serial command channel ({error})");
-          break Err(io::Error::new(io::ErrorKind::Other, message));
-        }
-        Ok(command) => match glue.translate(command) {
+      // Pull everything waiting in our command channel so that high priority data does not sit
+      // behind whatever was sent before it. Control and configuration commands are applied
+      // immediately.
+      let closed = loop {
+        let command = match self.commands.0.try_recv() {
+          Err(error) if error.is_empty() => break None,
+          Err(error) => break Some(error),
+          Ok(command) => command,
+        };
+
+        let priority = glue.priority(&command);
+
+        match glue.translate(command) {
           // When a user has explictly sent a control command, we'll use the `manual_disconnect`
           // flag to circumvent any attempt to connect.
-          Some(SerialCommand::Control(true)) => {
-            manual_disconnect = false;
-            None
-          }
+          Some(SerialCommand::Control(true)) => manual_disconnect = false,
           Some(SerialCommand::Control(false)) => {
             manual_disconnect = true;
             port = None;
-            None
           }
 
-          Some(SerialCommand::Configure(config)) => {
-            self.config = Some(config);
-            None
-          }
-          Some(SerialCommand::Data(serializable)) => Some(format!("{serializable}")),
-          None => {
-            tracing::warn!("unable to map from external serial command to internal command");
-            None
-          }
-        },
+          Some(SerialCommand::Configure(config)) => self.config = Some(config),
+          Some(SerialCommand::Data(data)) => match (data.encode().into_bytes(), priority) {
+            (Some(bytes), crate::eff::Priority::High) => urgent.push_back(bytes),
+            (Some(bytes), _) => pending.push_back(bytes),
+            (None, _) => tracing::debug!("serial data encoded to nothing, not writing it"),
+          },
+          None => tracing::warn!("unable to map from external serial command to internal command"),
+        }
       };
 
+      if let Some(error) = closed {
+        let message = format!("closed serial command channel ({error})");
+        break Err(Error::ChannelClosed(message));
+      }
+
+      // Check to see if we have anything waiting to be sent into our serial port.
+      let sendable_command = urgent.pop_front().or_else(|| pending.pop_front());
+
       port = match (manual_disconnect, self.config.as_ref(), port.take()) {
         (true, _, _) => None,
         (_, Some(config), None) => {
-          let new_port = serialport::new(&config.device, config.baud)
-            .open()
+          let new_port = Port::open(config)
             .map_err(|error| {
-              tracing::warn!("[{:?}] unable to open {:?} port - {error}", error.kind(), config);
+              tracing::warn!("unable to open {:?} port - {error}", config);
               error
             })
             .ok();
@@ -135,7 +453,7 @@ where
 
               self.messages.0.send(glue.connected()).await.map_err(|error| {
                 tracing::warn!("unable to send connected message - {error}");
-                io::Error::new(io::ErrorKind::Other, format!("serial-send failure: {error}"))
+                Error::ChannelClosed(format!("serial-send failure: {error}"))
               })?;
             }
           }
@@ -154,14 +472,21 @@ where
 
           self.messages.0.send(glue.disconnected()).await.map_err(|error| {
             tracing::warn!("unable to send disconnect message - {error}");
-            io::Error::new(io::ErrorKind::Other, format!("serial-send failure: {error}"))
+            Error::ChannelClosed(format!("serial-send failure: {error}"))
           })?;
         }
 
-        // If we received a command and were able to get something that implements the `Display`
-        // trait (was serializable), we have "dropped" a message that would've otherwise been sent.
-        if let Some(dropped) = sendable_command {
-          tracing::warn!("dropping received command due to missing serial connection - {dropped}");
+        // If we received a command and were able to encode it into something, we have "dropped" a
+        // message that would've otherwise been sent.
+        for dropped in sendable_command
+          .into_iter()
+          .chain(urgent.drain(..))
+          .chain(pending.drain(..))
+        {
+          tracing::warn!(
+            "dropping received command due to missing serial connection - {:?}",
+            String::from_utf8_lossy(&dropped)
+          );
         }
 
         async_std::task::sleep(std::time::Duration::from_secs(2)).await;
@@ -184,30 +509,72 @@ where
           continue;
         }
 
-        Ok(amount) => self.buffer.extend_from_slice(&buffer[0..amount]),
+        Ok(0) => (),
+        Ok(amount) => {
+          last_read = std::time::Instant::now();
+          self.buffer.extend_from_slice(&buffer[0..amount]);
+        }
+      }
+
+      // Parse every complete message out of our buffer, leaving whatever partial message follows
+      // them for the next read to complete.
+      for message in drain(&self.parser, &mut self.buffer) {
+        // Attempt to send our now-parsed message to the effect runtime.
+        if let Err(error) = self.messages.0.send(message).await {
+          tracing::warn!("unable to propagate parsed message - {error}");
+          return Err(Error::ChannelClosed("failed-serial-message-send".into()));
+        }
       }
 
-      // If we have content in our buffer, attempt to parse it and truncate the buffer back down to
-      // the amount of bytes the message consumes.
       if !self.buffer.is_empty() {
-        if let Some((message, bytes_taken)) = self.parser.parse(&self.buffer) {
-          // Attempt to send our now-parsed message to the effect runtime.
+        tracing::debug!("current buffer after parsing - {:X?}", self.buffer);
+      }
+
+      if !self.buffer.is_empty() && last_read.elapsed() >= IDLE_FLUSH {
+        tracing::debug!("flushing {} bytes without a line ending", self.buffer.len());
+
+        if let Some(message) = flush(&self.parser, &mut self.buffer) {
           if let Err(error) = self.messages.0.send(message).await {
-            tracing::warn!("unable to propagate parsed message - {error}");
-            break Err(io::Error::new(io::ErrorKind::Other, "failed-serial-message-send"));
+            tracing::warn!("unable to propagate flushed message - {error}");
+            return Err(Error::ChannelClosed("failed-serial-message-send".into()));
           }
-
-          self.buffer = self.buffer.into_iter().skip(bytes_taken).collect();
-          tracing::debug!("current buffer after truncate - {:X?}", self.buffer);
         }
       }
 
       // If, at the start of this iteration, we had a command we should be able to publish it now.
       // If that fails, we will clear out the connection.
       if let Some(payload) = sendable_command {
-        if let Err(error) = write!(unwrapped_port, "{payload}") {
-          tracing::warn!("unable to write command - {error}");
-          port = None;
+        let timeout = self
+          .config
+          .as_ref()
+          .map(SerialConfiguration::write_timeout)
+          .unwrap_or_else(|| std::time::Duration::from_millis(default_write_timeout()));
+
+        port = match port.take() {
+          Some(open) => write(open, payload, timeout).await,
+          None => None,
+        };
+
+        // Whatever the controller was doing with the lines before this one is unknown to us now, so
+        // the application hears of this like any other lost connection before we reconnect.
+        if port.is_none() {
+          is_connected = false;
+
+          self.messages.0.send(glue.disconnected()).await.map_err(|error| {
+            tracing::warn!("unable to send disconnect message - {error}");
+            Error::ChannelClosed(format!("serial-send failure: {error}"))
+          })?;
+
+          // Anything queued behind the stalled write was meant for the connection we just lost.
+          for dropped in urgent.drain(..).chain(pending.drain(..)) {
+            tracing::warn!(
+              "dropping queued command due to lost serial connection - {:?}",
+              String::from_utf8_lossy(&dropped)
+            );
+          }
+
+          async_std::task::sleep(std::time::Duration::from_secs(2)).await;
+          continue;
         }
       }
 
@@ -217,22 +584,109 @@ where
   }
 }
 
+/// Writes to a port from a blocking thread, so that a write which never completes does not freeze
+/// our loop along with it. The port is only given back when the write completes in time; otherwise
+/// the connection is torn down and re-established like any other that was lost.
+async fn write(mut port: Port, payload: Vec<u8>, timeout: std::time::Duration) -> Option<Port> {
+  let written = async_std::task::spawn_blocking(move || {
+    let result = io::Write::write_all(&mut port, &payload);
+    (port, result)
+  });
+
+  match async_std::future::timeout(timeout, written).await {
+    Ok((port, Ok(()))) => Some(port),
+    Ok((_, Err(error))) => {
+      tracing::warn!("unable to write command - {error}");
+      None
+    }
+    Err(_) => {
+      tracing::warn!("serial write stalled for more than {timeout:?}, reconnecting");
+      None
+    }
+  }
+}
+
+/// An open connection to the controller.
+enum Port {
+  Serial(Box<dyn serialport::SerialPort>),
+  Tcp(std::net::TcpStream),
+}
+
+impl Port {
+  /// Opens the device of a configuration, connecting to it over tcp when it has our `tcp://` prefix.
+  fn open(config: &SerialConfiguration) -> Result<Self> {
+    let addr = match config.device.strip_prefix(TCP_PREFIX) {
+      Some(addr) => addr,
+      None => {
+        return serialport::new(&config.device, config.baud)
+          .timeout(PORT_TIMEOUT)
+          .open()
+          .map(Self::Serial)
+          .map_err(Error::from);
+      }
+    };
+
+    let address = std::net::ToSocketAddrs::to_socket_addrs(addr)?
+      .next()
+      .ok_or_else(|| Error::Address(format!("unable to resolve '{addr}'")))?;
+    let stream = std::net::TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(PORT_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    Ok(Self::Tcp(stream))
+  }
+}
+
+impl io::Read for Port {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Self::Serial(port) => io::Read::read(port, buffer),
+      // Sockets time out with `WouldBlock` on some platforms, and are closed when a read comes back
+      // empty; both are reported the way a serial port would report them.
+      Self::Tcp(stream) => match io::Read::read(stream, buffer) {
+        Ok(0) if !buffer.is_empty() => Err(io::ErrorKind::BrokenPipe.into()),
+        Err(error) if error.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+        result => result,
+      },
+    }
+  }
+}
+
+impl io::Write for Port {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    match self {
+      Self::Serial(port) => io::Write::write(port, buffer),
+      Self::Tcp(stream) => io::Write::write(stream, buffer),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Self::Serial(port) => io::Write::flush(port),
+      Self::Tcp(stream) => io::Write::flush(stream),
+    }
+  }
+}
+
+/// Whether or not a serial device exists. Devices are files on unix; elsewhere (e.g `COM3` on
+/// windows) they are looked for among the ports listed by the system.
+fn device_exists(device: &str) -> bool {
+  if cfg!(unix) {
+    return std::path::Path::new(device).exists();
+  }
+
+  serialport::available_ports().map_or(true, |ports| {
+    ports.iter().any(|port| port.port_name.eq_ignore_ascii_case(device))
+  })
+}
+
 impl<C, M, O> crate::eff::Effect for Serial<C, M, O> {
   type Message = M;
   type Command = C;
 
-  fn detach(&mut self) -> io::Result<(channel::Receiver<Self::Message>, channel::Sender<Self::Command>)> {
-    let cmd_in = self
-      .commands
-      .1
-      .take()
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "already taken"))?;
-
-    let msg_out = self
-      .messages
-      .1
-      .take()
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "already taken"))?;
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
 
     Ok((msg_out, cmd_in))
   }
@@ -0,0 +1,151 @@
+//! The signals effect translates the unix signals sent to our process into application-specific
+//! messages, so that things like shutting down are handled by the application's `update` like
+//! anything else. The application decides when we should actually exit by sending an `Exit`
+//! command back, which resolves the `run` future of this effect.
+
+use crate::error::{Error, Result};
+use async_std::channel;
+use futures_lite::FutureExt;
+
+/// The signals we listen for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+  /// `SIGINT`, e.g ctrl-c in a terminal.
+  Interrupt,
+
+  /// `SIGTERM`, e.g a service manager stopping us.
+  Terminate,
+
+  /// `SIGHUP`, conventionally a request to reload configuration.
+  Hangup,
+
+  /// `SIGUSR1`, which we use as a request to dump the state of the application.
+  User1,
+}
+
+impl Signal {
+  /// Maps the raw signal number into our signal, if it is one we listen for.
+  fn from_raw(raw: i32) -> Option<Self> {
+    match raw {
+      signal_hook::consts::SIGINT => Some(Self::Interrupt),
+      signal_hook::consts::SIGTERM => Some(Self::Terminate),
+      signal_hook::consts::SIGHUP => Some(Self::Hangup),
+      signal_hook::consts::SIGUSR1 => Some(Self::User1),
+      _ => None,
+    }
+  }
+}
+
+/// The `SignalCommand` type defined here refers to the commands that are specific to the signals
+/// effect.
+pub enum SignalCommand {
+  /// Stops listening for signals and resolves our `run` future.
+  Exit,
+}
+
+pub trait SignalMap {
+  type Command;
+  type Message;
+
+  fn translate(&self, original: Self::Command) -> Option<SignalCommand>;
+
+  /// Defines the type of message that should be used when we receive a signal. Signals that do not
+  /// map into a message are ignored.
+  fn message(&self, signal: Signal) -> Option<Self::Message>;
+}
+
+pub struct Signals<C, M> {
+  commands: (channel::Receiver<C>, Option<channel::Sender<C>>),
+  messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
+}
+
+/// Whatever woke our run loop up.
+enum Event<C> {
+  Signal(i32),
+  Command(C),
+}
+
+impl<C, M> Signals<C, M> {
+  pub fn new() -> Self {
+    let commands = channel::unbounded();
+    let messages = channel::unbounded();
+
+    Self {
+      commands: (commands.1, Some(commands.0)),
+      messages: (messages.0, Some(messages.1)),
+    }
+  }
+
+  pub async fn run<T>(self, glue: T) -> Result<()>
+  where
+    T: SignalMap<Command = C, Message = M>,
+  {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+
+    let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP, SIGUSR1])?;
+    let handle = signals.handle();
+
+    // Waiting for signals blocks, so it is done on its own thread which forwards them to us.
+    let (sender, receiver) = channel::unbounded();
+    async_std::task::spawn_blocking(move || {
+      for raw in signals.forever() {
+        if sender.try_send(raw).is_err() {
+          break;
+        }
+      }
+    });
+
+    let result = loop {
+      let signal = async { receiver.recv().await.map(Event::Signal) };
+      let command = async { self.commands.0.recv().await.map(Event::Command) };
+
+      let event = match signal.race(command).await {
+        Ok(event) => event,
+        Err(error) => break Err(Error::ChannelClosed(format!("closed signal channel ({error})"))),
+      };
+
+      match event {
+        Event::Signal(raw) => {
+          let message = match Signal::from_raw(raw).and_then(|signal| glue.message(signal)) {
+            Some(message) => message,
+            None => continue,
+          };
+
+          tracing::info!("received signal {raw}, notifying application");
+
+          if let Err(error) = self.messages.0.send(message).await {
+            tracing::warn!("unable to send signal message - {error}");
+            break Err(Error::ChannelClosed("signal-send failure".into()));
+          }
+        }
+
+        Event::Command(command) => match glue.translate(command) {
+          Some(SignalCommand::Exit) => break Ok(()),
+          None => tracing::warn!("unable to map from external signal command to internal command"),
+        },
+      }
+    };
+
+    handle.close();
+    result
+  }
+}
+
+impl<C, M> Default for Signals<C, M> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<C, M> crate::eff::Effect for Signals<C, M> {
+  type Message = M;
+  type Command = C;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
+
+    Ok((msg_out, cmd_in))
+  }
+}
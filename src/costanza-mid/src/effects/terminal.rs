@@ -0,0 +1,204 @@
+//! The terminal effect listens on a unix socket for local "attach" sessions; each line written by a
+//! session is handed to the application, and the application decides what (if anything) is written
+//! back. This is what allows advanced users to talk to the controller directly without stopping the
+//! service. Access is controlled by the permissions of the socket itself, which is only accessible
+//! to the user we are running as.
+
+#[cfg(unix)]
+use crate::error::{Error, Result};
+#[cfg(unix)]
+use async_std::channel;
+#[cfg(unix)]
+use async_std::io::prelude::{BufReadExt, WriteExt};
+#[cfg(unix)]
+use async_std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use futures_lite::{FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+
+/// The configuration of the terminal effect.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Configuration {
+  /// The path of the unix socket sessions attach through.
+  pub socket: String,
+}
+
+/// The events produced by attached sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TerminalEvent {
+  /// A new session (by id) has attached.
+  Attached(String),
+
+  /// A session has written a line.
+  Line(String, String),
+
+  /// A session has gone away.
+  Detached(String),
+}
+
+/// The `TerminalCommand` type defined here refers to the commands that are specific to the
+/// terminal effect.
+#[derive(Debug)]
+pub enum TerminalCommand {
+  /// Writes a line to the session with the provided id.
+  Write(String, String),
+
+  /// Writes a final line to the session with the provided id and disconnects it.
+  Close(String, String),
+}
+
+pub trait TerminalMap {
+  type Command;
+  type Message;
+
+  fn translate(&self, original: Self::Command) -> Option<TerminalCommand>;
+
+  /// Defines the type of message that should be used for the events of our sessions.
+  fn message(&self, event: TerminalEvent) -> Self::Message;
+}
+
+#[cfg(unix)]
+pub struct Terminal<C, M> {
+  config: Configuration,
+  commands: (channel::Receiver<C>, Option<channel::Sender<C>>),
+  messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
+}
+
+/// Whatever woke our run loop up.
+#[cfg(unix)]
+enum Event<C> {
+  Connection(UnixStream),
+  Command(C),
+}
+
+#[cfg(unix)]
+impl<C, M> Terminal<C, M>
+where
+  M: Send + 'static,
+{
+  pub fn new(config: Configuration) -> Self {
+    let commands = channel::unbounded();
+    let messages = channel::unbounded();
+
+    Self {
+      config,
+      commands: (commands.1, Some(commands.0)),
+      messages: (messages.0, Some(messages.1)),
+    }
+  }
+
+  pub async fn run<T>(self, glue: T) -> Result<()>
+  where
+    T: TerminalMap<Command = C, Message = M> + Clone + Send + 'static,
+  {
+    // A socket left behind by a previous run would prevent us from binding.
+    if let Err(error) = std::fs::remove_file(&self.config.socket) {
+      if error.kind() != std::io::ErrorKind::NotFound {
+        return Err(error.into());
+      }
+    }
+
+    let listener = UnixListener::bind(&self.config.socket).await?;
+    let permissions = <std::fs::Permissions as std::os::unix::fs::PermissionsExt>::from_mode(0o600);
+    std::fs::set_permissions(&self.config.socket, permissions)?;
+    tracing::info!("terminal sessions may attach through '{}'", self.config.socket);
+
+    let mut sessions = std::collections::HashMap::new();
+
+    loop {
+      let connection = async {
+        let (stream, _) = listener.accept().await?;
+        Ok(Event::Connection(stream))
+      };
+      let command = async {
+        self
+          .commands
+          .0
+          .recv()
+          .await
+          .map(Event::Command)
+          .map_err(|error| Error::ChannelClosed(format!("closed terminal channel ({error})")))
+      };
+
+      match connection.race(command).await? {
+        Event::Connection(stream) => {
+          let id = uuid::Uuid::new_v4().to_string();
+          tracing::info!("terminal session '{id}' attached");
+          sessions.insert(id.clone(), stream.clone());
+          async_std::task::spawn(read_session(id, stream, self.messages.0.clone(), glue.clone()));
+        }
+
+        Event::Command(command) => {
+          let (id, line, close) = match glue.translate(command) {
+            Some(TerminalCommand::Write(id, line)) => (id, line, false),
+            Some(TerminalCommand::Close(id, line)) => (id, line, true),
+            None => {
+              tracing::warn!("unable to map from external terminal command to internal command");
+              continue;
+            }
+          };
+
+          let mut stream = match sessions.get(&id) {
+            Some(stream) => stream.clone(),
+            None => continue,
+          };
+
+          let written = stream.write_all(format!("{line}\n").as_bytes()).await;
+
+          if let Err(error) = written.as_ref() {
+            tracing::warn!("unable to write to terminal session '{id}' - {error}");
+          }
+
+          if close || written.is_err() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            sessions.remove(&id);
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Forwards every line written by a session to the application until it goes away.
+#[cfg(unix)]
+async fn read_session<T, M>(id: String, stream: UnixStream, messages: channel::Sender<M>, glue: T)
+where
+  T: TerminalMap<Message = M>,
+{
+  if messages
+    .send(glue.message(TerminalEvent::Attached(id.clone())))
+    .await
+    .is_err()
+  {
+    return;
+  }
+
+  let mut lines = async_std::io::BufReader::new(stream).lines();
+
+  while let Some(Ok(line)) = lines.next().await {
+    if messages
+      .send(glue.message(TerminalEvent::Line(id.clone(), line)))
+      .await
+      .is_err()
+    {
+      return;
+    }
+  }
+
+  tracing::info!("terminal session '{id}' detached");
+  let _ = messages.send(glue.message(TerminalEvent::Detached(id))).await;
+}
+
+#[cfg(unix)]
+impl<C, M> crate::eff::Effect for Terminal<C, M> {
+  type Message = M;
+  type Command = C;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
+
+    Ok((msg_out, cmd_in))
+  }
+}
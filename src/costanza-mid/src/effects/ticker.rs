@@ -1,14 +1,33 @@
+use crate::error::{Error, Result};
 use async_std::channel;
-use async_std::stream::StreamExt;
-use std::io;
+use futures_lite::future::FutureExt;
 
-pub struct Ticker<C, M> {
+/// Changes how (and whether) a running ticker ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickerCommand {
+  /// Ticks at the provided interval from here on; the next tick is an interval from now.
+  SetInterval(std::time::Duration),
+
+  /// Stops ticking until resumed.
+  Pause,
+
+  /// Ticks again after being paused; the next tick is an interval from now.
+  Resume,
+}
+
+/// What the ticker was woken up by.
+enum Wake {
+  Tick,
+  Command(Option<TickerCommand>),
+}
+
+pub struct Ticker<M> {
   interval: std::time::Duration,
-  commands: (channel::Receiver<C>, Option<channel::Sender<C>>),
+  commands: (channel::Receiver<TickerCommand>, Option<channel::Sender<TickerCommand>>),
   messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
 }
 
-impl<C, M> Ticker<C, M>
+impl<M> Ticker<M>
 where
   M: std::fmt::Debug,
 {
@@ -23,43 +42,65 @@ where
     }
   }
 
-  pub async fn run<F>(self, f: F) -> io::Result<()>
+  pub async fn run<F>(mut self, f: F) -> Result<()>
   where
     F: Fn() -> M,
   {
-    let mut ival = async_std::stream::interval(self.interval);
+    let mut next = std::time::Instant::now() + self.interval;
+    let mut paused = false;
 
     loop {
-      ival.next().await;
-      let message = f();
-      tracing::debug!("sending ticker message - {message:?}");
+      let tick = async {
+        match paused {
+          true => futures_lite::future::pending().await,
+          false => async_std::task::sleep(next.saturating_duration_since(std::time::Instant::now())).await,
+        }
 
-      if let Err(error) = self.messages.0.send(message).await {
-        tracing::warn!("unable to send tick - {error}");
-        break;
+        Wake::Tick
+      };
+      let command = async { Wake::Command(self.commands.0.recv().await.ok()) };
+
+      match command.race(tick).await {
+        Wake::Tick => {
+          let message = f();
+          tracing::debug!("sending ticker message - {message:?}");
+
+          if let Err(error) = self.messages.0.send(message).await {
+            tracing::warn!("unable to send tick - {error}");
+            break;
+          }
+
+          // A tick that was late (e.g the runtime was busy) does not cause a burst of ticks after it.
+          next = std::cmp::max(next + self.interval, std::time::Instant::now());
+        }
+        Wake::Command(Some(TickerCommand::SetInterval(interval))) => {
+          tracing::debug!("ticker interval set to {interval:?}");
+          self.interval = interval;
+          next = std::time::Instant::now() + interval;
+        }
+        Wake::Command(Some(TickerCommand::Pause)) => paused = true,
+        Wake::Command(Some(TickerCommand::Resume)) if paused => {
+          paused = false;
+          next = std::time::Instant::now() + self.interval;
+        }
+        Wake::Command(Some(TickerCommand::Resume)) => (),
+        // Nobody is left to tell us what to do (or to receive our ticks).
+        Wake::Command(None) => break,
       }
     }
 
-    Err(io::Error::new(io::ErrorKind::Other, "closing ticker effect channel"))
+    Err(Error::ChannelClosed("closing ticker effect channel".into()))
   }
 }
 
-impl<C, M> crate::eff::Effect for Ticker<C, M> {
+impl<M> crate::eff::Effect for Ticker<M> {
   type Message = M;
-  type Command = C;
-
-  fn detach(&mut self) -> io::Result<(channel::Receiver<Self::Message>, channel::Sender<Self::Command>)> {
-    let cmd_in = self
-      .commands
-      .1
-      .take()
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "already taken"))?;
-
-    let msg_out = self
-      .messages
-      .1
-      .take()
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "already taken"))?;
+  type Command = TickerCommand;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
 
     Ok((msg_out, cmd_in))
   }
@@ -0,0 +1,137 @@
+//! The watcher effect keeps an eye on a single file (our configuration), reading it whenever its
+//! modification time changes. Rather than depending on platform-specific notification apis, the
+//! file is polled; configuration files change rarely, and a few seconds of delay is fine. The file
+//! can also be read on demand (e.g when we receive `SIGHUP`) through a `Reload` command.
+
+use crate::error::{Error, Result};
+use async_std::channel;
+use futures_lite::FutureExt;
+
+/// How often the modification time of the file is checked.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The `WatcherCommand` type defined here refers to the commands that are specific to the watcher
+/// effect.
+#[derive(Debug)]
+pub enum WatcherCommand {
+  /// Reads the file immediately, whether or not it has changed.
+  Reload,
+}
+
+pub trait WatcherMap {
+  type Command;
+  type Message;
+
+  fn translate(&self, original: Self::Command) -> Option<WatcherCommand>;
+
+  /// Defines the type of message that should be used when the contents of the file have been read.
+  /// Contents that do not map into a message (e.g they could not be parsed) are ignored.
+  fn message(&self, contents: String) -> Option<Self::Message>;
+}
+
+pub struct Watcher<C, M> {
+  path: std::path::PathBuf,
+  commands: (channel::Receiver<C>, Option<channel::Sender<C>>),
+  messages: (channel::Sender<M>, Option<channel::Receiver<M>>),
+}
+
+/// Whatever woke our run loop up.
+enum Event<C> {
+  Poll,
+  Command(C),
+}
+
+/// Returns when the file was last modified, if it can be read at all.
+fn modified(path: &std::path::Path) -> Option<std::time::SystemTime> {
+  std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl<C, M> Watcher<C, M> {
+  pub fn new<P>(path: P) -> Self
+  where
+    P: Into<std::path::PathBuf>,
+  {
+    let commands = channel::unbounded();
+    let messages = channel::unbounded();
+
+    Self {
+      path: path.into(),
+      commands: (commands.1, Some(commands.0)),
+      messages: (messages.0, Some(messages.1)),
+    }
+  }
+
+  pub async fn run<T>(self, glue: T) -> Result<()>
+  where
+    T: WatcherMap<Command = C, Message = M>,
+  {
+    tracing::info!("watching '{}' for changes", self.path.display());
+    let mut last_modified = modified(&self.path);
+
+    loop {
+      let poll = async {
+        async_std::task::sleep(POLL_INTERVAL).await;
+        Ok::<Event<C>, channel::RecvError>(Event::Poll)
+      };
+      let command = async { self.commands.0.recv().await.map(Event::Command) };
+
+      let event = command
+        .race(poll)
+        .await
+        .map_err(|error| Error::ChannelClosed(format!("closed watcher channel ({error})")))?;
+
+      match event {
+        Event::Poll => {
+          let current = modified(&self.path);
+
+          if current.is_none() || current == last_modified {
+            continue;
+          }
+
+          last_modified = current;
+        }
+
+        Event::Command(command) => match glue.translate(command) {
+          Some(WatcherCommand::Reload) => last_modified = modified(&self.path),
+          None => {
+            tracing::warn!("unable to map from external watcher command to internal command");
+            continue;
+          }
+        },
+      }
+
+      let contents = match async_std::fs::read_to_string(&self.path).await {
+        Ok(contents) => contents,
+        Err(error) => {
+          tracing::warn!("unable to read '{}' - {error}", self.path.display());
+          continue;
+        }
+      };
+
+      let message = match glue.message(contents) {
+        Some(message) => message,
+        None => continue,
+      };
+
+      tracing::info!("'{}' has been read, notifying application", self.path.display());
+
+      if let Err(error) = self.messages.0.send(message).await {
+        tracing::warn!("unable to send watcher message - {error}");
+        return Err(Error::ChannelClosed("watcher-send failure".into()));
+      }
+    }
+  }
+}
+
+impl<C, M> crate::eff::Effect for Watcher<C, M> {
+  type Message = M;
+  type Command = C;
+
+  fn detach(&mut self) -> crate::eff::UnbindResult<Self::Message, Self::Command> {
+    let cmd_in = self.commands.1.take().ok_or(Error::AlreadyDetached)?;
+
+    let msg_out = self.messages.1.take().ok_or(Error::AlreadyDetached)?;
+
+    Ok((msg_out, cmd_in))
+  }
+}
@@ -0,0 +1,93 @@
+//! The error type shared by the effect runtimes and the application itself. Rather than stuffing
+//! every failure into an `io::Error` with a string payload, each failure is categorized so callers
+//! can branch on _why_ something went wrong.
+
+use std::io;
+
+/// Every failure produced by this library is one of these kinds.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  /// An underlying io failure (filesystem, sockets, etc...).
+  #[error("io failure - {0}")]
+  Io(#[from] io::Error),
+
+  /// One of the channels used to move messages and commands between an effect and the runtime
+  /// (or within an effect itself) has been closed.
+  #[error("closed channel - {0}")]
+  ChannelClosed(String),
+
+  /// The channels of an effect were already taken by a previous call to `detach`.
+  #[error("effect channels already detached")]
+  AlreadyDetached,
+
+  /// A websocket connection was closed or failed while reading/writing.
+  #[error("websocket failure - {0}")]
+  Websocket(String),
+
+  /// The session store could not be reached, or returned something we did not expect.
+  #[error("session store failure - {0}")]
+  SessionStore(String),
+
+  /// Our oauth provider could not be reached, or returned something we did not expect.
+  #[error("oauth failure - {0}")]
+  OAuth(String),
+
+  /// A json web token could not be encoded or decoded.
+  #[error("invalid token - {0}")]
+  Token(#[from] jsonwebtoken::errors::Error),
+
+  /// A serial device could not be found, listed or opened.
+  #[error("serial failure - {0}")]
+  Serial(#[from] serialport::Error),
+
+  /// The connection to the controller was lost while reading from or writing to it.
+  #[error("serial connection lost - {0}")]
+  SerialDisconnected(io::Error),
+
+  /// The controller did not answer a line sent to it in time.
+  #[error("no answer from the controller - {0}")]
+  SerialTimeout(String),
+
+  /// Data received from the controller could not be understood.
+  #[error("unrecognized controller data - {0}")]
+  Parse(String),
+
+  /// Some value could not be serialized or deserialized as json.
+  #[error("serialization failure - {0}")]
+  Serialization(#[from] serde_json::Error),
+
+  /// An address we were configured to listen on (or connect to) could not be resolved or bound.
+  #[error("unavailable address - {0}")]
+  Address(String),
+
+  /// A websocket frame could not be encoded or decoded in the encoding a client asked for.
+  #[error("encoding failure - {0}")]
+  Encoding(String),
+
+  /// The interactive prompt of a console could not be opened or read from.
+  #[error("prompt failure - {0}")]
+  Prompt(#[from] rustyline::error::ReadlineError),
+
+  /// Something asked of us is not available on this platform.
+  #[error("unsupported on this platform - {0}")]
+  Unsupported(String),
+
+  /// Something asked of us depends on configuration that was not provided.
+  #[error("missing configuration - {0}")]
+  Unconfigured(String),
+
+  /// Our log files could not be opened.
+  #[error("logging failure - {0}")]
+  Logging(String),
+
+  /// A notification could not be delivered to one of our sinks.
+  #[error("notification failure - {0}")]
+  Notify(String),
+
+  /// The configuration provided could not be parsed.
+  #[error("invalid configuration - {0}")]
+  Configuration(#[from] toml::de::Error),
+}
+
+/// A convenience alias used throughout the library.
+pub type Result<T> = std::result::Result<T, Error>;
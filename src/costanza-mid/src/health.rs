@@ -0,0 +1,96 @@
+//! This module contains the health of the application as seen from the outside (load balancers,
+//! service managers). It is shared between the application, which reports the state of the serial
+//! connection and the current job, and the http effect, which serves it.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// The state of the serial connection, without any of its details.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SerialHealth {
+  /// We are not connected to a controller.
+  #[default]
+  Disconnected,
+
+  /// We are waiting to hear back from an attempt to connect.
+  Connecting,
+
+  /// We are connected, and not sending a job.
+  Idle,
+
+  /// We are connected and sending a job.
+  Sending,
+}
+
+impl SerialHealth {
+  /// Whether or not we are connected to a controller.
+  pub(crate) fn connected(&self) -> bool {
+    matches!(self, Self::Idle | Self::Sending)
+  }
+}
+
+/// The progress of the job currently being sent.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct JobHealth {
+  /// The amount of lines sent so far.
+  pub(crate) sent: usize,
+
+  /// The amount of lines in the file.
+  pub(crate) total: usize,
+
+  /// When the job started.
+  pub(crate) started_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What the application has most recently reported about itself.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct ApplicationHealth {
+  /// The state of the serial connection.
+  pub(crate) serial: SerialHealth,
+
+  /// The job currently being sent, if any.
+  pub(crate) job: Option<JobHealth>,
+}
+
+/// A cheaply cloneable handle to the most recent health reported by the application.
+#[derive(Clone)]
+pub struct Health {
+  /// When we started.
+  started: std::time::Instant,
+
+  /// The most recent report.
+  report: Arc<Mutex<ApplicationHealth>>,
+}
+
+impl Default for Health {
+  fn default() -> Self {
+    Self {
+      started: std::time::Instant::now(),
+      report: Arc::new(Mutex::new(ApplicationHealth::default())),
+    }
+  }
+}
+
+impl Health {
+  /// Returns how long it has been since we started.
+  pub(crate) fn uptime(&self) -> std::time::Duration {
+    self.started.elapsed()
+  }
+
+  /// Replaces the most recent report.
+  pub(crate) fn report(&self, health: ApplicationHealth) {
+    match self.report.lock() {
+      Ok(mut report) => *report = health,
+      Err(poisoned) => *poisoned.into_inner() = health,
+    }
+  }
+
+  /// Returns the most recent report.
+  pub(crate) fn current(&self) -> ApplicationHealth {
+    match self.report.lock() {
+      Ok(report) => report.clone(),
+      Err(poisoned) => poisoned.into_inner().clone(),
+    }
+  }
+}
@@ -0,0 +1,274 @@
+//! This module contains the record of files (jobs) that have been sent through the serial
+//! connection. It is shared between the application, which records jobs as they finish, and the
+//! http effect, which exports them.
+
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Every version of the format our job records are persisted in.
+pub(crate) const MIGRATIONS: &[crate::migrations::Migration] = &[crate::migrations::Migration {
+  version: 1,
+  description: "initial job record format",
+  apply: verify_records,
+}];
+
+/// The first version of our format; ensures every line is a valid record without changing them.
+fn verify_records(contents: &str) -> Result<String> {
+  for line in contents.lines() {
+    serde_json::from_str::<JobRecord>(line)?;
+  }
+
+  Ok(contents.to_string())
+}
+
+/// How a job ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobOutcome {
+  /// Every line of the file was sent and acknowledged.
+  Completed,
+
+  /// An operator stopped the job (e.g by reconfiguring or closing the serial connection).
+  Cancelled,
+
+  /// The serial connection was lost (or the controller alarmed) while the job was running.
+  Failed,
+}
+
+impl std::fmt::Display for JobOutcome {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Self::Completed => write!(formatter, "completed"),
+      Self::Cancelled => write!(formatter, "cancelled"),
+      Self::Failed => write!(formatter, "failed"),
+    }
+  }
+}
+
+/// A single finished job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+  /// The name of the uploaded file, when provided.
+  pub name: Option<String>,
+
+  /// The material being cut, when provided.
+  pub material: Option<String>,
+
+  pub started_at: chrono::DateTime<chrono::Utc>,
+  pub finished_at: chrono::DateTime<chrono::Utc>,
+
+  /// The amount of lines that were sent to the controller.
+  pub lines_sent: usize,
+
+  pub outcome: JobOutcome,
+}
+
+impl JobRecord {
+  /// The amount of time between the start and end of this job.
+  fn duration(&self) -> chrono::Duration {
+    self.finished_at.signed_duration_since(self.started_at)
+  }
+}
+
+/// The values shared behind a `JobHistory` handle.
+#[derive(Default)]
+struct Inner {
+  /// When provided, every record is appended to this file as a line of json.
+  file: Option<(std::path::PathBuf, std::fs::File)>,
+
+  /// The records, in the order they finished.
+  records: Vec<JobRecord>,
+
+  /// Determines how many records are kept when pruning.
+  retention: Option<crate::retention::RetentionPolicy>,
+}
+
+/// A cheaply cloneable handle to the history of jobs run by the application.
+#[derive(Clone, Default)]
+pub struct JobHistory(Arc<Mutex<Inner>>);
+
+impl JobHistory {
+  /// Loads any records previously written to the file at the provided path, and opens it so new
+  /// records are appended to it.
+  pub(crate) fn open<P>(path: P) -> Result<Self>
+  where
+    P: AsRef<std::path::Path>,
+  {
+    let records = match std::fs::read_to_string(&path) {
+      Ok(contents) => contents
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<serde_json::Result<Vec<JobRecord>>>()?,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => vec![],
+      Err(error) => return Err(error.into()),
+    };
+
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    tracing::info!("loaded {} job records", records.len());
+
+    Ok(Self(Arc::new(Mutex::new(Inner {
+      file: Some((path.as_ref().to_path_buf(), file)),
+      records,
+      retention: None,
+    }))))
+  }
+
+  /// Sets the policy used to determine which records are removed when pruning, if any.
+  pub(crate) fn retain(&self, policy: Option<crate::retention::RetentionPolicy>) {
+    self.with(|inner| inner.retention = policy);
+  }
+
+  /// Removes the records that fall outside of our retention policy, rewriting our file when we
+  /// have one. When `dry_run` is true, nothing is removed. Returns the amount of records that were
+  /// (or would be) removed.
+  pub(crate) fn prune(&self, dry_run: bool) -> usize {
+    let mut removed = 0;
+
+    self.with(|inner| {
+      let policy = match inner.retention.as_ref() {
+        Some(policy) => policy,
+        None => return,
+      };
+
+      let timestamps = inner.records.iter().map(|record| record.finished_at);
+      removed = policy.excess(inner.records.len(), timestamps);
+
+      if dry_run || removed == 0 {
+        return;
+      }
+
+      inner.records.drain(0..removed);
+      tracing::info!("pruned {removed} job records");
+
+      if let Some((path, file)) = inner.file.as_mut() {
+        match rewrite(path, &inner.records) {
+          Ok(rewritten) => *file = rewritten,
+          Err(error) => tracing::warn!("unable to rewrite job records after pruning - {error}"),
+        }
+      }
+    });
+
+    removed
+  }
+
+  /// Adds a finished job to the history. Failing to persist the record is not terminal; it will
+  /// still be available until the application restarts.
+  pub(crate) fn record(&self, record: JobRecord) {
+    self.with(|inner| {
+      if let Some((_, file)) = inner.file.as_mut() {
+        let result = serde_json::to_string(&record)
+          .map_err(crate::error::Error::from)
+          .and_then(|line| writeln!(file, "{line}").map_err(crate::error::Error::from));
+
+        if let Err(error) = result {
+          tracing::warn!("unable to persist job record - {error}");
+        }
+      }
+
+      inner.records.push(record);
+    });
+  }
+
+  /// Returns the most recently finished job.
+  pub(crate) fn last(&self) -> Option<JobRecord> {
+    let mut last = None;
+    self.with(|inner| last = inner.records.last().cloned());
+    last
+  }
+
+  /// Returns up to `limit` of the most recently finished jobs, newest first.
+  pub(crate) fn recent(&self, limit: usize) -> Vec<JobRecord> {
+    let mut recent = vec![];
+    self.with(|inner| recent = inner.records.iter().rev().take(limit).cloned().collect());
+    recent
+  }
+
+  /// Renders every job as a row of comma-separated values.
+  pub(crate) fn jobs_csv(&self) -> String {
+    let mut output = String::from("name,material,started_at,finished_at,duration_seconds,lines_sent,outcome\n");
+
+    self.with(|inner| {
+      for record in &inner.records {
+        let row = [
+          csv_field(record.name.as_deref().unwrap_or_default()),
+          csv_field(record.material.as_deref().unwrap_or_default()),
+          record.started_at.to_rfc3339(),
+          record.finished_at.to_rfc3339(),
+          record.duration().num_seconds().to_string(),
+          record.lines_sent.to_string(),
+          record.outcome.to_string(),
+        ];
+        output.push_str(&row.join(","));
+        output.push('\n');
+      }
+    });
+
+    output
+  }
+
+  /// Renders the amount of jobs and machine-hours for every day a job was started on as rows of
+  /// comma-separated values.
+  pub(crate) fn stats_csv(&self) -> String {
+    let mut output = String::from("date,jobs,completed,machine_hours\n");
+    let mut days = std::collections::BTreeMap::new();
+
+    self.with(|inner| {
+      for record in &inner.records {
+        let (jobs, completed, seconds) = days.entry(record.started_at.date_naive()).or_insert((0, 0, 0));
+        *jobs += 1;
+        *seconds += record.duration().num_seconds().max(0);
+
+        if record.outcome == JobOutcome::Completed {
+          *completed += 1;
+        }
+      }
+    });
+
+    for (day, (jobs, completed, seconds)) in days {
+      let hours = seconds as f64 / 3600.0;
+      output.push_str(&format!("{day},{jobs},{completed},{hours:.2}\n"));
+    }
+
+    output
+  }
+
+  /// Runs the closure against our locked history.
+  fn with<F>(&self, apply: F)
+  where
+    F: FnOnce(&mut Inner),
+  {
+    let mut inner = match self.0.lock() {
+      Ok(inner) => inner,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    apply(&mut inner)
+  }
+}
+
+/// Replaces the contents of the file at the provided path with the records, returning the file
+/// re-opened for appending.
+fn rewrite(path: &std::path::Path, records: &[JobRecord]) -> Result<std::fs::File> {
+  let staging = path.with_extension("tmp");
+  let mut contents = String::new();
+
+  for record in records {
+    contents.push_str(&serde_json::to_string(record)?);
+    contents.push('\n');
+  }
+
+  std::fs::write(&staging, contents)?;
+  std::fs::rename(&staging, path)?;
+  Ok(std::fs::OpenOptions::new().append(true).open(path)?)
+}
+
+/// Quotes a value if it contains anything that would otherwise break the row apart.
+fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) {
+    return format!("\"{}\"", value.replace('"', "\"\""));
+  }
+
+  value.to_string()
+}
@@ -12,4 +12,77 @@ mod effects;
 
 mod app;
 
-pub use app::{run, Configuration};
+/// The error type shared across the effect runtimes and the application.
+mod error;
+
+/// The user-facing error codes shared with clients.
+mod catalog;
+
+/// The requests clients send over the websocket, and the responses they are sent.
+pub mod protocol;
+
+/// Instrumentation collected by the effect runtime.
+mod metrics;
+
+/// The health of the application, reported to load balancers and service managers.
+mod health;
+
+/// The history of jobs run by the application.
+mod jobs;
+
+/// Policies for pruning the data we accumulate.
+mod retention;
+
+/// Versioned migrations of the data we persist.
+mod migrations;
+
+/// Named sequences of lines operators can run.
+mod macros;
+
+/// Debug bundles attached to bug reports.
+mod diagnostics;
+
+/// Checks over a parsed configuration.
+mod validation;
+
+/// Environment variables layered over the configuration file.
+mod overrides;
+
+/// Where (and how) our logs are written.
+mod logging;
+
+/// The state restored when we start again after stopping mid-job.
+mod snapshot;
+
+/// Interactive terminal sessions attached to a running server.
+#[cfg(unix)]
+mod attach;
+
+/// Notifying systemd of our startup, shutdown and liveness.
+#[cfg(unix)]
+mod systemd;
+
+/// Listing serial devices, and sending lines to one without running the server.
+mod ports;
+
+/// A websocket client for a running server.
+pub mod client;
+
+/// A pseudo-terminal (or tcp) stand-in for a grbl controller.
+mod simulator;
+
+pub use app::{bundle_debug, check_migrations, logging, replay, run, terminal_socket, validate, Configuration};
+pub use app::{self_test, SelfTestReport};
+#[cfg(unix)]
+pub use attach::attach;
+pub use client::Client;
+pub use diagnostics::LogBuffer;
+pub use error::{Error, Result};
+pub use logging::{LogFormat, LoggingConfiguration};
+pub use overrides::load as load_configuration;
+pub use ports::{list_ports, send_lines, Port};
+pub use simulator::Simulator;
+pub use validation::{Issue, Severity, Validation};
+
+#[cfg(feature = "fixtures")]
+pub use app::{BenchFixture, ParsedStatus, ParserFixture};
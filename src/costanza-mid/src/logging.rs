@@ -0,0 +1,147 @@
+//! This module contains the configuration of our logs. Headless deployments (e.g a single board
+//! computer next to the machine) tend to ship their logs somewhere else, which is much easier when
+//! they are written as json and to a file that does not grow forever.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// How each log line is written.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+  /// The default, human-readable format.
+  #[default]
+  Human,
+
+  /// A single json object per line.
+  Json,
+}
+
+/// How often a new log file is started.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+  Minutely,
+  Hourly,
+  #[default]
+  Daily,
+  Never,
+}
+
+/// The configuration of our logs.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct LoggingConfiguration {
+  /// How each log line is written, both to our output and our file.
+  #[serde(default)]
+  format: LogFormat,
+
+  /// When provided, logs are also written to files starting with this path (e.g
+  /// `/var/log/costanza/costanza.log` produces `costanza.log.2024-01-01`).
+  #[serde(default)]
+  file: Option<String>,
+
+  /// How often a new log file is started.
+  #[serde(default)]
+  rotation: LogRotation,
+
+  /// The amount of log files kept around; older files are removed. When not provided, every file
+  /// is kept.
+  #[serde(default)]
+  max_files: Option<usize>,
+
+  /// The level logged by default (e.g `info`). The `RUST_LOG` environment variable takes
+  /// precedence.
+  #[serde(default)]
+  level: Option<String>,
+
+  /// Levels for specific modules, e.g `{ "costanza::effects::http" = "debug" }`.
+  #[serde(default)]
+  modules: std::collections::BTreeMap<String, String>,
+}
+
+impl LoggingConfiguration {
+  /// Returns how each log line is written.
+  pub fn format(&self) -> LogFormat {
+    self.format
+  }
+
+  /// Returns the directives of our filter, starting with the default level.
+  fn directives(&self) -> impl Iterator<Item = String> + '_ {
+    let modules = self.modules.iter().map(|(module, level)| format!("{module}={level}"));
+    self.level.iter().cloned().chain(modules)
+  }
+
+  /// Builds the filter applied to every log line. Directives from `RUST_LOG` are applied last, so
+  /// they take precedence over ours.
+  pub fn filter(&self) -> tracing_subscriber::EnvFilter {
+    let from_env = std::env::var(tracing_subscriber::EnvFilter::DEFAULT_ENV).unwrap_or_default();
+    let directives = self.directives().chain(from_env.split(',').map(String::from));
+
+    directives
+      .filter(|directive| !directive.is_empty())
+      .filter_map(|directive| directive.parse::<tracing_subscriber::filter::Directive>().ok())
+      .fold(tracing_subscriber::EnvFilter::default(), |filter, directive| {
+        filter.add_directive(directive)
+      })
+  }
+
+  /// Opens the appender our log files are written through, if we have been given a file.
+  pub fn appender(&self) -> Result<Option<tracing_appender::rolling::RollingFileAppender>> {
+    let path = match self.file.as_ref() {
+      Some(path) => std::path::Path::new(path),
+      None => return Ok(None),
+    };
+
+    let directory = path
+      .parent()
+      .filter(|parent| !parent.as_os_str().is_empty())
+      .unwrap_or_else(|| std::path::Path::new("."));
+    let prefix = path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .ok_or_else(|| Error::Logging(format!("'{}' is not a file", path.display())))?;
+
+    let rotation = match self.rotation {
+      LogRotation::Minutely => tracing_appender::rolling::Rotation::MINUTELY,
+      LogRotation::Hourly => tracing_appender::rolling::Rotation::HOURLY,
+      LogRotation::Daily => tracing_appender::rolling::Rotation::DAILY,
+      LogRotation::Never => tracing_appender::rolling::Rotation::NEVER,
+    };
+
+    let mut builder = tracing_appender::rolling::RollingFileAppender::builder()
+      .rotation(rotation)
+      .filename_prefix(prefix);
+
+    if let Some(max_files) = self.max_files {
+      builder = builder.max_log_files(max_files);
+    }
+
+    builder
+      .build(directory)
+      .map(Some)
+      .map_err(|error| Error::Logging(format!("unable to open '{}' - {error}", path.display())))
+  }
+
+  /// Records an error for every level or module directive we could not parse.
+  pub(crate) fn validate(&self, validation: &mut crate::validation::Validation) {
+    for directive in self.directives() {
+      if let Err(error) = directive.parse::<tracing_subscriber::filter::Directive>() {
+        validation.error("logging", format!("'{directive}' is not a valid level ({error})"));
+      }
+    }
+
+    if self.max_files == Some(0) {
+      validation.error("logging.max_files", "must be greater than zero");
+    }
+
+    let parent = self
+      .file
+      .as_ref()
+      .and_then(|file| std::path::Path::new(file).parent())
+      .filter(|parent| !parent.as_os_str().is_empty());
+
+    if let Some(parent) = parent.filter(|parent| !parent.is_dir()) {
+      validation.error("logging.file", format!("'{}' is not a directory", parent.display()));
+    }
+  }
+}
@@ -0,0 +1,110 @@
+//! This module contains named sequences of lines (macros, e.g "probe z") that operators can run
+//! through the serial connection the same way uploaded files are sent. Macros are either defined
+//! ahead of time in our configuration, or saved by clients while running.
+
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Every version of the format our saved macros are persisted in.
+pub(crate) const MIGRATIONS: &[crate::migrations::Migration] = &[crate::migrations::Migration {
+  version: 1,
+  description: "initial saved macro format",
+  apply: verify_macros,
+}];
+
+/// The first version of our format; ensures the file is a valid map of macros without changing it.
+fn verify_macros(contents: &str) -> Result<String> {
+  serde_json::from_str::<BTreeMap<String, String>>(contents)?;
+  Ok(contents.to_string())
+}
+
+/// The configuration schema for macros.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct MacroConfiguration {
+  /// When provided, macros saved by clients are persisted to (and loaded from) a json file at this
+  /// path.
+  pub(crate) path: Option<String>,
+
+  /// Macros defined ahead of time; the key is the name and the value the lines to send.
+  #[serde(default)]
+  definitions: BTreeMap<String, String>,
+}
+
+/// Every macro known to the application.
+#[derive(Debug, Default)]
+pub(crate) struct MacroLibrary {
+  /// Where saved macros are persisted, if anywhere.
+  path: Option<std::path::PathBuf>,
+
+  /// The macros from our configuration.
+  defined: BTreeMap<String, String>,
+
+  /// The macros saved by clients. These take precedence over defined macros of the same name.
+  saved: BTreeMap<String, String>,
+}
+
+impl MacroLibrary {
+  /// Creates the library from our configuration, loading any previously saved macros.
+  pub(crate) fn open(config: &MacroConfiguration) -> Result<Self> {
+    let path = config.path.as_ref().map(std::path::PathBuf::from);
+
+    let saved = match path.as_ref().map(std::fs::read_to_string) {
+      Some(Ok(contents)) => serde_json::from_str(&contents)?,
+      Some(Err(error)) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::default(),
+      Some(Err(error)) => return Err(error.into()),
+      None => BTreeMap::default(),
+    };
+
+    tracing::info!(
+      "loaded {} defined, {} saved macros",
+      config.definitions.len(),
+      saved.len()
+    );
+
+    Ok(Self {
+      path,
+      defined: config.definitions.clone(),
+      saved,
+    })
+  }
+
+  /// Returns the lines of the macro with the provided name.
+  pub(crate) fn get(&self, name: &str) -> Option<&str> {
+    self
+      .saved
+      .get(name)
+      .or_else(|| self.defined.get(name))
+      .map(String::as_str)
+  }
+
+  /// Returns every macro by name, with saved macros replacing defined ones of the same name.
+  pub(crate) fn all(&self) -> BTreeMap<&str, &str> {
+    self
+      .defined
+      .iter()
+      .chain(self.saved.iter())
+      .map(|(name, content)| (name.as_str(), content.as_str()))
+      .collect()
+  }
+
+  /// Saves a macro, replacing any other with the same name. Failing to persist the macro is not
+  /// terminal; it will still be available until the application restarts.
+  pub(crate) fn save(&mut self, name: String, content: String) {
+    self.saved.insert(name, content);
+
+    if let Some(path) = self.path.as_ref() {
+      if let Err(error) = persist(path, &self.saved) {
+        tracing::warn!("unable to persist macros - {error}");
+      }
+    }
+  }
+}
+
+/// Replaces the contents of the file at the provided path with the saved macros.
+fn persist(path: &std::path::Path, saved: &BTreeMap<String, String>) -> Result<()> {
+  let staging = path.with_extension("tmp");
+  std::fs::write(&staging, serde_json::to_string_pretty(saved)?)?;
+  std::fs::rename(&staging, path)?;
+  Ok(())
+}
@@ -0,0 +1,258 @@
+//! This module contains the instrumentation collected by the effect runtime while it is running,
+//! and the ability to render it in the prometheus text exposition format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// The upper bounds (in seconds) of the buckets used for our update latency histogram.
+const LATENCY_BUCKETS: [f64; 9] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+/// The amount of disconnected websocket clients whose traffic we hold on to.
+const MAX_DISCONNECTED_CLIENTS: usize = 20;
+
+/// The counters associated with a single source of messages (an effect or a subscription).
+#[derive(Default)]
+struct Source {
+  /// The total amount of messages received from this source.
+  messages: u64,
+
+  /// The amount of messages waiting in this source's channel the last time we looked.
+  message_depth: usize,
+
+  /// The amount of commands waiting to be picked up by this source, if it accepts commands.
+  command_depth: Option<usize>,
+}
+
+/// Sends to a single websocket client that take longer than this are logged as they happen.
+const SLOW_SEND_THRESHOLD: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// The traffic sent to a single websocket client.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub(crate) struct ClientTraffic {
+  /// The id of the client.
+  pub(crate) id: String,
+
+  /// Whether or not the client is still connected.
+  pub(crate) connected: bool,
+
+  /// The total amount of bytes sent to this client.
+  pub(crate) bytes_sent: u64,
+
+  /// The total amount of frames sent to this client.
+  pub(crate) frames_sent: u64,
+
+  /// The total time spent waiting on this client's socket while sending, in milliseconds.
+  pub(crate) blocked_ms: u128,
+
+  /// The longest time spent sending a single frame to this client, in milliseconds.
+  pub(crate) slowest_send_ms: u128,
+}
+
+/// The values shared behind a `Metrics` handle.
+#[derive(Default)]
+struct Inner {
+  sources: BTreeMap<String, Source>,
+  clients: BTreeMap<String, ClientTraffic>,
+  publish_failures: u64,
+  latency_buckets: [u64; LATENCY_BUCKETS.len()],
+  latency_sum: f64,
+  latency_count: u64,
+}
+
+/// A cheaply cloneable handle to the metrics of a running effect runtime. The runtime is the only
+/// writer; anything else (e.g the http effect) is expected to only render it.
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Mutex<Inner>>);
+
+impl Metrics {
+  /// Records a message having been received from the named source.
+  pub(crate) fn received(&self, source: &str) {
+    self.with(|inner| inner.sources.entry(source.to_string()).or_default().messages += 1);
+  }
+
+  /// Records the current depth of the channels associated with the named source.
+  pub(crate) fn depth(&self, source: &str, messages: usize, commands: Option<usize>) {
+    self.with(|inner| {
+      let source = inner.sources.entry(source.to_string()).or_default();
+      source.message_depth = messages;
+      source.command_depth = commands;
+    });
+  }
+
+  /// Records a failure to send a command into an effect.
+  pub(crate) fn publish_failed(&self) {
+    self.with(|inner| inner.publish_failures += 1);
+  }
+
+  /// Records a frame having been sent to a websocket client, along with how long we were blocked on
+  /// its socket while sending.
+  pub(crate) fn client_sent(&self, id: &str, bytes: usize, blocked: std::time::Duration) {
+    if blocked >= SLOW_SEND_THRESHOLD {
+      tracing::warn!(
+        "slow websocket client '{id}' took {}ms to receive {bytes} bytes",
+        blocked.as_millis()
+      );
+    }
+
+    self.with(|inner| {
+      let client = inner.clients.entry(id.to_string()).or_insert_with(|| ClientTraffic {
+        id: id.to_string(),
+        connected: true,
+        ..ClientTraffic::default()
+      });
+
+      client.bytes_sent += bytes as u64;
+      client.frames_sent += 1;
+      client.blocked_ms += blocked.as_millis();
+      client.slowest_send_ms = client.slowest_send_ms.max(blocked.as_millis());
+    });
+  }
+
+  /// Records a websocket client having disconnected. Only the most recently disconnected clients
+  /// are kept around, so the ones that just dropped off can still be looked into.
+  pub(crate) fn client_disconnected(&self, id: &str) {
+    self.with(|inner| {
+      if let Some(client) = inner.clients.get_mut(id) {
+        client.connected = false;
+      }
+
+      let disconnected = inner.clients.values().filter(|client| !client.connected).count();
+      if disconnected > MAX_DISCONNECTED_CLIENTS {
+        let mut stale = inner
+          .clients
+          .values()
+          .filter(|client| !client.connected)
+          .map(|client| (client.blocked_ms, client.id.clone()))
+          .collect::<Vec<(u128, String)>>();
+        stale.sort();
+
+        for (_, id) in stale.into_iter().take(disconnected - MAX_DISCONNECTED_CLIENTS) {
+          inner.clients.remove(&id);
+        }
+      }
+    });
+  }
+
+  /// Returns the clients we have spent the most time blocked on, worst first.
+  pub(crate) fn slowest_clients(&self, limit: usize) -> Vec<ClientTraffic> {
+    self.with(|inner| {
+      let mut clients = inner.clients.values().cloned().collect::<Vec<ClientTraffic>>();
+      clients.sort_by(|a, b| b.blocked_ms.cmp(&a.blocked_ms).then(b.bytes_sent.cmp(&a.bytes_sent)));
+      clients.truncate(limit);
+      clients
+    })
+  }
+
+  /// Records the amount of time it took the application to process a single update.
+  pub(crate) fn observe_update(&self, elapsed: std::time::Duration) {
+    let seconds = elapsed.as_secs_f64();
+
+    self.with(|inner| {
+      for (bucket, bound) in inner.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+        if seconds <= bound {
+          *bucket += 1;
+        }
+      }
+
+      inner.latency_sum += seconds;
+      inner.latency_count += 1;
+    });
+  }
+
+  /// Renders the current values in the prometheus text exposition format.
+  pub(crate) fn render(&self) -> String {
+    let mut output = String::new();
+
+    self.with(|inner| {
+      // Writing into a `String` cannot fail, so the results here are safe to ignore.
+      let _ = writeln!(
+        output,
+        "# HELP costanza_messages_total Messages received by the runtime."
+      );
+      let _ = writeln!(output, "# TYPE costanza_messages_total counter");
+      for (name, source) in &inner.sources {
+        let _ = writeln!(
+          output,
+          "costanza_messages_total{{source=\"{name}\"}} {}",
+          source.messages
+        );
+      }
+
+      let _ = writeln!(
+        output,
+        "# HELP costanza_channel_depth Items waiting in runtime channels."
+      );
+      let _ = writeln!(output, "# TYPE costanza_channel_depth gauge");
+      for (name, source) in &inner.sources {
+        let _ = writeln!(
+          output,
+          "costanza_channel_depth{{source=\"{name}\",direction=\"message\"}} {}",
+          source.message_depth
+        );
+
+        if let Some(depth) = source.command_depth {
+          let _ = writeln!(
+            output,
+            "costanza_channel_depth{{source=\"{name}\",direction=\"command\"}} {depth}"
+          );
+        }
+      }
+
+      let _ = writeln!(
+        output,
+        "# HELP costanza_publish_failures_total Commands that could not be sent."
+      );
+      let _ = writeln!(output, "# TYPE costanza_publish_failures_total counter");
+      let _ = writeln!(output, "costanza_publish_failures_total {}", inner.publish_failures);
+
+      let _ = writeln!(
+        output,
+        "# HELP costanza_update_duration_seconds Time spent in application updates."
+      );
+      let _ = writeln!(output, "# TYPE costanza_update_duration_seconds histogram");
+      for (count, bound) in inner.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+        let _ = writeln!(
+          output,
+          "costanza_update_duration_seconds_bucket{{le=\"{bound}\"}} {count}"
+        );
+      }
+      let _ = writeln!(
+        output,
+        "costanza_update_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        inner.latency_count
+      );
+      let _ = writeln!(output, "costanza_update_duration_seconds_sum {}", inner.latency_sum);
+      let _ = writeln!(output, "costanza_update_duration_seconds_count {}", inner.latency_count);
+
+      let _ = writeln!(
+        output,
+        "# HELP costanza_client_sent_bytes_total Bytes sent to connected websocket clients."
+      );
+      let _ = writeln!(output, "# TYPE costanza_client_sent_bytes_total counter");
+      for client in inner.clients.values().filter(|client| client.connected) {
+        let _ = writeln!(
+          output,
+          "costanza_client_sent_bytes_total{{client=\"{}\"}} {}",
+          client.id, client.bytes_sent
+        );
+      }
+    });
+
+    output
+  }
+
+  /// Runs the closure against our locked metrics. A poisoned lock only means a writer panicked
+  /// part way through an update, which is not a reason to stop recording.
+  fn with<F, T>(&self, apply: F) -> T
+  where
+    F: FnOnce(&mut Inner) -> T,
+  {
+    let mut inner = match self.0.lock() {
+      Ok(inner) => inner,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    apply(&mut inner)
+  }
+}
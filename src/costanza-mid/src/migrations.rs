@@ -0,0 +1,133 @@
+//! This module contains a small, versioned migration runner for the data we persist to disk. Each
+//! persisted file has a sibling `.version` file holding the version of the format it was last
+//! written in; at startup any newer migrations are applied in order, after taking a backup.
+
+use crate::error::{Error, Result};
+
+/// A single step in the evolution of a persisted format.
+pub(crate) struct Migration {
+  /// The version of the format this migration produces.
+  pub(crate) version: u32,
+
+  /// A short, human-readable summary of what changed.
+  pub(crate) description: &'static str,
+
+  /// Receives the contents of the file in the previous version's format and returns them in this
+  /// version's format.
+  pub(crate) apply: fn(&str) -> Result<String>,
+}
+
+/// A migration that has not been applied to a store yet.
+#[derive(Debug)]
+pub struct PendingMigration {
+  store: &'static str,
+  version: u32,
+  description: &'static str,
+}
+
+impl std::fmt::Display for PendingMigration {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(formatter, "{} v{}: {}", self.store, self.version, self.description)
+  }
+}
+
+/// A persisted file, along with every migration its format has gone through (ordered by version).
+pub(crate) struct Store<'a> {
+  pub(crate) name: &'static str,
+  pub(crate) path: &'a std::path::Path,
+  pub(crate) migrations: &'static [Migration],
+}
+
+impl<'a> Store<'a> {
+  /// Returns the path of the file holding the version of our data file.
+  fn version_path(&self) -> std::path::PathBuf {
+    let mut path = self.path.as_os_str().to_owned();
+    path.push(".version");
+    path.into()
+  }
+
+  /// Returns the latest version known to us.
+  fn latest(&self) -> u32 {
+    self.migrations.last().map_or(0, |migration| migration.version)
+  }
+
+  /// Returns the version our data file was last written in. Data files that existed before they
+  /// were versioned are considered version zero.
+  fn current(&self) -> Result<u32> {
+    match std::fs::read_to_string(self.version_path()) {
+      Ok(contents) => contents
+        .trim()
+        .parse()
+        .map_err(|error| Error::Parse(format!("invalid {} version - {error}", self.name))),
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(0),
+      Err(error) => Err(error.into()),
+    }
+  }
+
+  /// Returns the migrations that would be applied, after verifying each of them against our data
+  /// without writing anything.
+  pub(crate) fn check(&self) -> Result<Vec<PendingMigration>> {
+    let contents = match std::fs::read_to_string(self.path) {
+      Ok(contents) => contents,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+      Err(error) => return Err(error.into()),
+    };
+
+    let current = self.current()?;
+    let mut pending = vec![];
+
+    self
+      .migrations
+      .iter()
+      .filter(|migration| migration.version > current)
+      .try_fold(contents, |contents, migration| {
+        pending.push(PendingMigration {
+          store: self.name,
+          version: migration.version,
+          description: migration.description,
+        });
+        (migration.apply)(&contents)
+      })?;
+
+    Ok(pending)
+  }
+
+  /// Applies any migrations newer than the version our data file was last written in, backing the
+  /// file up first.
+  pub(crate) fn migrate(&self) -> Result<()> {
+    let contents = match std::fs::read_to_string(self.path) {
+      Ok(contents) => contents,
+      // Nothing has been written yet, anything that is will be in the latest format.
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+        return std::fs::write(self.version_path(), self.latest().to_string()).map_err(Error::from);
+      }
+      Err(error) => return Err(error.into()),
+    };
+
+    let current = self.current()?;
+
+    if current >= self.latest() {
+      return Ok(());
+    }
+
+    let mut backup = self.path.as_os_str().to_owned();
+    backup.push(format!(".v{current}.bak"));
+    std::fs::copy(self.path, &backup)?;
+    tracing::info!("backed up {} to {backup:?} before migrating", self.name);
+
+    let mut pending = self.migrations.iter().filter(|migration| migration.version > current);
+    let migrated = pending.try_fold(contents, |contents, migration| {
+      tracing::info!(
+        "migrating {} to v{} ({})",
+        self.name,
+        migration.version,
+        migration.description
+      );
+      (migration.apply)(&contents)
+    })?;
+
+    std::fs::write(self.path, migrated)?;
+    std::fs::write(self.version_path(), self.latest().to_string())?;
+    Ok(())
+  }
+}
@@ -0,0 +1,86 @@
+//! Layered configuration loading. The toml file is read first, and any field in it can then be
+//! overridden by an environment variable, which keeps secrets (e.g `jwt_secret`) out of the files
+//! of containerized deployments.
+//!
+//! Variables are named after the dotted path of the field they override, uppercased, with `__`
+//! between each table: `COSTANZA_HTTP__SESSION__JWT_SECRET` overrides `http.session.jwt_secret`.
+
+use crate::error::Result;
+
+/// Every variable considered an override starts with this.
+const PREFIX: &str = "COSTANZA_";
+
+/// What separates the tables in the name of a variable.
+const SEPARATOR: &str = "__";
+
+/// Parses the contents of a configuration file, applying the overrides found in our environment.
+pub fn load<T>(contents: &str) -> Result<T>
+where
+  T: serde::de::DeserializeOwned,
+{
+  let mut value = toml::from_str::<toml::Value>(contents)?;
+  apply(&mut value, std::env::vars());
+  Ok(value.try_into()?)
+}
+
+/// Applies every override among the variables provided onto our parsed configuration. Missing
+/// tables are created along the way.
+fn apply<I>(value: &mut toml::Value, variables: I)
+where
+  I: Iterator<Item = (String, String)>,
+{
+  for (name, raw) in variables {
+    let path = match name.strip_prefix(PREFIX) {
+      Some(path) if !path.is_empty() => path.to_lowercase(),
+      _ => continue,
+    };
+
+    let mut keys = path.split(SEPARATOR).collect::<Vec<&str>>();
+    let last = match keys.pop() {
+      Some(last) if !last.is_empty() => last,
+      _ => continue,
+    };
+
+    let table = match value.as_table_mut().and_then(|root| table_at(root, &keys)) {
+      Some(table) => table,
+      None => {
+        // Overriding a field inside of something that is not a table is most likely a typo in the
+        // name of the variable; our configuration is left alone.
+        eprintln!("ignoring '{name}', '{}' is not a table", keys.join("."));
+        continue;
+      }
+    };
+
+    let replacement = parse(&raw, table.get(last));
+    table.insert(last.to_string(), replacement);
+  }
+}
+
+/// Returns the table found by following the keys provided, creating any that are missing. Returns
+/// `None` if any of the keys belong to something other than a table.
+fn table_at<'a>(root: &'a mut toml::value::Table, keys: &[&str]) -> Option<&'a mut toml::value::Table> {
+  let mut table = root;
+
+  for key in keys {
+    table = table
+      .entry(key.to_string())
+      .or_insert_with(|| toml::Value::Table(Default::default()))
+      .as_table_mut()?;
+  }
+
+  Some(table)
+}
+
+/// Parses the value of a variable. When the field being overridden is already a string, the value
+/// is used as-is; otherwise it is parsed as toml (e.g numbers, booleans and arrays), falling back to
+/// a string.
+fn parse(raw: &str, existing: Option<&toml::Value>) -> toml::Value {
+  if let Some(toml::Value::String(_)) = existing {
+    return toml::Value::String(raw.to_string());
+  }
+
+  toml::from_str::<toml::value::Table>(&format!("value = {raw}"))
+    .ok()
+    .and_then(|mut table| table.remove("value"))
+    .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
@@ -0,0 +1,137 @@
+//! Talking to serial devices directly, without running the server: listing the devices available,
+//! and sending a few lines to one of them (e.g to check that a controller answers before pointing
+//! our configuration at it).
+
+use crate::error::{Error, Result};
+use std::io::{Read, Write};
+
+/// How long we wait for a controller that resets when its port is opened (as most arduino based
+/// ones do) to introduce itself before sending it anything.
+const WELCOME_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How long each read waits on the port itself.
+const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// A serial device found on this machine.
+#[derive(Debug)]
+pub struct Port {
+  /// The path (or name, on windows) the device is opened with.
+  pub name: String,
+
+  /// What kind of device it is, including the usb product when known.
+  pub description: String,
+}
+
+impl std::fmt::Display for Port {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(formatter, "{} ({})", self.name, self.description)
+  }
+}
+
+/// Returns every serial device found on this machine.
+pub fn list_ports() -> Result<Vec<Port>> {
+  let ports = serialport::available_ports()?;
+
+  Ok(
+    ports
+      .into_iter()
+      .map(|port| Port {
+        name: port.port_name,
+        description: match port.port_type {
+          serialport::SerialPortType::UsbPort(usb) => match usb.product {
+            Some(product) => format!("usb {:04x}:{:04x}, {product}", usb.vid, usb.pid),
+            None => format!("usb {:04x}:{:04x}", usb.vid, usb.pid),
+          },
+          serialport::SerialPortType::PciPort => "pci".into(),
+          serialport::SerialPortType::BluetoothPort => "bluetooth".into(),
+          serialport::SerialPortType::Unknown => "unknown".into(),
+        },
+      })
+      .collect(),
+  )
+}
+
+/// Sends lines to a serial device one at a time, waiting on the `ok` (or `error`) answering each
+/// before sending the next. Every line the device sends back is passed to `output` as it arrives.
+/// Returns whether or not every line was answered with an `ok`.
+pub fn send_lines<F>(
+  device: &str,
+  baud: u32,
+  lines: &[String],
+  timeout: std::time::Duration,
+  mut output: F,
+) -> Result<bool>
+where
+  F: FnMut(&str),
+{
+  let mut port = serialport::new(device, baud).timeout(READ_TIMEOUT).open()?;
+  let mut reader = Reader::default();
+
+  // Controllers that do not reset when opened have nothing to say; we carry on without hearing it.
+  reader.until(port.as_mut(), WELCOME_TIMEOUT, &mut output, |line| line.starts_with("Grbl"))?;
+
+  let mut accepted = true;
+
+  for line in lines {
+    writeln!(port, "{}", line.trim()).map_err(Error::SerialDisconnected)?;
+
+    let answer = reader.until(port.as_mut(), timeout, &mut output, |line| {
+      line == "ok" || line.starts_with("error:")
+    })?;
+
+    match answer {
+      Some(answer) => accepted &= answer == "ok",
+      None => {
+        let message = format!("no answer to '{}' after {timeout:?}", line.trim());
+        return Err(Error::SerialTimeout(message));
+      }
+    }
+  }
+
+  Ok(accepted)
+}
+
+/// Assembles the lines read from a port.
+#[derive(Default)]
+struct Reader {
+  buffer: Vec<u8>,
+}
+
+impl Reader {
+  /// Reads lines, passing each to `output`, until one matches `done` (which is returned) or nothing
+  /// has matched for `timeout`.
+  fn until<F, D>(
+    &mut self,
+    port: &mut dyn serialport::SerialPort,
+    timeout: std::time::Duration,
+    output: &mut F,
+    done: D,
+  ) -> Result<Option<String>>
+  where
+    F: FnMut(&str),
+    D: Fn(&str) -> bool,
+  {
+    let started = std::time::Instant::now();
+    let mut chunk = [0u8; 1024];
+
+    while started.elapsed() < timeout {
+      while let Some((line, taken)) = crate::effects::serial::frame(&self.buffer) {
+        let line = String::from_utf8_lossy(line).into_owned();
+        self.buffer.drain(..taken);
+        output(&line);
+
+        if done(&line) {
+          return Ok(Some(line));
+        }
+      }
+
+      match port.read(&mut chunk) {
+        Ok(amount) => self.buffer.extend_from_slice(&chunk[..amount]),
+        Err(error) if error.kind() == std::io::ErrorKind::TimedOut => (),
+        Err(error) => return Err(Error::SerialDisconnected(error)),
+      }
+    }
+
+    Ok(None)
+  }
+}
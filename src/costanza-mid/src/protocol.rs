@@ -0,0 +1,364 @@
+//! The schema of the frames exchanged with clients over the websocket, shared with clients written
+//! in rust (e.g the embedded ui) so that both sides use one definition of it. Clients send a
+//! `ClientMessage` for every request, and are sent a `ClientResponse` answering it.
+//!
+//! The state frames sent to clients are serialized from views borrowing the state of the
+//! application, and are not included here.
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::app::firmware::FirmwareFlavor;
+pub use crate::app::grbl::transform::Transform;
+pub use crate::effects::http::UploadMetadata;
+pub use crate::effects::serial::{AxisLimits, AxisMapping, SerialConfiguration, TravelLimits};
+
+/// The version of the websocket protocol spoken by this application. This should be bumped any
+/// time the schema of client requests or responses changes in a way clients need to know about.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// This type represents the schema of data that can be sent from individual websocket
+/// connections. The application receives that data as raw `String` data and will attempt to
+/// parse it here as json.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct ClientMessage {
+  // Every request from the client should have a unqiue identifier so the response that comes
+  // through the websocket can be re-associated on the client with the request.
+  pub tick: u32,
+
+  pub request: ClientMessageRequest,
+}
+
+/// Every request a client may send, tagged by its `kind`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClientMessageRequest {
+  /// Makes the requesting client the operator, demoting whoever was the operator before.
+  TakeOver,
+
+  /// Makes the requesting client the operator if nobody else is.
+  AcquireControl,
+
+  /// Releases the control lock if the requesting client holds it.
+  ReleaseControl,
+  RawSerial(RawSerialRequest),
+  Configuration(SerialConfiguration),
+  CloseSerial,
+  RetrySerial,
+  ResyncState(ResyncRequest),
+  ChatMessage(ChatRequest),
+  MarkEvent(MarkRequest),
+  FeedHold,
+  CycleStart,
+  HistoryPage(HistoryPageRequest),
+  SaveMacro(SaveMacroRequest),
+  RunMacro(RunMacroRequest),
+  StepMode(StepModeRequest),
+
+  /// Sends the next line of a job in step mode.
+  Step,
+  RunFromLine(RunFromLineRequest),
+  Jog(JogRequest),
+  StartJob(StartJobRequest),
+  BeginUpload(BeginUploadRequest),
+  UploadChunk(UploadChunkRequest),
+
+  /// Starts sending the file uploaded with the preceding `UploadChunk` requests.
+  FinishUpload,
+  Override(OverrideRequest),
+  Probe(ProbeRequest),
+
+  /// Resumes a job that stopped at a line waiting on the operator (e.g a tool change).
+  ResumeJob,
+  ResumeFromLine(ResumeFromLineRequest),
+
+  /// Stops the job being sent, resetting the controller so nothing it has buffered is run.
+  CancelJob,
+
+  /// Replaces what the requesting client wants added to its history from now on.
+  SetPreferences(ClientPreferences),
+  ResumeSession(ResumeSessionRequest),
+
+  /// Asks the controller for its settings (`$$`), which are sent to every client in a `settings`
+  /// frame once it has listed them.
+  ReadSettings,
+  WriteSetting(WriteSettingRequest),
+}
+
+/// Sends a line to the controller as it is.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RawSerialRequest {
+  pub value: String,
+
+  /// Sends the line in between the lines of a job being sent, rather than once it is done. This is
+  /// only accepted from admins (e.g adjusting a setting grbl accepts mid-job).
+  #[serde(default, rename = "override", skip_serializing_if = "std::ops::Not::not")]
+  pub force: bool,
+}
+
+/// Changes a single setting of the controller (e.g `$110=5000`).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct WriteSettingRequest {
+  pub id: u16,
+  pub value: f32,
+}
+
+/// Clients may ask for a complete snapshot of their state at any point; this is useful when they
+/// suspect they have missed updates.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ResyncRequest {
+  /// The maximum amount of the most recent history entries to include in the snapshot. When not
+  /// provided, the entire history is sent.
+  pub history: Option<usize>,
+}
+
+/// What a client wants added to its history. Clients that are only watching the machine (e.g a
+/// monitor next to it) have little use for the status reports received every few hundred
+/// milliseconds while polling, or for the `ok` answering every line of a job.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientPreferences {
+  /// Whether or not status reports (e.g `<Idle|MPos:0.000,0.000,0.000>`) are added.
+  #[serde(default = "default_preference")]
+  pub statuses: bool,
+
+  /// Whether or not everything the controller sends is added. When off, an `ok` is only added when
+  /// it answers a raw line sent by this client.
+  #[serde(default = "default_preference")]
+  pub verbose: bool,
+}
+
+/// Used by serde when a preference is not provided; clients are sent everything unless they ask not
+/// to be.
+fn default_preference() -> bool {
+  true
+}
+
+impl Default for ClientPreferences {
+  fn default() -> Self {
+    Self {
+      statuses: true,
+      verbose: true,
+    }
+  }
+}
+
+/// Clients only hold on to the history they have been sent since connecting; older entries can be
+/// requested a page at a time.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct HistoryPageRequest {
+  /// The sequence number of the oldest entry the client has; the page will contain the entries
+  /// that came before it. When not provided, the page ends with the most recent entry.
+  pub before: Option<u64>,
+
+  /// The maximum amount of entries to include in the page.
+  pub limit: usize,
+}
+
+/// Operators may leave short notes for each other (e.g "changing the bit, don't start") that are
+/// relayed to every connected client, attributed to the client that sent them.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatRequest {
+  pub content: String,
+}
+
+/// Operators may bookmark moments during a job (e.g "chatter here") so they can be reviewed against
+/// the gcode afterwards.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MarkRequest {
+  pub label: String,
+}
+
+/// Operators may save a named sequence of lines (e.g "probe z") to run later, replacing any macro
+/// with the same name.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SaveMacroRequest {
+  pub name: String,
+  pub content: String,
+}
+
+/// Runs a previously saved (or configured) macro, sending its lines like an uploaded file.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RunMacroRequest {
+  pub name: String,
+}
+
+/// Turns step mode on (or off) for the job being sent. While on, the lines of the job are only sent
+/// one at a time, as the operator asks for them with `Step` requests.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StepModeRequest {
+  pub enabled: bool,
+}
+
+/// Moves the machine relative to where it is. The distances are in millimeters along the axes as
+/// they are shown to operators (see `AxisMapping`), and are mapped back onto the machine axes.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct JogRequest {
+  #[serde(default)]
+  pub x: f32,
+  #[serde(default)]
+  pub y: f32,
+  #[serde(default)]
+  pub z: f32,
+  pub feed: f32,
+}
+
+/// An axis, as it is shown to operators.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayAxis {
+  X,
+  Y,
+  Z,
+}
+
+/// Moves the machine along a single axis (as shown to operators) until the probe touches something,
+/// giving up after `distance` millimeters. When `offset` is provided, the work offset of the axis is
+/// set so that the touched point is at it once the probe touches; e.g the thickness of a touch plate.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ProbeRequest {
+  pub axis: DisplayAxis,
+
+  /// How far to probe, signed by direction (e.g `-20` to probe down towards a touch plate).
+  pub distance: f32,
+  pub feed: f32,
+
+  #[serde(default)]
+  pub offset: Option<f32>,
+}
+
+/// The override adjusted by an `Override` request.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideKind {
+  Feed,
+  Rapid,
+  Spindle,
+}
+
+/// How an `Override` request adjusts its override.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideDirection {
+  /// Up by 10%.
+  Increase,
+
+  /// Down by 10%.
+  Decrease,
+
+  /// Up by 1%.
+  IncreaseFine,
+
+  /// Down by 1%.
+  DecreaseFine,
+
+  /// Back to 100%.
+  Reset,
+}
+
+/// Adjusts one of the grbl 1.1 overrides of the controller while it is running.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OverrideRequest {
+  /// Sent as `target`, since `kind` is already taken by the kind of the request.
+  #[serde(rename = "target")]
+  pub kind: OverrideKind,
+  pub direction: OverrideDirection,
+}
+
+/// Starts sending a file, the same way an upload does.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StartJobRequest {
+  pub content: String,
+
+  /// Runs the job without sending any of it to the controller; each line is answered on its own
+  /// once the time it is estimated to take has passed. This works without a machine connected.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub dry_run: bool,
+
+  /// Scales, rotates and offsets the file before it is sent.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub transform: Option<Transform>,
+
+  /// Sends only the lines of the file, without the lines our machine is configured to send before
+  /// and after every job.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub skip_sequences: bool,
+
+  #[serde(flatten)]
+  pub metadata: UploadMetadata,
+}
+
+/// Starts uploading a file over the websocket, a chunk at a time, for clients that struggle with
+/// long uploads over http (e.g tablets on a flaky network). Once every chunk has been received, a
+/// `FinishUpload` request starts sending it the same way an upload over http does.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BeginUploadRequest {
+  /// The size of the file, in bytes.
+  pub size: usize,
+
+  #[serde(flatten)]
+  pub metadata: UploadMetadata,
+}
+
+/// The next part of the file being uploaded. Chunks are numbered from `0`, and must arrive in order.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct UploadChunkRequest {
+  pub seq: u32,
+  pub data: String,
+}
+
+/// Restarts the most recent job at a line of its file (e.g after replacing a broken bit). Without
+/// `confirm`, nothing is sent; the client is sent a preview of the lines that would be sent first to
+/// restore the modal state of the controller.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RunFromLineRequest {
+  /// The line of the file to start from; the first line is `1`.
+  pub line: usize,
+
+  #[serde(default)]
+  pub confirm: bool,
+}
+
+/// Resumes the job a disconnect, alarm or restart interrupted, the same way `RunFromLine` restarts
+/// the most recent job. Without a line, the job is resumed at the line it was interrupted at; that
+/// line had been sent, but may not have finished.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ResumeFromLineRequest {
+  #[serde(default)]
+  pub line: Option<usize>,
+
+  #[serde(default)]
+  pub confirm: bool,
+}
+
+/// Clients that lose their connection (e.g a tablet dropping off the network for a moment) are
+/// given a new id when they reconnect. Sending the token from the hello frame of the connection that
+/// was lost picks up the state of that client, including everything added to its history since.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ResumeSessionRequest {
+  pub token: String,
+
+  /// The sequence number of the first history entry the client did not receive (one past the last
+  /// entry it has); every entry from there on is sent again.
+  pub since: u64,
+}
+
+/// The response to a request, matched to it by its tick.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ClientResponse {
+  pub tick: u32,
+
+  /// Either `ok` or `failed`.
+  pub status: String,
+
+  /// When the request was not accepted, the catalog code of the failure (see `/api/errors`).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub code: Option<String>,
+
+  /// The values of the parameters of the code.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub params: Option<std::collections::BTreeMap<String, String>>,
+
+  /// The english message of the code, for clients that have not loaded the catalog.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub detail: Option<String>,
+}
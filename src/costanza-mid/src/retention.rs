@@ -0,0 +1,245 @@
+//! This module contains the policies used to determine how much of the data we accumulate while
+//! running (client history, job records, our message journal, etc...) we hold on to.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// The amount of a single class of data we will hold on to. When both limits are provided, data
+/// outside of either one is pruned.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RetentionPolicy {
+  /// Data older than this many days is pruned. Only applies to data that is timestamped.
+  days: Option<i64>,
+
+  /// Only the most recent amount of entries are kept.
+  count: Option<usize>,
+}
+
+impl RetentionPolicy {
+  /// Returns the maximum amount of entries to keep, if limited.
+  pub(crate) fn count(&self) -> Option<usize> {
+    self.count
+  }
+
+  /// Given the total amount of entries and the timestamps of those entries ordered oldest first,
+  /// returns the amount of the oldest entries that fall outside of this policy.
+  pub(crate) fn excess<I>(&self, total: usize, timestamps: I) -> usize
+  where
+    I: Iterator<Item = chrono::DateTime<chrono::Utc>>,
+  {
+    let by_count = self.count.map_or(0, |count| total.saturating_sub(count));
+
+    let by_age = self.days.map_or(0, |days| {
+      let cutoff = chrono::Utc::now() - chrono::Duration::days(days);
+      timestamps.take_while(|timestamp| *timestamp < cutoff).count()
+    });
+
+    by_count.max(by_age)
+  }
+}
+
+/// The retention configuration, with a policy per class of data.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RetentionConfiguration {
+  /// The amount of seconds between each pruning.
+  #[serde(default = "default_interval")]
+  pub(crate) interval: u64,
+
+  /// The history held for each connected client. These entries are not timestamped, so only the
+  /// `count` is used; the oldest entries beyond it are dropped as new ones are added.
+  pub(crate) history: Option<RetentionPolicy>,
+
+  /// The records of finished jobs.
+  pub(crate) jobs: Option<RetentionPolicy>,
+
+  /// The lines of our message journal, the timeline of events received by the application. Lines
+  /// recorded before the journal was timestamped count as the oldest.
+  pub(crate) events: Option<RetentionPolicy>,
+
+  /// The frames written to the websocket capture, a transcript of what was exchanged with clients.
+  pub(crate) transcripts: Option<RetentionPolicy>,
+
+  /// The file of the most recent job, held on to (and snapshotted) so it can be run again from any
+  /// line. Only one is ever held, so this is mostly useful with `days`.
+  pub(crate) job_files: Option<RetentionPolicy>,
+}
+
+/// Used by serde when no interval is provided; once an hour.
+fn default_interval() -> u64 {
+  3600
+}
+
+/// The amount of entries per class of data that were (or would be) pruned.
+#[derive(Serialize, Debug, Default)]
+pub struct PruneReport {
+  pub(crate) jobs: usize,
+  pub(crate) events: usize,
+  pub(crate) transcripts: usize,
+  pub(crate) job_files: usize,
+}
+
+/// The values shared behind a `Pruner` handle.
+#[derive(Default)]
+struct Inner {
+  config: Option<RetentionConfiguration>,
+
+  /// The records of finished jobs, which hold on to their own policy.
+  jobs: crate::jobs::JobHistory,
+
+  /// Where our message journal is recorded, if anywhere.
+  journal: Option<std::path::PathBuf>,
+
+  /// The websocket capture, which prunes its own file.
+  capture: Option<crate::effects::http::Capture>,
+
+  /// When the job whose file the application is holding on to finished, if it is holding one.
+  job_file: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A cheaply cloneable handle to the data our policies apply to outside of the application state
+/// itself. The application prunes through it periodically, and admins preview what the next pruning
+/// would remove through our http server.
+#[derive(Clone, Default)]
+pub struct Pruner(Arc<Mutex<Inner>>);
+
+impl Pruner {
+  /// Creates a handle for the provided job records, journal and websocket capture. Nothing is
+  /// pruned until it has been configured.
+  pub(crate) fn new(
+    jobs: crate::jobs::JobHistory,
+    journal: Option<std::path::PathBuf>,
+    capture: crate::effects::http::Capture,
+  ) -> Self {
+    Self(Arc::new(Mutex::new(Inner {
+      jobs,
+      journal,
+      capture: Some(capture),
+      ..Inner::default()
+    })))
+  }
+
+  /// Applies the policies of a (possibly new) configuration.
+  pub(crate) fn configure(&self, config: Option<RetentionConfiguration>) {
+    self.with(|inner| {
+      inner
+        .jobs
+        .retain(config.as_ref().and_then(|config| config.jobs.clone()));
+      inner.config = config;
+    });
+  }
+
+  /// Returns the websocket capture whose file we prune.
+  pub(crate) fn capture(&self) -> Option<crate::effects::http::Capture> {
+    self.with(|inner| inner.capture.clone())
+  }
+
+  /// Sets when the job whose file the application is holding on to finished, if it is holding one.
+  pub(crate) fn set_job_file(&self, finished_at: Option<chrono::DateTime<chrono::Utc>>) {
+    self.with(|inner| inner.job_file = finished_at);
+  }
+
+  /// Removes the data that falls outside of our policies. When `dry_run` is true, nothing is removed.
+  /// Returns the amount of entries that were (or would be) removed; a job file that was removed is
+  /// forgotten here, and is expected to be dropped by the application.
+  ///
+  /// Our journal is written by the effect runtime between the updates of the application, which is
+  /// the only place anything but a dry run happens; it is never pruned while a line is being added.
+  pub(crate) fn prune(&self, dry_run: bool) -> PruneReport {
+    self.with(|inner| {
+      let config = inner.config.as_ref();
+      let (events, transcripts, job_files) = (
+        config.and_then(|config| config.events.clone()),
+        config.and_then(|config| config.transcripts.clone()),
+        config.and_then(|config| config.job_files.clone()),
+      );
+
+      let jobs = inner.jobs.prune(dry_run);
+
+      let events = match (inner.journal.as_ref(), events.as_ref()) {
+        (Some(path), Some(policy)) => prune_lines(path, policy, dry_run).unwrap_or_else(|error| {
+          tracing::warn!("unable to prune message journal - {error}");
+          0
+        }),
+        _ => 0,
+      };
+
+      let transcripts = match (inner.capture.as_ref(), transcripts.as_ref()) {
+        (Some(capture), Some(policy)) => capture.prune(policy, dry_run),
+        _ => 0,
+      };
+
+      let job_files = match (inner.job_file, job_files.as_ref()) {
+        (Some(finished_at), Some(policy)) => policy.excess(1, std::iter::once(finished_at)),
+        _ => 0,
+      };
+
+      if job_files > 0 && !dry_run {
+        tracing::info!("pruned the file of the job finished at {:?}", inner.job_file);
+        inner.job_file = None;
+      }
+
+      PruneReport {
+        jobs,
+        events,
+        transcripts,
+        job_files,
+      }
+    })
+  }
+
+  /// Runs the closure against our locked state.
+  fn with<F, T>(&self, apply: F) -> T
+  where
+    F: FnOnce(&mut Inner) -> T,
+  {
+    let mut inner = match self.0.lock() {
+      Ok(inner) => inner,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    apply(&mut inner)
+  }
+}
+
+/// The only part of a line of json we look at when pruning.
+#[derive(Deserialize)]
+struct Timestamped {
+  at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Removes the oldest lines of a file of json lines (each carrying an `at` timestamp) that fall
+/// outside of a policy. The file is rewritten in place, so anything appending to it carries on
+/// appending to the same file. Lines without a timestamp count as the oldest. When `dry_run` is
+/// true, nothing is removed. Returns the amount of lines that were (or would be) removed.
+pub(crate) fn prune_lines<P>(path: P, policy: &RetentionPolicy, dry_run: bool) -> std::io::Result<usize>
+where
+  P: AsRef<std::path::Path>,
+{
+  let contents = match std::fs::read_to_string(&path) {
+    Ok(contents) => contents,
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+    Err(error) => return Err(error),
+  };
+
+  let lines = contents.lines().collect::<Vec<&str>>();
+  let timestamps = lines.iter().map(|line| {
+    serde_json::from_str::<Timestamped>(line)
+      .ok()
+      .and_then(|line| line.at)
+      .unwrap_or_else(|| std::time::UNIX_EPOCH.into())
+  });
+  let removed = policy.excess(lines.len(), timestamps);
+
+  if dry_run || removed == 0 {
+    return Ok(removed);
+  }
+
+  let mut kept = lines[removed..].join("\n");
+  if !kept.is_empty() {
+    kept.push('\n');
+  }
+
+  std::fs::write(&path, kept)?;
+  tracing::info!("pruned {removed} lines of '{}'", path.as_ref().display());
+  Ok(removed)
+}
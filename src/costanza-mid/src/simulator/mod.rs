@@ -0,0 +1,909 @@
+//! A stand-in for a grbl 1.1 controller that is reachable through a pseudo-terminal (on unix) or a
+//! tcp socket (anywhere, including windows, where there are no pseudo-terminals). It knows enough
+//! of the protocol to exercise our side of it end to end: moves (`G0`/`G1`, `G28`/`G30`), homing
+//! (`$H`), jogging (`$J=`) and probing (`G38.2`/`G38.3`) travel toward their targets at the rate they
+//! were commanded at, status reports follow the machine along the way, and the realtime feed hold,
+//! cycle start, soft reset and jog cancel bytes are honored. Work coordinate systems are set with
+//! `G10`/`G92` and listed with `$#`, while settings are listed with `$$` and changed with `$x=value`.
+//! Moves beyond its travel trigger a soft limit alarm, cleared by `$X` (or homing). Lines it does not
+//! understand are acknowledged without doing anything.
+//!
+//! This is used by the `mock-grbl` binary during development and by the self-test.
+
+use settings::Settings;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{atomic, Arc};
+
+/// The settings and stored positions of the simulated controller.
+mod settings;
+
+/// The amount of moves the controller holds on to ahead of the one it is making. Lines that would
+/// add a move beyond this are not acknowledged until there is room for them.
+const PLANNER_BLOCKS: usize = 15;
+
+/// Millimeters in an inch, for lines in inches (`G20`).
+const MILLIMETERS_PER_INCH: f32 = 25.4;
+
+/// Where (in machine coordinates along `Z`) the simulated probe touches something; e.g the top of a
+/// touch plate. Probing along any other axis never touches anything.
+const PROBE_SURFACE: f32 = -50.0;
+
+/// How long we wait before reading again while nothing has the device open.
+const UNOPENED_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How long each read from a tcp connection waits on it, so that the machine keeps moving while
+/// nothing is being sent to it.
+const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// What the controller prints when it starts (or is reset).
+const WELCOME: &str = "Grbl 1.1h ['$' for help]";
+
+/// The realtime bytes the controller acts on as soon as they are received.
+const STATUS_QUERY: u8 = b'?';
+const FEED_HOLD: u8 = b'!';
+const CYCLE_START: u8 = b'~';
+const SOFT_RESET: u8 = 0x18;
+const JOG_CANCEL: u8 = 0x85;
+
+/// Why a move is being made, which decides what the controller reports while making it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotionKind {
+  Feed,
+  Jog,
+  Home,
+
+  /// A probing cycle, along with whether it touches anything before getting to its target, and
+  /// whether not touching anything alarms the machine (`G38.2`) or not (`G38.3`).
+  Probe {
+    touches: bool,
+    required: bool,
+  },
+}
+
+/// A single move held by the planner.
+#[derive(Debug, Clone, Copy)]
+struct Motion {
+  kind: MotionKind,
+
+  /// Where the move ends, in machine coordinates.
+  target: [f32; 3],
+
+  /// How fast the move is made, in millimeters per minute.
+  rate: f32,
+}
+
+/// The modal state of the controller, as set by the lines it has been sent.
+#[derive(Debug, Clone, Copy)]
+struct Modal {
+  relative: bool,
+  inches: bool,
+
+  /// Whether moves without a motion word are rapid (`G0`) or at the feed rate (`G1`).
+  rapid: bool,
+
+  /// The feed rate, in millimeters per minute; moves at the feed rate are refused until it is set.
+  feed: Option<f32>,
+
+  /// The work coordinate system in use (`0` for `G54`).
+  coordinate: usize,
+}
+
+impl Default for Modal {
+  fn default() -> Self {
+    Self {
+      relative: false,
+      inches: false,
+      rapid: true,
+      feed: None,
+      coordinate: 0,
+    }
+  }
+}
+
+/// The commands of a line that only apply to the line they are on.
+#[derive(Debug, Clone, Copy)]
+enum NonModal {
+  /// `G10`, moving the origin of a work coordinate system.
+  SetCoordinate,
+
+  /// `G28` or `G30` (`0` or `1`), going to a stored position.
+  Predefined(usize),
+
+  /// `G28.1` or `G30.1` (`0` or `1`), storing where the machine is.
+  StorePredefined(usize),
+
+  /// `G92`, offsetting every work coordinate system.
+  SetOffset,
+
+  /// `G92.1`, clearing the offset of `G92`.
+  ClearOffset,
+}
+
+/// The words of a single line, e.g `G0 X10` is `[('G', 0.0), ('X', 10.0)]`.
+fn words(line: &str) -> Result<Vec<(char, f32)>, u8> {
+  let mut words = vec![];
+  let mut chars = line.chars().filter(|c| !c.is_whitespace()).peekable();
+
+  while let Some(letter) = chars.next() {
+    let letter = letter.to_ascii_uppercase();
+
+    // Comments are dropped, along with everything after a `;`.
+    match letter {
+      '(' => {
+        chars.by_ref().find(|c| *c == ')');
+        continue;
+      }
+      ';' => break,
+      // Program delimiters are ignored.
+      '%' => continue,
+      letter if !letter.is_ascii_alphabetic() => return Err(1),
+      _ => (),
+    }
+
+    let mut value = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+')) {
+      value.push(c);
+    }
+
+    // A letter without a number is grbl's "bad number format".
+    words.push((letter, value.parse::<f32>().map_err(|_| 2)?));
+  }
+
+  Ok(words)
+}
+
+/// Converts a value of a line into millimeters.
+fn millimeters(value: f32, inches: bool) -> f32 {
+  match inches {
+    true => value * MILLIMETERS_PER_INCH,
+    false => value,
+  }
+}
+
+/// Returns where a probe moving between two points (in machine coordinates) touches the simulated
+/// surface, if it does.
+fn touch(from: [f32; 3], to: [f32; 3]) -> Option<[f32; 3]> {
+  if from[2] <= PROBE_SURFACE {
+    return Some(from);
+  }
+
+  if to[2] > PROBE_SURFACE {
+    return None;
+  }
+
+  let along = (from[2] - PROBE_SURFACE) / (from[2] - to[2]);
+  Some([0, 1, 2].map(|index| from[index] + (to[index] - from[index]) * along))
+}
+
+/// The state of the simulated machine.
+#[derive(Debug, Default)]
+pub struct Machine {
+  mpos: [f32; 3],
+
+  /// The offset of `G92`, applied on top of the work coordinate system in use.
+  g92: [f32; 3],
+
+  /// Where the last probing cycle ended, and whether it touched anything.
+  probe: ([f32; 3], bool),
+
+  modal: Modal,
+  settings: Settings,
+
+  /// The moves yet to be made, the first of which is being made.
+  planner: VecDeque<Motion>,
+
+  /// Lines received while the planner was full, which are handled as it makes room.
+  blocked: VecDeque<String>,
+
+  alarm: bool,
+  held: bool,
+
+  /// Whether the response of a homing or probing cycle is owed once the machine is done with it.
+  cycle: bool,
+
+  /// When the machine was last moved along.
+  moved_at: Option<std::time::Instant>,
+}
+
+impl Machine {
+  /// Returns the state reported in status reports.
+  fn state(&self) -> &'static str {
+    match (self.alarm, self.held, self.planner.front().map(|motion| motion.kind)) {
+      (true, _, _) => "Alarm",
+      (false, _, Some(MotionKind::Home)) => "Home",
+      (false, true, Some(_)) => "Hold:0",
+      (false, false, Some(MotionKind::Jog)) => "Jog",
+      (false, false, Some(MotionKind::Feed | MotionKind::Probe { .. })) => "Run",
+      (false, _, None) => "Idle",
+    }
+  }
+
+  /// Returns how far the work coordinates are from the machine coordinates.
+  fn wco(&self) -> [f32; 3] {
+    let offset = self.settings.offset(self.modal.coordinate);
+    [0, 1, 2].map(|index| offset[index] + self.g92[index])
+  }
+
+  /// Returns where the machine will be once every move in the planner has been made.
+  fn planned(&self) -> [f32; 3] {
+    self.planner.back().map_or(self.mpos, |motion| motion.target)
+  }
+
+  /// Returns the fastest the machine moves, in millimeters per minute.
+  fn max_rate(&self) -> f32 {
+    (0..3)
+      .map(|axis| self.settings.get(settings::MAX_RATE + axis))
+      .fold(f32::MAX, f32::min)
+  }
+
+  /// Returns whether a target (in machine coordinates) is beyond the travel of the machine, when
+  /// soft limits are enabled.
+  fn beyond_travel(&self, target: &[f32; 3]) -> bool {
+    let enabled = self.settings.get(settings::SOFT_LIMITS) != 0.0;
+    let travel = [0, 1, 2].map(|axis| self.settings.get(settings::MAX_TRAVEL + axis));
+    enabled && target.iter().zip(travel).any(|(axis, travel)| axis.abs() > travel)
+  }
+
+  /// Returns the status report of the controller.
+  fn status(&self) -> String {
+    let [x, y, z] = self.mpos;
+    let [wx, wy, wz] = self.wco();
+    let rate = match (self.held, self.planner.front()) {
+      (false, Some(motion)) => motion.rate,
+      _ => 0.0,
+    };
+
+    format!(
+      "<{}|MPos:{x:.3},{y:.3},{z:.3}|FS:{rate:.0},0|WCO:{wx:.3},{wy:.3},{wz:.3}>",
+      self.state()
+    )
+  }
+
+  /// Returns the result of the last probing cycle, as reported by `$#` and once the cycle is done.
+  fn probe_report(&self) -> String {
+    let ([x, y, z], touched) = self.probe;
+    format!("[PRB:{x:.3},{y:.3},{z:.3}:{}]", u8::from(touched))
+  }
+
+  /// Returns the lines listed by `$#`.
+  fn parameters(&self) -> Vec<String> {
+    let [x, y, z] = self.g92;
+    let mut parameters = self.settings.positions();
+    parameters.push(format!("[G92:{x:.3},{y:.3},{z:.3}]"));
+    parameters.push("[TLO:0.000]".to_string());
+    parameters.push(self.probe_report());
+    parameters.push("ok".to_string());
+    parameters
+  }
+
+  /// Returns the lines the controller responds to a realtime byte with.
+  pub fn realtime(&mut self, byte: u8, now: std::time::Instant) -> Vec<String> {
+    let mut responses = self.tick(now);
+
+    let reply = match byte {
+      STATUS_QUERY => vec![self.status()],
+      // Jogs are cancelled by a feed hold, rather than held.
+      FEED_HOLD
+        if self
+          .planner
+          .front()
+          .is_some_and(|motion| motion.kind == MotionKind::Jog) =>
+      {
+        self.cancel_jog();
+        vec![]
+      }
+      FEED_HOLD if !self.planner.is_empty() => {
+        tracing::info!("simulator holding");
+        self.held = true;
+        vec![]
+      }
+      CYCLE_START if self.held => {
+        tracing::info!("simulator resuming");
+        self.held = false;
+        vec![]
+      }
+      JOG_CANCEL => {
+        self.cancel_jog();
+        vec![]
+      }
+      SOFT_RESET => self.reset(),
+      _ => vec![],
+    };
+
+    responses.extend(reply);
+    responses
+  }
+
+  /// Drops any jog being made, along with any queued after it.
+  fn cancel_jog(&mut self) {
+    self.planner.retain(|motion| motion.kind != MotionKind::Jog);
+  }
+
+  /// Resets the controller, dropping everything it was doing. Resetting while moving loses track of
+  /// where the machine is, which alarms it.
+  fn reset(&mut self) -> Vec<String> {
+    let moving = !self.planner.is_empty() && !self.held;
+    let mut responses = vec![];
+
+    self.planner.clear();
+    self.blocked.clear();
+    self.modal = Modal::default();
+    self.held = false;
+    self.cycle = false;
+
+    if moving {
+      tracing::warn!("simulator reset while moving, alarming");
+      self.alarm = true;
+      responses.push("ALARM:3".to_string());
+    }
+
+    responses.push(WELCOME.to_string());
+
+    if self.alarm {
+      responses.push("[MSG:'$H'|'$X' to unlock]".to_string());
+    }
+
+    responses
+  }
+
+  /// Returns the lines the controller responds to a single line with.
+  pub fn command(&mut self, line: &str, now: std::time::Instant) -> Vec<String> {
+    let mut responses = self.tick(now);
+
+    let line = line.trim();
+    if line.is_empty() {
+      return responses;
+    }
+
+    // Everything is handled in order; nothing goes ahead of a line waiting on the planner, and
+    // nothing is handled until a homing or probing cycle is done.
+    match self.blocked.is_empty() && !self.cycle {
+      true => responses.extend(self.handle(line)),
+      false => self.blocked.push_back(line.to_string()),
+    }
+
+    responses
+  }
+
+  /// Handles a single line, returning nothing when it is blocked until the planner has room.
+  fn handle(&mut self, line: &str) -> Vec<String> {
+    let upper = line.to_ascii_uppercase();
+
+    match (upper.as_str(), self.alarm) {
+      ("?", _) => vec![self.status()],
+      ("$X", true) => {
+        tracing::info!("simulator unlocked");
+        self.alarm = false;
+        vec!["[MSG:Caution: Unlocked]".into(), "ok".into()]
+      }
+      ("$H", _) if self.settings.get(settings::HOMING) == 0.0 => vec!["error:5".into()],
+      ("$H", _) if !self.planner.is_empty() => vec!["error:8".into()],
+      ("$H", _) => {
+        tracing::info!("simulator homing");
+        self.alarm = false;
+        self.cycle = true;
+        self.planner.push_back(Motion {
+          kind: MotionKind::Home,
+          target: [0.0; 3],
+          rate: self.settings.get(settings::HOMING_RATE),
+        });
+        vec![]
+      }
+      // Settings and parameters can be looked at (and changed) while alarmed, but not while moving.
+      ("$$", _) => {
+        let mut responses = self.settings.list();
+        responses.push("ok".into());
+        responses
+      }
+      ("$#", _) => self.parameters(),
+      (setting, _) if setting.starts_with('$') && !setting.starts_with("$J=") && setting.contains('=') => {
+        if !self.planner.is_empty() {
+          return vec!["error:8".into()];
+        }
+
+        match self.settings.set(&setting[1..]) {
+          Ok(()) => vec!["ok".into()],
+          Err(code) => vec![format!("error:{code}")],
+        }
+      }
+      // Everything other than status queries, homing, unlocking and settings is refused until
+      // unlocked.
+      (_, true) => vec!["error:9".into()],
+      (jog, false) if jog.starts_with("$J=") => self.jog(&jog[3..]),
+      // Other system commands are not simulated.
+      (system, false) if system.starts_with('$') => vec!["ok".into()],
+      (_, false) if self.planner.len() > PLANNER_BLOCKS => {
+        self.blocked.push_back(line.to_string());
+        vec![]
+      }
+      (line, false) => match self.gcode(line) {
+        Ok(responses) => responses,
+        Err(code) => vec![format!("error:{code}")],
+      },
+    }
+  }
+
+  /// Handles a jog (the part of a `$J=` line after the `=`).
+  fn jog(&mut self, line: &str) -> Vec<String> {
+    let idle = self.planner.iter().all(|motion| motion.kind == MotionKind::Jog);
+    if !idle || self.held {
+      return vec!["error:8".into()];
+    }
+
+    let words = match words(line) {
+      Ok(words) => words,
+      Err(code) => return vec![format!("error:{code}")],
+    };
+
+    // Jogs have their own distance mode and units, defaulting to those of the machine.
+    let mut relative = self.modal.relative;
+    let mut inches = self.modal.inches;
+    let mut machine = false;
+    let mut feed = None;
+    let mut axes = [None; 3];
+
+    for (letter, value) in words {
+      match (letter, value as u32) {
+        ('G', 90) => relative = false,
+        ('G', 91) => relative = true,
+        ('G', 20) => inches = true,
+        ('G', 21) => inches = false,
+        ('G', 53) => machine = true,
+        ('F', _) => feed = Some(value),
+        ('X', _) => axes[0] = Some(value),
+        ('Y', _) => axes[1] = Some(value),
+        ('Z', _) => axes[2] = Some(value),
+        _ => return vec!["error:16".into()],
+      }
+    }
+
+    let feed = match feed {
+      Some(feed) if feed > 0.0 => millimeters(feed, inches).min(self.max_rate()),
+      _ => return vec!["error:22".into()],
+    };
+
+    let axes = axes.map(|axis| axis.map(|value| millimeters(value, inches)));
+    let target = self.target(axes, relative, machine);
+    if self.beyond_travel(&target) {
+      return vec!["error:15".into()];
+    }
+
+    self.planner.push_back(Motion {
+      kind: MotionKind::Jog,
+      target,
+      rate: feed,
+    });
+    vec!["ok".into()]
+  }
+
+  /// Returns where the provided axis values (in millimeters) move the machine, in machine
+  /// coordinates.
+  fn target(&self, axes: [Option<f32>; 3], relative: bool, machine: bool) -> [f32; 3] {
+    // Moves are planned from where the last queued move ends, not from where the machine is now.
+    let from = self.planned();
+    let wco = self.wco();
+    let mut target = from;
+
+    for (index, value) in axes.into_iter().enumerate() {
+      if let Some(value) = value {
+        target[index] = match (relative, machine) {
+          (true, _) => from[index] + value,
+          (false, true) => value,
+          (false, false) => value + wco[index],
+        };
+      }
+    }
+
+    target
+  }
+
+  /// Handles a line of gcode, returning the code of the error it is refused with, if it is.
+  fn gcode(&mut self, line: &str) -> Result<Vec<String>, u8> {
+    let mut modal = self.modal;
+    let mut machine = false;
+    let mut non_modal = None;
+    let mut motion = None;
+    let mut probe = None;
+    let mut axes = [None; 3];
+    let mut l = None;
+    let mut p = None;
+
+    for (letter, value) in words(line)? {
+      // Codes with a decimal (e.g `G28.1`) are kept apart from their whole counterparts.
+      let code = (value * 10.0).round() as u32;
+
+      match (letter, code) {
+        ('G', 0) => motion = Some(true),
+        ('G', 10) => motion = Some(false),
+        ('G', 100) => non_modal = Some(NonModal::SetCoordinate),
+        ('G', 200) => modal.inches = true,
+        ('G', 210) => modal.inches = false,
+        ('G', 280) => non_modal = Some(NonModal::Predefined(0)),
+        ('G', 281) => non_modal = Some(NonModal::StorePredefined(0)),
+        ('G', 300) => non_modal = Some(NonModal::Predefined(1)),
+        ('G', 301) => non_modal = Some(NonModal::StorePredefined(1)),
+        ('G', 382) => probe = Some(true),
+        ('G', 383) => probe = Some(false),
+        ('G', 530) => machine = true,
+        ('G', 540 | 550 | 560 | 570 | 580 | 590) => modal.coordinate = (code as usize - 540) / 10,
+        ('G', 900) => modal.relative = false,
+        ('G', 910) => modal.relative = true,
+        ('G', 920) => non_modal = Some(NonModal::SetOffset),
+        ('G', 921) => non_modal = Some(NonModal::ClearOffset),
+        ('F', _) => modal.feed = Some(millimeters(value, modal.inches)),
+        ('L', _) => l = Some(value.round() as u32),
+        ('P', _) => p = Some(value.round() as usize),
+        ('X', _) => axes[0] = Some(value),
+        ('Y', _) => axes[1] = Some(value),
+        ('Z', _) => axes[2] = Some(value),
+        // Everything else (e.g spindle and coolant words) is accepted without doing anything.
+        _ => (),
+      }
+    }
+
+    if let Some(rapid) = motion {
+      modal.rapid = rapid;
+    }
+
+    let axes = axes.map(|axis| axis.map(|value| millimeters(value, modal.inches)));
+    let moving = axes.iter().any(Option::is_some);
+    let feeding = probe.is_some() || (!modal.rapid && moving && non_modal.is_none());
+    if feeding && modal.feed.is_none() {
+      return Err(22);
+    }
+
+    self.modal = modal;
+
+    let rapid = self.max_rate();
+    let feed = modal.feed.unwrap_or(rapid).min(rapid);
+    let mut targets = vec![];
+
+    match non_modal {
+      // `G10 L2` sets the origin of a work coordinate system, while `G10 L20` sets it so that the
+      // machine is at the provided position; `P0` is the one in use.
+      Some(NonModal::SetCoordinate) => {
+        let coordinate = match p {
+          Some(0) => modal.coordinate,
+          Some(index @ 1..=6) => index - 1,
+          _ => return Err(29),
+        };
+
+        let planned = self.planned();
+        let mut offset = self.settings.offset(coordinate);
+
+        for (index, value) in axes.into_iter().enumerate() {
+          if let Some(value) = value {
+            offset[index] = match l {
+              Some(2) => value,
+              Some(20) => planned[index] - self.g92[index] - value,
+              _ => return Err(20),
+            };
+          }
+        }
+
+        self.settings.set_offset(coordinate, offset);
+      }
+      Some(NonModal::StorePredefined(index)) => {
+        let planned = self.planned();
+        self.settings.set_predefined(index, planned);
+      }
+      // `G28` and `G30` go through the provided axis words (if any) on their way.
+      Some(NonModal::Predefined(index)) => {
+        if moving {
+          targets.push((MotionKind::Feed, self.target(axes, modal.relative, false), rapid));
+        }
+
+        targets.push((MotionKind::Feed, self.settings.predefined(index), rapid));
+      }
+      Some(NonModal::SetOffset) => {
+        let planned = self.planned();
+        let offset = self.settings.offset(modal.coordinate);
+
+        for (index, value) in axes.into_iter().enumerate() {
+          if let Some(value) = value {
+            self.g92[index] = planned[index] - offset[index] - value;
+          }
+        }
+      }
+      Some(NonModal::ClearOffset) => self.g92 = [0.0; 3],
+      None if moving => {
+        let target = self.target(axes, modal.relative, machine);
+
+        match probe {
+          Some(required) => {
+            let touched = touch(self.planned(), target);
+            let kind = MotionKind::Probe {
+              touches: touched.is_some(),
+              required,
+            };
+            targets.push((kind, touched.unwrap_or(target), feed));
+          }
+          None if modal.rapid => targets.push((MotionKind::Feed, target, rapid)),
+          None => targets.push((MotionKind::Feed, target, feed)),
+        }
+      }
+      None => (),
+    }
+
+    if targets.iter().any(|(_, target, _)| self.beyond_travel(target)) {
+      tracing::warn!("simulator move beyond travel ({line}), alarming");
+      self.alarm = true;
+      self.planner.clear();
+      self.blocked.clear();
+      return Ok(vec!["ALARM:2".into()]);
+    }
+
+    for (kind, target, rate) in targets {
+      self.planner.push_back(Motion { kind, target, rate });
+    }
+
+    // Probing cycles are only acknowledged once they are done.
+    if probe.is_some() && moving {
+      self.cycle = true;
+      return Ok(vec![]);
+    }
+
+    Ok(vec!["ok".into()])
+  }
+
+  /// Handles a homing or probing cycle being done, returning what the controller has to say about it.
+  fn finish_cycle(&mut self, kind: MotionKind) -> Vec<String> {
+    self.cycle = false;
+
+    match kind {
+      MotionKind::Home => {
+        tracing::info!("simulator homed");
+        vec!["ok".into()]
+      }
+      MotionKind::Probe { touches, required } => {
+        self.probe = (self.mpos, touches);
+        let report = self.probe_report();
+
+        if touches || !required {
+          return vec![report, "ok".into()];
+        }
+
+        tracing::warn!("simulator probe touched nothing, alarming");
+        self.alarm = true;
+        self.planner.clear();
+        self.blocked.clear();
+        vec![report, "ALARM:5".into()]
+      }
+      MotionKind::Feed | MotionKind::Jog => vec![],
+    }
+  }
+
+  /// Moves the machine along for however long it has been since it last was, returning whatever the
+  /// controller has to say about it (e.g the `ok` of a finished homing cycle, or of lines that were
+  /// waiting on room in the planner).
+  pub fn tick(&mut self, now: std::time::Instant) -> Vec<String> {
+    let elapsed = self
+      .moved_at
+      .map_or(0.0, |at| now.saturating_duration_since(at).as_secs_f32());
+    self.moved_at = Some(now);
+
+    let mut responses = vec![];
+    let mut remaining = elapsed;
+
+    while remaining > 0.0 && !self.held && !self.alarm {
+      let motion = match self.planner.front() {
+        Some(motion) => *motion,
+        None => break,
+      };
+
+      let delta = [0, 1, 2].map(|index| motion.target[index] - self.mpos[index]);
+      let distance = delta.iter().map(|axis| axis * axis).sum::<f32>().sqrt();
+      let reach = motion.rate / 60.0 * remaining;
+
+      if reach < distance {
+        self.mpos = [0, 1, 2].map(|index| self.mpos[index] + delta[index] * reach / distance);
+        break;
+      }
+
+      self.mpos = motion.target;
+      remaining -= distance / (motion.rate / 60.0);
+      self.planner.pop_front();
+
+      if self.cycle && self.planner.is_empty() {
+        responses.extend(self.finish_cycle(motion.kind));
+      }
+    }
+
+    while self.planner.len() <= PLANNER_BLOCKS && !self.cycle {
+      let line = match self.blocked.pop_front() {
+        Some(line) => line,
+        None => break,
+      };
+
+      responses.extend(self.handle(&line));
+    }
+
+    responses
+  }
+}
+
+/// Where the simulator is reached from.
+enum Endpoint {
+  /// The main side of a pseudo-terminal pair; our serial connection opens the secondary side.
+  #[cfg(unix)]
+  Terminal(serialport::TTYPort),
+
+  /// A listening socket, along with the connection it most recently accepted. Only one connection
+  /// is served at a time, the way only one program can have a serial port open.
+  Tcp(std::net::TcpListener, Option<std::net::TcpStream>),
+}
+
+impl Endpoint {
+  /// Reads whatever was written to us, timing out when nothing was. Fails while nothing is
+  /// connected.
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    let (listener, connection) = match self {
+      #[cfg(unix)]
+      Self::Terminal(main) => return io::Read::read(main, buffer),
+      Self::Tcp(listener, connection) => (listener, connection),
+    };
+
+    let stream = match connection {
+      Some(stream) => stream,
+      None => {
+        // The listener does not block, so this fails right away while nobody is connecting.
+        let (stream, peer) = listener.accept()?;
+        tracing::info!("simulator accepted connection from {peer}");
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        connection.insert(stream)
+      }
+    };
+
+    match io::Read::read(stream, buffer) {
+      Ok(0) if !buffer.is_empty() => {
+        tracing::info!("simulator connection closed");
+        *connection = None;
+        Err(io::ErrorKind::NotConnected.into())
+      }
+      // Sockets time out with `WouldBlock` on some platforms.
+      Err(error) if error.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+      Err(error) if error.kind() != io::ErrorKind::TimedOut => {
+        *connection = None;
+        Err(error)
+      }
+      result => result,
+    }
+  }
+
+  /// Writes a line to whatever is connected. Lines written while nothing is connected over tcp are
+  /// dropped, as is the connection if writing to it fails.
+  fn write_line(&mut self, line: &str) -> io::Result<()> {
+    match self {
+      #[cfg(unix)]
+      Self::Terminal(main) => writeln!(main, "{line}"),
+      Self::Tcp(_, connection) => {
+        if let Some(Err(error)) = connection.as_mut().map(|stream| writeln!(stream, "{line}")) {
+          tracing::warn!("unable to write to simulator connection - {error}");
+          *connection = None;
+        }
+
+        Ok(())
+      }
+    }
+  }
+}
+
+/// A simulated controller, along with the pseudo-terminal (or socket) it is reachable through.
+pub struct Simulator {
+  endpoint: Endpoint,
+  device: Option<String>,
+  machine: Machine,
+
+  /// The part of a line received so far.
+  line: Vec<u8>,
+}
+
+impl Simulator {
+  /// Opens a new pseudo-terminal pair for the simulator. Only the main side is held on to; holding
+  /// the secondary side would keep our serial connection from locking it when it opens the device.
+  #[cfg(unix)]
+  pub fn open() -> io::Result<Self> {
+    let (main, secondary) = serialport::TTYPort::pair()?;
+
+    Ok(Self {
+      device: serialport::SerialPort::name(&secondary),
+      endpoint: Endpoint::Terminal(main),
+      machine: Machine::default(),
+      line: vec![],
+    })
+  }
+
+  /// Listens for tcp connections at the provided address (e.g `127.0.0.1:0`, for any free port)
+  /// instead of opening a pseudo-terminal. The device our serial connection should be configured
+  /// with is then a `tcp://` one.
+  pub fn listen<A>(addr: A) -> io::Result<Self>
+  where
+    A: std::net::ToSocketAddrs,
+  {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let device = format!("{}{}", crate::effects::serial::TCP_PREFIX, listener.local_addr()?);
+
+    Ok(Self {
+      endpoint: Endpoint::Tcp(listener, None),
+      device: Some(device),
+      machine: Machine::default(),
+      line: vec![],
+    })
+  }
+
+  /// Keeps the settings (and stored positions) of the simulator in a file, loading them from it now
+  /// and writing them back whenever they change.
+  pub fn with_settings<P>(mut self, path: P) -> io::Result<Self>
+  where
+    P: AsRef<std::path::Path>,
+  {
+    self.machine.settings = Settings::load(path)?;
+    Ok(self)
+  }
+
+  /// Returns the device our serial connection should be configured with to reach the simulator.
+  pub fn device(&self) -> Option<String> {
+    self.device.clone()
+  }
+
+  /// Returns the lines the controller responds to the provided bytes with. Realtime bytes are acted
+  /// on as they arrive, even in the middle of a line.
+  fn receive(&mut self, bytes: &[u8], now: std::time::Instant) -> Vec<String> {
+    let mut responses = vec![];
+
+    for byte in bytes.iter().copied() {
+      match byte {
+        STATUS_QUERY | FEED_HOLD | CYCLE_START | SOFT_RESET | 0x80..=0xFF => {
+          // A reset also drops whatever part of a line was received before it.
+          if byte == SOFT_RESET {
+            self.line.clear();
+          }
+
+          responses.extend(self.machine.realtime(byte, now));
+        }
+        b'\n' | b'\r' => {
+          let line = String::from_utf8_lossy(&self.line).to_string();
+          self.line.clear();
+          responses.extend(self.machine.command(&line, now));
+        }
+        byte => self.line.push(byte),
+      }
+    }
+
+    responses
+  }
+
+  /// Answers whatever is written to the device until `running` is cleared.
+  pub fn run(mut self, running: Arc<atomic::AtomicBool>) -> io::Result<()> {
+    while running.load(atomic::Ordering::Relaxed) {
+      let mut buffer = [0u8; 1024];
+
+      let responses = match self.endpoint.read(&mut buffer) {
+        Ok(amount) => {
+          tracing::debug!(
+            "simulator read {amount} bytes - {:?}",
+            String::from_utf8_lossy(&buffer[0..amount])
+          );
+          self.receive(&buffer[0..amount], std::time::Instant::now())
+        }
+        Err(error) if error.kind() == io::ErrorKind::TimedOut => self.machine.tick(std::time::Instant::now()),
+        // Reading fails while nothing has the device open.
+        Err(error) => {
+          tracing::trace!("simulator device not open - {error}");
+          std::thread::sleep(UNOPENED_DELAY);
+          self.machine.tick(std::time::Instant::now())
+        }
+      };
+
+      for response in responses {
+        self.endpoint.write_line(&response)?;
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,246 @@
+//! What the simulated controller keeps in its eeprom: the `$` settings (listed with `$$`, changed
+//! with `$x=value`) and the positions reported by `$#` that outlive a reset (the `G54`-`G59` work
+//! coordinate systems, and the `G28`/`G30` positions). When a file is provided, these are read from
+//! it on startup and written back on every change, in the same format they are listed in; e.g
+//!
+//! ```text
+//! $110=5000.000
+//! [G54:10.000,20.000,-5.000]
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Whether moves beyond the travel of the machine alarm it (`$20`).
+pub const SOFT_LIMITS: u16 = 20;
+
+/// Whether `$H` is accepted (`$22`).
+pub const HOMING: u16 = 22;
+
+/// The rate (in millimeters per minute) the machine homes at (`$25`).
+pub const HOMING_RATE: u16 = 25;
+
+/// The fastest (in millimeters per minute) the `X` axis moves at; `Y` and `Z` follow it (`$110`).
+pub const MAX_RATE: u16 = 110;
+
+/// How far (in millimeters) the `X` axis may travel from its origin; `Y` and `Z` follow it (`$130`).
+pub const MAX_TRAVEL: u16 = 130;
+
+/// Every setting the controller knows of, along with its value until changed.
+const DEFAULTS: [(u16, f32); 34] = [
+  (0, 10.0),
+  (1, 25.0),
+  (2, 0.0),
+  (3, 0.0),
+  (4, 0.0),
+  (5, 0.0),
+  (6, 0.0),
+  (10, 1.0),
+  (11, 0.010),
+  (12, 0.002),
+  (13, 0.0),
+  (SOFT_LIMITS, 1.0),
+  (21, 0.0),
+  (HOMING, 1.0),
+  (23, 0.0),
+  (24, 25.0),
+  (HOMING_RATE, 2500.0),
+  (26, 250.0),
+  (27, 1.0),
+  (30, 1000.0),
+  (31, 0.0),
+  (32, 0.0),
+  (100, 250.0),
+  (101, 250.0),
+  (102, 250.0),
+  (MAX_RATE, 5000.0),
+  (MAX_RATE + 1, 5000.0),
+  (MAX_RATE + 2, 5000.0),
+  (120, 10.0),
+  (121, 10.0),
+  (122, 10.0),
+  (MAX_TRAVEL, 500.0),
+  (MAX_TRAVEL + 1, 500.0),
+  (MAX_TRAVEL + 2, 500.0),
+];
+
+/// The settings holding whole numbers (e.g flags and masks), which are listed without decimals.
+const INTEGERS: [u16; 17] = [0, 1, 2, 3, 4, 5, 6, 10, 13, 20, 21, 22, 23, 26, 30, 31, 32];
+
+/// The names of the stored positions, in the order they are listed by `$#`.
+const POSITIONS: [&str; 8] = ["G54", "G55", "G56", "G57", "G58", "G59", "G28", "G30"];
+
+/// Where the `G28` position is among the stored positions; `G30` follows it.
+const PREDEFINED: usize = 6;
+
+#[derive(Debug)]
+pub struct Settings {
+  values: BTreeMap<u16, f32>,
+
+  /// The origin of each work coordinate system, followed by the `G28` and `G30` positions; all in
+  /// machine coordinates.
+  positions: [[f32; 3]; 8],
+
+  /// Where changes are written to, if anywhere.
+  path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      values: DEFAULTS.into_iter().collect(),
+      positions: [[0.0; 3]; 8],
+      path: None,
+    }
+  }
+}
+
+/// Parses a listed position (e.g `[G54:10.000,20.000,-5.000]`), returning where it is among the
+/// stored positions along with its axes.
+fn parse_position(line: &str) -> Option<(usize, [f32; 3])> {
+  let (name, raw) = line.strip_prefix('[')?.strip_suffix(']')?.split_once(':')?;
+  let index = POSITIONS.iter().position(|known| *known == name)?;
+  let axes = raw
+    .split(',')
+    .map(|raw| raw.trim().parse::<f32>().ok())
+    .collect::<Option<Vec<f32>>>()?;
+
+  match axes[..] {
+    [x, y, z] => Some((index, [x, y, z])),
+    _ => None,
+  }
+}
+
+impl Settings {
+  /// Loads the settings from a file, which is written to on every change from here on. A file that
+  /// does not exist yet starts out with the defaults.
+  pub fn load<P>(path: P) -> io::Result<Self>
+  where
+    P: AsRef<Path>,
+  {
+    let mut settings = Self {
+      path: Some(path.as_ref().to_path_buf()),
+      ..Self::default()
+    };
+
+    let contents = match std::fs::read_to_string(path.as_ref()) {
+      Ok(contents) => contents,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => {
+        tracing::info!("no simulator settings at {:?}, starting with defaults", path.as_ref());
+        return Ok(settings);
+      }
+      Err(error) => return Err(error),
+    };
+
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+      let loaded = match line.strip_prefix('$') {
+        Some(setting) => settings.apply(setting).is_ok(),
+        None => match parse_position(line) {
+          Some((index, position)) => {
+            settings.positions[index] = position;
+            true
+          }
+          None => false,
+        },
+      };
+
+      if !loaded {
+        tracing::warn!("ignoring unrecognized simulator setting '{line}'");
+      }
+    }
+
+    Ok(settings)
+  }
+
+  /// Returns the value of a setting.
+  pub fn get(&self, id: u16) -> f32 {
+    self.values.get(&id).copied().unwrap_or_default()
+  }
+
+  /// Returns every setting, as listed by `$$`.
+  pub fn list(&self) -> Vec<String> {
+    self
+      .values
+      .iter()
+      .map(|(id, value)| match INTEGERS.contains(id) {
+        true => format!("${id}={value:.0}"),
+        false => format!("${id}={value:.3}"),
+      })
+      .collect()
+  }
+
+  /// Returns every stored position, as listed by `$#`.
+  pub fn positions(&self) -> Vec<String> {
+    POSITIONS
+      .iter()
+      .zip(self.positions)
+      .map(|(name, [x, y, z])| format!("[{name}:{x:.3},{y:.3},{z:.3}]"))
+      .collect()
+  }
+
+  /// Returns the origin of a work coordinate system (`0` for `G54`), in machine coordinates.
+  pub fn offset(&self, coordinate: usize) -> [f32; 3] {
+    self.positions[coordinate]
+  }
+
+  /// Moves the origin of a work coordinate system (`0` for `G54`).
+  pub fn set_offset(&mut self, coordinate: usize, offset: [f32; 3]) {
+    self.positions[coordinate] = offset;
+    self.save();
+  }
+
+  /// Returns a predefined position (`0` for `G28`, `1` for `G30`), in machine coordinates.
+  pub fn predefined(&self, index: usize) -> [f32; 3] {
+    self.positions[PREDEFINED + index]
+  }
+
+  /// Stores a predefined position (`0` for `G28`, `1` for `G30`).
+  pub fn set_predefined(&mut self, index: usize, position: [f32; 3]) {
+    self.positions[PREDEFINED + index] = position;
+    self.save();
+  }
+
+  /// Changes a setting from the part of a `$x=value` line after the `$`, returning the code of the
+  /// error it is refused with, if it is.
+  pub fn set(&mut self, line: &str) -> Result<(), u8> {
+    self.apply(line)?;
+    self.save();
+    Ok(())
+  }
+
+  /// Changes a setting without writing it anywhere.
+  fn apply(&mut self, line: &str) -> Result<(), u8> {
+    let (id, value) = line.split_once('=').ok_or(3u8)?;
+    let id = id.trim().parse::<u16>().map_err(|_| 3u8)?;
+    let value = value.trim().parse::<f32>().map_err(|_| 2u8)?;
+
+    if value < 0.0 {
+      return Err(4);
+    }
+
+    let setting = self.values.get_mut(&id).ok_or(3u8)?;
+    *setting = match INTEGERS.contains(&id) {
+      true => value.trunc(),
+      false => value,
+    };
+
+    Ok(())
+  }
+
+  /// Writes every setting and stored position to our file, if we have one. Failing to do so is not
+  /// worth failing the line that changed them over.
+  fn save(&self) {
+    let path = match &self.path {
+      Some(path) => path,
+      None => return,
+    };
+
+    let mut contents = self.list();
+    contents.extend(self.positions());
+
+    if let Err(error) = std::fs::write(path, contents.join("\n") + "\n") {
+      tracing::warn!("unable to save simulator settings to {path:?} - {error}");
+    }
+  }
+}
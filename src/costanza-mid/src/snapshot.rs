@@ -0,0 +1,107 @@
+//! This module contains the periodic snapshot of the parts of the application worth keeping across
+//! restarts: the serial configuration, where the machine was, and the job being sent. When we crash
+//! (or lose power) in the middle of a job, the snapshot lets operators know exactly where it was
+//! interrupted, and run it again from that line.
+
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+
+/// Every version of the format our snapshot is persisted in.
+pub(crate) const MIGRATIONS: &[crate::migrations::Migration] = &[crate::migrations::Migration {
+  version: 1,
+  description: "initial snapshot format",
+  apply: verify_snapshot,
+}];
+
+/// The first version of our format; ensures the file is a valid snapshot without changing it.
+fn verify_snapshot(contents: &str) -> Result<String> {
+  serde_json::from_str::<Snapshot>(contents)?;
+  Ok(contents.to_string())
+}
+
+/// The configuration schema for snapshots.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SnapshotConfiguration {
+  /// Where the snapshot is written to (and restored from).
+  pub(crate) path: String,
+
+  /// The amount of seconds between each snapshot.
+  #[serde(default = "default_interval")]
+  pub(crate) interval: u64,
+}
+
+/// Used by serde when no interval is provided.
+fn default_interval() -> u64 {
+  10
+}
+
+/// The file of a job, kept so it can be run again from any line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JobFile {
+  pub(crate) source: Vec<String>,
+  pub(crate) metadata: crate::effects::http::UploadMetadata,
+
+  /// When the job finished; not provided while it is being sent.
+  #[serde(default)]
+  pub(crate) finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// How far a job has gotten; persisted when the job is interrupted, and sent to clients while it runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct JobProgress {
+  /// The name of the uploaded file, when provided.
+  pub(crate) name: Option<String>,
+
+  /// The line of the file most recently sent to the controller, where the first line is `1`.
+  pub(crate) line: usize,
+
+  /// The amount of lines in the file.
+  pub(crate) total: usize,
+
+  pub(crate) started_at: chrono::DateTime<chrono::Utc>,
+
+  /// The machine position most recently reported while the job was running, in `X`, `Y`, `Z` order.
+  pub(crate) position: Option<[f32; 3]>,
+}
+
+/// Everything we persist about the application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+  pub(crate) taken_at: chrono::DateTime<chrono::Utc>,
+
+  /// The serial configuration in use.
+  pub(crate) serial: Option<crate::effects::serial::SerialConfiguration>,
+
+  /// The machine position most recently reported by the controller, in `X`, `Y`, `Z` order.
+  pub(crate) position: Option<[f32; 3]>,
+
+  /// The file of the job being sent, or of the most recent one.
+  pub(crate) job: Option<JobFile>,
+
+  /// The progress of the job being sent. When restored, this is the job our restart interrupted.
+  pub(crate) progress: Option<JobProgress>,
+}
+
+/// Loads the snapshot at the provided path, if one has been written.
+pub(crate) fn load<P>(path: P) -> Result<Option<Snapshot>>
+where
+  P: AsRef<std::path::Path>,
+{
+  match std::fs::read_to_string(path) {
+    Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(error) => Err(error.into()),
+  }
+}
+
+/// Writes the snapshot to the provided path. The snapshot is staged next to it first, so a crash
+/// while writing never leaves us with half of one.
+pub(crate) fn write<P>(path: P, snapshot: &Snapshot) -> Result<()>
+where
+  P: AsRef<std::path::Path>,
+{
+  let staging = path.as_ref().with_extension("tmp");
+  std::fs::write(&staging, serde_json::to_string(snapshot)?)?;
+  std::fs::rename(&staging, path)?;
+  Ok(())
+}
@@ -0,0 +1,93 @@
+//! Support for running as a `Type=notify` systemd service. Nothing here does anything unless we were
+//! started by systemd with a `NOTIFY_SOCKET`, which makes it safe to call unconditionally.
+//!
+//! Rather than linking against libsystemd, the (small) notification protocol is implemented here:
+//! newline-separated assignments written as a single datagram to the socket systemd gave us.
+
+use std::os::unix::net::UnixDatagram;
+
+/// The environment variable holding the path of the socket notifications are sent to.
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// The environment variable holding the amount of microseconds systemd waits between watchdog
+/// notifications before restarting us.
+const WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+
+/// The environment variable holding the pid the watchdog is meant for, when set.
+const WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+/// Tells systemd we have finished starting up.
+pub(crate) fn ready() {
+  notify("READY=1");
+}
+
+/// Tells systemd we are shutting down.
+pub(crate) fn stopping() {
+  notify("STOPPING=1");
+}
+
+/// Sends a notification to systemd, if we were started with a socket to send it to. Failures are
+/// logged and otherwise ignored; being supervised is never a reason to stop running.
+fn notify(state: &str) {
+  let path = match std::env::var_os(NOTIFY_SOCKET) {
+    Some(path) => path,
+    None => return,
+  };
+
+  let result = UnixDatagram::unbound().and_then(|socket| send(&socket, &path, state));
+
+  match result {
+    Ok(_) => tracing::debug!("sent systemd notification '{state}'"),
+    Err(error) => tracing::warn!("unable to send systemd notification '{state}' - {error}"),
+  }
+}
+
+/// Writes the state to the socket at the provided path. Paths starting with `@` refer to the
+/// abstract namespace, which only exists on linux.
+fn send(socket: &UnixDatagram, path: &std::ffi::OsStr, state: &str) -> std::io::Result<usize> {
+  #[cfg(target_os = "linux")]
+  if let Some(name) = path.to_str().and_then(|path| path.strip_prefix('@')) {
+    use std::os::linux::net::SocketAddrExt;
+    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    return socket.send_to_addr(state.as_bytes(), &address);
+  }
+
+  socket.send_to(state.as_bytes(), path)
+}
+
+/// Periodically tells systemd we are still alive. This is petted from the loop of the effect
+/// runtime, so a runtime that stops processing messages gets restarted.
+pub(crate) struct Watchdog {
+  /// How often we notify systemd; half of the timeout it was configured with.
+  interval: std::time::Duration,
+
+  /// When we last notified systemd.
+  last: Option<std::time::Instant>,
+}
+
+impl Watchdog {
+  /// Returns a watchdog if systemd has been configured to expect one from our process.
+  pub(crate) fn from_env() -> Option<Self> {
+    let micros = std::env::var(WATCHDOG_USEC).ok()?.parse::<u64>().ok()?;
+
+    if let Ok(pid) = std::env::var(WATCHDOG_PID) {
+      if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return None;
+      }
+    }
+
+    let interval = std::time::Duration::from_micros(micros) / 2;
+    tracing::info!("notifying the systemd watchdog every {interval:?}");
+    Some(Self { interval, last: None })
+  }
+
+  /// Notifies systemd, unless we have done so recently.
+  pub(crate) fn pet(&mut self) {
+    if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+      return;
+    }
+
+    self.last = Some(std::time::Instant::now());
+    notify("WATCHDOG=1");
+  }
+}
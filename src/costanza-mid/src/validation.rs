@@ -0,0 +1,98 @@
+//! Checks over a parsed configuration that its types cannot express on their own (addresses that
+//! parse, devices that exist, secrets long enough to be worth anything). Deployments are checked
+//! before starting so a bad configuration fails fast instead of leaving a half-started server.
+
+/// How serious an issue is. Only errors prevent the application from starting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+  /// Something that is likely a mistake, but that we can run with.
+  Warning,
+
+  /// Something we cannot run with.
+  Error,
+}
+
+/// A single problem with a configuration.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Issue {
+  /// How serious the problem is.
+  pub severity: Severity,
+
+  /// The dotted path of the offending field (e.g `http.session.jwt_secret`).
+  pub field: String,
+
+  /// What is wrong with it.
+  pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let severity = match self.severity {
+      Severity::Warning => "warning",
+      Severity::Error => "error",
+    };
+
+    write!(formatter, "{severity}: {} - {}", self.field, self.message)
+  }
+}
+
+/// Every issue found while validating a configuration.
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct Validation {
+  /// The issues, in the order they were found.
+  issues: Vec<Issue>,
+}
+
+impl Validation {
+  /// Records an issue that prevents the application from starting.
+  pub(crate) fn error<F, M>(&mut self, field: F, message: M)
+  where
+    F: std::fmt::Display,
+    M: std::fmt::Display,
+  {
+    self.push(Severity::Error, field, message);
+  }
+
+  /// Records an issue that is likely a mistake, but that we can run with.
+  pub(crate) fn warning<F, M>(&mut self, field: F, message: M)
+  where
+    F: std::fmt::Display,
+    M: std::fmt::Display,
+  {
+    self.push(Severity::Warning, field, message);
+  }
+
+  /// Records an issue.
+  fn push<F, M>(&mut self, severity: Severity, field: F, message: M)
+  where
+    F: std::fmt::Display,
+    M: std::fmt::Display,
+  {
+    self.issues.push(Issue {
+      severity,
+      field: field.to_string(),
+      message: message.to_string(),
+    });
+  }
+
+  /// Records an error unless the value is an absolute url.
+  pub(crate) fn url<F>(&mut self, field: F, value: &str)
+  where
+    F: std::fmt::Display,
+  {
+    if let Err(error) = tide::http::Url::parse(value) {
+      self.error(field, format!("'{value}' is not a valid url ({error})"));
+    }
+  }
+
+  /// Returns every issue found.
+  pub fn issues(&self) -> &[Issue] {
+    &self.issues
+  }
+
+  /// Whether or not any of the issues prevent the application from starting.
+  pub fn has_errors(&self) -> bool {
+    self.issues.iter().any(|issue| issue.severity == Severity::Error)
+  }
+}
@@ -0,0 +1,4 @@
+G21 G90 G17
+G0 X0 Y10
+G3 F400 X-10 Y0 I0 J-10
+G2 X0 Y10 I10 J0
@@ -0,0 +1,4 @@
+G21 G90 G17
+G0 X10 Y0
+G3 X0 Y10 I-10 J0 F400
+G2 X10 Y0 I0 J-10
@@ -0,0 +1,4 @@
+G20 G90
+G0 X2 Y3 Z0.6
+G21
+G0 X26.4 Y51.8 Z3.04
@@ -0,0 +1,4 @@
+G20 G90
+G0 X1 Y1 Z0.5
+G21
+G0 X1 Y1 Z0.5
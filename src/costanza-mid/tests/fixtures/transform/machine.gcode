@@ -0,0 +1,9 @@
+G21 G90
+G53 G0 Z-5
+G28
+G28 G91 Z0
+G90
+G30 X1 Y1
+G0 X1 Y1 (comments are dropped from moved lines)
+(but kept on lines without coordinates)
+M5
@@ -0,0 +1,6 @@
+G21 G90
+G0 Z4
+G0 X10 Y20
+G1 F600 X20 Y30 Z-2
+G1 X10
+G0 Z4
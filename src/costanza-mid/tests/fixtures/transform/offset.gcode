@@ -0,0 +1,6 @@
+G21 G90
+G0 Z5
+G0 X0 Y0
+G1 X10 Y10 Z-1 F600
+G1 X0
+G0 Z5
@@ -0,0 +1,4 @@
+G21 G90 G17
+G0 X0 Y0
+G2 F400 X20 Y0 R10
+G3 X0 Y0 R-10
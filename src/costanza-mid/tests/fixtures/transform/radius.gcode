@@ -0,0 +1,4 @@
+G21 G90 G17
+G0 X0 Y0
+G2 X10 Y0 R5 F400
+G3 X0 Y0 R-5
@@ -0,0 +1,7 @@
+G21 G90
+G0 X11 Y21 Z4
+G91
+G1 F600 X1 Y1
+G1 Z-1
+G90
+G1 X15 Y25 Z-1
@@ -0,0 +1,7 @@
+G21 G90
+G0 X1 Y1 Z5
+G91
+G1 X1 Y1 F600
+G1 Z-1
+G90
+G1 X5 Y5 Z0
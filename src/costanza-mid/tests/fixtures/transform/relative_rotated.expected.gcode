@@ -0,0 +1,4 @@
+G21 G90
+G0 X-1 Y1
+G91 G0 X0 Y1
+G90 G1 F600 X-2 Y2
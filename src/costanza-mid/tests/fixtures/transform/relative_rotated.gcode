@@ -0,0 +1,4 @@
+G21 G90
+G0 X1 Y1
+G91 G0 X1
+G90 G1 Y2 F600
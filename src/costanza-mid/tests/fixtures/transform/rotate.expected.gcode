@@ -0,0 +1,6 @@
+G21 G90
+G0 X0 Y10
+G1 F800 X-10 Y10
+G1 X0 Y10
+G1 X0 Y0
+G1 Z-1
@@ -0,0 +1,6 @@
+G21 G90
+G0 X10 Y0
+G1 X10 Y10 F800
+G1 Y0
+G1 X0
+G1 Z-1
@@ -0,0 +1,6 @@
+G21 G90
+G0 X20 Y10
+G1 F500 X40
+G1 Y-5 Z-1
+M3 S12000
+G1 X0.25 Y0.5
@@ -0,0 +1,6 @@
+G21 G90
+G0 X10 Y5
+G1 X20 F500
+G1 Y-2.5 Z-1
+M3 S12000
+G1 X0.125 Y0.25
@@ -0,0 +1,147 @@
+//! Property tests for the parsers controller output goes through. Arbitrary bytes, split across
+//! arbitrary reads (including in the middle of a character or a `\r\n`), never panic or lose a
+//! complete line, and generated status reports parse back to what they were generated from.
+
+use costanza::ParserFixture;
+use proptest::prelude::*;
+
+/// The states a status report may start with, including ones followed by a substate.
+const STATES: [&str; 10] = [
+  "Idle", "Run", "Hold:0", "Hold:1", "Jog", "Alarm", "Door:2", "Check", "Home", "Sleep",
+];
+
+/// Splits bytes into the reads ending at each of the provided offsets (taken modulo the length of
+/// the bytes, in any order), followed by whatever is left.
+fn reads<'a>(bytes: &'a [u8], cuts: &[usize]) -> Vec<&'a [u8]> {
+  let mut cuts = cuts.iter().map(|cut| cut % (bytes.len() + 1)).collect::<Vec<usize>>();
+  cuts.sort_unstable();
+
+  let mut start = 0;
+  let mut reads = vec![];
+  for cut in cuts.into_iter().chain(std::iter::once(bytes.len())) {
+    reads.push(&bytes[start..cut]);
+    start = cut;
+  }
+
+  reads
+}
+
+/// Splits bytes into lines at every `\r\n`, bare `\r` or `\n`, returning them along with whatever
+/// follows the last of them. A trailing `\r` is part of what follows, since a `\n` may come next.
+fn framed(bytes: &[u8]) -> (Vec<String>, Vec<u8>) {
+  let mut lines = vec![];
+  let mut start = 0;
+  let mut index = 0;
+
+  while index < bytes.len() {
+    let ending = match (bytes[index], bytes.get(index + 1)) {
+      (b'\r', Some(b'\n')) => 2,
+      (b'\r', None) => break,
+      (b'\r' | b'\n', _) => 1,
+      _ => {
+        index += 1;
+        continue;
+      }
+    };
+
+    lines.push(String::from_utf8_lossy(&bytes[start..index]).into_owned());
+    index += ending;
+    start = index;
+  }
+
+  (lines, bytes[start..].to_vec())
+}
+
+/// Feeds the reads through a fresh parser, returning every line they completed along with whatever
+/// was left waiting on the rest of its line.
+fn parse(reads: Vec<&[u8]>) -> (Vec<String>, Vec<u8>) {
+  let mut fixture = ParserFixture::default();
+  let lines = reads.into_iter().flat_map(|read| fixture.read(read)).collect();
+  (lines, fixture.remainder().to_vec())
+}
+
+/// Renders axes (in thousandths of a millimeter) the way the controller reports them.
+fn axes(thousandths: [i32; 3]) -> String {
+  thousandths.map(|axis| format!("{:.3}", axis as f32 / 1000.0)).join(",")
+}
+
+/// Whether parsed axes are (close enough to) the ones that were rendered.
+fn matches(parsed: [f32; 3], thousandths: [i32; 3]) -> bool {
+  parsed
+    .iter()
+    .zip(thousandths)
+    .all(|(parsed, axis)| (parsed - axis as f32 / 1000.0).abs() < 0.001)
+}
+
+proptest! {
+  #[test]
+  fn arbitrary_reads_keep_every_line(
+    bytes in prop::collection::vec(any::<u8>(), 0..512),
+    cuts in prop::collection::vec(any::<usize>(), 0..16),
+  ) {
+    let (lines, remainder) = parse(reads(&bytes, &cuts));
+    let (expected, expected_remainder) = framed(&bytes);
+
+    prop_assert_eq!(lines, expected);
+    prop_assert_eq!(remainder, expected_remainder);
+  }
+
+  #[test]
+  fn text_split_mid_character_round_trips(
+    text in prop::collection::vec(("[^\r\n]{0,40}", prop::sample::select(vec!["\n", "\r\n", "\r"])), 0..16),
+    prompt in prop::option::of("[^\r\n]{1,20}"),
+    cuts in prop::collection::vec(any::<usize>(), 0..16),
+  ) {
+    let mut joined = text.iter().map(|(line, ending)| format!("{line}{ending}")).collect::<String>();
+    joined.push_str(prompt.as_deref().unwrap_or_default());
+
+    let mut fixture = ParserFixture::default();
+    let mut lines = reads(joined.as_bytes(), &cuts)
+      .into_iter()
+      .flat_map(|read| fixture.read(read))
+      .collect::<Vec<String>>();
+    lines.extend(fixture.flush());
+
+    let mut expected = text.into_iter().map(|(line, _)| line).collect::<Vec<String>>();
+    expected.extend(prompt);
+
+    prop_assert_eq!(lines, expected);
+    prop_assert!(fixture.remainder().is_empty());
+  }
+
+  #[test]
+  fn arbitrary_lines_do_not_panic(line in any::<String>()) {
+    let _ = ParserFixture::status(&line);
+  }
+
+  #[test]
+  fn status_reports_round_trip(
+    state in prop::sample::select(STATES.to_vec()),
+    position in prop::array::uniform3(-1_000_000i32..1_000_000),
+    offset in prop::option::of(prop::array::uniform3(-1_000_000i32..1_000_000)),
+    overrides in prop::option::of(prop::array::uniform3(10u16..=200)),
+    feed in 0u32..10_000,
+  ) {
+    let mut report = format!("<{state}|MPos:{}|FS:{feed},0", axes(position));
+    if let Some([feed, rapid, spindle]) = overrides {
+      report.push_str(&format!("|Ov:{feed},{rapid},{spindle}"));
+    }
+    if let Some(offset) = offset {
+      report.push_str(&format!("|WCO:{}", axes(offset)));
+    }
+    report.push('>');
+
+    let parsed = match ParserFixture::status(&report) {
+      Some(parsed) => parsed,
+      None => return Err(TestCaseError::fail(format!("'{report}' did not parse"))),
+    };
+
+    prop_assert_eq!(parsed.state.as_str(), state.split(':').next().unwrap_or_default());
+    prop_assert!(matches(parsed.position, position), "{:?} from '{}'", parsed, report);
+    prop_assert_eq!(parsed.work_offset.is_some(), offset.is_some());
+    if let (Some(parsed_offset), Some(offset)) = (parsed.work_offset, offset) {
+      prop_assert!(matches(parsed_offset, offset), "{:?} from '{}'", parsed, report);
+    }
+    prop_assert_eq!(parsed.overrides, overrides);
+  }
+}
@@ -0,0 +1,31 @@
+//! Boots the application against the simulated controller (over a pseudo-terminal, or tcp outside of
+//! unix), driven by a scripted client, and walks through connecting, configuring, changing settings,
+//! uploading, streaming, alarming and disconnecting; this is the same walk-through as the `self-test`
+//! subcommand. The scripted client sends the application the messages the http effect would, so the
+//! http effect itself (its routes, auth and websockets) is not exercised here.
+
+/// The example configuration; only its timing, keep-alive and control settings are used.
+const CONFIGURATION: &str = include_str!("../../../config-example.toml");
+
+#[async_std::test]
+async fn self_test_walkthrough() -> costanza::Result<()> {
+  let config = costanza::load_configuration::<costanza::Configuration>(CONFIGURATION)?;
+  let report = costanza::self_test(config).await?;
+
+  let stages = report.stages.iter().map(|stage| stage.name).collect::<Vec<&str>>();
+  assert_eq!(
+    stages,
+    [
+      "connect",
+      "configure",
+      "settings",
+      "upload",
+      "stream",
+      "alarm",
+      "disconnect"
+    ],
+    "{report}"
+  );
+  assert!(report.passed(), "{report}");
+  Ok(())
+}
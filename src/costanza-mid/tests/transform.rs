@@ -0,0 +1,133 @@
+//! Golden files for the transform applied to jobs before they are sent. Every case pairs a file
+//! (`tests/fixtures/transform/<name>.gcode`) with what it is expected to be once transformed
+//! (`<name>.expected.gcode`); lines the transform refuses are checked on their own.
+
+use costanza::protocol::Transform;
+
+/// Every golden file, along with the scale, rotation and offset it is transformed by.
+const CASES: [(&str, &str, &str, Transform); 9] = [
+  (
+    "scale",
+    include_str!("fixtures/transform/scale.gcode"),
+    include_str!("fixtures/transform/scale.expected.gcode"),
+    transform(2.0, 0.0, [0.0; 3]),
+  ),
+  (
+    "rotate",
+    include_str!("fixtures/transform/rotate.gcode"),
+    include_str!("fixtures/transform/rotate.expected.gcode"),
+    transform(1.0, 90.0, [0.0; 3]),
+  ),
+  (
+    "offset",
+    include_str!("fixtures/transform/offset.gcode"),
+    include_str!("fixtures/transform/offset.expected.gcode"),
+    transform(1.0, 0.0, [10.0, 20.0, -1.0]),
+  ),
+  (
+    "relative",
+    include_str!("fixtures/transform/relative.gcode"),
+    include_str!("fixtures/transform/relative.expected.gcode"),
+    transform(1.0, 0.0, [10.0, 20.0, -1.0]),
+  ),
+  (
+    "relative_rotated",
+    include_str!("fixtures/transform/relative_rotated.gcode"),
+    include_str!("fixtures/transform/relative_rotated.expected.gcode"),
+    transform(1.0, 90.0, [0.0; 3]),
+  ),
+  (
+    "inches",
+    include_str!("fixtures/transform/inches.gcode"),
+    include_str!("fixtures/transform/inches.expected.gcode"),
+    transform(1.0, 0.0, [25.4, 50.8, 2.54]),
+  ),
+  (
+    "arcs",
+    include_str!("fixtures/transform/arcs.gcode"),
+    include_str!("fixtures/transform/arcs.expected.gcode"),
+    transform(1.0, 90.0, [0.0; 3]),
+  ),
+  (
+    "radius",
+    include_str!("fixtures/transform/radius.gcode"),
+    include_str!("fixtures/transform/radius.expected.gcode"),
+    transform(2.0, 0.0, [0.0; 3]),
+  ),
+  (
+    "machine",
+    include_str!("fixtures/transform/machine.gcode"),
+    include_str!("fixtures/transform/machine.expected.gcode"),
+    transform(2.0, 45.0, [10.0; 3]),
+  ),
+];
+
+/// The transform scaling, rotating and offsetting points by the provided amounts.
+const fn transform(scale: f64, rotation: f64, offset: [f64; 3]) -> Transform {
+  Transform {
+    scale,
+    rotation,
+    offset,
+  }
+}
+
+/// Splits the contents of a file into the lines a job would be made of.
+fn lines(contents: &str) -> Vec<String> {
+  contents.lines().map(String::from).collect()
+}
+
+/// Returns the error the transform refuses the lines with, failing if it does not.
+fn refused(transform: Transform, contents: &str) -> String {
+  match transform.apply(&lines(contents)) {
+    Ok(transformed) => panic!("'{contents}' was transformed into {transformed:?}"),
+    Err(error) => error.to_string(),
+  }
+}
+
+#[test]
+fn golden_files() {
+  for (name, input, expected, transform) in CASES {
+    let transformed = transform
+      .apply(&lines(input))
+      .unwrap_or_else(|error| panic!("{name} was refused - {error}"));
+
+    assert_eq!(transformed, lines(expected), "{name} does not match its golden file");
+  }
+}
+
+#[test]
+fn coordinate_offsets_are_refused() {
+  let scaled = transform(2.0, 0.0, [0.0; 3]);
+
+  assert!(refused(scaled, "G21\nG92 X0 Y0").contains("line 2"));
+  assert!(refused(scaled, "G10 L20 P1 X0").contains("coordinate offsets"));
+}
+
+#[test]
+fn arcs_outside_of_the_plane_are_refused_when_scaled_or_rotated() {
+  let arc = "G18\nG2 X1 Z1 I1 K0";
+
+  assert!(refused(transform(2.0, 0.0, [0.0; 3]), arc).contains("outside of the X/Y plane"));
+  assert!(refused(transform(1.0, 30.0, [0.0; 3]), arc).contains("outside of the X/Y plane"));
+
+  // Offsets alone move the whole arc, which is fine in any plane.
+  let offset = transform(1.0, 0.0, [5.0; 3]).apply(&lines(arc));
+  assert_eq!(offset.ok(), Some(lines("G18\nG2 K0 X6 Z6 I1")));
+}
+
+#[test]
+fn rotating_an_unknown_axis_is_refused() {
+  let rotated = transform(1.0, 90.0, [0.0; 3]);
+  assert!(refused(rotated, "G90\nG0 X10").contains("not known yet"));
+
+  // Whole turns leave points where they were, so the other axis is not needed.
+  let turned = transform(1.0, 360.0, [0.0; 3]).apply(&lines("G90\nG0 X10"));
+  assert_eq!(turned.ok(), Some(lines("G90\nG0 X10")));
+}
+
+#[test]
+fn invalid_transforms_are_refused() {
+  assert!(refused(transform(0.0, 0.0, [0.0; 3]), "G0 X1").contains("greater than zero"));
+  assert!(refused(transform(1.0, f64::NAN, [0.0; 3]), "G0 X1").contains("finite"));
+  assert!(refused(transform(1.0, 0.0, [f64::INFINITY, 0.0, 0.0]), "G0 X1").contains("finite"));
+}
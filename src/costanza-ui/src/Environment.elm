@@ -7,4 +7,5 @@ type alias Environment =
     , loginURL : String
     , logoutURL : String
     , version : String
+    , csrfToken : String
     }
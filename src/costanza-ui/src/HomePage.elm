@@ -16,6 +16,7 @@ import Set
 import StateSync as SS
 import Time
 import Url
+import Url.Builder
 
 
 type Request
@@ -107,14 +108,22 @@ init env key url =
     }
 
 
-uploadUrl : Env.Environment -> String
-uploadUrl env =
-    env.apiRoot ++ "/upload"
+uploadUrl : Env.Environment -> File.File -> String
+uploadUrl env file =
+    env.apiRoot ++ "/upload" ++ Url.Builder.toQuery [ Url.Builder.string "name" (File.name file) ]
 
 
 upload : Env.Environment -> File.File -> Cmd Message
 upload env file =
-    Http.post { body = Http.fileBody file, expect = Http.expectWhatever FileUploadResult, url = uploadUrl env }
+    Http.request
+        { method = "POST"
+        , headers = [ Http.header "X-CSRF-Token" env.csrfToken ]
+        , body = Http.fileBody file
+        , expect = Http.expectWhatever FileUploadResult
+        , url = uploadUrl env file
+        , timeout = Nothing
+        , tracker = Nothing
+        }
 
 
 update : Message -> ( HomePage, Env.Environment, Nav.Key ) -> ( HomePage, Cmd Message )
@@ -243,7 +252,7 @@ update message ( home, env, key ) =
                     ( { home | lastError = Just (JD.errorToString error) }, Cmd.none )
 
         AttemptSend payload ->
-            ( consumeInput home payload, sendInputMessage payload home.requestTick )
+            ( consumeInput home, sendInputMessage payload home.requestTick )
 
         KeyUp TerminalInputKeyUp 13 ->
             case String.isEmpty home.currentInput of
@@ -251,7 +260,7 @@ update message ( home, env, key ) =
                     ( home, Cmd.none )
 
                 False ->
-                    ( consumeInput home home.currentInput, sendInputMessage home.currentInput home.requestTick )
+                    ( consumeInput home, sendInputMessage home.currentInput home.requestTick )
 
         UpdateHomeInput value ->
             ( { home | currentInput = value }, Cmd.none )
@@ -276,13 +285,10 @@ bumpTick home =
     { home | requestTick = newTick, pendingTicks = pending }
 
 
-consumeInput : HomePage -> String -> HomePage
-consumeInput home payload =
-    let
-        newHistory =
-            List.append home.history [ SS.SentRequest { tick = home.requestTick, request = SS.RawSerial payload } ]
-    in
-    bumpTick (makePending { home | currentInput = "", history = newHistory })
+consumeInput : HomePage -> HomePage
+consumeInput home =
+    -- The request will be added to our history by the server as part of the next state update.
+    bumpTick (makePending { home | currentInput = "" })
 
 
 onKeyUp : (Int -> msg) -> Html.Attribute msg
@@ -448,6 +454,12 @@ renderHistoryEntry entry =
                 , Html.div [] [ Html.text message ]
                 ]
 
+        SS.Marker label ->
+            Html.div [ AT.class "flex items-center" ]
+                [ Html.div [ AT.class "mr-4" ] [ Icon.view Icon.CircleDot ]
+                , Html.div [] [ Html.text label ]
+                ]
+
 
 outerResponseDecoder : JD.Decoder WebsocketResponse
 outerResponseDecoder =
@@ -509,6 +521,10 @@ handleInnerWebsocketMessage key home message =
                 ( "ok", True, Terminal ) ->
                     ( { home | pendingTicks = newPending, lastRequest = Done (Ok ()) }, Cmd.none )
 
+                -- Requests that were not accepted carry a code (and usually some detail) explaining why.
+                ( "failed", True, _ ) ->
+                    ( { home | pendingTicks = newPending, lastError = Just (responseError requestResponse) }, Cmd.none )
+
                 _ ->
                     ( home, Cmd.none )
 
@@ -533,8 +549,27 @@ handleInnerWebsocketMessage key home message =
 
                         _ ->
                             Cmd.none
+
+                -- Delta updates only contain the entries we have not seen yet.
+                nextHistory =
+                    if state.delta then
+                        List.append home.history state.history
+
+                    else
+                        state.history
             in
-            ( { home | history = state.history, connection = nextConnection }, redirCommand )
+            ( { home | history = nextHistory, connection = nextConnection }, redirCommand )
+
+        -- TODO: chat messages are not rendered anywhere yet.
+        Ok (SS.Chat _) ->
+            ( home, Cmd.none )
+
+        -- TODO: macros are not listed anywhere yet.
+        Ok (SS.Macros _) ->
+            ( home, Cmd.none )
+
+        Ok (SS.HistoryPage older) ->
+            ( { home | history = List.append older home.history }, Cmd.none )
 
         Err error ->
             ( { home | lastError = Just (JD.errorToString error) }, Cmd.none )
@@ -678,3 +713,37 @@ sendWebsocketPayload value =
             JE.object [ ( "kind", JE.string "websocket" ), ( "payload", JE.string (JE.encode 0 value) ) ]
     in
     Boot.sendMessage (JE.encode 0 values)
+
+
+responseError : SS.ResponseContent -> String
+responseError response =
+    case ( response.code, response.detail ) of
+        ( Just "not_operator", _ ) ->
+            "Only the operator may control the machine."
+
+        ( Just "serial_unavailable", _ ) ->
+            "The serial connection is not available."
+
+        ( Just "job_active", _ ) ->
+            "Not available while a job is running."
+
+        ( Just "raw_during_job", _ ) ->
+            "Not available while a job is running."
+
+        ( Just "jog_during_job", _ ) ->
+            "Not available while a job is running."
+
+        ( Just "rate_limited", _ ) ->
+            "Too many requests; slow down and try again."
+
+        ( Just "insufficient_authority", _ ) ->
+            "You are not allowed to do that."
+
+        ( Just "csrf_mismatch", _ ) ->
+            "Your session is out of date; reload the page and try again."
+
+        ( _, Just detail ) ->
+            detail
+
+        _ ->
+            "The request failed."
@@ -102,10 +102,13 @@ update message model =
             case ( payload.ok, payload.session.user ) of
                 ( True, Just sessionData ) ->
                     let
+                        sessionEnv =
+                            { env | csrfToken = payload.csrfToken }
+
                         ( initialRoute, routeCmd ) =
-                            Routing.authorized env url key sessionData
+                            Routing.authorized sessionEnv url key sessionData
                     in
-                    ( Authorized initialRoute { env = env, session = sessionData, key = key }
+                    ( Authorized initialRoute { env = sessionEnv, session = sessionData, key = key }
                     , Cmd.batch [ Boot.startWebsocket, routeCmd |> Cmd.map RouteMessage ]
                     )
 
@@ -172,10 +175,13 @@ modelFromSessionPayload env url key payload =
     case ( payload.ok, payload.session.user ) of
         ( True, Just session ) ->
             let
+                sessionEnv =
+                    { env | csrfToken = payload.csrfToken }
+
                 ( routeModel, routeCmd ) =
-                    Routing.authorized env url key session
+                    Routing.authorized sessionEnv url key session
             in
-            ( Authorized routeModel { env = env, session = session, key = key }, routeCmd |> Cmd.map RouteMessage )
+            ( Authorized routeModel { env = sessionEnv, session = session, key = key }, routeCmd |> Cmd.map RouteMessage )
 
         _ ->
             ( Unauthorized env, Cmd.none )
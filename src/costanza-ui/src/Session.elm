@@ -17,6 +17,7 @@ type alias SessionData =
 type alias SessionPayload =
     { ok : Bool
     , session : SessionData
+    , csrfToken : String
     }
 
 
@@ -35,6 +36,7 @@ sessionFieldDecoder =
 
 decode : JD.Decoder SessionPayload
 decode =
-    JD.map2 SessionPayload
+    JD.map3 SessionPayload
         (JD.field "ok" JD.bool)
         (JD.field "session" sessionFieldDecoder)
+        (JD.oneOf [ JD.field "csrf_token" JD.string, JD.succeed "" ])
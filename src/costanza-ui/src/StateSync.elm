@@ -1,4 +1,4 @@
-module StateSync exposing (ResponseContent, SentRequestKinds(..), StateHistoryEntry(..), StatePayload, StateSyncKinds(..), parseMessage)
+module StateSync exposing (ChatEntry, ResponseContent, SentRequestKinds(..), StateHistoryEntry(..), StatePayload, StateSyncKinds(..), parseMessage)
 
 import Json.Decode as JD
 
@@ -6,13 +6,22 @@ import Json.Decode as JD
 type alias StatePayload =
     { tick : Int
     , serialAvailable : Bool
+    , delta : Bool
     , history : List StateHistoryEntry
     }
 
 
+type alias ChatEntry =
+    { author : String
+    , content : String
+    }
+
+
 type alias ResponseContent =
     { status : String
     , tick : Int
+    , code : Maybe String
+    , detail : Maybe String
     }
 
 
@@ -30,11 +39,15 @@ type alias SentRequestEntry =
 type StateSyncKinds
     = State StatePayload
     | Response ResponseContent
+    | Chat ChatEntry
+    | HistoryPage (List StateHistoryEntry)
+    | Macros (List String)
 
 
 type StateHistoryEntry
     = SentRequest SentRequestEntry
     | ReceivedData String
+    | Marker String
 
 
 sentRequestKindDecoder : String -> JD.Decoder SentRequestKinds
@@ -64,6 +77,9 @@ kindDecoder kind =
                 (JD.field "request" sentRequestDecoder)
                 |> JD.map SentRequest
 
+        "marker" ->
+            JD.field "label" JD.string |> JD.map Marker
+
         _ ->
             JD.fail ("unrecognized history kind: " ++ kind)
 
@@ -73,6 +89,15 @@ historyDecoder =
     JD.field "history_kind" JD.string |> JD.andThen kindDecoder
 
 
+stateDecoder : JD.Decoder StatePayload
+stateDecoder =
+    JD.map4 StatePayload
+        (JD.field "tick" JD.int)
+        (JD.field "serial_available" JD.bool)
+        (JD.field "delta" JD.bool)
+        (JD.field "history" (JD.list historyDecoder))
+
+
 parseMessage : String -> Result JD.Error StateSyncKinds
 parseMessage payload =
     let
@@ -82,21 +107,38 @@ parseMessage payload =
     case parsedKind of
         Ok "response" ->
             JD.decodeString
-                (JD.map2 ResponseContent
+                (JD.map4 ResponseContent
                     (JD.field "status" JD.string)
                     (JD.field "tick" JD.int)
+                    (JD.maybe (JD.field "code" JD.string))
+                    (JD.maybe (JD.field "detail" JD.string))
                 )
                 payload
                 |> Result.map Response
 
-        Ok "state" ->
+        Ok "hello" ->
+            JD.decodeString (JD.field "state" stateDecoder) payload
+                |> Result.map State
+
+        Ok "chat" ->
             JD.decodeString
-                (JD.map3 StatePayload
-                    (JD.field "tick" JD.int)
-                    (JD.field "serial_available" JD.bool)
-                    (JD.field "history" (JD.list historyDecoder))
+                (JD.map2 ChatEntry
+                    (JD.field "author" JD.string)
+                    (JD.field "content" JD.string)
                 )
                 payload
+                |> Result.map Chat
+
+        Ok "history_page" ->
+            JD.decodeString (JD.field "history" (JD.list historyDecoder)) payload
+                |> Result.map HistoryPage
+
+        Ok "macros" ->
+            JD.decodeString (JD.field "macros" (JD.map (List.map Tuple.first) (JD.keyValuePairs JD.string))) payload
+                |> Result.map Macros
+
+        Ok "state" ->
+            JD.decodeString stateDecoder payload
                 |> Result.map State
 
         Ok unrecognized ->
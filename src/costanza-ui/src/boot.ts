@@ -20,6 +20,7 @@ type Flags = {
   loginURL: string;
   logoutURL: string;
   version: string;
+  csrfToken: string;
 };
 
 type Ports = {
@@ -59,7 +60,8 @@ type TElmMessage =
     }
 
     console.log("booting");
-    const flags = { apiRoot, version, loginURL, uiRoot, logoutURL };
+    // The csrf token is only known once the session has been loaded by the elm runtime.
+    const flags = { apiRoot, version, loginURL, uiRoot, logoutURL, csrfToken: "" };
     const app = Elm.Main.init({ flags });
 
     let websocket: WebSocket | undefined = void 0;